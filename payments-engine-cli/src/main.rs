@@ -1,8 +1,10 @@
 mod process;
 
-use payments_engine::Engine;
+use payments_engine::{Engine, TracingAuditLog};
+use payments_engine_core::source::{ChainedSource, MergedSource, TransactionSource};
+use payments_engine_csv::{decompress_gzip_if_needed, AsyncWriter, CsvSource};
 use payments_engine_store_memory::MemoryStore;
-use std::env::current_dir;
+use std::{env::current_dir, path::Path, process::ExitCode};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -12,31 +14,148 @@ use structopt::StructOpt;
     long_about = "🧰  Small utility to process payments from a csv file"
 )]
 pub struct Cli {
-    /// The path to the csv file containing the transactions
-    #[structopt(parse(from_os_str))]
-    pub path: std::path::PathBuf,
+    /// The paths to the csv files containing the transactions.
+    /// They're processed in order, as if they were a single file
+    #[structopt(parse(from_os_str), required = true)]
+    pub paths: Vec<std::path::PathBuf>,
+    /// Merges the files by transaction id instead of processing them in order.
+    /// Every file must be sorted by transaction id
+    #[structopt(long)]
+    pub merge: bool,
+    /// Writes the balances to the given file instead of stdout.
+    /// It's created, or truncated if it exists, before processing the transactions
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: Option<std::path::PathBuf>,
+    /// Increases the logging verbosity (-v for debug, -vv for trace)
+    #[structopt(short, long, parse(from_occurrences))]
+    pub verbose: u8,
+    /// Only logs errors
+    #[structopt(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Tags all the traces and audit lines of the run with the given batch id
+    #[structopt(long)]
+    pub batch_id: Option<String>,
+    /// Prints the balances as an aligned table instead of CSV
+    #[structopt(long)]
+    pub pretty: bool,
+    /// Validates the transactions without printing the balances.
+    /// Exits with an error if any of them can't be parsed or processed
+    #[structopt(long)]
+    pub validate: bool,
+    /// Stops at the first record that can't be parsed or the first rejected transaction,
+    /// exiting with its error instead of printing the balances
+    #[structopt(long)]
+    pub fail_fast: bool,
+    /// Prints the counters of the transactions read, by kind, to stderr
+    #[structopt(long)]
+    pub stats: bool,
+}
+
+impl Cli {
+    /// Returns the tracing level selected by the verbosity flags, if any.
+    pub const fn log_level(&self) -> Option<tracing::Level> {
+        if self.quiet {
+            return Some(tracing::Level::ERROR);
+        }
+        match self.verbose {
+            0 => None,
+            1 => Some(tracing::Level::DEBUG),
+            _ => Some(tracing::Level::TRACE),
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() -> anyhow::Result<ExitCode> {
     let cli = Cli::from_args();
     dotenv::dotenv().ok();
-    set_up_tracing();
+    set_up_tracing(cli.log_level());
     tracing::info!("Starting the Payments Engine CLI");
-    let file_path = current_dir()?.join(cli.path);
+    let current_dir = current_dir()?;
 
-    let mut reader = tokio::fs::File::open(file_path).await?;
-    let engine = Engine::new(MemoryStore::default());
-    let mut writer = tokio::io::stdout();
+    // gzipped files are transparently decompressed
+    let mut readers = Vec::with_capacity(cli.paths.len());
+    for path in &cli.paths {
+        let file = tokio::fs::File::open(current_dir.join(path)).await?;
+        readers.push(decompress_gzip_if_needed(file).await?);
+    }
+    // the output is opened upfront, so permission errors are reported before processing anything
+    let output = cli.output.as_ref().map(|path| current_dir.join(path));
+    let mut writer = open_output(output.as_deref()).await?;
+    let mut engine = Engine::new(MemoryStore::default());
+    // the transactions of tagged runs are audited as they're processed,
+    // so the audit lines are tagged with the batch id by the `run` span
+    if cli.batch_id.is_some() {
+        engine = engine.with_audit_log(TracingAuditLog);
+    }
 
-    process::process_transactions(&mut reader, &mut writer, engine).await?;
-    Ok(())
+    let mut sources: Vec<Box<dyn TransactionSource>> = Vec::with_capacity(readers.len());
+    for reader in &mut readers {
+        sources.push(Box::new(CsvSource::new(reader).await));
+    }
+    let options = process::ProcessOptions {
+        batch_id: cli.batch_id,
+        pretty: cli.pretty,
+        validate: cli.validate,
+        mode: if cli.fail_fast {
+            process::ProcessMode::FailFast
+        } else {
+            process::ProcessMode::ContinueOnError
+        },
+    };
+    let summary = if cli.merge {
+        let source = MergedSource::new(sources);
+        process::process_transactions(source, &mut writer, engine, options).await?
+    } else {
+        let source = ChainedSource::new(sources);
+        process::process_transactions(source, &mut writer, engine, options).await?
+    };
+    tracing::info!(
+        applied = summary.transactions.applied,
+        rejected = summary.transactions.rejected,
+        ignored = summary.transactions.ignored,
+        source_errors = summary.source_errors,
+        "Payments Engine CLI finished"
+    );
+    if cli.stats {
+        eprintln!("{}", summary.stats);
+    }
+    if cli.validate && !summary.is_clean() {
+        anyhow::bail!(
+            "Validation failed: {} records couldn't be parsed and {} transactions were rejected",
+            summary.source_errors,
+            summary.transactions.rejected
+        );
+    }
+    // the balances are printed anyway, but scripts can tell that something went wrong
+    if summary.stats.has_failures() {
+        tracing::error!(
+            "{} records couldn't be parsed or were rejected",
+            summary.stats.errors
+        );
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Opens the writer of the balances: the file at the given path, or stdout if there's none.
+async fn open_output(path: Option<&Path>) -> std::io::Result<Box<AsyncWriter>> {
+    Ok(match path {
+        Some(path) => Box::new(tokio::fs::File::create(path).await?),
+        None => Box::new(tokio::io::stdout()),
+    })
 }
 
-fn set_up_tracing() {
+fn set_up_tracing(level: Option<tracing::Level>) {
+    let mut env_filter = tracing_subscriber::EnvFilter::from_default_env();
+    if let Some(level) = level {
+        // the verbosity flags override the default level set by the env filter.
+        env_filter = env_filter.add_directive(level.into());
+    }
+
     let tracing = tracing_subscriber::fmt()
         .with_timer(tracing_subscriber::fmt::time::UtcTime::rfc_3339())
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env());
+        .with_env_filter(env_filter);
 
     if cfg!(debug_assertions) {
         tracing.pretty().init();
@@ -44,3 +163,102 @@ fn set_up_tracing() {
         tracing.json().init();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::from_iter_safe(std::iter::once("payments-engine-cli").chain(args.iter().copied()))
+            .unwrap()
+    }
+
+    #[test]
+    fn verbosity_flags_map_to_tracing_levels() {
+        assert_eq!(cli(&["tx.csv"]).log_level(), None);
+        assert_eq!(
+            cli(&["-v", "tx.csv"]).log_level(),
+            Some(tracing::Level::DEBUG)
+        );
+        assert_eq!(
+            cli(&["-vv", "tx.csv"]).log_level(),
+            Some(tracing::Level::TRACE)
+        );
+        assert_eq!(
+            cli(&["-vvv", "tx.csv"]).log_level(),
+            Some(tracing::Level::TRACE)
+        );
+        assert_eq!(
+            cli(&["-q", "tx.csv"]).log_level(),
+            Some(tracing::Level::ERROR)
+        );
+    }
+
+    #[test]
+    fn accepts_several_paths_but_at_least_one() {
+        assert_eq!(
+            cli(&["day1.csv", "day2.csv"]).paths,
+            vec![
+                std::path::PathBuf::from("day1.csv"),
+                std::path::PathBuf::from("day2.csv")
+            ]
+        );
+        assert!(Cli::from_iter_safe(["payments-engine-cli"]).is_err());
+    }
+
+    #[test]
+    fn output_is_optional() {
+        assert_eq!(cli(&["tx.csv"]).output, None);
+        assert_eq!(
+            cli(&["--output", "balances.csv", "tx.csv"]).output,
+            Some(std::path::PathBuf::from("balances.csv"))
+        );
+        assert_eq!(
+            cli(&["-o", "balances.csv", "tx.csv"]).output,
+            Some(std::path::PathBuf::from("balances.csv"))
+        );
+    }
+
+    #[tokio::test]
+    async fn balances_are_written_to_the_output_file() {
+        let path = std::env::temp_dir().join(format!(
+            "payments-engine-cli-output-{}.csv",
+            std::process::id()
+        ));
+        let mut input = "type,client,tx,amount\ndeposit,2,1,10\ndeposit,1,2,5".as_bytes();
+
+        let mut writer = open_output(Some(&path)).await.unwrap();
+        process::process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut writer,
+            Engine::new(MemoryStore::default()),
+            process::ProcessOptions::default(),
+        )
+        .await
+        .unwrap();
+        drop(writer);
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            csv,
+            "client,available,held,total,locked\n1,5,0,5,false\n2,10,0,10,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn output_files_that_cannot_be_created_fail_upfront() {
+        let path = std::env::temp_dir()
+            .join("payments-engine-cli-missing-dir")
+            .join("balances.csv");
+
+        assert!(open_output(Some(&path)).await.is_err());
+    }
+
+    #[test]
+    fn verbose_and_quiet_flags_conflict() {
+        let result = Cli::from_iter_safe(["payments-engine-cli", "-v", "-q", "tx.csv"]);
+        assert!(result.is_err());
+    }
+}
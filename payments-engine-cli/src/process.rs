@@ -1,41 +1,276 @@
-use futures::StreamExt;
-use payments_engine_core::engine::Engine;
-use payments_engine_csv::{read_csv_async, write_csv_async, AsyncReader, AsyncWriter};
+use comfy_table::{presets::ASCII_FULL, CellAlignment, Table};
+use futures::{Stream, StreamExt};
+use payments_engine_core::{
+    account::Account,
+    engine::{Engine, ProcessSummary},
+    source::TransactionSource,
+    transaction::Transaction,
+};
+use payments_engine_csv::{write_csv_async, AsyncWriter};
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+use tokio::io::AsyncWriteExt;
 use tracing::instrument;
 
-/// Processes all the transactions coming from an async reader
+/// How [`process_transactions`] handles the records that can't be read and the rejected transactions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProcessMode {
+    /// Logs and counts the errors, and keeps processing the rest of the transactions.
+    #[default]
+    ContinueOnError,
+    /// Stops at the first error and returns it, e.g. to validate clean fixtures.
+    FailFast,
+}
+
+/// Options to customize [`process_transactions`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProcessOptions {
+    /// Tags the run with the given batch id.
+    pub batch_id: Option<String>,
+    /// Writes the report as an aligned table instead of CSV.
+    pub pretty: bool,
+    /// Only validates the transactions, so the report is not written.
+    /// Parse errors and rejected transactions are still logged and counted.
+    pub validate: bool,
+    /// Whether to keep processing after an error. [`ProcessMode::ContinueOnError`] by default.
+    pub mode: ProcessMode,
+}
+
+/// Counters of the transactions read during a [`process_transactions`] run, by kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessStats {
+    pub deposits: u64,
+    pub withdrawals: u64,
+    pub disputes: u64,
+    pub reviews: u64,
+    pub resolves: u64,
+    pub chargebacks: u64,
+    pub adjustments: u64,
+    pub opens: u64,
+    pub closes: u64,
+    /// Records that couldn't be read from the source plus transactions rejected by the engine.
+    pub errors: u64,
+}
+
+impl ProcessStats {
+    /// Counts the given [`Transaction`] under its kind.
+    fn record(&mut self, transaction: &Transaction) {
+        let counter = match transaction {
+            Transaction::Deposit { .. } => &mut self.deposits,
+            Transaction::Withdrawal { .. } => &mut self.withdrawals,
+            Transaction::Dispute { .. } => &mut self.disputes,
+            Transaction::Review { .. } => &mut self.reviews,
+            Transaction::Resolve { .. } => &mut self.resolves,
+            Transaction::ChargeBack { .. } => &mut self.chargebacks,
+            Transaction::Adjustment { .. } => &mut self.adjustments,
+            Transaction::OpenAccount { .. } => &mut self.opens,
+            Transaction::CloseAccount { .. } => &mut self.closes,
+        };
+        *counter += 1;
+    }
+
+    /// Returns true if any record couldn't be read or any transaction was rejected.
+    pub const fn has_failures(&self) -> bool {
+        self.errors > 0
+    }
+}
+
+impl fmt::Display for ProcessStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "deposits={} withdrawals={} disputes={} reviews={} resolves={} chargebacks={} adjustments={} opens={} closes={} errors={}",
+            self.deposits,
+            self.withdrawals,
+            self.disputes,
+            self.reviews,
+            self.resolves,
+            self.chargebacks,
+            self.adjustments,
+            self.opens,
+            self.closes,
+            self.errors
+        )
+    }
+}
+
+/// Summary of a [`process_transactions`] run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunSummary {
+    /// Outcomes of the transactions processed by the engine.
+    pub transactions: ProcessSummary,
+    /// Records that couldn't be read from the source.
+    pub source_errors: u64,
+    /// Counters of the transactions read, by kind.
+    pub stats: ProcessStats,
+}
+
+impl RunSummary {
+    /// Returns true if every record was read and no transaction was rejected.
+    pub const fn is_clean(&self) -> bool {
+        self.source_errors == 0 && self.transactions.rejected == 0
+    }
+}
+
+/// Processes all the transactions coming from a [`TransactionSource`]
 /// and writes the results to an async writer.
+/// Returns the [`RunSummary`] of the processed transactions.
 /// Note that this function is generic over a [`Engine`] implementation.
-#[instrument(skip(reader, writer, engine))]
+///
+/// With [`ProcessMode::FailFast`], the first record that can't be read or the first rejected transaction
+/// stops the run and its error is returned, so no report is written.
+///
+/// The whole run is wrapped in a `run` span, tagged with the `batch_id` if provided,
+/// so every trace and audit line can be correlated to its batch.
+#[instrument(name = "run", skip(source, writer, engine, options), fields(batch_id))]
 pub async fn process_transactions<E: Engine>(
-    reader: &mut AsyncReader,
+    source: impl TransactionSource,
     writer: &mut AsyncWriter,
     engine: E,
-) -> anyhow::Result<()> {
-    let mut transaction_stream = read_csv_async(reader).await;
-
-    while let Some(transaction) = transaction_stream.next().await {
-        match transaction {
-            Ok(transaction) => {
-                if let Err(e) = engine.process_transaction(transaction).await {
-                    tracing::error!(error=?e, "Error processing transaction: {}", e);
+    options: ProcessOptions,
+) -> anyhow::Result<RunSummary> {
+    if let Some(batch_id) = &options.batch_id {
+        tracing::Span::current().record("batch_id", &batch_id.as_str());
+    }
+    // source errors are logged, counted and skipped, so the engine only gets valid transactions.
+    // failing fast, the first one ends the stream instead and it's kept to be returned.
+    let fail_fast = options.mode == ProcessMode::FailFast;
+    let source_errors = AtomicU64::new(0);
+    let errors = &source_errors;
+    let first_source_error = Mutex::new(None);
+    let first_error = &first_source_error;
+    let stats = Mutex::new(ProcessStats::default());
+    let kinds = &stats;
+    let transactions = futures::stream::unfold(source, move |mut source| async move {
+        loop {
+            match source.next().await {
+                Some(Ok(transaction)) => {
+                    if let Ok(mut kinds) = kinds.lock() {
+                        kinds.record(&transaction);
+                    }
+                    return Some((transaction, source));
+                }
+                Some(Err(e)) => {
+                    tracing::error!("Transaction source error: {}", e);
+                    errors.fetch_add(1, Ordering::Relaxed);
+                    if fail_fast {
+                        if let Ok(mut first_error) = first_error.lock() {
+                            *first_error = Some(e);
+                        }
+                        return None;
+                    }
                 }
+                None => return None,
             }
-            Err(e) => tracing::error!("CSV deserialization error: {}", e),
         }
+    });
+    let transactions = if fail_fast {
+        engine
+            .try_process_transactions(Box::pin(transactions))
+            .await?
+    } else {
+        engine.process_transactions(Box::pin(transactions)).await
+    };
+    if let Some(e) = first_source_error.into_inner().ok().flatten() {
+        return Err(e.into());
     }
+    let source_errors = source_errors.load(Ordering::Relaxed);
+    let mut stats = stats.into_inner().unwrap_or_default();
+    stats.errors = source_errors + transactions.rejected;
+    let summary = RunSummary {
+        transactions,
+        source_errors,
+        stats,
+    };
+    tracing::info!(?summary, "Transactions processed");
 
-    let report = engine.report().await?;
-    write_csv_async(writer, report).await?;
+    if options.validate {
+        tracing::info!("Validation only, skipping the report");
+        return Ok(summary);
+    }
 
+    let report = match engine.report().await {
+        Ok(report) => report,
+        Err(e) => {
+            tracing::warn!(error=?e, "Error getting the report, writing a partial one: {}", e);
+            partial_report(&engine).await?
+        }
+    };
+    if options.pretty {
+        write_table_async(writer, report).await?;
+    } else {
+        write_csv_async(writer, report).await?;
+    }
+
+    Ok(summary)
+}
+
+/// Fetches the accounts one by one, skipping the ones that can't be retrieved.
+/// It fails only if the client ids can't be retrieved.
+async fn partial_report<E: Engine>(
+    engine: &E,
+) -> anyhow::Result<Pin<Box<dyn Stream<Item = Account> + Send>>> {
+    let mut accounts = Vec::new();
+    for client in engine.client_ids().await? {
+        match engine.get_account(client).await {
+            Ok(account) => accounts.push(account),
+            Err(e) => tracing::warn!(error=?e, "Skipping account {} in the report: {}", client, e),
+        }
+    }
+    Ok(Box::pin(futures::stream::iter(accounts)))
+}
+
+/// Writes the [`Account`] balances as an aligned ASCII table, with right-aligned amounts.
+async fn write_table_async(
+    writer: &mut AsyncWriter,
+    mut account_stream: impl Stream<Item = Account> + Send + Unpin,
+) -> anyhow::Result<()> {
+    let mut table = Table::new();
+    table
+        .load_preset(ASCII_FULL)
+        .set_header(["client", "available", "held", "total", "locked"]);
+
+    while let Some(mut account) = account_stream.next().await {
+        account.to_max_display_precision();
+        table.add_row([
+            account.client.to_string(),
+            account.available.to_string(),
+            account.held.to_string(),
+            account.total.to_string(),
+            account.locked.to_string(),
+        ]);
+    }
+    for index in 1..=3 {
+        if let Some(column) = table.column_mut(index) {
+            column.set_cell_alignment(CellAlignment::Right);
+        }
+    }
+
+    writer.write_all(format!("{}\n", table).as_bytes()).await?;
+    writer.flush().await?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use payments_engine::Engine;
+    use async_trait::async_trait;
+    use payments_engine::{Engine, TracingAuditLog};
+    use payments_engine_core::{
+        dec,
+        engine::EngineError,
+        source::{ChainedSource, SourceError},
+        transaction::Transaction,
+    };
+    use payments_engine_csv::CsvSource;
     use payments_engine_store_memory::MemoryStore;
+    use std::collections::VecDeque;
     use tokio::io::BufWriter;
 
     #[tokio::test]
@@ -57,18 +292,464 @@ mod tests {
 
         let engine = Engine::new(MemoryStore::default());
 
-        process_transactions(&mut input, &mut output, engine)
+        process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut output,
+            engine,
+            ProcessOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        let buffer = output.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+
+        assert_eq!(
+            csv,
+            "client,available,held,total,locked\n1,250,0,250,false\n2,0,0,0,true\n"
+        );
+    }
+
+    /// Processes the given CSV with the given [`ProcessMode`], returning the result and the written output.
+    async fn process_with_mode(
+        input: &'static str,
+        mode: ProcessMode,
+    ) -> (anyhow::Result<RunSummary>, String) {
+        let mut input = input.as_bytes();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+        let options = ProcessOptions {
+            mode,
+            ..ProcessOptions::default()
+        };
+
+        let result = process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut output,
+            engine,
+            options,
+        )
+        .await;
+
+        let output = String::from_utf8(output.into_inner()).unwrap();
+        (result, output)
+    }
+
+    const WITH_A_BAD_ROW: &str =
+        "type,client,tx,amount\ndeposit,1,1,100\nbogus,1,2,1\ndeposit,1,3,50";
+    const WITH_A_REJECTED_TRANSACTION: &str =
+        "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,500\ndeposit,1,3,50";
+
+    #[tokio::test]
+    async fn continue_on_error_mode_skips_bad_rows_and_rejected_transactions() {
+        for input in [WITH_A_BAD_ROW, WITH_A_REJECTED_TRANSACTION] {
+            let (result, output) = process_with_mode(input, ProcessMode::ContinueOnError).await;
+
+            let summary = result.unwrap();
+            assert_eq!(summary.transactions.applied, 2);
+            assert_eq!(summary.stats.errors, 1);
+            assert_eq!(
+                output,
+                "client,available,held,total,locked\n1,150,0,150,false\n"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn fail_fast_mode_stops_at_the_first_bad_row() {
+        let (result, output) = process_with_mode(WITH_A_BAD_ROW, ProcessMode::FailFast).await;
+
+        let err = result.unwrap_err();
+        assert!(err.downcast_ref::<SourceError>().is_some(), "{}", err);
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fail_fast_mode_stops_at_the_first_rejected_transaction() {
+        let (result, output) =
+            process_with_mode(WITH_A_REJECTED_TRANSACTION, ProcessMode::FailFast).await;
+
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<EngineError>(),
+            Some(&EngineError::InsufficientAvailableFunds)
+        );
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn fail_fast_mode_processes_clean_inputs() {
+        let input = "type,client,tx,amount\ndeposit,1,1,100\ndispute,1,9,\ndeposit,1,3,50";
+
+        let (result, output) = process_with_mode(input, ProcessMode::FailFast).await;
+
+        let summary = result.unwrap();
+        assert_eq!(
+            summary.transactions,
+            ProcessSummary {
+                applied: 2,
+                rejected: 0,
+                ignored: 1,
+            }
+        );
+        assert_eq!(
+            output,
+            "client,available,held,total,locked\n1,150,0,150,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_the_summary_of_the_processed_transactions() {
+        let mut input = r"
+        type,client,tx,amount
+        deposit,1,1,100
+        withdrawal,1,2,500
+        dispute,1,3
+        dispute,1,1
+        bogus,1,4,1"
+            .as_bytes();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+
+        let summary = process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut output,
+            engine,
+            ProcessOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            summary,
+            RunSummary {
+                transactions: ProcessSummary {
+                    applied: 2,
+                    rejected: 1,
+                    ignored: 1,
+                },
+                source_errors: 1,
+                stats: ProcessStats {
+                    deposits: 1,
+                    withdrawals: 1,
+                    disputes: 2,
+                    errors: 2,
+                    ..ProcessStats::default()
+                },
+            }
+        );
+        assert!(!summary.is_clean());
+    }
+
+    #[tokio::test]
+    async fn counts_the_transactions_by_kind() {
+        let mut input = r"
+        type,client,tx,amount
+        deposit,1,1,100
+        deposit,1,2,50
+        withdrawal,1,3,30
+        withdrawal,1,4,1000
+        dispute,1,1
+        resolve,1,1
+        dispute,1,2
+        chargeback,1,2
+        deposit,1,5,not-a-number
+        refund,1,6,10
+        deposit,1,7
+        dispute,2,99"
+            .as_bytes();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+
+        let summary = process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut output,
+            engine,
+            ProcessOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            summary.stats,
+            ProcessStats {
+                deposits: 3,
+                withdrawals: 2,
+                disputes: 3,
+                reviews: 0,
+                resolves: 1,
+                chargebacks: 1,
+                adjustments: 0,
+                opens: 0,
+                closes: 0,
+                errors: 4,
+            }
+        );
+        assert_eq!(
+            summary.stats.to_string(),
+            "deposits=3 withdrawals=2 disputes=3 reviews=0 resolves=1 chargebacks=1 adjustments=0 opens=0 closes=0 errors=4"
+        );
+    }
+
+    /// Captures the logs written by a [`tracing_subscriber::fmt`] subscriber.
+    #[derive(Clone, Default)]
+    struct Logs(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Logs {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Logs {
+        /// Processes the input tagged with `batch-42` with the given engine, returning the logs of the run.
+        async fn of_tagged_run(input: &'static str, engine: Engine<MemoryStore>) -> String {
+            use tracing::instrument::WithSubscriber;
+
+            let logs = Self::default();
+            let writer_logs = logs.clone();
+            let subscriber = tracing_subscriber::fmt()
+                .with_max_level(tracing::Level::INFO)
+                .with_ansi(false)
+                .with_writer(move || writer_logs.clone())
+                .finish();
+            let mut input = input.as_bytes();
+            let mut output = BufWriter::new(Vec::<u8>::new());
+
+            process_transactions(
+                CsvSource::new(&mut input).await,
+                &mut output,
+                engine,
+                ProcessOptions {
+                    batch_id: Some("batch-42".to_string()),
+                    ..ProcessOptions::default()
+                },
+            )
+            .with_subscriber(subscriber)
+            .await
+            .unwrap();
+
+            let logs = logs.0.lock().unwrap().clone();
+            String::from_utf8(logs).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_id_is_recorded_in_the_root_span() {
+        let engine = Engine::new(MemoryStore::default());
+
+        let logs = Logs::of_tagged_run("type,client,tx,amount\nwithdrawal,1,1,100", engine).await;
+
+        assert!(logs.contains("run{batch_id=\"batch-42\"}"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn audit_lines_carry_the_batch_id() {
+        let engine = Engine::new(MemoryStore::default()).with_audit_log(TracingAuditLog);
+
+        let logs = Logs::of_tagged_run(
+            "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,500",
+            engine,
+        )
+        .await;
+
+        let audit_lines = logs
+            .lines()
+            .filter(|line| line.contains("audit:"))
+            .collect::<Vec<_>>();
+        assert_eq!(audit_lines.len(), 2, "{}", logs);
+        for line in audit_lines {
+            assert!(line.contains("run{batch_id=\"batch-42\"}"), "{}", line);
+        }
+    }
+
+    /// In-memory [`TransactionSource`].
+    struct VecSource(VecDeque<Result<Transaction, SourceError>>);
+
+    #[async_trait]
+    impl TransactionSource for VecSource {
+        async fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+            self.0.pop_front()
+        }
+    }
+
+    #[tokio::test]
+    async fn processes_transactions_from_any_source() {
+        let source = VecSource(VecDeque::from(vec![
+            Ok(Transaction::deposit(1, 1, dec!(100))),
+            Err(SourceError::ReadError("broken".to_string())),
+            Ok(Transaction::withdrawal(2, 1, dec!(40))),
+        ]));
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+
+        let summary = process_transactions(source, &mut output, engine, ProcessOptions::default())
+            .await
+            .unwrap();
+
+        let buffer = output.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+        assert_eq!(csv, "client,available,held,total,locked\n1,60,0,60,false\n");
+        assert_eq!(summary.transactions.applied, 2);
+        assert_eq!(summary.source_errors, 1);
+    }
+
+    #[tokio::test]
+    async fn processes_several_inputs_as_a_single_one() {
+        let mut day1 = "type,client,tx,amount\ndeposit,1,1,100\ndeposit,2,2,10".as_bytes();
+        let mut day2 = "type,client,tx,amount\nwithdrawal,1,3,40\ndispute,2,2,".as_bytes();
+        let source = ChainedSource::new([
+            Box::new(CsvSource::new(&mut day1).await) as Box<dyn TransactionSource>,
+            Box::new(CsvSource::new(&mut day2).await),
+        ]);
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+
+        let summary = process_transactions(source, &mut output, engine, ProcessOptions::default())
+            .await
+            .unwrap();
+
+        let buffer = output.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+        assert_eq!(summary.transactions.applied, 4);
+        assert!(csv.contains("1,60,0,60,false\n"), "{}", csv);
+        assert!(csv.contains("2,0,10,10,false\n"), "{}", csv);
+    }
+
+    #[tokio::test]
+    async fn writes_a_partial_report_if_the_report_fails() {
+        let source = VecSource(VecDeque::from(vec![
+            Ok(Transaction::deposit(1, 1, dec!(100))),
+            Ok(Transaction::deposit(2, 2, dec!(50))),
+        ]));
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let store = MemoryStore::default();
+        store.set_enable_get_all_accounts_failure(true);
+        let engine = Engine::new(store.clone());
+
+        process_transactions(source, &mut output, engine, ProcessOptions::default())
             .await
             .unwrap();
 
         let buffer = output.into_inner();
         let csv = String::from_utf8_lossy(&buffer);
+        assert_eq!(
+            csv,
+            "client,available,held,total,locked\n1,100,0,100,false\n2,50,0,50,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn failures_are_flagged_even_if_the_report_is_written() {
+        let mut input = r"
+        type,client,tx,amount
+        deposit,1,1,100
+        withdrawal,1,2,500
+        deposito,1,3,1"
+            .as_bytes();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+
+        let summary = process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut output,
+            engine,
+            ProcessOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(!output.into_inner().is_empty());
+        assert!(summary.stats.has_failures());
+    }
 
-        // the order is not guaranteed
-        let expected = (csv
-            == "client,available,held,total,locked\n1,250,0,250,false\n2,0,0,0,true\n")
-            || (csv == "client,available,held,total,locked\n2,0,0,0,true\n1,250,0,250,false\n");
+    #[tokio::test]
+    async fn validation_mode_reports_errors_without_writing_the_report() {
+        let mut input = r"
+        type,client,tx,amount
+        deposit,1,1,100
+        withdrawal,1,2,500
+        deposito,1,3,1"
+            .as_bytes();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+        let options = ProcessOptions {
+            validate: true,
+            ..ProcessOptions::default()
+        };
+
+        let summary = process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut output,
+            engine,
+            options,
+        )
+        .await
+        .unwrap();
+
+        assert!(output.into_inner().is_empty());
+        assert_eq!(summary.transactions.rejected, 1);
+        assert_eq!(summary.source_errors, 1);
+        assert!(!summary.is_clean());
+        assert!(summary.stats.has_failures());
+    }
 
-        assert!(expected);
+    #[tokio::test]
+    async fn validation_mode_is_clean_if_every_transaction_is_valid() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,50".as_bytes();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+        let options = ProcessOptions {
+            validate: true,
+            ..ProcessOptions::default()
+        };
+
+        let summary = process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut output,
+            engine,
+            options,
+        )
+        .await
+        .unwrap();
+
+        assert!(output.into_inner().is_empty());
+        assert!(summary.is_clean());
+    }
+
+    #[tokio::test]
+    async fn writes_a_pretty_table_if_enabled() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,100.5\ndeposit,2,2,2".as_bytes();
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        let engine = Engine::new(MemoryStore::default());
+        let options = ProcessOptions {
+            pretty: true,
+            ..ProcessOptions::default()
+        };
+
+        process_transactions(
+            CsvSource::new(&mut input).await,
+            &mut output,
+            engine,
+            options,
+        )
+        .await
+        .unwrap();
+
+        let buffer = output.into_inner();
+        let table = String::from_utf8_lossy(&buffer);
+        let header = table.lines().nth(1).unwrap();
+        assert!(header.contains("client"));
+        assert!(header.contains("available"));
+        assert!(header.contains("held"));
+        assert!(header.contains("total"));
+        assert!(header.contains("locked"));
+        // amounts are right-aligned
+        assert!(table.contains("| 1      |     100.5 |    0 | 100.5 | false  |"));
+        assert!(table.contains("| 2      |         2 |    0 |     2 | false  |"));
     }
 }
@@ -0,0 +1,10 @@
+use crate::{account::Account, engine::EngineResult, transaction::Transaction};
+
+/// The [`AuditLog`] keeps an append-only record of every [`Transaction`] the engine attempted,
+/// along with its outcome, including the rejected ones.
+///
+/// It's separate from the [`Store`](crate::store::Store), which only keeps the state needed to compute the balances.
+pub trait AuditLog: Send + Sync {
+    /// Appends the attempted [`Transaction`] and the resulting [`Account`] or error.
+    fn record(&self, transaction: &Transaction, outcome: &EngineResult<Account>);
+}
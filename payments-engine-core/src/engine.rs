@@ -1,10 +1,11 @@
 use crate::{
     account::Account,
-    common::ClientId,
+    common::{Amount, ClientId},
     store::StoreError,
-    transaction::{Transaction, TransactionId},
+    transaction::{DisputeState, Transaction, TransactionId},
 };
 use async_trait::async_trait;
+use futures::StreamExt;
 use std::pin::Pin;
 use thiserror::Error;
 
@@ -14,15 +15,70 @@ use thiserror::Error;
 pub trait Engine: Send + Sync {
     /// Process a single transaction.
     async fn process_transaction(&self, transaction: Transaction) -> EngineResult<Account>;
+    /// Process a stream of transactions and returns a [`ProcessSummary`] of the outcomes.
+    ///
+    /// The default implementation just calls [`Engine::process_transaction`] for every transaction,
+    /// so it can't tell ignored transactions apart from applied ones.
+    /// Implementors should override it if they can batch the store writes or detect ignored transactions.
+    async fn process_transactions<T>(&self, mut transactions: T) -> ProcessSummary
+    where
+        T: futures::Stream<Item = Transaction> + Send + Unpin + 'async_trait,
+    {
+        let mut summary = ProcessSummary::default();
+        while let Some(transaction) = transactions.next().await {
+            match self.process_transaction(transaction).await {
+                Ok(_) => summary.applied += 1,
+                Err(e) => {
+                    tracing::error!(error=?e, "Error processing transaction: {}", e);
+                    summary.rejected += 1;
+                }
+            }
+        }
+        summary
+    }
+    /// Process a stream of transactions, stopping at the first rejected one and returning its error.
+    /// Otherwise, it returns a [`ProcessSummary`] of the outcomes.
+    ///
+    /// The default implementation just calls [`Engine::process_transaction`] for every transaction,
+    /// so it can't tell ignored transactions apart from applied ones.
+    async fn try_process_transactions<T>(&self, mut transactions: T) -> EngineResult<ProcessSummary>
+    where
+        T: futures::Stream<Item = Transaction> + Send + Unpin + 'async_trait,
+    {
+        let mut summary = ProcessSummary::default();
+        while let Some(transaction) = transactions.next().await {
+            self.process_transaction(transaction).await?;
+            summary.applied += 1;
+        }
+        Ok(summary)
+    }
     /// Get the current state of all the accounts.
     async fn report(&self) -> EngineResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>>;
+    /// Get a page of up to `limit` accounts sorted by client, skipping the first `offset` ones.
+    async fn report_page(&self, offset: usize, limit: usize) -> EngineResult<Vec<Account>>;
+    /// Get the ids of all the clients with an account.
+    async fn client_ids(&self) -> EngineResult<Vec<ClientId>>;
+    /// Get the current state of a single existing account.
+    /// It returns an [`EngineError::AccountNotFound`] if the client has no account.
+    async fn get_account(&self, client: ClientId) -> EngineResult<Account>;
+}
+
+/// Summary of the outcomes of [`Engine::process_transactions`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessSummary {
+    /// Transactions applied to the accounts.
+    pub applied: u64,
+    /// Transactions rejected with an error.
+    pub rejected: u64,
+    /// Transactions ignored because their referenced transaction doesn't exist.
+    pub ignored: u64,
 }
 
 /// Result for [`Engine`] operations.
 pub type EngineResult<T> = Result<T, EngineError>;
 
 /// Error type for [`Engine`] operations.
-#[derive(Debug, Error, PartialEq)]
+#[derive(Debug, Clone, Error, PartialEq)]
 pub enum EngineError {
     #[error(transparent)]
     Store(#[from] StoreError),
@@ -30,7 +86,9 @@ pub enum EngineError {
     InsufficientAvailableFunds,
     #[error("Insufficient held funds")]
     InsufficientHeldFunds,
-    #[error("The referenced transaction {id} was not a deposit")]
+    #[error("Account of client {client} has no held funds at all")]
+    NoHeldFunds { client: ClientId },
+    #[error("The referenced transaction {id} was neither a deposit nor a withdrawal")]
     WrongTransactionRef { id: TransactionId },
     #[error(
         "The referenced transaction {id} with client {client} was not from client {wrong_client}"
@@ -42,10 +100,48 @@ pub enum EngineError {
     },
     #[error("Transaction with id {id} has negative amount")]
     NegativeAmountTransaction { id: TransactionId },
+    #[error("Transaction with id {id} has amount {amount} which exceeds the limit of {limit}")]
+    AmountExceedsLimit {
+        id: TransactionId,
+        amount: Amount,
+        limit: Amount,
+    },
     #[error("Transaction with id {id} it's already under dispute")]
     DoubleDispute { id: TransactionId },
+    #[error("Transaction with id {id} is not under dispute")]
+    NotUnderDispute { id: TransactionId },
+    #[error("Disputed amount exceeds the amount of transaction {id}")]
+    DisputeExceedsTransaction { id: TransactionId },
+    #[error("Client {client} has too many open disputes")]
+    TooManyOpenDisputes { client: ClientId },
+    #[error("Transaction with id {id} has a zero amount and can't be disputed")]
+    ZeroAmountDispute { id: TransactionId },
+    #[error("Transaction with id {id} can't move from {from:?} to {to:?} dispute state")]
+    InvalidDisputeTransition {
+        id: TransactionId,
+        from: DisputeState,
+        to: DisputeState,
+    },
+    #[error("Client {client} is sanctioned")]
+    SanctionedClient { client: ClientId },
+    #[error("Account of client {client} doesn't exist")]
+    AccountNotFound { client: ClientId },
     #[error("Tried to apply transaction with id {tx} to a locked account {id}")]
     LockedAccount { id: ClientId, tx: TransactionId },
+    #[error("There are no transaction ids left to generate")]
+    TransactionIdSpaceExhausted,
+    #[error("Amount {amount} can't be represented with {places} decimal places")]
+    PrecisionOverflow { amount: Amount, places: u32 },
+    #[error("Account {client} total doesn't match its available and held funds")]
+    IntegrityViolation { client: ClientId },
+    #[error("Transactions can only be replayed into an empty store")]
+    ReplayIntoNonEmptyStore,
+    #[error("Account {client} can't be opened as it already exists")]
+    AccountAlreadyExists { client: ClientId },
+    #[error("Account {client} can't be closed as its total is {total}")]
+    NonZeroBalanceOnClose { client: ClientId, total: Amount },
+    #[error("Account {client} may be in an inconsistent state after a failed rollback")]
+    AccountInInconsistentState { client: ClientId },
     #[error("Unknwon error: {0}")]
     UnknownError(String),
     #[error("Transaction was unable to complete. You may have unstable state.")]
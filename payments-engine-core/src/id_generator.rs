@@ -0,0 +1,11 @@
+use crate::transaction::TransactionId;
+
+/// The [`IdGenerator`] is responsible for minting new [`TransactionId`]s for synthetic transactions
+/// (adjustments, reversals, etc.) which must never collide with the ids coming from the input.
+pub trait IdGenerator: Send + Sync {
+    /// Registers an input [`TransactionId`] so it's never generated.
+    fn observe(&self, id: TransactionId);
+    /// Generates a new [`TransactionId`], or [`None`] if there are no ids left.
+    /// Implementations must never wrap around, as it would reuse ids.
+    fn next_id(&self) -> Option<TransactionId>;
+}
@@ -2,9 +2,14 @@
 
 //! Core types and traits for [payments-engine]
 //!
-//! Library authors that want to provide [`engine::Engine`] or [`store::Store`] implementations should use this crate.
+//! Library authors that want to provide [`engine::Engine`], [`store::Store`] or [`source::TransactionSource`] implementations should use this crate.
+pub mod audit;
 pub mod engine;
+pub mod id_generator;
+pub mod migration;
 mod models;
+pub mod sanctions;
+pub mod source;
 pub mod store;
 
 // re-exporting decimal macros
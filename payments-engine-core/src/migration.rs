@@ -0,0 +1,144 @@
+use crate::store::{StoreError, StoreResult};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Field holding the version of the format of a serialized store.
+pub const VERSION_FIELD: &str = "version";
+
+/// The [`StoreMigration`] trait upgrades the serialized state of a store written by an older engine,
+/// e.g. adding a new field of the [`Account`](crate::account::Account) with its default value.
+///
+/// Every migration upgrades the state from [`StoreMigration::source_version`] to the next version.
+pub trait StoreMigration: Send + Sync {
+    /// Version of the format this migration upgrades from.
+    fn source_version(&self) -> u32;
+    /// Upgrades the serialized state to the next version of the format.
+    /// The [`VERSION_FIELD`] is updated by [`load_versioned`], so migrations don't need to touch it.
+    fn migrate(&self, state: Value) -> StoreResult<Value>;
+}
+
+/// Deserializes a versioned JSON state, applying the migrations needed to upgrade it to the given `version` first.
+/// It returns a [`StoreError::MigrationError`] if the state is newer than `version` or if a migration is missing.
+pub fn load_versioned<T: DeserializeOwned>(
+    json: &str,
+    version: u32,
+    migrations: &[&dyn StoreMigration],
+) -> StoreResult<T> {
+    let mut state: Value = serde_json::from_str(json).map_err(migration_error)?;
+    let mut current = state
+        .get(VERSION_FIELD)
+        .and_then(Value::as_u64)
+        .and_then(|current| u32::try_from(current).ok())
+        .ok_or_else(|| {
+            tracing::error!("Serialized state has no valid version");
+            StoreError::MigrationError("missing version".to_string())
+        })?;
+    if current > version {
+        tracing::error!(
+            "Serialized state version {} is newer than {}",
+            current,
+            version
+        );
+        return Err(StoreError::MigrationError(format!(
+            "version {} is newer than the supported {}",
+            current, version
+        )));
+    }
+
+    while current < version {
+        let migration = migrations
+            .iter()
+            .find(|migration| migration.source_version() == current)
+            .ok_or_else(|| {
+                tracing::error!("No migration from version {}", current);
+                StoreError::MigrationError(format!("no migration from version {}", current))
+            })?;
+        tracing::info!("Migrating serialized state from version {}", current);
+        state = migration.migrate(state)?;
+        current += 1;
+        if let Some(object) = state.as_object_mut() {
+            object.insert(VERSION_FIELD.to_string(), current.into());
+        }
+    }
+
+    serde_json::from_value(state).map_err(migration_error)
+}
+
+fn migration_error(e: serde_json::Error) -> StoreError {
+    tracing::error!("Error while deserializing the state: {}", e);
+    StoreError::MigrationError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{common::Amount, dec};
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    struct AccountV2 {
+        client: u16,
+        available: Amount,
+        locked: bool,
+        frozen: bool,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct StateV2 {
+        version: u32,
+        accounts: Vec<AccountV2>,
+    }
+
+    /// Adds the `frozen` field to the accounts of the version 1.
+    struct AddFrozen;
+
+    impl StoreMigration for AddFrozen {
+        fn source_version(&self) -> u32 {
+            1
+        }
+
+        fn migrate(&self, mut state: Value) -> StoreResult<Value> {
+            let accounts = state["accounts"]
+                .as_array_mut()
+                .ok_or_else(|| StoreError::MigrationError("missing accounts".to_string()))?;
+            for account in accounts {
+                account["frozen"] = Value::Bool(false);
+            }
+            Ok(state)
+        }
+    }
+
+    const V1: &str = r#"{"version":1,"accounts":[{"client":1,"available":"10","held":"0","total":"10","locked":true}]}"#;
+
+    #[test]
+    fn older_states_are_migrated_with_defaults() {
+        let state: StateV2 = load_versioned(V1, 2, &[&AddFrozen]).unwrap();
+
+        assert_eq!(state.version, 2);
+        assert_eq!(state.accounts.len(), 1);
+        assert_eq!(state.accounts[0].client, 1);
+        assert_eq!(state.accounts[0].available, dec!(10));
+        assert!(state.accounts[0].locked);
+        assert!(!state.accounts[0].frozen);
+    }
+
+    #[test]
+    fn states_fail_to_load_if_a_migration_is_missing() {
+        let result = load_versioned::<StateV2>(V1, 2, &[]);
+
+        assert_eq!(
+            result.unwrap_err(),
+            StoreError::MigrationError("no migration from version 1".to_string())
+        );
+    }
+
+    #[test]
+    fn newer_states_are_rejected() {
+        let result = load_versioned::<StateV2>(r#"{"version":3,"accounts":[]}"#, 2, &[&AddFrozen]);
+
+        assert_eq!(
+            result.unwrap_err(),
+            StoreError::MigrationError("version 3 is newer than the supported 2".to_string())
+        );
+    }
+}
@@ -1,7 +1,23 @@
 use crate::common::{Amount, ClientId};
 use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-const MAX_DISPLAY_PRECISION: u32 = 4;
+/// Maximum number of decimal places used when displaying an [`Account`].
+/// Note that amounts keep their full precision internally and are only rounded for display.
+pub const MAX_DISPLAY_PRECISION: u32 = 4;
+
+/// Inconsistencies detected by [`Account::try_to_max_display_precision`].
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AccountError {
+    #[error("Total of the account {client} overflows")]
+    TotalOverflow { client: ClientId },
+    #[error("Total of the account {client} is {stored} but available + held is {computed}")]
+    TotalMismatch {
+        client: ClientId,
+        stored: Amount,
+        computed: Amount,
+    },
+}
 
 /// Represents the current state of the client's account.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -43,17 +59,179 @@ impl Account {
         }
     }
 
+    /// Returns true if the `total` equals `available + held`, without overflowing.
+    #[must_use]
+    pub fn is_consistent(&self) -> bool {
+        self.available.checked_add(self.held) == Some(self.total)
+    }
+
     /// Mutates the [`Account`] for displaying purposes and sets the ammounts up to 4 decimal places.
+    /// Amounts below that precision will be displayed as zero, even if they're retained internally.
     pub fn to_max_display_precision(&mut self) {
-        self.available = rescale_to_max_precision(self.available);
-        self.held = rescale_to_max_precision(self.held);
-        self.total = rescale_to_max_precision(self.total);
+        self.to_display_precision(MAX_DISPLAY_PRECISION);
+    }
+
+    /// Mutates the [`Account`] for displaying purposes and sets the ammounts up to the given decimal places.
+    /// Amounts with more decimals are rounded, but amounts with fewer decimals are NOT padded, e.g. `1.5` stays `1.5`.
+    /// Use [`Account::to_padded_display_precision`] to always display the given decimal places.
+    pub fn to_display_precision(&mut self, places: u32) {
+        self.available = rescale_to_precision(self.available, places);
+        self.held = rescale_to_precision(self.held, places);
+        self.total = rescale_to_precision(self.total, places);
+    }
+
+    /// Mutates the [`Account`] for displaying purposes and sets the ammounts to exactly the given decimal places.
+    /// Amounts with more decimals are rounded and amounts with fewer decimals are padded with zeros, e.g. `1.5` becomes `1.50`.
+    pub fn to_padded_display_precision(&mut self, places: u32) {
+        self.available.rescale(places);
+        self.held.rescale(places);
+        self.total.rescale(places);
+    }
+
+    /// Checked version of [`Account::to_max_display_precision`].
+    /// The `total` is recomputed as the sum of the rescaled `available` and `held` instead of being rescaled on its own,
+    /// so the displayed total always matches the displayed funds.
+    ///
+    /// Returns an error, leaving the [`Account`] untouched, if the sum overflows
+    /// or if it disagrees with the stored `total`.
+    pub fn try_to_max_display_precision(&mut self) -> Result<(), AccountError> {
+        let computed =
+            self.available
+                .checked_add(self.held)
+                .ok_or(AccountError::TotalOverflow {
+                    client: self.client,
+                })?;
+        if computed != self.total {
+            tracing::error!(account = ?self, %computed, "Account total is inconsistent");
+            return Err(AccountError::TotalMismatch {
+                client: self.client,
+                stored: self.total,
+                computed,
+            });
+        }
+        let available = rescale_to_precision(self.available, MAX_DISPLAY_PRECISION);
+        let held = rescale_to_precision(self.held, MAX_DISPLAY_PRECISION);
+        let total = available
+            .checked_add(held)
+            .ok_or(AccountError::TotalOverflow {
+                client: self.client,
+            })?;
+        self.available = available;
+        self.held = held;
+        self.total = total;
+        Ok(())
     }
 }
 
-fn rescale_to_max_precision(mut amount: Amount) -> Amount {
-    if amount.scale() > MAX_DISPLAY_PRECISION {
-        amount.rescale(MAX_DISPLAY_PRECISION);
+fn rescale_to_precision(mut amount: Amount, places: u32) -> Amount {
+    if amount.scale() > places {
+        amount.rescale(places);
     }
     amount
 }
+
+/// Returns true if a non-zero [`Amount`] would be displayed as zero once rescaled to [`MAX_DISPLAY_PRECISION`].
+#[must_use]
+pub fn vanishes_at_display_precision(amount: Amount) -> bool {
+    !amount.is_zero() && rescale_to_precision(amount, MAX_DISPLAY_PRECISION).is_zero()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn try_to_max_display_precision_rescales_consistent_accounts() {
+        let mut account = Account::seeded(1, dec!(1.00001), dec!(2.00002), false);
+
+        account.try_to_max_display_precision().unwrap();
+
+        assert_eq!(
+            account,
+            Account::seeded(1, dec!(1.0000), dec!(2.0000), false)
+        );
+    }
+
+    #[test]
+    fn try_to_max_display_precision_sums_the_rescaled_funds() {
+        // the total rounds to 0.0001 on its own, but each of the funds already does
+        let mut account = Account::seeded(1, dec!(0.00005), dec!(0.00005), false);
+
+        account.try_to_max_display_precision().unwrap();
+
+        assert_eq!(account.available, dec!(0.0001));
+        assert_eq!(account.held, dec!(0.0001));
+        assert_eq!(account.total, dec!(0.0002));
+    }
+
+    #[test]
+    fn accounts_are_consistent_if_the_total_is_the_sum_of_available_and_held() {
+        let mut account = Account::seeded(1, dec!(10), dec!(5), false);
+        assert!(account.is_consistent());
+
+        account.total = dec!(20);
+        assert!(!account.is_consistent());
+    }
+
+    #[test]
+    fn try_to_max_display_precision_flags_inconsistent_totals() {
+        let mut account = Account::seeded(1, dec!(10), dec!(5), false);
+        account.total = dec!(20);
+        let original = account.clone();
+
+        let err = account.try_to_max_display_precision().unwrap_err();
+
+        assert_eq!(
+            err,
+            AccountError::TotalMismatch {
+                client: 1,
+                stored: dec!(20),
+                computed: dec!(15),
+            }
+        );
+        assert_eq!(account, original);
+
+        // the unchecked version keeps the stored total
+        account.to_max_display_precision();
+        assert_eq!(account.total, dec!(20));
+    }
+
+    #[test]
+    fn to_display_precision_rounds_but_does_not_pad() {
+        let mut account = Account::seeded(1, dec!(1.23456789), dec!(1.5), false);
+
+        account.to_display_precision(2);
+
+        assert_eq!(account.available.to_string(), "1.23");
+        assert_eq!(account.held.to_string(), "1.5");
+        assert_eq!(account.total.to_string(), "2.73");
+    }
+
+    #[test]
+    fn to_padded_display_precision_rounds_and_pads() {
+        let mut account = Account::seeded(1, dec!(1.23456789), dec!(1.5), false);
+
+        account.to_padded_display_precision(8);
+
+        assert_eq!(account.available.to_string(), "1.23456789");
+        assert_eq!(account.held.to_string(), "1.50000000");
+        assert_eq!(account.total.to_string(), "2.73456789");
+
+        account.to_padded_display_precision(2);
+
+        assert_eq!(account.available.to_string(), "1.23");
+        assert_eq!(account.held.to_string(), "1.50");
+        assert_eq!(account.total.to_string(), "2.73");
+    }
+
+    #[test]
+    fn try_to_max_display_precision_flags_overflows() {
+        let mut account = Account::seeded(1, Amount::MAX, Amount::ZERO, false);
+        account.held = Amount::MAX;
+
+        let err = account.try_to_max_display_precision().unwrap_err();
+
+        assert_eq!(err, AccountError::TotalOverflow { client: 1 });
+    }
+}
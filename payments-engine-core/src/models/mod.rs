@@ -1,3 +1,4 @@
 pub mod account;
 pub mod common;
+pub mod money;
 pub mod transaction;
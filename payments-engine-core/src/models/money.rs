@@ -0,0 +1,221 @@
+use crate::{account::MAX_DISPLAY_PRECISION, common::Amount};
+use serde::{Deserialize, Serialize};
+use std::{fmt, ops::Neg};
+use thiserror::Error;
+
+/// Error type for [`Money`] operations.
+#[derive(Debug, Clone, Copy, Error, PartialEq, Eq)]
+pub enum MoneyError {
+    #[error("Can't operate on {left} and {right} money")]
+    CurrencyMismatch { left: Currency, right: Currency },
+    #[error("The operation overflows")]
+    Overflow,
+}
+
+/// Currencies supported by [`Money`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    /// United States dollar.
+    #[default]
+    Usd,
+}
+
+impl Currency {
+    /// Returns the ISO 4217 code of the [`Currency`], e.g. `USD`.
+    #[must_use]
+    pub const fn code(self) -> &'static str {
+        match self {
+            Self::Usd => "USD",
+        }
+    }
+
+    /// Returns the symbol of the [`Currency`], e.g. `$`.
+    #[must_use]
+    pub const fn symbol(self) -> &'static str {
+        match self {
+            Self::Usd => "$",
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// An [`Amount`] in a given [`Currency`].
+///
+/// Plain amounts are converted into [`Currency::Usd`] money, and the other way around by dropping the currency.
+/// Note that the engine and the stores keep working with plain amounts for now, as every account is in [`Currency::Usd`].
+/// Their math will move to [`Money`] once several currencies are supported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Money {
+    amount: Amount,
+    currency: Currency,
+}
+
+impl Money {
+    /// Creates a new [`Money`] with the given [`Amount`] and [`Currency`].
+    #[must_use]
+    pub const fn new(amount: Amount, currency: Currency) -> Self {
+        Self { amount, currency }
+    }
+
+    /// Creates a new [`Money`] in [`Currency::Usd`].
+    #[must_use]
+    pub const fn usd(amount: Amount) -> Self {
+        Self::new(amount, Currency::Usd)
+    }
+
+    /// Returns the [`Amount`], with its full precision.
+    #[must_use]
+    pub const fn amount(&self) -> Amount {
+        self.amount
+    }
+
+    /// Returns the [`Currency`].
+    #[must_use]
+    pub const fn currency(&self) -> Currency {
+        self.currency
+    }
+
+    /// Adds both amounts.
+    /// It returns a [`MoneyError::CurrencyMismatch`] if they're in different currencies
+    /// or a [`MoneyError::Overflow`] if it overflows.
+    pub fn checked_add(self, other: Self) -> Result<Self, MoneyError> {
+        self.same_currency(other)?;
+        self.amount
+            .checked_add(other.amount)
+            .map(|amount| Self::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Subtracts both amounts.
+    /// It returns a [`MoneyError::CurrencyMismatch`] if they're in different currencies
+    /// or a [`MoneyError::Overflow`] if it overflows.
+    pub fn checked_sub(self, other: Self) -> Result<Self, MoneyError> {
+        self.same_currency(other)?;
+        self.amount
+            .checked_sub(other.amount)
+            .map(|amount| Self::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    fn same_currency(self, other: Self) -> Result<(), MoneyError> {
+        if self.currency == other.currency {
+            Ok(())
+        } else {
+            tracing::error!(
+                "Can't operate on {} and {} money",
+                self.currency,
+                other.currency
+            );
+            Err(MoneyError::CurrencyMismatch {
+                left: self.currency,
+                right: other.currency,
+            })
+        }
+    }
+}
+
+impl From<Amount> for Money {
+    fn from(amount: Amount) -> Self {
+        Self::usd(amount)
+    }
+}
+
+impl From<Money> for Amount {
+    fn from(money: Money) -> Self {
+        money.amount
+    }
+}
+
+/// Displays the amount with the symbol of the currency, padded to [`MAX_DISPLAY_PRECISION`] decimal places, e.g. `$100.0000`.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let amount = self.amount.round_dp(MAX_DISPLAY_PRECISION);
+        let sign = if amount.is_sign_negative() && !amount.is_zero() {
+            "-"
+        } else {
+            ""
+        };
+        write!(
+            f,
+            "{}{}{:.precision$}",
+            sign,
+            self.currency.symbol(),
+            amount.abs(),
+            precision = MAX_DISPLAY_PRECISION as usize
+        )
+    }
+}
+
+impl Neg for Money {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.amount, self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dec;
+
+    #[test]
+    fn money_is_displayed_with_the_currency_symbol_and_four_decimals() {
+        assert_eq!(Money::usd(dec!(100)).to_string(), "$100.0000");
+        assert_eq!(Money::usd(dec!(1.5)).to_string(), "$1.5000");
+        assert_eq!(Money::usd(dec!(1.23456)).to_string(), "$1.2346");
+        assert_eq!(Money::usd(dec!(-5)).to_string(), "-$5.0000");
+        assert_eq!(Money::usd(dec!(-0.00001)).to_string(), "$0.0000");
+        assert_eq!(Money::default().to_string(), "$0.0000");
+        assert_eq!(Currency::Usd.to_string(), "USD");
+    }
+
+    #[test]
+    fn money_can_be_added_and_subtracted() {
+        let money = Money::usd(dec!(10.5));
+
+        assert_eq!(
+            money.checked_add(Money::usd(dec!(2))),
+            Ok(Money::usd(dec!(12.5)))
+        );
+        assert_eq!(
+            money.checked_sub(Money::usd(dec!(20))),
+            Ok(Money::usd(dec!(-9.5)))
+        );
+        assert_eq!(-money, Money::usd(dec!(-10.5)));
+        assert_eq!(
+            Money::usd(Amount::MAX).checked_add(Money::usd(dec!(1))),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(
+            Money::usd(Amount::MIN).checked_sub(Money::usd(dec!(1))),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn money_converts_from_and_into_amounts() {
+        let money: Money = dec!(1.2345678).into();
+
+        assert_eq!(money, Money::new(dec!(1.2345678), Currency::Usd));
+        assert_eq!(Amount::from(money), dec!(1.2345678));
+        assert_eq!(money.amount(), dec!(1.2345678));
+        assert_eq!(money.currency(), Currency::Usd);
+    }
+
+    #[test]
+    fn money_round_trips_through_serde() {
+        let money = Money::usd(dec!(100.25));
+
+        let json = serde_json::to_string(&money).unwrap();
+
+        assert_eq!(json, r#"{"amount":"100.25","currency":"USD"}"#);
+        assert_eq!(serde_json::from_str::<Money>(&json).unwrap(), money);
+    }
+}
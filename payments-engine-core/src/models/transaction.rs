@@ -11,39 +11,134 @@ pub struct TransactionInfo {
     pub id: TransactionId,
     /// Id of the client.
     pub client_id: ClientId,
+    /// When the transaction happened, in seconds since the Unix epoch, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
 }
 
 impl TransactionInfo {
-    /// Creates a new [`TransactionInfo`] with the given parameters.
+    /// Creates a new [`TransactionInfo`] with the given parameters and no timestamp.
     #[must_use]
     pub const fn new(id: TransactionId, client_id: ClientId) -> Self {
-        Self { id, client_id }
+        Self {
+            id,
+            client_id,
+            timestamp: None,
+        }
+    }
+
+    /// Sets the timestamp, in seconds since the Unix epoch.
+    #[must_use]
+    pub const fn with_timestamp(mut self, timestamp: Option<u64>) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+}
+
+/// The dispute lifecycle of a [`Transaction::Deposit`] or a [`Transaction::Withdrawal`].
+///
+/// The legal transitions are:
+/// - `Undisputed` or `Resolved` → `Open`, when the deposit gets disputed.
+/// - `Open` → `UnderReview`, when the dispute gets reviewed.
+/// - `Open` or `UnderReview` → `Resolved`, when the dispute gets resolved.
+/// - `UnderReview` → `ChargedBack`, when the dispute ends up in a chargeback.
+/// - `Open` → `ChargedBack`, only if reviews are not required.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DisputeState {
+    /// The transaction has never been disputed.
+    #[default]
+    Undisputed,
+    /// The transaction has been disputed and its funds are held.
+    Open,
+    /// The dispute is being reviewed. Its funds are still held.
+    UnderReview,
+    /// The dispute was resolved and the held funds were released.
+    Resolved,
+    /// The dispute ended up in a chargeback.
+    ChargedBack,
+}
+
+impl DisputeState {
+    /// Returns true if the funds of the transaction are held due to a dispute.
+    #[must_use]
+    pub const fn is_under_dispute(self) -> bool {
+        matches!(self, Self::Open | Self::UnderReview)
+    }
+
+    /// Returns true if moving to the `next` state is a legal transition.
+    /// If `review_required` is set, only reviewed disputes can be charged back.
+    #[must_use]
+    pub const fn can_transition_to(self, next: Self, review_required: bool) -> bool {
+        match (self, next) {
+            (Self::Undisputed | Self::Resolved, Self::Open)
+            | (Self::Open, Self::UnderReview)
+            | (Self::Open | Self::UnderReview, Self::Resolved)
+            | (Self::UnderReview, Self::ChargedBack) => true,
+            (Self::Open, Self::ChargedBack) => !review_required,
+            _ => false,
+        }
     }
 }
 
 /// A [`Transaction`] to be processed by the engine.
+///
+/// It's serialized with the default externally tagged representation, which is unambiguous,
+/// e.g. `{"Dispute":{"info":{"id":1,"client_id":2}}}`. Amounts are serialized as strings to keep their precision.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Transaction {
     /// Credit to the client's asset account. It should increase the available and total funds of the client account.
     Deposit {
         info: TransactionInfo,
         amount: Amount,
-        under_dispute: bool,
+        dispute_state: DisputeState,
+        /// Amount held by the current or last dispute, if it was a partial one or it held less than the amount.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        disputed_amount: Option<Amount>,
     },
     /// Debit to the client's asset account. It should decrease the available and total funds of the client account.
     Withdrawal {
         info: TransactionInfo,
         amount: Amount,
+        dispute_state: DisputeState,
+        /// Amount held by the current or last dispute, if it was a partial one.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        disputed_amount: Option<Amount>,
     },
     /// Represents a client's claim that a transaction was erroneus and should be reversed.
-    /// Available funds should decrease, held funds should increase and total funds should remain the same.
-    Dispute { info: TransactionInfo },
+    /// For deposits, available funds should decrease, held funds should increase and total funds should remain the same.
+    /// For withdrawals, the withdrawn funds are held, so held and total funds should increase.
+    /// If an amount is given, only that part of the transaction is disputed. Otherwise, the whole of it.
+    Dispute {
+        info: TransactionInfo,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        amount: Option<Amount>,
+    },
+    /// Represents the review of an open dispute. It has no effect on the funds.
+    Review { info: TransactionInfo },
     /// Represents a resolution to a dispute, releasing the associated held funds.
-    /// Held funds should decrease and available funds should increase. Total funds should remain the same.
+    /// For deposits, held funds should decrease and available funds should increase. Total funds should remain the same.
+    /// For withdrawals, the debit stands, so held and total funds should decrease.
     Resolve { info: TransactionInfo },
     /// Represents the client reversing a transaction after a dispute.
-    /// Held funds and total funds should decrease. The client's account gets immediately frozen.
+    /// For deposits, held funds and total funds should decrease.
+    /// For withdrawals, the debit is reversed, so held funds should decrease and available funds should increase.
+    /// The client's account gets immediately frozen.
     ChargeBack { info: TransactionInfo },
+    /// Represents a manual correction posted by an accountant, along with the reason for it.
+    /// A positive amount credits the available and total funds, a negative one debits them.
+    Adjustment {
+        info: TransactionInfo,
+        amount: Amount,
+        reason: String,
+    },
+    /// Represents the opening of the client's account, which may be locked from the beginning,
+    /// e.g. when migrating accounts pending verification. The account must not exist yet.
+    OpenAccount { info: TransactionInfo, locked: bool },
+    /// Represents the client closing its account.
+    /// Only existing accounts whose total funds are zero can be closed, and they get locked.
+    /// There's no other closed state, so a closed account looks like one frozen by a chargeback,
+    /// and unlocking it reopens it.
+    CloseAccount { info: TransactionInfo },
 }
 
 impl Transaction {
@@ -53,11 +148,12 @@ impl Transaction {
         Self::Deposit {
             info: TransactionInfo::new(id, client_id),
             amount,
-            under_dispute: false,
+            dispute_state: DisputeState::Undisputed,
+            disputed_amount: None,
         }
     }
 
-    /// Creates a new [`Transaction::Deposit`] with the given parameters and sets the under dispute flag.
+    /// Creates a new [`Transaction::Deposit`] with the given parameters and an [`DisputeState::Open`] dispute.
     #[must_use]
     pub const fn deposit_under_dispute(
         id: TransactionId,
@@ -67,29 +163,99 @@ impl Transaction {
         Self::Deposit {
             info: TransactionInfo::new(id, client_id),
             amount,
-            under_dispute: true,
+            dispute_state: DisputeState::Open,
+            disputed_amount: None,
         }
     }
 
-    /// Sets the ``under_dispute`` flag to true or false if the [`Transaction`] is a [`Transaction::Deposit`].
-    pub fn set_under_dispute(&mut self, disputed: bool) {
+    /// Returns the name of the kind of [`Transaction`], e.g. `deposit`, as used in the CSV input.
+    #[must_use]
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::Deposit { .. } => "deposit",
+            Self::Withdrawal { .. } => "withdrawal",
+            Self::Dispute { .. } => "dispute",
+            Self::Review { .. } => "review",
+            Self::Resolve { .. } => "resolve",
+            Self::ChargeBack { .. } => "chargeback",
+            Self::Adjustment { .. } => "adjustment",
+            Self::OpenAccount { .. } => "open",
+            Self::CloseAccount { .. } => "close",
+        }
+    }
+
+    /// Returns the [`DisputeState`] if the [`Transaction`] is a [`Transaction::Deposit`] or a [`Transaction::Withdrawal`].
+    #[must_use]
+    pub const fn dispute_state(&self) -> Option<DisputeState> {
+        match self {
+            Self::Deposit { dispute_state, .. } | Self::Withdrawal { dispute_state, .. } => {
+                Some(*dispute_state)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the [`Transaction`] is a [`Transaction::Deposit`] or a [`Transaction::Withdrawal`]
+    /// whose funds are held due to a dispute.
+    #[must_use]
+    pub const fn is_under_dispute(&self) -> bool {
+        match self.dispute_state() {
+            Some(state) => state.is_under_dispute(),
+            None => false,
+        }
+    }
+
+    /// Returns the [`Amount`] held by the current or last dispute
+    /// if the [`Transaction`] is a [`Transaction::Deposit`] or a [`Transaction::Withdrawal`].
+    /// It's the whole amount unless a partial dispute was opened or the dispute could only hold part of it.
+    #[must_use]
+    pub const fn disputed_amount(&self) -> Option<Amount> {
+        match self {
+            Self::Deposit {
+                amount,
+                disputed_amount,
+                ..
+            }
+            | Self::Withdrawal {
+                amount,
+                disputed_amount,
+                ..
+            } => match disputed_amount {
+                Some(disputed) => Some(*disputed),
+                None => Some(*amount),
+            },
+            _ => None,
+        }
+    }
+
+    /// Sets the [`Amount`] held by a partial dispute, or [`None`] if the whole amount is disputed,
+    /// if the [`Transaction`] is a [`Transaction::Deposit`] or a [`Transaction::Withdrawal`].
+    pub fn set_disputed_amount(&mut self, amount: Option<Amount>) {
         if let Transaction::Deposit {
-            ref mut under_dispute,
+            ref mut disputed_amount,
+            ..
+        }
+        | Transaction::Withdrawal {
+            ref mut disputed_amount,
             ..
         } = self
         {
-            *under_dispute = disputed;
+            *disputed_amount = amount;
         }
     }
 
-    /// Toggles the ``under_dispute`` flag if [`Transaction`] is a [`Transaction::Deposit`].
-    pub fn toggle_under_dispute(&mut self) {
+    /// Sets the [`DisputeState`] if the [`Transaction`] is a [`Transaction::Deposit`] or a [`Transaction::Withdrawal`].
+    pub fn set_dispute_state(&mut self, state: DisputeState) {
         if let Transaction::Deposit {
-            ref mut under_dispute,
+            ref mut dispute_state,
+            ..
+        }
+        | Transaction::Withdrawal {
+            ref mut dispute_state,
             ..
         } = self
         {
-            *under_dispute = !*under_dispute;
+            *dispute_state = state;
         }
     }
 
@@ -99,14 +265,49 @@ impl Transaction {
         Self::Withdrawal {
             info: TransactionInfo::new(id, client_id),
             amount,
+            dispute_state: DisputeState::Undisputed,
+            disputed_amount: None,
+        }
+    }
+
+    /// Creates a new [`Transaction::Withdrawal`] with the given parameters and an [`DisputeState::Open`] dispute.
+    #[must_use]
+    pub const fn withdrawal_under_dispute(
+        id: TransactionId,
+        client_id: ClientId,
+        amount: Amount,
+    ) -> Self {
+        Self::Withdrawal {
+            info: TransactionInfo::new(id, client_id),
+            amount,
+            dispute_state: DisputeState::Open,
+            disputed_amount: None,
         }
     }
 
-    /// Creates a new [`Transaction::Dispute`] with the given parameters.
+    /// Creates a new [`Transaction::Dispute`] of the whole referenced transaction with the given parameters.
     #[must_use]
     pub const fn dispute(id: TransactionId, client_id: ClientId) -> Self {
         Self::Dispute {
             info: TransactionInfo::new(id, client_id),
+            amount: None,
+        }
+    }
+
+    /// Creates a new [`Transaction::Dispute`] of only part of the referenced transaction.
+    #[must_use]
+    pub const fn partial_dispute(id: TransactionId, client_id: ClientId, amount: Amount) -> Self {
+        Self::Dispute {
+            info: TransactionInfo::new(id, client_id),
+            amount: Some(amount),
+        }
+    }
+
+    /// Creates a new [`Transaction::Review`] with the given parameters.
+    #[must_use]
+    pub const fn review(id: TransactionId, client_id: ClientId) -> Self {
+        Self::Review {
+            info: TransactionInfo::new(id, client_id),
         }
     }
 
@@ -126,31 +327,117 @@ impl Transaction {
         }
     }
 
+    /// Creates a new [`Transaction::Adjustment`] with the given parameters.
+    #[must_use]
+    pub fn adjustment(
+        id: TransactionId,
+        client_id: ClientId,
+        amount: Amount,
+        reason: impl Into<String>,
+    ) -> Self {
+        Self::Adjustment {
+            info: TransactionInfo::new(id, client_id),
+            amount,
+            reason: reason.into(),
+        }
+    }
+
+    /// Creates a new [`Transaction::OpenAccount`] with the given parameters.
+    #[must_use]
+    pub const fn open_account(id: TransactionId, client_id: ClientId, locked: bool) -> Self {
+        Self::OpenAccount {
+            info: TransactionInfo::new(id, client_id),
+            locked,
+        }
+    }
+
+    /// Creates a new [`Transaction::CloseAccount`] with the given parameters.
+    #[must_use]
+    pub const fn close_account(id: TransactionId, client_id: ClientId) -> Self {
+        Self::CloseAccount {
+            info: TransactionInfo::new(id, client_id),
+        }
+    }
+
     /// Returns a reference of the [`TransactionInfo`] of this [`Transaction`].
     #[must_use]
     pub const fn info(&self) -> &TransactionInfo {
         match self {
             Self::Deposit { info, .. }
             | Self::Withdrawal { info, .. }
-            | Self::Dispute { info }
+            | Self::Dispute { info, .. }
+            | Self::Review { info }
             | Self::Resolve { info }
-            | Self::ChargeBack { info } => info,
+            | Self::ChargeBack { info }
+            | Self::OpenAccount { info, .. }
+            | Self::CloseAccount { info }
+            | Self::Adjustment { info, .. } => info,
         }
     }
 
+    /// Sets the timestamp of this [`Transaction`], in seconds since the Unix epoch.
+    #[must_use]
+    pub fn with_timestamp(mut self, timestamp: Option<u64>) -> Self {
+        match &mut self {
+            Self::Deposit { info, .. }
+            | Self::Withdrawal { info, .. }
+            | Self::Dispute { info, .. }
+            | Self::Review { info }
+            | Self::Resolve { info }
+            | Self::ChargeBack { info }
+            | Self::OpenAccount { info, .. }
+            | Self::CloseAccount { info }
+            | Self::Adjustment { info, .. } => info.timestamp = timestamp,
+        }
+        self
+    }
+
+    /// Returns true if the [`Transaction`] references another transaction,
+    /// i.e. disputes, reviews, resolves and chargebacks.
+    #[must_use]
+    pub const fn references_transaction(&self) -> bool {
+        matches!(
+            self,
+            Self::Dispute { .. }
+                | Self::Review { .. }
+                | Self::Resolve { .. }
+                | Self::ChargeBack { .. }
+        )
+    }
+
     /// Returns the [`Amount`] associated to this [`Transaction`].
+    /// For [`Transaction::Dispute`], it's the partially disputed amount, if any.
     #[must_use]
     pub const fn amount(&self) -> Option<Amount> {
         match self {
-            Self::Deposit { amount, .. } | Self::Withdrawal { amount, .. } => Some(*amount),
+            Self::Deposit { amount, .. }
+            | Self::Withdrawal { amount, .. }
+            | Self::Adjustment { amount, .. } => Some(*amount),
+            Self::Dispute { amount, .. } => *amount,
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable reference to the [`Amount`] associated to this [`Transaction`], if any.
+    /// It refers to the same [`Amount`] as [`Transaction::amount`].
+    pub fn amount_mut(&mut self) -> Option<&mut Amount> {
+        match self {
+            Self::Deposit { amount, .. }
+            | Self::Withdrawal { amount, .. }
+            | Self::Adjustment { amount, .. } => Some(amount),
+            Self::Dispute { amount, .. } => amount.as_mut(),
             _ => None,
         }
     }
 
     /// Returns true if the amount is negative.
+    /// Note that [`Transaction::Adjustment`] amounts are signed, so they're never considered negative.
     #[must_use]
     pub fn has_negative_amount(&self) -> bool {
-        self.amount().map_or(false, |amount| amount < Amount::ZERO)
+        match self {
+            Self::Adjustment { .. } => false,
+            _ => self.amount().is_some_and(|amount| amount < Amount::ZERO),
+        }
     }
 }
 
@@ -160,23 +447,166 @@ mod tests {
     use rust_decimal_macros::dec;
 
     #[tokio::test]
-    async fn tx_is_mutated_when_setting_under_deposit() {
+    async fn tx_is_mutated_when_setting_dispute_state() {
         let mut deposit = Transaction::deposit(1, 1, dec!(1));
-        deposit.set_under_dispute(true);
+        deposit.set_dispute_state(DisputeState::Open);
         assert_eq!(deposit, Transaction::deposit_under_dispute(1, 1, dec!(1)));
+        assert!(deposit.is_under_dispute());
+
+        deposit.set_dispute_state(DisputeState::Resolved);
+        assert_eq!(deposit.dispute_state(), Some(DisputeState::Resolved));
+        assert!(!deposit.is_under_dispute());
     }
 
     #[tokio::test]
-    async fn tx_is_mutated_when_toggling_under_deposit() {
-        let mut deposit_not_under = Transaction::deposit(1, 1, dec!(1));
-        let mut deposit_under = Transaction::deposit_under_dispute(2, 1, dec!(1));
-        deposit_not_under.toggle_under_dispute();
-        deposit_under.toggle_under_dispute();
+    async fn withdrawals_have_dispute_state() {
+        let mut withdrawal = Transaction::withdrawal(1, 1, dec!(1));
+        withdrawal.set_dispute_state(DisputeState::Open);
         assert_eq!(
-            deposit_not_under,
-            Transaction::deposit_under_dispute(1, 1, dec!(1))
+            withdrawal,
+            Transaction::withdrawal_under_dispute(1, 1, dec!(1))
         );
-        assert_eq!(deposit_under, Transaction::deposit(2, 1, dec!(1)));
+        assert!(withdrawal.is_under_dispute());
+    }
+
+    #[tokio::test]
+    async fn only_deposits_and_withdrawals_have_dispute_state() {
+        let mut adjustment = Transaction::adjustment(1, 1, dec!(1), "fee correction");
+        adjustment.set_dispute_state(DisputeState::Open);
+        assert_eq!(adjustment.dispute_state(), None);
+        assert!(!adjustment.is_under_dispute());
+    }
+
+    #[test]
+    fn legal_dispute_transitions() {
+        use DisputeState::*;
+        assert!(Undisputed.can_transition_to(Open, true));
+        assert!(Resolved.can_transition_to(Open, true));
+        assert!(Open.can_transition_to(UnderReview, true));
+        assert!(Open.can_transition_to(Resolved, true));
+        assert!(UnderReview.can_transition_to(Resolved, true));
+        assert!(UnderReview.can_transition_to(ChargedBack, true));
+        assert!(Open.can_transition_to(ChargedBack, false));
+    }
+
+    #[test]
+    fn illegal_dispute_transitions() {
+        use DisputeState::*;
+        assert!(!Open.can_transition_to(ChargedBack, true));
+        assert!(!Open.can_transition_to(Open, false));
+        assert!(!UnderReview.can_transition_to(Open, false));
+        assert!(!Undisputed.can_transition_to(UnderReview, false));
+        assert!(!Undisputed.can_transition_to(Resolved, false));
+        assert!(!Undisputed.can_transition_to(ChargedBack, false));
+        assert!(!ChargedBack.can_transition_to(Open, false));
+        assert!(!Resolved.can_transition_to(ChargedBack, false));
+    }
+
+    #[test]
+    fn every_variant_round_trips_through_json() {
+        let mut disputed_withdrawal = Transaction::withdrawal(2, 1, dec!(0.0001));
+        disputed_withdrawal.set_dispute_state(DisputeState::ChargedBack);
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(10.12345678)),
+            Transaction::deposit_under_dispute(1, 1, dec!(10)),
+            Transaction::withdrawal(2, 1, dec!(5)),
+            disputed_withdrawal,
+            Transaction::dispute(3, 1),
+            Transaction::review(4, 1),
+            Transaction::resolve(5, 1),
+            Transaction::chargeback(6, 1),
+            Transaction::adjustment(7, 1, dec!(-1.5), "fee correction"),
+            Transaction::close_account(8, 1),
+            Transaction::open_account(9, 2, true),
+            Transaction::open_account(10, 3, false),
+        ];
+
+        for transaction in transactions {
+            let json = serde_json::to_string(&transaction).unwrap();
+            let deserialized: Transaction = serde_json::from_str(&json).unwrap();
+            assert_eq!(deserialized, transaction, "{}", json);
+        }
+    }
+
+    #[test]
+    fn json_representation_is_externally_tagged() {
+        let deposit = Transaction::deposit_under_dispute(1, 2, dec!(1.50));
+        let dispute = Transaction::dispute(1, 2);
+
+        assert_eq!(
+            serde_json::to_string(&deposit).unwrap(),
+            r#"{"Deposit":{"info":{"id":1,"client_id":2},"amount":"1.50","dispute_state":"Open"}}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&dispute).unwrap(),
+            r#"{"Dispute":{"info":{"id":1,"client_id":2}}}"#
+        );
+    }
+
+    #[test]
+    fn timestamps_round_trip_through_json() {
+        let deposit = Transaction::deposit(1, 2, dec!(1.50)).with_timestamp(Some(1_700_000_000));
+        let dispute = Transaction::dispute(1, 2).with_timestamp(Some(1_700_000_001));
+
+        for transaction in [deposit, dispute] {
+            let json = serde_json::to_string(&transaction).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Transaction>(&json).unwrap(),
+                transaction
+            );
+        }
+        assert_eq!(
+            serde_json::to_string(&Transaction::dispute(1, 2).with_timestamp(Some(10))).unwrap(),
+            r#"{"Dispute":{"info":{"id":1,"client_id":2,"timestamp":10}}}"#
+        );
+    }
+
+    #[test]
+    fn missing_timestamps_default_to_none() {
+        let transaction =
+            serde_json::from_str::<Transaction>(r#"{"Dispute":{"info":{"id":1,"client_id":2}}}"#)
+                .unwrap();
+
+        assert_eq!(transaction.info().timestamp, None);
+        assert_eq!(transaction, Transaction::dispute(1, 2));
+    }
+
+    #[test]
+    fn disputed_amount_is_the_whole_amount_unless_partially_disputed() {
+        let mut deposit = Transaction::deposit(1, 1, dec!(100));
+        assert_eq!(deposit.disputed_amount(), Some(dec!(100)));
+
+        deposit.set_disputed_amount(Some(dec!(30)));
+        assert_eq!(deposit.disputed_amount(), Some(dec!(30)));
+        assert_eq!(deposit.amount(), Some(dec!(100)));
+
+        let mut dispute = Transaction::dispute(1, 1);
+        dispute.set_disputed_amount(Some(dec!(30)));
+        assert_eq!(dispute.disputed_amount(), None);
+    }
+
+    #[test]
+    fn partial_disputes_round_trip_through_json() {
+        let dispute = Transaction::partial_dispute(1, 2, dec!(30));
+        let mut deposit = Transaction::deposit_under_dispute(1, 2, dec!(100));
+        deposit.set_disputed_amount(Some(dec!(30)));
+
+        assert_eq!(
+            serde_json::to_string(&dispute).unwrap(),
+            r#"{"Dispute":{"info":{"id":1,"client_id":2},"amount":"30"}}"#
+        );
+        for transaction in [dispute, deposit] {
+            let json = serde_json::to_string(&transaction).unwrap();
+            assert_eq!(
+                serde_json::from_str::<Transaction>(&json).unwrap(),
+                transaction
+            );
+        }
+        assert_eq!(
+            Transaction::partial_dispute(1, 2, dec!(30)).amount(),
+            Some(dec!(30))
+        );
+        assert_eq!(Transaction::dispute(1, 2).amount(), None);
     }
 
     #[tokio::test]
@@ -191,4 +621,11 @@ mod tests {
         assert!(!deposit_zero.has_negative_amount());
         assert!(!dispute.has_negative_amount());
     }
+
+    #[tokio::test]
+    async fn negative_adjustments_are_not_negative_amounts() {
+        let adjustment = Transaction::adjustment(1, 1, dec!(-1), "fee correction");
+        assert_eq!(adjustment.amount(), Some(dec!(-1)));
+        assert!(!adjustment.has_negative_amount());
+    }
 }
@@ -0,0 +1,8 @@
+use crate::common::ClientId;
+
+/// The [`SanctionsChecker`] tells whether a client is sanctioned,
+/// in which case the movement of money from or to its account must be blocked.
+pub trait SanctionsChecker: Send + Sync {
+    /// Returns true if the client is sanctioned.
+    fn is_sanctioned(&self, client: ClientId) -> bool;
+}
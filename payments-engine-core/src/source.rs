@@ -0,0 +1,298 @@
+use crate::transaction::{Transaction, TransactionId};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use thiserror::Error;
+
+/// Error type for [`TransactionSource`] implementations.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum SourceError {
+    #[error("Error while reading the transaction: {0}")]
+    ReadError(String),
+    #[error(
+        "Transaction id {current} is lower than the previous one {previous} of the same source"
+    )]
+    OutOfOrder {
+        previous: TransactionId,
+        current: TransactionId,
+    },
+    #[error("Unknwon error: {0}")]
+    UnknownError(String),
+}
+
+/// The [`TransactionSource`] trait is an abstraction over the input of the transactions,
+/// so they can come from a CSV file, a queue, a database...
+#[async_trait]
+pub trait TransactionSource: Send {
+    /// Returns the next [`Transaction`] or [`None`] if the source is exhausted.
+    /// Errors are returned per transaction, so the source can keep being read after an error.
+    async fn next(&mut self) -> Option<Result<Transaction, SourceError>>;
+}
+
+/// [`TransactionSource`] reading from several sources sequentially,
+/// so they behave as a single one, e.g. when the transactions are split across files.
+#[derive(Default)]
+pub struct ChainedSource<'a> {
+    sources: VecDeque<Box<dyn TransactionSource + 'a>>,
+}
+
+impl<'a> ChainedSource<'a> {
+    /// Creates a new [`ChainedSource`] reading the given sources in order.
+    #[must_use]
+    pub fn new(sources: impl IntoIterator<Item = Box<dyn TransactionSource + 'a>>) -> Self {
+        Self {
+            sources: sources.into_iter().collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionSource for ChainedSource<'_> {
+    async fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+        while let Some(source) = self.sources.front_mut() {
+            match source.next().await {
+                Some(result) => return Some(result),
+                None => {
+                    self.sources.pop_front();
+                }
+            }
+        }
+        None
+    }
+}
+
+/// [`TransactionSource`] merging several sources sorted by transaction id into a single sorted one,
+/// e.g. when the transactions of the same period come from different feeds.
+///
+/// The merge is lazy, so only the next [`Transaction`] of every source is buffered.
+/// Errors are yielded as soon as they're read, as they have no id.
+/// Note that disputes, reviews, resolves and chargebacks carry the id of the transaction they reference,
+/// so transactions creating an id are yielded before the ones referencing it, whatever their source.
+/// Otherwise, transactions with the same id are yielded in the order of the sources.
+///
+/// A source creating a transaction with a lower id than a previous one is not sorted,
+/// so the transaction is replaced by a [`SourceError::OutOfOrder`] instead of being merged out of order.
+#[derive(Default)]
+pub struct MergedSource<'a> {
+    /// The sources along with their next [`Transaction`], if it was already read,
+    /// and the id of the last transaction they created.
+    sources: Vec<(Box<dyn TransactionSource + 'a>, Next, Option<TransactionId>)>,
+}
+
+/// Next result of a source, as returned by [`TransactionSource::next`].
+type Next = Option<Result<Transaction, SourceError>>;
+
+impl<'a> MergedSource<'a> {
+    /// Creates a new [`MergedSource`] merging the given sources, which must be sorted by transaction id.
+    #[must_use]
+    pub fn new(sources: impl IntoIterator<Item = Box<dyn TransactionSource + 'a>>) -> Self {
+        Self {
+            sources: sources
+                .into_iter()
+                .map(|source| (source, None, None))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionSource for MergedSource<'_> {
+    async fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+        // reading the next transaction of the sources consumed so far, dropping the exhausted ones
+        let mut index = 0;
+        while index < self.sources.len() {
+            let (source, next, last_id) = &mut self.sources[index];
+            if next.is_none() {
+                *next = source.next().await;
+                match next {
+                    None => {
+                        self.sources.remove(index);
+                        continue;
+                    }
+                    Some(Ok(transaction)) if !transaction.references_transaction() => {
+                        let current = transaction.info().id;
+                        match *last_id {
+                            Some(previous) if current < previous => {
+                                tracing::error!(
+                                    "Transaction id {} is lower than the previous one {} of its source",
+                                    current,
+                                    previous
+                                );
+                                *next = Some(Err(SourceError::OutOfOrder { previous, current }));
+                            }
+                            _ => *last_id = Some(current),
+                        }
+                    }
+                    Some(_) => {}
+                }
+            }
+            index += 1;
+        }
+
+        // creating transactions go first, so they're yielded before the ones referencing them
+        let key = |next: &Next| match next {
+            Some(Ok(transaction)) => (transaction.info().id, transaction.references_transaction()),
+            _ => (TransactionId::MAX, true),
+        };
+        let index = self
+            .sources
+            .iter()
+            .position(|(_, next, _)| matches!(next, Some(Err(_))))
+            .or_else(|| {
+                self.sources
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, (_, next, _))| key(next))
+                    .map(|(index, _)| index)
+            })?;
+        self.sources[index].1.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dec;
+
+    /// In-memory [`TransactionSource`].
+    struct VecSource(VecDeque<Result<Transaction, SourceError>>);
+
+    #[async_trait]
+    impl TransactionSource for VecSource {
+        async fn next(&mut self) -> Option<Result<Transaction, SourceError>> {
+            self.0.pop_front()
+        }
+    }
+
+    fn boxed(transactions: Vec<Result<Transaction, SourceError>>) -> Box<dyn TransactionSource> {
+        Box::new(VecSource(transactions.into()))
+    }
+
+    #[tokio::test]
+    async fn chained_sources_are_read_in_order() {
+        let mut source = ChainedSource::new([
+            boxed(vec![Ok(Transaction::deposit(1, 1, dec!(1)))]),
+            boxed(vec![]),
+            boxed(vec![
+                Err(SourceError::ReadError("broken".to_string())),
+                Ok(Transaction::deposit(2, 1, dec!(2))),
+            ]),
+        ]);
+
+        assert_eq!(
+            source.next().await,
+            Some(Ok(Transaction::deposit(1, 1, dec!(1))))
+        );
+        assert_eq!(
+            source.next().await,
+            Some(Err(SourceError::ReadError("broken".to_string())))
+        );
+        assert_eq!(
+            source.next().await,
+            Some(Ok(Transaction::deposit(2, 1, dec!(2))))
+        );
+        assert_eq!(source.next().await, None);
+        assert_eq!(source.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn merged_sources_are_globally_sorted() {
+        let mut source = MergedSource::new([
+            boxed(vec![
+                Ok(Transaction::deposit(1, 1, dec!(1))),
+                Ok(Transaction::deposit(4, 1, dec!(4))),
+                Ok(Transaction::dispute(4, 1)),
+                Ok(Transaction::deposit(5, 1, dec!(5))),
+            ]),
+            boxed(vec![]),
+            boxed(vec![
+                Ok(Transaction::deposit(2, 2, dec!(2))),
+                Ok(Transaction::deposit(3, 2, dec!(3))),
+                Err(SourceError::ReadError("broken".to_string())),
+                Ok(Transaction::deposit(6, 2, dec!(6))),
+            ]),
+        ]);
+
+        let mut merged = Vec::new();
+        while let Some(result) = source.next().await {
+            merged.push(result);
+        }
+
+        assert_eq!(
+            merged,
+            vec![
+                Ok(Transaction::deposit(1, 1, dec!(1))),
+                Ok(Transaction::deposit(2, 2, dec!(2))),
+                Ok(Transaction::deposit(3, 2, dec!(3))),
+                Err(SourceError::ReadError("broken".to_string())),
+                Ok(Transaction::deposit(4, 1, dec!(4))),
+                Ok(Transaction::dispute(4, 1)),
+                Ok(Transaction::deposit(5, 1, dec!(5))),
+                Ok(Transaction::deposit(6, 2, dec!(6))),
+            ]
+        );
+        assert_eq!(source.next().await, None);
+    }
+
+    #[tokio::test]
+    async fn merged_sources_yield_transactions_before_their_disputes() {
+        let mut source = MergedSource::new([
+            boxed(vec![
+                Ok(Transaction::dispute(2, 2)),
+                Ok(Transaction::deposit(3, 1, dec!(3))),
+            ]),
+            boxed(vec![
+                Ok(Transaction::deposit(1, 2, dec!(1))),
+                Ok(Transaction::deposit(2, 2, dec!(2))),
+            ]),
+        ]);
+
+        let mut merged = Vec::new();
+        while let Some(result) = source.next().await {
+            merged.push(result);
+        }
+
+        assert_eq!(
+            merged,
+            vec![
+                Ok(Transaction::deposit(1, 2, dec!(1))),
+                Ok(Transaction::deposit(2, 2, dec!(2))),
+                Ok(Transaction::dispute(2, 2)),
+                Ok(Transaction::deposit(3, 1, dec!(3))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn merged_sources_report_ids_going_backwards() {
+        let mut source = MergedSource::new([
+            boxed(vec![
+                Ok(Transaction::deposit(1, 1, dec!(1))),
+                Ok(Transaction::deposit(4, 1, dec!(4))),
+                Ok(Transaction::dispute(1, 1)),
+                Ok(Transaction::deposit(2, 1, dec!(2))),
+                Ok(Transaction::deposit(5, 1, dec!(5))),
+            ]),
+            boxed(vec![Ok(Transaction::deposit(3, 2, dec!(3)))]),
+        ]);
+
+        let mut merged = Vec::new();
+        while let Some(result) = source.next().await {
+            merged.push(result);
+        }
+
+        assert_eq!(
+            merged,
+            vec![
+                Ok(Transaction::deposit(1, 1, dec!(1))),
+                Ok(Transaction::deposit(3, 2, dec!(3))),
+                Ok(Transaction::deposit(4, 1, dec!(4))),
+                Ok(Transaction::dispute(1, 1)),
+                Err(SourceError::OutOfOrder {
+                    previous: 4,
+                    current: 2
+                }),
+                Ok(Transaction::deposit(5, 1, dec!(5))),
+            ]
+        );
+    }
+}
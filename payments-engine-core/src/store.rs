@@ -1,14 +1,15 @@
 use crate::{
     account::Account,
-    common::ClientId,
-    transaction::{Transaction, TransactionId},
+    common::{Amount, ClientId},
+    transaction::{DisputeState, Transaction, TransactionId},
 };
 use async_trait::async_trait;
+use futures::StreamExt;
 use std::pin::Pin;
 use thiserror::Error;
 
 /// Error type for [`Store`] implementations.
-#[derive(Error, Debug, PartialEq, Eq)]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
 pub enum StoreError {
     #[error("Transaction with id {id} not found")]
     NotFound { id: TransactionId },
@@ -18,10 +19,34 @@ pub enum StoreError {
     AccessError(String),
     #[error("Unknwon error: {0}")]
     UnknownError(String),
+    #[error("Operation {0} is not supported by the store")]
+    Unsupported(&'static str),
+    #[error("Error while migrating the store: {0}")]
+    MigrationError(String),
 }
 
 pub type StoreResult<T> = Result<T, StoreError>;
 
+/// Outcome of [`Store::upsert_account`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    /// The [`Account`] didn't exist and it was created.
+    Created,
+    /// The [`Account`] already existed and it was updated.
+    Updated,
+}
+
+/// Reports an [`Account`] whose held funds don't match the sum of its under dispute deposits and withdrawals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DisputeMismatch {
+    /// id of the client.
+    pub client: ClientId,
+    /// The held funds of the account.
+    pub held: Amount,
+    /// The sum of the amounts of the under dispute deposits and withdrawals of the client.
+    pub disputed: Amount,
+}
+
 /// The [`Store`] traits is an abstraction over the storage of the transactions and accounts.
 #[async_trait]
 pub trait Store: Send + Sync {
@@ -33,23 +58,151 @@ pub trait Store: Send + Sync {
     async fn create_transaction(&self, transaction: Transaction) -> StoreResult<Transaction>;
     /// Deletes a [`Transaction`].
     async fn delete_transaction(&self, id: TransactionId) -> StoreResult<()>;
-    /// Sets a [`Transaction`] under dispute.
-    async fn set_transaction_under_dispute(
+    /// Drops the stored [`Transaction`], if any, but keeps its id reserved.
+    /// The id can't be reused by another [`Transaction`] and can't be referenced by disputes either.
+    async fn reserve_transaction_id(&self, id: TransactionId) -> StoreResult<()>;
+    /// Sets the [`DisputeState`] of a [`Transaction`].
+    async fn set_dispute_state(&self, id: TransactionId, state: DisputeState) -> StoreResult<()>;
+    /// Sets the [`Amount`] held by a partial dispute of a [`Transaction`], or [`None`] if the whole amount is disputed.
+    async fn set_disputed_amount(
         &self,
         id: TransactionId,
-        under_dispute: bool,
+        amount: Option<Amount>,
     ) -> StoreResult<()>;
-    /// Toggles the under dispute flag
-    async fn toggle_under_dispute(&self, id: TransactionId) -> StoreResult<()>;
+    /// Sets the [`Amount`] of a stored [`Transaction`] in place, e.g. when a withdrawal was only partially filled.
+    /// Transactions which are not stored are left as they are.
+    async fn set_transaction_amount(&self, id: TransactionId, amount: Amount) -> StoreResult<()>;
+    /// Returns how many times a [`Transaction`] has been disputed, i.e. moved to [`DisputeState::Open`].
+    /// If the [`Transaction`] doesn't exist, it returns an [`StoreError::NotFound`].
+    async fn dispute_count(&self, id: TransactionId) -> StoreResult<u32>;
+    /// Keeps a [`Transaction`] that was rejected but should be retained for later reconciliation.
+    /// Pending transactions don't affect the accounts and can't be referenced by disputes.
+    async fn add_pending_transaction(&self, transaction: Transaction) -> StoreResult<()>;
+    /// Returns the pending [`Transaction`], sorted by id.
+    async fn get_pending_transactions(&self) -> StoreResult<Vec<Transaction>>;
+    /// Returns the stored [`Transaction`] of the client, sorted by id.
+    /// Pending transactions are not included.
+    ///
+    /// The default implementation returns an [`StoreError::Unsupported`],
+    /// as stores can't be scanned through the rest of the trait.
+    async fn get_transactions_for_client(&self, client: ClientId) -> StoreResult<Vec<Transaction>> {
+        tracing::error!("Store can't get the transactions of client {}", client);
+        Err(StoreError::Unsupported("get_transactions_for_client"))
+    }
+    /// Gets the current state of the [`Account`], or [`None`] if it doesn't exist in the [`Store`].
+    async fn get_account(&self, id: ClientId) -> StoreResult<Option<Account>>;
     /// Gets the current state of the [`Account`].
     /// If the [`Account`] does not exist, it will return an empty [`Account`].
     /// Note that the account is not created in the [`Store`] yet.
-    async fn get_account(&self, id: ClientId) -> StoreResult<Account>;
+    async fn get_or_default_account(&self, id: ClientId) -> StoreResult<Account> {
+        Ok(self
+            .get_account(id)
+            .await?
+            .unwrap_or_else(|| Account::new(id)))
+    }
+    /// Gets the current state of the [`Account`] and the [`Transaction`] with the given id in one round-trip.
+    /// If the [`Transaction`] doesn't exist, [`None`] is returned alongside the [`Account`].
+    /// The [`Account`] follows the same semantics as in [`Store::get_or_default_account`].
+    ///
+    /// The default implementation just calls [`Store::get_or_default_account`] and [`Store::get_transaction`],
+    /// but stores should override it if they can fetch both at once.
+    async fn get_account_and_transaction(
+        &self,
+        client: ClientId,
+        id: TransactionId,
+    ) -> StoreResult<(Account, Option<Transaction>)> {
+        let account = self.get_or_default_account(client).await?;
+        match self.get_transaction(id).await {
+            Ok(transaction) => Ok((account, Some(transaction))),
+            Err(StoreError::NotFound { .. }) => Ok((account, None)),
+            Err(e) => Err(e),
+        }
+    }
     /// Updates the state of the [`Account`].
     /// If the [`Account`] does not exist, it will create the [`Account`].
-    async fn upsert_account(&self, account: &Account) -> StoreResult<()>;
+    /// Returns whether the [`Account`] was created or updated.
+    async fn upsert_account(&self, account: &Account) -> StoreResult<UpsertOutcome>;
+    /// Updates the state of all the given accounts, creating the ones that do not exist.
+    ///
+    /// It's the same bulk operation as [`Store::load_accounts`], which stores override instead,
+    /// so it just calls it with a copy of the accounts.
+    async fn upsert_accounts(&self, accounts: &[Account]) -> StoreResult<()> {
+        self.load_accounts(accounts.to_vec()).await
+    }
+    /// Seeds the [`Store`] with the given accounts, e.g. the ending balances of a previous run.
+    /// Existing accounts are replaced.
+    ///
+    /// The default implementation just calls [`Store::upsert_account`] for every [`Account`],
+    /// but stores should override it if they can load them all at once.
+    async fn load_accounts(&self, accounts: Vec<Account>) -> StoreResult<()> {
+        for account in &accounts {
+            self.upsert_account(account).await?;
+        }
+        Ok(())
+    }
     /// Returns the current balance of all the clients [`Account`].
     async fn get_all_accounts(
         &self,
     ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>>;
+    /// Returns up to `limit` [`Account`] sorted by client, starting right after the `after` client.
+    /// Use the last client of a page as the cursor of the next one, and [`None`] to get the first page.
+    ///
+    /// The default implementation collects and sorts all the accounts from [`Store::get_all_accounts`],
+    /// but stores should override it if they can fetch a single page.
+    async fn get_accounts_page(
+        &self,
+        after: Option<ClientId>,
+        limit: usize,
+    ) -> StoreResult<Vec<Account>> {
+        let mut accounts = self
+            .get_all_accounts()
+            .await?
+            .filter(|account| {
+                futures::future::ready(after.is_none_or(|after| account.client > after))
+            })
+            .collect::<Vec<_>>()
+            .await;
+        accounts.sort_by_key(|account| account.client);
+        accounts.truncate(limit);
+        Ok(accounts)
+    }
+    /// Returns up to `limit` [`Account`] sorted by client, skipping the first `offset` ones.
+    /// Pages are stable as long as no accounts are created in between,
+    /// otherwise [`Store::get_accounts_page`] should be preferred.
+    ///
+    /// The default implementation collects and sorts all the accounts from [`Store::get_all_accounts`],
+    /// but stores should override it if they can fetch a single page.
+    async fn get_accounts_at_offset(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> StoreResult<Vec<Account>> {
+        let mut accounts = self.get_all_accounts().await?.collect::<Vec<_>>().await;
+        accounts.sort_by_key(|account| account.client);
+        Ok(accounts.into_iter().skip(offset).take(limit).collect())
+    }
+    /// Returns the ids of all the clients with an [`Account`] in the [`Store`], sorted.
+    /// Along with [`Store::get_account`], it allows fetching the accounts one by one.
+    async fn get_client_ids(&self) -> StoreResult<Vec<ClientId>>;
+    /// Returns the deposits and withdrawals which are currently under dispute.
+    async fn get_disputed_transactions(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Transaction> + Send>>>;
+    /// Returns how many deposits and withdrawals of the client are currently under dispute.
+    ///
+    /// The default implementation counts them from [`Store::get_disputed_transactions`],
+    /// but stores should override it if they can count them directly.
+    async fn open_disputes_count(&self, client: ClientId) -> StoreResult<u32> {
+        let count = self
+            .get_disputed_transactions()
+            .await?
+            .filter(|transaction| futures::future::ready(transaction.info().client_id == client))
+            .count()
+            .await;
+        Ok(u32::try_from(count).unwrap_or(u32::MAX))
+    }
+    /// Checks that the held funds of every [`Account`] equal the sum of its under dispute deposits and withdrawals.
+    /// This is useful at startup, to confirm that the persisted disputes are consistent.
+    /// Returns the list of mismatches, which will be empty if everything is consistent.
+    async fn verify_dispute_consistency(&self) -> StoreResult<Vec<DisputeMismatch>>;
 }
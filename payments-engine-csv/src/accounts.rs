@@ -0,0 +1,105 @@
+use super::{error::ReaderError, reader::AsyncReader};
+use payments_engine_core::account::Account;
+use tokio_stream::StreamExt;
+use tracing::instrument;
+
+/// Reads asynchronously the [`Account`] balances written by [`write_csv_async`](crate::write_csv_async),
+/// i.e. a CSV with the `client,available,held,total,locked` columns.
+///
+/// This closes the loop between runs: the ending balances of a run can be loaded
+/// as the opening balances of the next one with [`Store::load_accounts`](payments_engine_core::store::Store::load_accounts).
+#[instrument(skip(reader))]
+pub async fn read_accounts_csv_async(
+    reader: &mut AsyncReader,
+) -> impl futures::Stream<Item = Result<Account, ReaderError>> + '_ {
+    csv_async::AsyncReaderBuilder::new()
+        .trim(csv_async::Trim::All)
+        .create_deserializer(reader)
+        .into_deserialize::<Account>()
+        .map(|account| {
+            account.map_err(|e| {
+                let error = ReaderError::from(e);
+                tracing::error!(%error, "Error deserializing account");
+                error
+            })
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::write_csv_async;
+    use futures::TryStreamExt;
+    use payments_engine_core::{dec, store::Store};
+    use payments_engine_store_memory::MemoryStore;
+    use tokio::io::BufWriter;
+
+    #[tokio::test]
+    async fn reads_the_accounts_csv() {
+        let mut input =
+            "client,available,held,total,locked\n1,1.5,0.25,1.75,false\n2, 0 ,0,0,true\n"
+                .as_bytes();
+
+        let accounts = read_accounts_csv_async(&mut input)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        assert_eq!(
+            accounts,
+            vec![
+                Account::seeded(1, dec!(1.5), dec!(0.25), false),
+                Account::seeded(2, dec!(0), dec!(0), true),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_malformed_accounts() {
+        let mut input =
+            "client,available,held,total,locked\n1,abc,0,0,false\n2,1,0,1,false\n".as_bytes();
+
+        let accounts = read_accounts_csv_async(&mut input)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert!(matches!(
+            accounts[0],
+            Err(ReaderError::Deserialization { line: 2, .. })
+        ));
+        assert_eq!(accounts[1], Ok(Account::seeded(2, dec!(1), dec!(0), false)));
+    }
+
+    #[tokio::test]
+    async fn written_reports_can_seed_a_new_store() {
+        let report = vec![
+            Account::seeded(1, dec!(10.1234), dec!(5), false),
+            Account::seeded(2, dec!(-3), dec!(0), true),
+            Account::seeded(3, dec!(0), dec!(0.0001), false),
+        ];
+        let mut output = BufWriter::new(Vec::<u8>::new());
+        write_csv_async(&mut output, futures::stream::iter(report.clone()))
+            .await
+            .unwrap();
+        let mut input = std::io::Cursor::new(output.into_inner());
+
+        let accounts = read_accounts_csv_async(&mut input)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let store = MemoryStore::new();
+        store.load_accounts(accounts).await.unwrap();
+
+        let mut loaded = store
+            .get_all_accounts()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+        loaded.sort_by_key(|account| account.client);
+        assert_eq!(loaded, report);
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Row emitted in place of a line exceeding the maximum record length.
+pub const RECORD_TOO_LARGE_MARKER: &str = "\u{0}record-too-large\u{0}";
+
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// [`AsyncRead`] adapter that never buffers more than `max` bytes per line.
+///
+/// Lines are held back until they're complete. If a line exceeds `max` bytes,
+/// its content is discarded up to the next newline and replaced by [`RECORD_TOO_LARGE_MARKER`],
+/// so the CSV reader can report it while keeping the line numbers untouched.
+pub struct BoundedLines<R> {
+    inner: R,
+    max: usize,
+    line: Vec<u8>,
+    pending: VecDeque<u8>,
+    skipping: bool,
+    eof: bool,
+}
+
+impl<R: AsyncRead + Unpin> BoundedLines<R> {
+    pub fn new(inner: R, max: usize) -> Self {
+        Self {
+            inner,
+            max,
+            line: Vec::new(),
+            pending: VecDeque::new(),
+            skipping: false,
+            eof: false,
+        }
+    }
+
+    fn push_marker(&mut self) {
+        self.pending.extend(RECORD_TOO_LARGE_MARKER.as_bytes());
+        self.pending.push_back(b'\n');
+    }
+
+    fn consume(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            if self.skipping {
+                if byte == b'\n' {
+                    self.skipping = false;
+                    self.push_marker();
+                }
+                continue;
+            }
+            self.line.push(byte);
+            if byte == b'\n' {
+                self.pending.extend(self.line.drain(..));
+            } else if self.line.len() > self.max {
+                self.line.clear();
+                self.skipping = true;
+            }
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.skipping {
+            self.skipping = false;
+            self.push_marker();
+        } else {
+            self.pending.extend(self.line.drain(..));
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BoundedLines<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.pending.is_empty() {
+                let len = buf.remaining().min(this.pending.len());
+                let bytes = this.pending.drain(..len).collect::<Vec<_>>();
+                buf.put_slice(&bytes);
+                return Poll::Ready(Ok(()));
+            }
+            if this.eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = [0_u8; CHUNK_SIZE];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Ready(Ok(())) => {
+                    let filled = chunk_buf.filled();
+                    if filled.is_empty() {
+                        this.eof = true;
+                        this.finish();
+                    } else {
+                        this.consume(filled);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    async fn read_all(input: &str, max: usize) -> String {
+        let mut reader = BoundedLines::new(input.as_bytes(), max);
+        let mut output = String::new();
+        reader.read_to_string(&mut output).await.unwrap();
+        output
+    }
+
+    #[tokio::test]
+    async fn lines_within_the_limit_are_untouched() {
+        let input = "type,client,tx,amount\ndeposit,1,1,100\ndeposit,1,2,100";
+        assert_eq!(read_all(input, 21).await, input);
+    }
+
+    #[tokio::test]
+    async fn lines_over_the_limit_are_replaced_by_the_marker() {
+        let input = "short\nthis line is too long\nshort\nthis one too";
+        let expected = format!("short\n{m}\nshort\n{m}\n", m = RECORD_TOO_LARGE_MARKER);
+        assert_eq!(read_all(input, 10).await, expected);
+    }
+}
@@ -0,0 +1,55 @@
+use payments_engine_core::transaction::TransactionId;
+use thiserror::Error;
+
+/// Error type for the CSV reader.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ReaderError {
+    #[error("Record at line {line} could not be deserialized: {message}. Record: {record}")]
+    Deserialization {
+        line: u64,
+        record: String,
+        message: String,
+    },
+    #[error("Unknown transaction kind {kind} at line {line}")]
+    UnknownTransactionKind { kind: String, line: u64 },
+    #[error("Invalid value {value} for the numeric field {field} at line {line}")]
+    InvalidNumericField {
+        field: String,
+        value: String,
+        line: u64,
+    },
+    #[error("IO error while reading the CSV: {0}")]
+    Io(String),
+    #[error("Record at line {line} exceeds the maximum record length")]
+    RecordTooLarge { line: u64 },
+    #[error("Header doesn't match the expected columns. Missing: {missing:?}. Extra: {extra:?}")]
+    SchemaMismatch {
+        missing: Vec<String>,
+        extra: Vec<String>,
+    },
+    #[error("Transaction with id {id} has no amount")]
+    MissingAmount { id: TransactionId },
+    #[error("Transaction with id {id} has negative amount")]
+    NegativeAmount { id: TransactionId },
+    #[error("Amount at line {line} exceeds the maximum magnitude")]
+    AmountOutOfRange { line: u64 },
+    #[error("Transaction id {current} at line {line} is lower than the previous one {previous}")]
+    NonMonotonicTxId {
+        previous: TransactionId,
+        current: TransactionId,
+        line: u64,
+    },
+}
+
+impl From<csv_async::Error> for ReaderError {
+    fn from(e: csv_async::Error) -> Self {
+        match e.kind() {
+            csv_async::ErrorKind::Io(e) => Self::Io(e.to_string()),
+            _ => Self::Deserialization {
+                line: e.position().map_or(0, csv_async::Position::line),
+                record: String::new(),
+                message: e.to_string(),
+            },
+        }
+    }
+}
@@ -1,6 +1,13 @@
 //! Library to asynchronously read CSV transactions from a stream and write the final account balances.
 //!
 //! It exposes a couple of functions for read [`read_csv_async`] and write [`write_csv_async`].
+//! Balances can also be written as newline-delimited JSON with [`write_json_async`].
+//! [`write_csv_async_with_hash`] returns a deterministic hash of the written CSV to verify it between systems.
+//! The written balances can be read back with [`read_accounts_csv_async`], e.g. to seed the store of the next run.
+//! [`read_csv_async_with_progress`] pairs every transaction with the number of rows read so far, to report the progress of big files.
+//! Transactions published as newline-delimited JSON, with the same field names, can be read with [`read_json_async`], or with [`read_json_async_with_options`] to validate their amounts with the same [`ReaderOptions`].
+//! Gzipped input can be read by decorating the reader with [`decompress_gzip_if_needed`].
+//! [`CsvSource`] can be used as a [`TransactionSource`](payments_engine_core::source::TransactionSource).
 //!
 //! The transactions must be in CSV format and must abide to the following structure:
 //!
@@ -12,11 +19,27 @@
 //! ```
 //!
 //! Note that the reader is a little bit flexible with the columns and that `amount` is totally optional for some of the transaction types.
+//! The columns are read by name, so they can be in any order. Headerless input is read with the [`ColumnPositions`] of the [`ReaderOptions`].
+//! A leading UTF-8 BOM, e.g. from files exported by Excel, is skipped.
 #![allow(clippy::module_name_repetitions)]
 
+mod accounts;
+mod bounded;
+mod error;
 mod reader;
+mod source;
 mod transaction;
 mod writer;
 
-pub use reader::{read_csv_async, AsyncReader};
-pub use writer::{write_csv_async, AsyncWriter};
+pub use accounts::read_accounts_csv_async;
+pub use error::ReaderError;
+pub use reader::{
+    decompress_gzip_if_needed, read_csv_async, read_csv_async_with_options,
+    read_csv_async_with_progress, read_json_async, read_json_async_with_options, AsyncReader,
+    ColumnPositions, ReaderOptions, Trim,
+};
+pub use source::CsvSource;
+pub use writer::{
+    write_csv_async, write_csv_async_with_hash, write_csv_async_with_options, write_csv_gz_async,
+    write_json_async, write_split_csv_async, AsyncWriter, SortBy, WriterOptions,
+};
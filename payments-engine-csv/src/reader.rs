@@ -1,28 +1,419 @@
-use super::transaction::Transaction;
-use payments_engine_core::transaction::Transaction as EngineTransaction;
+use super::{
+    bounded::{BoundedLines, RECORD_TOO_LARGE_MARKER},
+    error::ReaderError,
+    transaction::{Transaction, TransactionKind},
+};
+use async_compression::tokio::bufread::GzipDecoder;
+pub use csv_async::Trim;
+use payments_engine_core::{
+    common::{Amount, ClientId},
+    transaction::{Transaction as EngineTransaction, TransactionId},
+};
+use serde::{
+    de::{value::StrDeserializer, IntoDeserializer},
+    Deserialize,
+};
+use tokio::io::AsyncBufReadExt;
 use tokio_stream::StreamExt;
 use tracing::instrument;
 
 pub type AsyncReader = dyn tokio::io::AsyncRead + Send + Sync + Unpin;
 
+/// Columns expected in the header of the CSV.
+const EXPECTED_COLUMNS: [&str; 4] = ["type", "client", "tx", "amount"];
+/// Columns that may be present in the header of the CSV.
+const OPTIONAL_COLUMNS: [&str; 2] = ["timestamp", "locked"];
+
+/// Zero-based positions of the columns of headerless input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ColumnPositions {
+    /// Position of the `type` column.
+    pub kind: usize,
+    /// Position of the `client` column.
+    pub client: usize,
+    /// Position of the `tx` column.
+    pub tx: usize,
+    /// Position of the `amount` column.
+    pub amount: usize,
+    /// Position of the optional `timestamp` column, if any.
+    pub timestamp: Option<usize>,
+    /// Position of the optional `locked` column of the opened accounts, if any.
+    pub locked: Option<usize>,
+}
+
+impl Default for ColumnPositions {
+    fn default() -> Self {
+        Self {
+            kind: 0,
+            client: 1,
+            tx: 2,
+            amount: 3,
+            timestamp: Some(4),
+            locked: None,
+        }
+    }
+}
+
+impl ColumnPositions {
+    /// Builds a header naming every column at its position.
+    /// Unmapped positions get an empty name, so they're ignored.
+    fn header(&self) -> csv_async::StringRecord {
+        let columns = [
+            (Some(self.kind), EXPECTED_COLUMNS[0]),
+            (Some(self.client), EXPECTED_COLUMNS[1]),
+            (Some(self.tx), EXPECTED_COLUMNS[2]),
+            (Some(self.amount), EXPECTED_COLUMNS[3]),
+            (self.timestamp, OPTIONAL_COLUMNS[0]),
+            (self.locked, OPTIONAL_COLUMNS[1]),
+        ];
+        let len = columns
+            .iter()
+            .filter_map(|(position, _)| *position)
+            .max()
+            .map_or(0, |max| max + 1);
+        let mut names = vec![""; len];
+        for (position, name) in columns {
+            if let Some(position) = position {
+                names[position] = name;
+            }
+        }
+        csv_async::StringRecord::from(names)
+    }
+}
+
+/// Options to customize the behavior of [`read_csv_async_with_options`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReaderOptions {
+    /// Field delimiter. Comma by default.
+    pub delimiter: u8,
+    /// Which whitespaces around the fields are trimmed: the ones of the header, the ones of the records, both or none.
+    /// Both are trimmed by default, i.e. [`Trim::All`].
+    pub trim: Trim,
+    /// Allows records with a different number of fields. Enabled by default.
+    pub flexible: bool,
+    /// Whether the first line is a header. Enabled by default.
+    /// The columns of a header can be in any order, as they're read by name.
+    /// Otherwise, columns are read positionally according to [`ReaderOptions::column_positions`].
+    pub has_headers: bool,
+    /// Positions of the columns in headerless input, `type,client,tx,amount[,timestamp]` by default.
+    /// It has no effect if there's a header.
+    pub column_positions: ColumnPositions,
+    /// Maximum length in bytes of a single record.
+    /// Records exceeding it are skipped and reported as [`ReaderError::RecordTooLarge`]
+    /// instead of being buffered. There's no limit by default.
+    pub max_record_length: Option<usize>,
+    /// Requires the header to contain exactly the expected columns, in any order.
+    /// Otherwise, a [`ReaderError::SchemaMismatch`] is returned and no records are read.
+    /// By default, unexpected columns are ignored. It has no effect on headerless input.
+    pub strict_header: bool,
+    /// Requires the ids of the transactions being created to be non-decreasing.
+    /// Transactions with a lower id than a previous one are reported as [`ReaderError::NonMonotonicTxId`].
+    /// Disputes, reviews, resolves and chargebacks refer to older ids, so they're not checked.
+    /// Disabled by default.
+    pub monotonic_tx_ids: bool,
+    /// Rejects deposits and withdrawals with a negative amount as [`ReaderError::NegativeAmount`]
+    /// instead of leaving them to the engine. Disabled by default.
+    pub reject_negative_amounts: bool,
+    /// Rejects deposits and withdrawals with no amount as [`ReaderError::MissingAmount`]
+    /// instead of defaulting it to zero. The amount of the rest of transactions is always optional.
+    /// Disabled by default.
+    pub reject_missing_amounts: bool,
+    /// Maximum absolute value accepted for an amount.
+    /// Amounts exceeding it are reported as [`ReaderError::AmountOutOfRange`]. There's no limit by default.
+    pub max_amount_magnitude: Option<Amount>,
+}
+
+impl Default for ReaderOptions {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            trim: Trim::All,
+            flexible: true,
+            has_headers: true,
+            column_positions: ColumnPositions::default(),
+            max_record_length: None,
+            strict_header: false,
+            monotonic_tx_ids: false,
+            reject_negative_amounts: false,
+            reject_missing_amounts: false,
+            max_amount_magnitude: None,
+        }
+    }
+}
+
+/// Magic bytes at the start of any gzip stream.
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+
+/// Transparently decompresses the reader if it's gzipped, detected by the gzip magic bytes.
+/// Otherwise, the content is read as is.
+/// The result can be passed to [`read_csv_async`] as any other reader.
+#[instrument(skip(reader))]
+pub async fn decompress_gzip_if_needed(
+    reader: impl tokio::io::AsyncRead + Send + Sync + Unpin + 'static,
+) -> std::io::Result<Box<AsyncReader>> {
+    let mut reader = tokio::io::BufReader::new(reader);
+    let is_gzip = reader.fill_buf().await?.starts_with(&GZIP_MAGIC_BYTES);
+    let reader: Box<AsyncReader> = if is_gzip {
+        tracing::debug!("Gzipped input detected");
+        Box::new(GzipDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+    Ok(reader)
+}
+
 /// Reads a CSV file asynchronously.
+///
+/// Note that [`ReaderError`] can be converted into an [`anyhow::Error`], so `?` can be used by the callers.
 #[instrument(skip(reader))]
 pub async fn read_csv_async(
     reader: &mut AsyncReader,
-) -> impl futures::Stream<Item = Result<EngineTransaction, anyhow::Error>> + '_ {
-    csv_async::AsyncReaderBuilder::new()
-        .flexible(true)
-        .trim(csv_async::Trim::All)
-        .create_reader(reader)
-        .into_records()
-        .map(|record| {
-            record
-                .and_then(|r| {
-                    r.deserialize::<Transaction>(None)
-                        .map(std::convert::Into::into)
-                })
-                .map_err(anyhow::Error::from)
-        })
+) -> impl futures::Stream<Item = Result<EngineTransaction, ReaderError>> + '_ {
+    read_csv_async_with_options(reader, ReaderOptions::default()).await
+}
+
+/// Reads a CSV file asynchronously with the given [`ReaderOptions`].
+#[instrument(skip(reader))]
+pub async fn read_csv_async_with_options<'a>(
+    reader: &'a mut AsyncReader,
+    options: ReaderOptions,
+) -> impl futures::Stream<Item = Result<EngineTransaction, ReaderError>> + 'a {
+    let reader: Box<dyn tokio::io::AsyncRead + Send + Sync + Unpin + 'a> =
+        match options.max_record_length {
+            Some(max) => Box::new(BoundedLines::new(reader, max)),
+            None => Box::new(reader),
+        };
+
+    let mut csv_reader = csv_async::AsyncReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .flexible(options.flexible)
+        .has_headers(options.has_headers)
+        .trim(options.trim)
+        .create_reader(reader);
+
+    // the records are always deserialized by name, so the order of the columns doesn't matter.
+    // headerless input gets a synthetic header built from the column positions.
+    let mut header_error = None;
+    let headers = if options.has_headers {
+        match csv_reader.headers().await {
+            Ok(record) => {
+                if options.strict_header {
+                    header_error = schema_mismatch(record);
+                }
+                Some(record.clone())
+            }
+            Err(e) => {
+                header_error = Some(e.into());
+                None
+            }
+        }
+    } else {
+        Some(options.column_positions.header())
+    };
+    // no records are read if the header is not valid
+    let limit = if header_error.is_some() {
+        0
+    } else {
+        usize::MAX
+    };
+
+    // highest tx id seen so far, used to enforce monotonic tx ids
+    let mut previous_id = None;
+
+    let records = csv_reader.into_records().take(limit).map(move |record| {
+        let record = record?;
+        let line = record.position().map_or(0, csv_async::Position::line);
+        if record.len() == 1 && &record[0] == RECORD_TOO_LARGE_MARKER {
+            tracing::error!("Skipping record at line {} as it's too large", line);
+            return Err(ReaderError::RecordTooLarge { line });
+        }
+        let transaction = record
+            .deserialize::<Transaction>(headers.as_ref())
+            .map_err(|e| deserialization_error(&record, headers.as_ref(), &e))?;
+        validate_amount(&transaction, &options, line)?;
+        if options.monotonic_tx_ids && transaction.kind.creates_transaction() {
+            match previous_id {
+                Some(previous) if transaction.id < previous => {
+                    tracing::error!(
+                        "Transaction id {} at line {} is lower than the previous one {}",
+                        transaction.id,
+                        line,
+                        previous
+                    );
+                    return Err(ReaderError::NonMonotonicTxId {
+                        previous,
+                        current: transaction.id,
+                        line,
+                    });
+                }
+                _ => previous_id = Some(transaction.id),
+            }
+        }
+        Ok(transaction.into())
+    });
+
+    tokio_stream::iter(header_error.map(Err)).chain(records)
+}
+
+/// Reads a CSV file asynchronously with the given [`ReaderOptions`],
+/// pairing every item with the number of rows read so far, malformed ones included.
+/// This is useful to report the progress of big files.
+#[instrument(skip(reader))]
+pub async fn read_csv_async_with_progress<'a>(
+    reader: &'a mut AsyncReader,
+    options: ReaderOptions,
+) -> impl futures::Stream<Item = (u64, Result<EngineTransaction, ReaderError>)> + 'a {
+    let transactions = read_csv_async_with_options(reader, options).await;
+    futures::StreamExt::zip(futures::stream::iter(1..), transactions)
+}
+
+/// Reads newline-delimited JSON transactions asynchronously, one object per line, e.g. `{"type":"deposit","client":1,"tx":1,"amount":"1.5"}`.
+/// The field names are the same as the CSV columns and the amounts must be strings to keep their precision.
+///
+/// Blank lines are skipped, while malformed ones yield a [`ReaderError::Deserialization`] without stopping the stream.
+#[instrument(skip(reader))]
+pub async fn read_json_async(
+    reader: &mut AsyncReader,
+) -> impl futures::Stream<Item = Result<EngineTransaction, ReaderError>> + '_ {
+    read_json_async_with_options(reader, ReaderOptions::default()).await
+}
+
+/// Reads newline-delimited JSON transactions asynchronously with the given [`ReaderOptions`].
+/// The amounts are validated as in [`read_csv_async_with_options`],
+/// i.e. `reject_negative_amounts`, `reject_missing_amounts` and `max_amount_magnitude`.
+/// The options about the CSV format, such as the delimiter or the header, have no effect.
+#[instrument(skip(reader))]
+pub async fn read_json_async_with_options(
+    reader: &mut AsyncReader,
+    options: ReaderOptions,
+) -> impl futures::Stream<Item = Result<EngineTransaction, ReaderError>> + '_ {
+    let lines = tokio_stream::wrappers::LinesStream::new(tokio::io::BufReader::new(reader).lines());
+    let mut line_number = 0;
+    lines.filter_map(move |line| {
+        line_number += 1;
+        let line = match line {
+            Ok(line) if line.trim().is_empty() => return None,
+            Ok(line) => line,
+            Err(e) => return Some(Err(ReaderError::Io(e.to_string()))),
+        };
+        let transaction = serde_json::from_str::<Transaction>(&line)
+            .map_err(|e| {
+                tracing::error!("Line {} could not be deserialized: {}", line_number, e);
+                ReaderError::Deserialization {
+                    line: line_number,
+                    record: line.clone(),
+                    message: e.to_string(),
+                }
+            })
+            .and_then(|transaction| {
+                validate_amount(&transaction, &options, line_number)?;
+                Ok(transaction)
+            });
+        Some(transaction.map(Into::into))
+    })
+}
+
+/// Validates the amount of a deserialized transaction according to the [`ReaderOptions`].
+fn validate_amount(
+    transaction: &Transaction,
+    options: &ReaderOptions,
+    line: u64,
+) -> Result<(), ReaderError> {
+    if let (Some(max), Some(amount)) = (options.max_amount_magnitude, transaction.amount) {
+        if amount.abs() > max {
+            tracing::error!("Amount at line {} exceeds the maximum magnitude", line);
+            return Err(ReaderError::AmountOutOfRange { line });
+        }
+    }
+    if options.reject_missing_amounts
+        && transaction.kind.has_amount()
+        && transaction.amount.is_none()
+    {
+        tracing::error!("Transaction with id {} has no amount", transaction.id);
+        return Err(ReaderError::MissingAmount { id: transaction.id });
+    }
+    if options.reject_negative_amounts
+        && transaction
+            .amount
+            .is_some_and(|amount| amount < Amount::ZERO)
+    {
+        tracing::error!("Transaction with id {} has negative amount", transaction.id);
+        return Err(ReaderError::NegativeAmount { id: transaction.id });
+    }
+    Ok(())
+}
+
+/// Categorizes the deserialization error of a record by checking its fields.
+fn deserialization_error(
+    record: &csv_async::StringRecord,
+    headers: Option<&csv_async::StringRecord>,
+    error: &csv_async::Error,
+) -> ReaderError {
+    let line = record.position().map_or(0, csv_async::Position::line);
+    // named fields if there are headers, positional ones otherwise
+    let field = |name: &str| {
+        let index = headers.map_or_else(
+            || EXPECTED_COLUMNS.iter().position(|column| *column == name),
+            |headers| headers.iter().position(|header| header == name),
+        );
+        index
+            .and_then(|index| record.get(index))
+            .unwrap_or_default()
+    };
+    let invalid_numeric_field = |name: &str| ReaderError::InvalidNumericField {
+        field: name.to_string(),
+        value: field(name).to_string(),
+        line,
+    };
+
+    let kind = field("type");
+    let kind_deserializer: StrDeserializer<'_, serde::de::value::Error> = kind.into_deserializer();
+    let error = if TransactionKind::deserialize(kind_deserializer).is_err() {
+        ReaderError::UnknownTransactionKind {
+            kind: kind.to_string(),
+            line,
+        }
+    } else if field("client").parse::<ClientId>().is_err() {
+        invalid_numeric_field("client")
+    } else if field("tx").parse::<TransactionId>().is_err() {
+        invalid_numeric_field("tx")
+    } else if !field("amount").is_empty() && field("amount").parse::<Amount>().is_err() {
+        invalid_numeric_field("amount")
+    } else {
+        ReaderError::Deserialization {
+            line,
+            record: record.iter().collect::<Vec<_>>().join(","),
+            message: error.to_string(),
+        }
+    };
+    tracing::error!(%error, "Error deserializing record");
+    error
+}
+
+/// Compares the header with the [`EXPECTED_COLUMNS`] and returns the diff, if any.
+/// The [`OPTIONAL_COLUMNS`] are never reported.
+fn schema_mismatch(headers: &csv_async::StringRecord) -> Option<ReaderError> {
+    let missing = EXPECTED_COLUMNS
+        .iter()
+        .filter(|column| !headers.iter().any(|header| header == **column))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    let extra = headers
+        .iter()
+        .filter(|header| !EXPECTED_COLUMNS.contains(header) && !OPTIONAL_COLUMNS.contains(header))
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+
+    if missing.is_empty() && extra.is_empty() {
+        None
+    } else {
+        tracing::error!(
+            ?missing,
+            ?extra,
+            "Header doesn't match the expected columns"
+        );
+        Some(ReaderError::SchemaMismatch { missing, extra })
+    }
 }
 
 #[cfg(test)]
@@ -31,7 +422,7 @@ mod tests {
     use futures::{FutureExt, TryStreamExt};
     use payments_engine_core::dec;
 
-    const ERR: &'static str = "err";
+    const ERR: &str = "err";
 
     #[tokio::test]
     async fn reads_csv_async_works_ok() {
@@ -77,6 +468,57 @@ mod tests {
         assert_eq!(result, expected)
     }
 
+    #[tokio::test]
+    async fn reads_csv_async_with_every_trim_policy() {
+        let input = "type , client, tx, amount\ndeposit,1, 1,10\n deposit ,1,2,10";
+        let read = |trim| async move {
+            let mut input = input.as_bytes();
+            let options = ReaderOptions {
+                trim,
+                ..ReaderOptions::default()
+            };
+            read_csv_async_with_options(&mut input, options)
+                .map(|tx| tx.map_err(|_| ERR))
+                .await
+                .collect::<Vec<_>>()
+                .await
+        };
+
+        let trimmed = vec![
+            Ok(EngineTransaction::deposit(1, 1, dec!(10))),
+            Ok(EngineTransaction::deposit(2, 1, dec!(10))),
+        ];
+        assert_eq!(read(Trim::All).await, trimmed);
+        // the columns are not found if the header keeps its whitespaces
+        assert_eq!(read(Trim::None).await, vec![Err(ERR), Err(ERR)]);
+        assert_eq!(read(Trim::Fields).await, vec![Err(ERR), Err(ERR)]);
+        // the whitespaces of the records are kept, so they're no longer valid
+        assert_eq!(read(Trim::Headers).await, vec![Err(ERR), Err(ERR)]);
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_keeping_the_whitespaces_of_the_records() {
+        let mut input = "type, client, tx, amount\ndeposit,1,1, 10".as_bytes();
+        let options = ReaderOptions {
+            trim: Trim::Headers,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![Err(ReaderError::InvalidNumericField {
+                field: "amount".to_string(),
+                value: " 10".to_string(),
+                line: 2,
+            })]
+        );
+    }
+
     #[tokio::test]
     async fn reads_csv_async_works_ok_with_untrimmed_content() {
         let mut input = r"
@@ -142,4 +584,779 @@ mod tests {
 
         assert_eq!(result, expected)
     }
+
+    #[tokio::test]
+    async fn reads_csv_async_skips_records_exceeding_max_length() {
+        let huge = "9".repeat(1024 * 1024);
+        let input = format!(
+            "type,client,tx,amount\ndeposit,1,1,100\ndeposit,1,2,{}\nwithdrawal,1,3,50",
+            huge
+        );
+        let mut input = std::io::Cursor::new(input.into_bytes());
+        let options = ReaderOptions {
+            max_record_length: Some(64),
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Ok(EngineTransaction::deposit(1, 1, dec!(100))),
+            Err(ReaderError::RecordTooLarge { line: 3 }),
+            Ok(EngineTransaction::withdrawal(3, 1, dec!(50))),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    const EXTRA_COLUMN_INPUT: &str = "type,client,tx,amount,currency\ndeposit,1,1,100,EUR";
+
+    #[tokio::test]
+    async fn reads_csv_async_ignores_extra_columns_in_lenient_mode() {
+        let mut input = EXTRA_COLUMN_INPUT.as_bytes();
+
+        let result = read_csv_async(&mut input)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![Ok(EngineTransaction::deposit(1, 1, dec!(100)))]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_reports_schema_mismatch_in_strict_mode() {
+        let mut input = EXTRA_COLUMN_INPUT.as_bytes();
+        let options = ReaderOptions {
+            strict_header: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![Err(ReaderError::SchemaMismatch {
+            missing: vec![],
+            extra: vec!["currency".to_string()],
+        })];
+
+        assert_eq!(result, expected)
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_accepts_reordered_columns_in_strict_mode() {
+        let mut input = "tx,type,amount,client\n1,deposit,100,1".as_bytes();
+        let options = ReaderOptions {
+            strict_header: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![Ok(EngineTransaction::deposit(1, 1, dec!(100)))]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_json_async_ok() {
+        let mut input = r#"{"type":"deposit","client":1,"tx":1,"amount":"10.5"}
+{"type":"withdrawal","client":1,"tx":2,"amount":"2"}
+
+{"type":"dispute","client":1,"tx":1}
+{"type":"chargeback","client":1,"tx":1,"timestamp":1700000000}"#
+            .as_bytes();
+
+        let result = read_json_async(&mut input).await.collect::<Vec<_>>().await;
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(EngineTransaction::deposit(1, 1, dec!(10.5))),
+                Ok(EngineTransaction::withdrawal(2, 1, dec!(2))),
+                Ok(EngineTransaction::dispute(1, 1)),
+                Ok(EngineTransaction::chargeback(1, 1).with_timestamp(Some(1_700_000_000))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_json_async_yields_an_error_for_malformed_lines() {
+        let mut input = r#"{"type":"deposit","client":1,"tx":1,"amount":"10"}
+{"type":"deposit","client":1,
+{"type":"deposito","client":1,"tx":3,"amount":"10"}
+{"type":"deposit","client":1,"tx":4,"amount":"5"}"#
+            .as_bytes();
+
+        let result = read_json_async(&mut input).await.collect::<Vec<_>>().await;
+
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0], Ok(EngineTransaction::deposit(1, 1, dec!(10))));
+        assert!(matches!(
+            &result[1],
+            Err(ReaderError::Deserialization { line: 2, record, .. }) if record == r#"{"type":"deposit","client":1,"#
+        ));
+        assert!(matches!(
+            result[2],
+            Err(ReaderError::Deserialization { line: 3, .. })
+        ));
+        assert_eq!(result[3], Ok(EngineTransaction::deposit(4, 1, dec!(5))));
+    }
+
+    #[tokio::test]
+    async fn reads_json_async_validates_the_amounts_as_the_csv_reader() {
+        let mut input = r#"{"type":"deposit","client":1,"tx":1,"amount":"-10"}
+{"type":"withdrawal","client":1,"tx":2}
+{"type":"deposit","client":1,"tx":3,"amount":"10000"}
+{"type":"deposit","client":1,"tx":4,"amount":"5"}"#
+            .as_bytes();
+        let options = ReaderOptions {
+            reject_negative_amounts: true,
+            reject_missing_amounts: true,
+            max_amount_magnitude: Some(dec!(1000)),
+            ..ReaderOptions::default()
+        };
+
+        let result = read_json_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![
+                Err(ReaderError::NegativeAmount { id: 1 }),
+                Err(ReaderError::MissingAmount { id: 2 }),
+                Err(ReaderError::AmountOutOfRange { line: 3 }),
+                Ok(EngineTransaction::deposit(4, 1, dec!(5))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_maps_the_open_type_with_the_optional_locked_column() {
+        let mut input =
+            "type,client,tx,amount,locked\nopen,1,1,,true\nopen,2,2,,false\nopen,3,3,,".as_bytes();
+        let options = ReaderOptions {
+            strict_header: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(EngineTransaction::open_account(1, 1, true)),
+                Ok(EngineTransaction::open_account(2, 2, false)),
+                Ok(EngineTransaction::open_account(3, 3, false)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_maps_the_close_type() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,100\nclose,1,2,".as_bytes();
+
+        let result = read_csv_async(&mut input)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(EngineTransaction::deposit(1, 1, dec!(100))),
+                Ok(EngineTransaction::close_account(2, 1)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_maps_the_optional_timestamp_column() {
+        let mut input = "type,client,tx,amount,timestamp,currency
+            deposit,1,1,100,1700000000,EUR
+            dispute,1,1,,,EUR"
+            .as_bytes();
+
+        let result = read_csv_async(&mut input)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(EngineTransaction::deposit(1, 1, dec!(100)).with_timestamp(Some(1_700_000_000))),
+                Ok(EngineTransaction::dispute(1, 1)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_accepts_the_timestamp_column_in_strict_mode() {
+        let mut input = "timestamp,tx,type,amount,client\n1700000000,1,deposit,100,1".as_bytes();
+        let options = ReaderOptions {
+            strict_header: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![Ok(
+                EngineTransaction::deposit(1, 1, dec!(100)).with_timestamp(Some(1_700_000_000))
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_reports_non_monotonic_tx_ids() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,1\ndeposit,1,2,1\ndispute,1,2,\ndeposit,1,5,1\ndeposit,1,4,1".as_bytes();
+        let options = ReaderOptions {
+            monotonic_tx_ids: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Ok(EngineTransaction::deposit(1, 1, dec!(1))),
+            Ok(EngineTransaction::deposit(2, 1, dec!(1))),
+            Ok(EngineTransaction::dispute(2, 1)),
+            Ok(EngineTransaction::deposit(5, 1, dec!(1))),
+            Err(ReaderError::NonMonotonicTxId {
+                previous: 5,
+                current: 4,
+                line: 6,
+            }),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_allows_disputes_of_earlier_deposits_with_monotonic_tx_ids() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,1\ndeposit,1,2,1\ndispute,1,1,\nresolve,1,1,\ndeposit,1,3,1\nchargeback,1,2,".as_bytes();
+        let options = ReaderOptions {
+            monotonic_tx_ids: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Ok(EngineTransaction::deposit(1, 1, dec!(1))),
+            Ok(EngineTransaction::deposit(2, 1, dec!(1))),
+            Ok(EngineTransaction::dispute(1, 1)),
+            Ok(EngineTransaction::resolve(1, 1)),
+            Ok(EngineTransaction::deposit(3, 1, dec!(1))),
+            Ok(EngineTransaction::chargeback(2, 1)),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_rejects_negative_amounts() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,-1\ndeposit,1,2,1\nwithdrawal,1,3,-0.5\nwithdrawal,1,4,0.5".as_bytes();
+        let options = ReaderOptions {
+            reject_negative_amounts: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Err(ReaderError::NegativeAmount { id: 1 }),
+            Ok(EngineTransaction::deposit(2, 1, dec!(1))),
+            Err(ReaderError::NegativeAmount { id: 3 }),
+            Ok(EngineTransaction::withdrawal(4, 1, dec!(0.5))),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_rejects_missing_amounts() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,\nwithdrawal,1,2\ndeposit,1,3,1\ndispute,1,3,\nresolve,1,3\nchargeback,1,3,".as_bytes();
+        let options = ReaderOptions {
+            reject_missing_amounts: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Err(ReaderError::MissingAmount { id: 1 }),
+            Err(ReaderError::MissingAmount { id: 2 }),
+            Ok(EngineTransaction::deposit(3, 1, dec!(1))),
+            Ok(EngineTransaction::dispute(3, 1)),
+            Ok(EngineTransaction::resolve(3, 1)),
+            Ok(EngineTransaction::chargeback(3, 1)),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_defaults_missing_amounts_to_zero() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,\nwithdrawal,1,2".as_bytes();
+
+        let result = read_csv_async(&mut input).await.collect::<Vec<_>>().await;
+
+        let expected = vec![
+            Ok(EngineTransaction::deposit(1, 1, dec!(0))),
+            Ok(EngineTransaction::withdrawal(2, 1, dec!(0))),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_rejects_amounts_exceeding_max_magnitude() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,1000000000000000\nwithdrawal,1,2,-1000000000000000\ndeposit,1,3,1000000000000".as_bytes();
+        let options = ReaderOptions {
+            max_amount_magnitude: Some(dec!(1000000000000)),
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Err(ReaderError::AmountOutOfRange { line: 2 }),
+            Err(ReaderError::AmountOutOfRange { line: 3 }),
+            Ok(EngineTransaction::deposit(3, 1, dec!(1000000000000))),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_keeps_negative_amounts_by_default() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,-1".as_bytes();
+
+        let result = read_csv_async(&mut input).await.collect::<Vec<_>>().await;
+
+        assert_eq!(result, vec![Ok(EngineTransaction::deposit(1, 1, dec!(-1)))])
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_with_custom_delimiters() {
+        for delimiter in [b';', b'\t'] {
+            let input = "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,50"
+                .replace(',', &char::from(delimiter).to_string());
+            let mut input = std::io::Cursor::new(input.into_bytes());
+            let options = ReaderOptions {
+                delimiter,
+                ..ReaderOptions::default()
+            };
+
+            let result = read_csv_async_with_options(&mut input, options)
+                .map(|tx| tx.map_err(|_| ERR))
+                .await
+                .collect::<Vec<_>>()
+                .await;
+
+            let expected = vec![
+                Ok(EngineTransaction::deposit(1, 1, dec!(100))),
+                Ok(EngineTransaction::withdrawal(2, 1, dec!(50))),
+            ];
+
+            assert_eq!(result, expected)
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_with_semicolons_fails_with_default_delimiter() {
+        let mut input = "type;client;tx;amount\ndeposit;1;1;100".as_bytes();
+
+        let result = read_csv_async(&mut input)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result, vec![Err(ERR)])
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_without_headers() {
+        let data = "deposit,1,1,100\nwithdrawal,1,2,50\ndispute,1,1,";
+        let with_headers = format!("type,client,tx,amount\n{}", data).into_bytes();
+        let without_headers = data.as_bytes().to_vec();
+        let options = ReaderOptions {
+            has_headers: false,
+            ..ReaderOptions::default()
+        };
+
+        let mut with_headers = std::io::Cursor::new(with_headers);
+        let with_headers = read_csv_async(&mut with_headers)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        let mut without_headers = std::io::Cursor::new(without_headers);
+        let without_headers = read_csv_async_with_options(&mut without_headers, options)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        let expected = vec![
+            Ok(EngineTransaction::deposit(1, 1, dec!(100))),
+            Ok(EngineTransaction::withdrawal(2, 1, dec!(50))),
+            Ok(EngineTransaction::dispute(1, 1)),
+        ];
+
+        assert_eq!(with_headers, expected);
+        assert_eq!(without_headers, expected);
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_accepts_any_column_order_with_headers() {
+        let input = "type,client,amount,tx\ndeposit,1,100,10\nwithdrawal,1,40,11\ndispute,1,,10";
+        let expected = vec![
+            Ok(EngineTransaction::deposit(10, 1, dec!(100))),
+            Ok(EngineTransaction::withdrawal(11, 1, dec!(40))),
+            Ok(EngineTransaction::dispute(10, 1)),
+        ];
+
+        for strict_header in [false, true] {
+            let options = ReaderOptions {
+                strict_header,
+                ..ReaderOptions::default()
+            };
+            let result = read_csv_async_with_options(&mut input.as_bytes(), options)
+                .map(|tx| tx.map_err(|_| ERR))
+                .await
+                .collect::<Vec<_>>()
+                .await;
+
+            assert_eq!(result, expected, "strict_header: {}", strict_header);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_with_custom_column_positions() {
+        let mut input = "deposit,1,100,10\nwithdrawal,1,40,11\ndispute,1,,10".as_bytes();
+        let options = ReaderOptions {
+            has_headers: false,
+            column_positions: ColumnPositions {
+                amount: 2,
+                tx: 3,
+                ..ColumnPositions::default()
+            },
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .map(|tx| tx.map_err(|_| ERR))
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(EngineTransaction::deposit(10, 1, dec!(100))),
+                Ok(EngineTransaction::withdrawal(11, 1, dec!(40))),
+                Ok(EngineTransaction::dispute(10, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn column_positions_build_a_header_with_every_column() {
+        let positions = ColumnPositions {
+            kind: 3,
+            client: 0,
+            tx: 1,
+            amount: 5,
+            timestamp: None,
+            locked: Some(2),
+        };
+
+        assert_eq!(
+            positions.header(),
+            csv_async::StringRecord::from(vec!["client", "tx", "locked", "type", "", "amount"])
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_gzipped_csv_async_as_the_plain_one() {
+        use async_compression::tokio::write::GzipEncoder;
+        use tokio::io::AsyncWriteExt;
+
+        let plain = "type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,50\ndispute,1,1,";
+        let mut encoder = GzipEncoder::new(Vec::new());
+        encoder.write_all(plain.as_bytes()).await.unwrap();
+        encoder.shutdown().await.unwrap();
+        let gzipped = encoder.into_inner();
+
+        let mut results = vec![];
+        for input in [plain.as_bytes().to_vec(), gzipped] {
+            let mut reader = decompress_gzip_if_needed(std::io::Cursor::new(input))
+                .await
+                .unwrap();
+            let result = read_csv_async(&mut reader)
+                .map(|tx| tx.map_err(|_| ERR))
+                .await
+                .collect::<Vec<_>>()
+                .await;
+            results.push(result);
+        }
+
+        assert_eq!(
+            results[0],
+            vec![
+                Ok(EngineTransaction::deposit(1, 1, dec!(100))),
+                Ok(EngineTransaction::withdrawal(2, 1, dec!(50))),
+                Ok(EngineTransaction::dispute(1, 1)),
+            ]
+        );
+        assert_eq!(results[0], results[1]);
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_categorizes_errors() {
+        let mut input = r"
+        type,client,tx,amount
+        deposito,1,1,100
+        deposit,-1,2,100
+        deposit,1,tx,100
+        deposit,1,4,1OO
+        deposit,1,5,100"
+            .as_bytes();
+
+        let result = read_csv_async(&mut input).await.collect::<Vec<_>>().await;
+
+        let invalid = |field: &str, value: &str, line| ReaderError::InvalidNumericField {
+            field: field.to_string(),
+            value: value.to_string(),
+            line,
+        };
+        let expected = vec![
+            Err(ReaderError::UnknownTransactionKind {
+                kind: "deposito".to_string(),
+                line: 3,
+            }),
+            Err(invalid("client", "-1", 4)),
+            Err(invalid("tx", "tx", 5)),
+            Err(invalid("amount", "1OO", 6)),
+            Ok(EngineTransaction::deposit(5, 1, dec!(100))),
+        ];
+
+        assert_eq!(result, expected)
+    }
+
+    #[tokio::test]
+    async fn reads_csv_async_accepts_the_max_ids_but_not_beyond() {
+        let mut input = r"
+        type,client,tx,amount
+        deposit,65535,4294967295,1
+        deposit,65536,1,1
+        deposit,1,4294967296,1"
+            .as_bytes();
+
+        let result = read_csv_async(&mut input).await.collect::<Vec<_>>().await;
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(EngineTransaction::deposit(
+                    TransactionId::MAX,
+                    ClientId::MAX,
+                    dec!(1)
+                )),
+                Err(ReaderError::InvalidNumericField {
+                    field: "client".to_string(),
+                    value: "65536".to_string(),
+                    line: 4,
+                }),
+                Err(ReaderError::InvalidNumericField {
+                    field: "tx".to_string(),
+                    value: "4294967296".to_string(),
+                    line: 5,
+                }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_any_subset_of_the_optional_columns() {
+        // `currency` and `reason` are not supported yet, but feeds may already include them
+        let optional_columns = [
+            ("amount", "1.5"),
+            ("timestamp", "1700000000"),
+            ("currency", "EUR"),
+            ("reason", "refund"),
+        ];
+
+        for mask in 0..(1 << optional_columns.len()) {
+            let present = optional_columns
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, column)| *column)
+                .collect::<Vec<_>>();
+            let is_present = |name: &str| present.iter().any(|(column, _)| *column == name);
+
+            for filled in [true, false] {
+                let mut header = vec!["type", "client", "tx"];
+                let mut row = vec!["deposit", "1", "7"];
+                for (column, value) in &present {
+                    header.push(column);
+                    row.push(if filled { value } else { "" });
+                }
+                let input = format!("{}\n{}", header.join(","), row.join(","));
+                let mut input = std::io::Cursor::new(input.into_bytes());
+
+                let result = read_csv_async(&mut input).await.collect::<Vec<_>>().await;
+
+                let amount = if filled && is_present("amount") {
+                    dec!(1.5)
+                } else {
+                    dec!(0)
+                };
+                let timestamp = (filled && is_present("timestamp")).then_some(1_700_000_000);
+                assert_eq!(
+                    result,
+                    vec![Ok(
+                        EngineTransaction::deposit(7, 1, amount).with_timestamp(timestamp)
+                    )],
+                    "columns: {:?}, filled: {}",
+                    present,
+                    filled
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_counts_every_row_including_malformed_ones() {
+        let mut input = r"
+        type,client,tx,amount
+        deposit,1,1,100
+        deposito,1,2,100
+        withdrawal,1,3,50
+        deposit,one,4,100
+        dispute,1,1,"
+            .as_bytes();
+
+        let rows = read_csv_async_with_progress(&mut input, ReaderOptions::default())
+            .await
+            .map(|(row, result)| (row, result.is_ok()))
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            rows,
+            vec![(1, true), (2, false), (3, true), (4, false), (5, true)]
+        );
+    }
+
+    /// Byte order mark some tools, e.g. Excel, write at the start of UTF-8 files.
+    /// The CSV reader already skips it, so it's not read as part of the first column name.
+    const UTF8_BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
+
+    #[tokio::test]
+    async fn reads_csv_async_skipping_the_utf8_bom() {
+        let input = [
+            UTF8_BOM.as_slice(),
+            b"type,client,tx,amount\ndeposit,1,1,100\nwithdrawal,1,2,50",
+        ]
+        .concat();
+        let mut input = std::io::Cursor::new(input);
+        let options = ReaderOptions {
+            strict_header: true,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![
+                Ok(EngineTransaction::deposit(1, 1, dec!(100))),
+                Ok(EngineTransaction::withdrawal(2, 1, dec!(50))),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn reads_headerless_csv_async_skipping_the_utf8_bom() {
+        let input = [UTF8_BOM.as_slice(), b"deposit,1,1,100"].concat();
+        let mut input = std::io::Cursor::new(input);
+        let options = ReaderOptions {
+            has_headers: false,
+            ..ReaderOptions::default()
+        };
+
+        let result = read_csv_async_with_options(&mut input, options)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            result,
+            vec![Ok(EngineTransaction::deposit(1, 1, dec!(100)))]
+        );
+    }
+
+    #[tokio::test]
+    async fn reader_errors_can_be_propagated_as_anyhow_errors() {
+        async fn first(mut input: &'static [u8]) -> anyhow::Result<EngineTransaction> {
+            let transaction = read_csv_async(&mut input).await.next().await.unwrap()?;
+            Ok(transaction)
+        }
+
+        assert!(first(b"type,client,tx,amount\ndeposit,1,1,1").await.is_ok());
+        let err = first(b"type,client,tx,amount\ndeposito,1,1,1")
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<ReaderError>().is_some());
+    }
 }
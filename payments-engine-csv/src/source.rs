@@ -0,0 +1,68 @@
+use super::{
+    error::ReaderError,
+    reader::{read_csv_async_with_options, AsyncReader, ReaderOptions},
+};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use payments_engine_core::{
+    source::{SourceError, TransactionSource},
+    transaction::Transaction as EngineTransaction,
+};
+use std::pin::Pin;
+
+type TransactionStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<EngineTransaction, ReaderError>> + Send + 'a>>;
+
+/// [`TransactionSource`] reading the transactions from a CSV.
+pub struct CsvSource<'a> {
+    stream: TransactionStream<'a>,
+}
+
+impl<'a> CsvSource<'a> {
+    /// Creates a new [`CsvSource`] reading from the given reader.
+    pub async fn new(reader: &'a mut AsyncReader) -> CsvSource<'a> {
+        Self::with_options(reader, ReaderOptions::default()).await
+    }
+
+    /// Creates a new [`CsvSource`] reading from the given reader with the given [`ReaderOptions`].
+    pub async fn with_options(
+        reader: &'a mut AsyncReader,
+        options: ReaderOptions,
+    ) -> CsvSource<'a> {
+        Self {
+            stream: Box::pin(read_csv_async_with_options(reader, options).await),
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionSource for CsvSource<'_> {
+    async fn next(&mut self) -> Option<Result<EngineTransaction, SourceError>> {
+        self.stream
+            .next()
+            .await
+            .map(|tx| tx.map_err(|e| SourceError::ReadError(e.to_string())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use payments_engine_core::dec;
+
+    #[tokio::test]
+    async fn csv_source_yields_transactions_and_errors() {
+        let mut input = "type,client,tx,amount\ndeposit,1,1,100\ndeposito,1,2,100".as_bytes();
+        let mut source = CsvSource::new(&mut input).await;
+
+        assert_eq!(
+            source.next().await,
+            Some(Ok(EngineTransaction::deposit(1, 1, dec!(100))))
+        );
+        assert!(matches!(
+            source.next().await,
+            Some(Err(SourceError::ReadError(_)))
+        ));
+        assert_eq!(source.next().await, None);
+    }
+}
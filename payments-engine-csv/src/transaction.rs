@@ -1,6 +1,6 @@
 use payments_engine_core::{
     common::{Amount, ClientId},
-    transaction::{Transaction as EngineTransaction, TransactionId, TransactionInfo},
+    transaction::{DisputeState, Transaction as EngineTransaction, TransactionId, TransactionInfo},
 };
 use serde::{Deserialize, Serialize};
 
@@ -14,13 +14,42 @@ pub enum TransactionKind {
     Withdrawal,
     // Represents a client's claim that a transaction was erroneus and should be reversed.
     Dispute,
+    /// Represents the review of an open dispute.
+    Review,
     /// Represents a resolution to a dispute, releasing the associated held funds.
     Resolve,
     /// Represents the client reversing a transaction after a dispute.
     ChargeBack,
+    /// Represents the opening of the client's account, locked or not.
+    #[serde(rename = "open")]
+    OpenAccount,
+    /// Represents the client closing its account.
+    #[serde(rename = "close")]
+    CloseAccount,
+}
+
+impl TransactionKind {
+    /// Whether the [`TransactionKind`] is expected to have an amount,
+    /// i.e. [`TransactionKind::Deposit`] and [`TransactionKind::Withdrawal`].
+    #[must_use]
+    pub const fn has_amount(&self) -> bool {
+        matches!(self, Self::Deposit | Self::Withdrawal)
+    }
+
+    /// Whether the [`TransactionKind`] creates a transaction with its own id,
+    /// instead of referencing an existing one like disputes, reviews, resolves and chargebacks do.
+    #[must_use]
+    pub const fn creates_transaction(&self) -> bool {
+        matches!(
+            self,
+            Self::Deposit | Self::Withdrawal | Self::OpenAccount | Self::CloseAccount
+        )
+    }
 }
 
 /// Represents a client's [`Account`] transaction.
+///
+/// Optional columns are [`Option`] and `#[serde(default)]`, so feeds can include any subset of them.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Transaction {
     /// The [`Transaction`] variant.
@@ -36,22 +65,39 @@ pub struct Transaction {
     /// It will be informed only for [`TransactionKind::Deposit`] and [`TransactionKind::Withdrawal`]
     #[serde(default)]
     pub amount: Option<Amount>,
+    /// When the transaction happened, in seconds since the Unix epoch.
+    /// It's optional and it will be `None` if the column is missing or empty.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Whether the account is opened locked.
+    /// It's only used by [`TransactionKind::OpenAccount`], which opens unlocked accounts if it's missing.
+    #[serde(default)]
+    pub locked: Option<bool>,
 }
 
 impl From<Transaction> for EngineTransaction {
     fn from(tx: Transaction) -> Self {
-        match tx.kind {
+        let timestamp = tx.timestamp;
+        let transaction = match tx.kind {
             TransactionKind::Deposit => Self::Deposit {
                 info: TransactionInfo::new(tx.id, tx.client_id),
                 amount: tx.amount.unwrap_or_default(),
-                under_dispute: false,
+                dispute_state: DisputeState::Undisputed,
+                disputed_amount: None,
             },
             TransactionKind::Withdrawal => Self::Withdrawal {
                 info: TransactionInfo::new(tx.id, tx.client_id),
                 amount: tx.amount.unwrap_or_default(),
+                dispute_state: DisputeState::Undisputed,
+                disputed_amount: None,
             },
+            // the amount of the dispute rows is ignored, so they always dispute the whole transaction
             TransactionKind::Dispute => Self::Dispute {
                 info: TransactionInfo::new(tx.id, tx.client_id),
+                amount: None,
+            },
+            TransactionKind::Review => Self::Review {
+                info: TransactionInfo::new(tx.id, tx.client_id),
             },
             TransactionKind::Resolve => Self::Resolve {
                 info: TransactionInfo::new(tx.id, tx.client_id),
@@ -59,7 +105,15 @@ impl From<Transaction> for EngineTransaction {
             TransactionKind::ChargeBack => Self::ChargeBack {
                 info: TransactionInfo::new(tx.id, tx.client_id),
             },
-        }
+            TransactionKind::OpenAccount => Self::OpenAccount {
+                info: TransactionInfo::new(tx.id, tx.client_id),
+                locked: tx.locked.unwrap_or_default(),
+            },
+            TransactionKind::CloseAccount => Self::CloseAccount {
+                info: TransactionInfo::new(tx.id, tx.client_id),
+            },
+        };
+        transaction.with_timestamp(timestamp)
     }
 }
 
@@ -75,6 +129,8 @@ mod tests {
             id: 1,
             client_id: 1,
             amount: Some(dec!(1.0000)),
+            timestamp: None,
+            locked: None,
         };
 
         let engine_transaction: EngineTransaction = transaction.clone().into();
@@ -92,6 +148,8 @@ mod tests {
             id: 1,
             client_id: 1,
             amount: None,
+            timestamp: None,
+            locked: None,
         };
 
         let engine_transaction: EngineTransaction = transaction.clone().into();
@@ -102,6 +160,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn conversion_keeps_the_timestamp() {
+        let transaction = Transaction {
+            kind: TransactionKind::Deposit,
+            id: 1,
+            client_id: 1,
+            amount: Some(dec!(1.0000)),
+            timestamp: Some(1_700_000_000),
+            locked: None,
+        };
+
+        let engine_transaction: EngineTransaction = transaction.into();
+
+        assert_eq!(
+            engine_transaction,
+            EngineTransaction::deposit(1, 1, dec!(1.0000)).with_timestamp(Some(1_700_000_000))
+        );
+    }
+
     #[test]
     fn conversion_to_withdrawal_works() {
         let transaction = Transaction {
@@ -109,6 +186,8 @@ mod tests {
             id: 1,
             client_id: 1,
             amount: Some(dec!(1.0000)),
+            timestamp: None,
+            locked: None,
         };
 
         let engine_transaction: EngineTransaction = transaction.clone().into();
@@ -126,6 +205,8 @@ mod tests {
             id: 1,
             client_id: 1,
             amount: None,
+            timestamp: None,
+            locked: None,
         };
 
         let engine_transaction: EngineTransaction = transaction.clone().into();
@@ -143,6 +224,8 @@ mod tests {
             id: 1,
             client_id: 1,
             amount: None,
+            timestamp: None,
+            locked: None,
         };
 
         let resolve = Transaction {
@@ -150,6 +233,8 @@ mod tests {
             id: 1,
             client_id: 1,
             amount: None,
+            timestamp: None,
+            locked: None,
         };
 
         let chargeback = Transaction {
@@ -157,11 +242,23 @@ mod tests {
             id: 1,
             client_id: 1,
             amount: None,
+            timestamp: None,
+            locked: None,
+        };
+
+        let review = Transaction {
+            kind: TransactionKind::Review,
+            id: 1,
+            client_id: 1,
+            amount: None,
+            timestamp: None,
+            locked: None,
         };
 
         let engine_dispute: EngineTransaction = dispute.clone().into();
         let engine_resolve: EngineTransaction = resolve.clone().into();
         let engine_chargeback: EngineTransaction = chargeback.clone().into();
+        let engine_review: EngineTransaction = review.clone().into();
 
         assert_eq!(
             engine_dispute,
@@ -177,5 +274,10 @@ mod tests {
             engine_chargeback,
             EngineTransaction::chargeback(chargeback.id, chargeback.client_id),
         );
+
+        assert_eq!(
+            engine_review,
+            EngineTransaction::review(review.id, review.client_id),
+        );
     }
 }
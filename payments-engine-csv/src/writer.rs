@@ -1,21 +1,231 @@
+use async_compression::tokio::write::GzipEncoder;
 use futures::StreamExt;
-use payments_engine_core::account::Account;
+use payments_engine_core::{
+    account::{Account, MAX_DISPLAY_PRECISION},
+    common::{Amount, ClientId},
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
 use tracing::instrument;
 
 pub type AsyncWriter = dyn tokio::io::AsyncWrite + Send + Sync + Unpin;
 
+/// Order of the [`Account`] balances written by [`write_csv_async_with_options`].
+/// Accounts with the same total are sorted by client, so the order is deterministic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortBy {
+    /// Sorted by client id.
+    ClientId,
+    /// Largest totals first.
+    TotalDesc,
+    /// Smallest totals first.
+    TotalAsc,
+}
+
+impl SortBy {
+    /// Sorts the given accounts in this order.
+    fn sort(self, accounts: &mut [Account]) {
+        match self {
+            Self::ClientId => accounts.sort_unstable_by_key(|account| account.client),
+            Self::TotalDesc => accounts.sort_unstable_by(|a, b| {
+                b.total.cmp(&a.total).then_with(|| a.client.cmp(&b.client))
+            }),
+            Self::TotalAsc => accounts.sort_unstable_by(|a, b| {
+                a.total.cmp(&b.total).then_with(|| a.client.cmp(&b.client))
+            }),
+        }
+    }
+}
+
+/// Options to customize the behavior of [`write_csv_async_with_options`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriterOptions {
+    /// Emits the `total` as the freshly computed `available + held` instead of the stored value.
+    /// This is a safety net against drift bugs. The stored value is used by default.
+    pub computed_total: bool,
+    /// Decimal places of the amounts. [`MAX_DISPLAY_PRECISION`] by default.
+    pub precision: u32,
+    /// Pads the amounts with zeros up to the `precision`.
+    /// By default, amounts are only rounded, so `1.5` is written as `1.5` instead of `1.5000`.
+    pub pad_precision: bool,
+    /// Field delimiter, e.g. `b'\t'` for tab-separated output. Comma by default.
+    pub delimiter: u8,
+    /// Sorts the balances before writing them. They're written in the order of the stream by default.
+    ///
+    /// Note that sorting requires keeping all the accounts in memory, while they're streamed one by one otherwise.
+    pub sort_by: Option<SortBy>,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            computed_total: false,
+            precision: MAX_DISPLAY_PRECISION,
+            pad_precision: false,
+            delimiter: b',',
+            sort_by: None,
+        }
+    }
+}
+
 /// Writes a CSV asynchronously with information about the [`Account`] balances.
+/// The writer is flushed once all the accounts have been written, and flushing errors are returned.
 #[instrument(skip(writer, account_stream))]
 pub async fn write_csv_async(
     writer: &mut AsyncWriter,
+    account_stream: impl futures::Stream<Item = Account> + Send + Unpin,
+) -> anyhow::Result<()> {
+    write_csv_async_with_options(writer, account_stream, WriterOptions::default()).await
+}
+
+/// Writes a CSV asynchronously with information about the [`Account`] balances using the given [`WriterOptions`].
+/// The writer is flushed once all the accounts have been written.
+/// If [`WriterOptions::sort_by`] is set, the whole stream is buffered in order to sort it.
+#[instrument(skip(writer, account_stream))]
+pub async fn write_csv_async_with_options(
+    writer: &mut AsyncWriter,
+    account_stream: impl futures::Stream<Item = Account> + Send + Unpin,
+    options: WriterOptions,
+) -> anyhow::Result<()> {
+    match options.sort_by {
+        Some(sort_by) => {
+            let mut accounts = account_stream.collect::<Vec<_>>().await;
+            sort_by.sort(&mut accounts);
+            serialize_accounts(writer, futures::stream::iter(accounts), options).await
+        }
+        None => serialize_accounts(writer, account_stream, options).await,
+    }
+}
+
+/// Writes a CSV asynchronously with the [`Account`] balances sorted by client using the given [`WriterOptions`],
+/// and returns the hex encoded SHA-256 hash of the written CSV, e.g. to verify the report between systems.
+/// As the balances are sorted, the hash doesn't depend on the order of the stream.
+///
+/// Note that all the accounts are kept in memory in order to sort them.
+#[instrument(skip(writer, account_stream))]
+pub async fn write_csv_async_with_hash(
+    writer: &mut AsyncWriter,
+    account_stream: impl futures::Stream<Item = Account> + Send + Unpin,
+    options: WriterOptions,
+) -> anyhow::Result<String> {
+    let mut accounts = account_stream.collect::<Vec<_>>().await;
+    accounts.sort_by_key(|account| account.client);
+
+    let mut csv = Vec::new();
+    serialize_accounts(&mut csv, futures::stream::iter(accounts), options).await?;
+    writer.write_all(&csv).await?;
+    writer.flush().await?;
+
+    let hash = Sha256::digest(&csv)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    Ok(hash)
+}
+
+/// Projection of an [`Account`] with its available balance.
+#[derive(Debug, Serialize)]
+struct AvailableBalance {
+    client: ClientId,
+    available: Amount,
+    locked: bool,
+}
+
+/// Projection of an [`Account`] with its held balance.
+#[derive(Debug, Serialize)]
+struct HeldBalance {
+    client: ClientId,
+    held: Amount,
+}
+
+/// Writes the [`Account`] balances asynchronously as two separate CSVs:
+/// one with `client,available,locked` and another one with `client,held`.
+#[instrument(skip(available_writer, held_writer, account_stream))]
+pub async fn write_split_csv_async(
+    available_writer: &mut AsyncWriter,
+    held_writer: &mut AsyncWriter,
     mut account_stream: impl futures::Stream<Item = Account> + Send + Unpin,
 ) -> anyhow::Result<()> {
-    let mut writer = csv_async::AsyncSerializer::from_writer(writer);
+    let mut available_writer = csv_async::AsyncSerializer::from_writer(available_writer);
+    let mut held_writer = csv_async::AsyncSerializer::from_writer(held_writer);
 
     while let Some(mut account) = account_stream.next().await {
         account.to_max_display_precision();
+        available_writer
+            .serialize(AvailableBalance {
+                client: account.client,
+                available: account.available,
+                locked: account.locked,
+            })
+            .await?;
+        held_writer
+            .serialize(HeldBalance {
+                client: account.client,
+                held: account.held,
+            })
+            .await?;
+    }
+    available_writer.flush().await?;
+    held_writer.flush().await?;
+
+    Ok(())
+}
+
+/// Writes a gzipped CSV asynchronously with information about the [`Account`] balances.
+/// The encoder is finalized once all the accounts have been written.
+#[instrument(skip(writer, account_stream))]
+pub async fn write_csv_gz_async(
+    writer: &mut AsyncWriter,
+    account_stream: impl futures::Stream<Item = Account> + Send + Unpin,
+) -> anyhow::Result<()> {
+    let mut encoder = GzipEncoder::new(writer);
+    serialize_accounts(&mut encoder, account_stream, WriterOptions::default()).await?;
+    encoder.shutdown().await?;
+    Ok(())
+}
+
+/// Writes the [`Account`] balances asynchronously as newline-delimited JSON, one object per account.
+/// The field names are the same as in the CSV output.
+#[instrument(skip(writer, account_stream))]
+pub async fn write_json_async(
+    writer: &mut AsyncWriter,
+    mut account_stream: impl futures::Stream<Item = Account> + Send + Unpin,
+) -> anyhow::Result<()> {
+    while let Some(mut account) = account_stream.next().await {
+        account.to_max_display_precision();
+        let mut line = serde_json::to_vec(&account)?;
+        line.push(b'\n');
+        writer.write_all(&line).await?;
+    }
+    writer.flush().await?;
+
+    Ok(())
+}
+
+/// Serializes the [`Account`] balances as CSV into any async writer, flushing it at the end.
+/// The serializer only flushes on drop by blocking the thread and ignoring the errors, so truncated outputs would go unnoticed.
+async fn serialize_accounts(
+    writer: impl tokio::io::AsyncWrite + Send + Unpin,
+    mut account_stream: impl futures::Stream<Item = Account> + Send + Unpin,
+    options: WriterOptions,
+) -> anyhow::Result<()> {
+    let mut writer = csv_async::AsyncWriterBuilder::new()
+        .delimiter(options.delimiter)
+        .create_serializer(writer);
+
+    while let Some(mut account) = account_stream.next().await {
+        if options.computed_total {
+            account.total = account.available + account.held;
+        }
+        if options.pad_precision {
+            account.to_padded_display_precision(options.precision);
+        } else {
+            account.to_display_precision(options.precision);
+        }
         writer.serialize(account).await?;
     }
+    writer.flush().await?;
 
     Ok(())
 }
@@ -23,8 +233,93 @@ pub async fn write_csv_async(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use async_compression::tokio::bufread::GzipDecoder;
     use payments_engine_core::dec;
-    use tokio::io::BufWriter;
+    use std::{
+        io,
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio::io::{AsyncReadExt, AsyncWrite, BufWriter};
+
+    /// Writer which only exposes the written bytes once they're flushed, like a network socket.
+    #[derive(Default)]
+    struct FlushedWriter {
+        pending: Vec<u8>,
+        flushed: Vec<u8>,
+    }
+
+    impl AsyncWrite for FlushedWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            self.pending.extend_from_slice(buf);
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            let pending = std::mem::take(&mut self.pending);
+            self.flushed.extend(pending);
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.poll_flush(cx)
+        }
+    }
+
+    /// Writer whose flushes always fail.
+    struct FailingFlushWriter;
+
+    impl AsyncWrite for FailingFlushWriter {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "broken pipe",
+            )))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.poll_flush(cx)
+        }
+    }
+
+    #[tokio::test]
+    async fn writes_csv_async_flushes_the_writer() {
+        let input = (1..=500)
+            .map(|client| Account::seeded(client, dec!(1.5), dec!(0), false))
+            .collect::<Vec<_>>();
+        let mut writer = FlushedWriter::default();
+
+        write_csv_async(&mut writer, futures::stream::iter(input))
+            .await
+            .unwrap();
+
+        let csv = String::from_utf8(writer.flushed).unwrap();
+        assert!(writer.pending.is_empty());
+        assert_eq!(csv.lines().count(), 501);
+        assert!(csv.starts_with("client,available,held,total,locked\n1,1.5,0,1.5,false\n"));
+        assert!(csv.ends_with("500,1.5,0,1.5,false\n"));
+    }
+
+    #[tokio::test]
+    async fn writes_csv_async_returns_the_flush_errors() {
+        let input = vec![Account::seeded(1, dec!(1.5), dec!(0), false)];
+
+        let result = write_csv_async(&mut FailingFlushWriter, futures::stream::iter(input)).await;
+
+        assert!(result.is_err());
+    }
 
     #[tokio::test]
     async fn writes_csv_async_ok() {
@@ -86,4 +381,305 @@ mod tests {
             "client,available,held,total,locked\n1,23.2320,1.0,24.2320,false\n"
         );
     }
+
+    #[tokio::test]
+    async fn writes_stored_total_by_default() {
+        let mut drifted = Account::seeded(1, dec!(10), dec!(5), false);
+        drifted.total = dec!(20);
+        let account_stream = futures::stream::iter(vec![drifted]);
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let result = write_csv_async(&mut writer, account_stream).await;
+
+        assert!(result.is_ok());
+
+        let buffer = writer.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+
+        assert_eq!(csv, "client,available,held,total,locked\n1,10,5,20,false\n");
+    }
+
+    /// Writes a few accounts, two of them with the same total, with the given [`SortBy`] and returns their clients.
+    async fn written_clients(sort_by: Option<SortBy>) -> Vec<String> {
+        let input = vec![
+            Account::seeded(3, dec!(5), dec!(0), false),
+            Account::seeded(1, dec!(10), dec!(0), false),
+            Account::seeded(4, dec!(1), dec!(4), false),
+            Account::seeded(2, dec!(20), dec!(0), false),
+        ];
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        let options = WriterOptions {
+            sort_by,
+            ..WriterOptions::default()
+        };
+
+        write_csv_async_with_options(&mut writer, futures::stream::iter(input), options)
+            .await
+            .unwrap();
+
+        String::from_utf8(writer.into_inner())
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn writes_csv_async_in_stream_order_by_default() {
+        assert_eq!(written_clients(None).await, vec!["3", "1", "4", "2"]);
+    }
+
+    #[tokio::test]
+    async fn writes_csv_async_sorted_by_client_id() {
+        assert_eq!(
+            written_clients(Some(SortBy::ClientId)).await,
+            vec!["1", "2", "3", "4"]
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_csv_async_sorted_by_descending_total() {
+        assert_eq!(
+            written_clients(Some(SortBy::TotalDesc)).await,
+            vec!["2", "1", "3", "4"]
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_csv_async_sorted_by_ascending_total() {
+        assert_eq!(
+            written_clients(Some(SortBy::TotalAsc)).await,
+            vec!["3", "4", "1", "2"]
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_computed_total_if_enabled() {
+        let mut drifted = Account::seeded(1, dec!(10), dec!(5), false);
+        drifted.total = dec!(20);
+        let account_stream = futures::stream::iter(vec![drifted]);
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        let options = WriterOptions {
+            computed_total: true,
+            ..WriterOptions::default()
+        };
+
+        let result = write_csv_async_with_options(&mut writer, account_stream, options).await;
+
+        assert!(result.is_ok());
+
+        let buffer = writer.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+
+        assert_eq!(csv, "client,available,held,total,locked\n1,10,5,15,false\n");
+    }
+
+    #[tokio::test]
+    async fn writes_csv_async_with_custom_precision() {
+        let input = vec![Account::seeded(1, dec!(23.23256), dec!(1.5), false)];
+        let options = WriterOptions {
+            precision: 2,
+            ..WriterOptions::default()
+        };
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let result =
+            write_csv_async_with_options(&mut writer, futures::stream::iter(input), options).await;
+
+        assert!(result.is_ok());
+
+        let buffer = writer.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+
+        assert_eq!(
+            csv,
+            "client,available,held,total,locked\n1,23.23,1.5,24.73,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_csv_async_with_padded_precision() {
+        let input = vec![Account::seeded(1, dec!(23.23256), dec!(1.5), false)];
+        let options = WriterOptions {
+            precision: 8,
+            pad_precision: true,
+            ..WriterOptions::default()
+        };
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let result =
+            write_csv_async_with_options(&mut writer, futures::stream::iter(input), options).await;
+
+        assert!(result.is_ok());
+
+        let buffer = writer.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+
+        assert_eq!(
+            csv,
+            "client,available,held,total,locked\n1,23.23256000,1.50000000,24.73256000,false\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_tab_separated_csv_async() {
+        let input = vec![
+            Account::seeded(1, dec!(23.23256), dec!(1.5), false),
+            Account::seeded(2, dec!(4), Amount::ZERO, true),
+        ];
+        let options = WriterOptions {
+            delimiter: b'\t',
+            ..WriterOptions::default()
+        };
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let result =
+            write_csv_async_with_options(&mut writer, futures::stream::iter(input), options).await;
+
+        assert!(result.is_ok());
+
+        let buffer = writer.into_inner();
+        let csv = String::from_utf8_lossy(&buffer);
+
+        assert_eq!(
+            csv,
+            "client\tavailable\theld\ttotal\tlocked\n1\t23.2326\t1.5\t24.7326\tfalse\n2\t4\t0\t4\ttrue\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn hash_of_the_report_does_not_depend_on_the_order_of_the_accounts() {
+        let accounts = vec![
+            Account::seeded(2, dec!(4.0), dec!(2.2101), true),
+            Account::seeded(1, dec!(23.2320), dec!(0.0000), false),
+            Account::seeded(3, dec!(1), dec!(0), false),
+        ];
+        let mut reversed = accounts.clone();
+        reversed.reverse();
+
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+        let hash = write_csv_async_with_hash(
+            &mut writer,
+            futures::stream::iter(accounts.clone()),
+            WriterOptions::default(),
+        )
+        .await
+        .unwrap();
+        let mut reversed_writer = BufWriter::new(Vec::<u8>::new());
+        let reversed_hash = write_csv_async_with_hash(
+            &mut reversed_writer,
+            futures::stream::iter(reversed),
+            WriterOptions::default(),
+        )
+        .await
+        .unwrap();
+
+        let buffer = writer.into_inner();
+        assert_eq!(
+            String::from_utf8_lossy(&buffer),
+            "client,available,held,total,locked\n1,23.2320,0.0000,23.2320,false\n2,4.0,2.2101,6.2101,true\n3,1,0,1,false\n"
+        );
+        assert_eq!(buffer, reversed_writer.into_inner());
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, reversed_hash);
+
+        let mut other_writer = BufWriter::new(Vec::<u8>::new());
+        let other_hash = write_csv_async_with_hash(
+            &mut other_writer,
+            futures::stream::iter(accounts.into_iter().skip(1)),
+            WriterOptions::default(),
+        )
+        .await
+        .unwrap();
+        assert_ne!(hash, other_hash);
+    }
+
+    #[tokio::test]
+    async fn writes_gzipped_csv_async_ok() {
+        let input = vec![
+            Account::seeded(1, dec!(23.2320), dec!(0.0000), false),
+            Account::seeded(2, dec!(4.0), dec!(2.2101), true),
+        ];
+        let mut plain_writer = BufWriter::new(Vec::<u8>::new());
+        let mut gz_writer = BufWriter::new(Vec::<u8>::new());
+
+        write_csv_async(&mut plain_writer, futures::stream::iter(input.clone()))
+            .await
+            .unwrap();
+        let result = write_csv_gz_async(&mut gz_writer, futures::stream::iter(input)).await;
+
+        assert!(result.is_ok());
+
+        let gzipped = gz_writer.into_inner();
+        let mut decoder = GzipDecoder::new(gzipped.as_slice());
+        let mut csv = String::new();
+        decoder.read_to_string(&mut csv).await.unwrap();
+
+        assert_eq!(csv, String::from_utf8_lossy(&plain_writer.into_inner()));
+    }
+
+    #[tokio::test]
+    async fn writes_json_async_ok() {
+        let input = vec![
+            Account::seeded(1, dec!(23.23201), dec!(0.0000), false),
+            Account::seeded(2, dec!(4.0), dec!(2.2101), true),
+        ];
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let result = write_json_async(&mut writer, futures::stream::iter(input)).await;
+
+        assert!(result.is_ok());
+
+        let buffer = writer.into_inner();
+        let json = String::from_utf8_lossy(&buffer);
+
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"client":1,"available":"23.2320","held":"0.0000","total":"23.2320","locked":false}"#,
+                "\n",
+                r#"{"client":2,"available":"4.0","held":"2.2101","total":"6.2101","locked":true}"#,
+                "\n"
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn writes_empty_json_if_no_accounts() {
+        let mut writer = BufWriter::new(Vec::<u8>::new());
+
+        let result = write_json_async(&mut writer, futures::stream::empty()).await;
+
+        assert!(result.is_ok());
+        assert!(writer.into_inner().is_empty());
+    }
+
+    #[tokio::test]
+    async fn writes_split_csv_async_ok() {
+        let input = vec![
+            Account::seeded(1, dec!(23.23201), dec!(1.5), false),
+            Account::seeded(2, dec!(4.0), dec!(0), true),
+        ];
+        let mut available_writer = BufWriter::new(Vec::<u8>::new());
+        let mut held_writer = BufWriter::new(Vec::<u8>::new());
+
+        let result = write_split_csv_async(
+            &mut available_writer,
+            &mut held_writer,
+            futures::stream::iter(input),
+        )
+        .await;
+
+        assert!(result.is_ok());
+
+        let available = available_writer.into_inner();
+        let held = held_writer.into_inner();
+
+        assert_eq!(
+            String::from_utf8_lossy(&available),
+            "client,available,locked\n1,23.2320,false\n2,4.0,true\n"
+        );
+        assert_eq!(String::from_utf8_lossy(&held), "client,held\n1,1.5\n2,0\n");
+    }
 }
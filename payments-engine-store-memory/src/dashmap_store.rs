@@ -0,0 +1,559 @@
+use async_trait::async_trait;
+use dashmap::{mapref::entry::Entry, DashMap, DashSet};
+use payments_engine_core::{
+    account::Account,
+    common::{Amount, ClientId},
+    store::{DisputeMismatch, Store, StoreError, StoreResult, UpsertOutcome},
+    transaction::{DisputeState, Transaction, TransactionId},
+};
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+use tracing::instrument;
+
+/// In-Memory implementation of the Store trait backed by sharded [`DashMap`] instead of a single [`std::sync::RwLock`] per map.
+/// Concurrent clients only contend when their entries fall in the same shard,
+/// which makes it a better fit than [`MemoryStore`](crate::MemoryStore) when many clients are processed at once.
+///
+/// Like [`MemoryStore`](crate::MemoryStore), cloning a [`DashMapStore`] shares the same underlying maps.
+///
+/// # Important
+/// This store only cares about [`Transaction::Deposit`] transactions so all the other variants are not really stored.
+/// Storing [`Transaction::Withdrawal`] transactions too can be enabled with [`DashMapStore::storing_withdrawals`].
+/// Otherwise, only the ids of the withdrawals are kept, so replayed withdrawals are rejected too.
+/// The ids of adjustments and account openings and closings are kept as well, so they can't be replayed either.
+///
+/// Reads spanning several maps, like [`Store::get_account_and_transaction`], lock each shard independently,
+/// so they're not a consistent snapshot of the whole store.
+#[derive(Debug, Default, Clone)]
+pub struct DashMapStore(Arc<DashMapInner>);
+
+#[derive(Debug, Default)]
+struct DashMapInner {
+    deposits: DashMap<TransactionId, Transaction>,
+    accounts: DashMap<ClientId, Account>,
+    dispute_counts: DashMap<TransactionId, u32>,
+    pending: DashMap<TransactionId, Transaction>,
+    /// ids of the transactions which are not stored, e.g. withdrawals or adjustments, to reject duplicates.
+    reserved_ids: DashSet<TransactionId>,
+    /// number of deposits and withdrawals under dispute by client,
+    /// updated along with the disputed state of the transactions.
+    open_disputes: DashMap<ClientId, u32>,
+    store_withdrawals: bool,
+}
+
+impl DashMapInner {
+    /// Updates the open disputes of the client when one of its transactions gets in or out of dispute.
+    fn track_open_disputes(&self, client: ClientId, was: bool, is: bool) {
+        if was == is {
+            return;
+        }
+        let mut count = self.open_disputes.entry(client).or_default();
+        if is {
+            *count += 1;
+        } else {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    /// Drops the stored transaction, if any, keeping the open disputes of its client up to date.
+    fn remove_deposit(&self, id: TransactionId) {
+        if let Some((_, transaction)) = self.deposits.remove(&id) {
+            self.track_open_disputes(
+                transaction.info().client_id,
+                transaction.is_under_dispute(),
+                false,
+            );
+        }
+    }
+}
+
+impl DashMapStore {
+    /// Creates a new [`DashMapStore`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a new [`DashMapStore`] that stores [`Transaction::Withdrawal`] transactions along with the deposits,
+    /// so they can be looked up later, e.g. to dispute them.
+    #[must_use]
+    pub fn storing_withdrawals() -> Self {
+        Self(Arc::new(DashMapInner {
+            store_withdrawals: true,
+            ..DashMapInner::default()
+        }))
+    }
+}
+
+#[async_trait]
+impl Store for DashMapStore {
+    /// Gets a transaction by its id.
+    /// If it doesn't exist, it returns an [`StoreError::NotFound].
+    #[instrument(skip(self))]
+    async fn get_transaction(&self, id: TransactionId) -> StoreResult<Transaction> {
+        tracing::debug!("Getting transaction {}", id);
+        let result = self
+            .0
+            .deposits
+            .get(&id)
+            .map(|deposit| deposit.clone())
+            .ok_or(StoreError::NotFound { id });
+
+        if result.is_err() {
+            tracing::error!("Error while getting transaction: {:?}", result);
+        }
+
+        result
+    }
+
+    /// Creates a new [`Transaction`] and returns it.
+    /// If the [`Transaction`] already exists, it returns an [`StoreError::AlreadyExists`].
+    /// Withdrawals are stored too if the store was created with [`DashMapStore::storing_withdrawals`].
+    #[instrument(skip(self))]
+    async fn create_transaction(&self, transaction: Transaction) -> StoreResult<Transaction> {
+        tracing::debug!("Creating transaction: {:?}", transaction);
+        let is_stored = match transaction {
+            Transaction::Deposit { .. } => true,
+            Transaction::Withdrawal { .. } => self.0.store_withdrawals,
+            _ => false,
+        };
+        let transaction_id = transaction.info().id;
+        if transaction.references_transaction() {
+            return Ok(transaction);
+        }
+        if !is_stored {
+            // the id is reserved first, so two concurrent transactions can't both get it
+            let reserved = self.0.reserved_ids.insert(transaction_id);
+            if reserved && !self.0.deposits.contains_key(&transaction_id) {
+                return Ok(transaction);
+            }
+            if reserved {
+                self.0.reserved_ids.remove(&transaction_id);
+            }
+            tracing::error!("Transaction {} already exists", transaction_id);
+            return Err(StoreError::AlreadyExists { id: transaction_id });
+        }
+        match self.0.deposits.entry(transaction_id) {
+            Entry::Vacant(e) if !self.0.reserved_ids.contains(&transaction_id) => {
+                self.0.track_open_disputes(
+                    transaction.info().client_id,
+                    false,
+                    transaction.is_under_dispute(),
+                );
+                e.insert(transaction.clone());
+                Ok(transaction)
+            }
+            _ => {
+                tracing::error!("Transaction {} already exists", transaction_id);
+                Err(StoreError::AlreadyExists { id: transaction_id })
+            }
+        }
+    }
+
+    /// Deletes a [`Transaction`].
+    #[instrument(skip(self))]
+    async fn delete_transaction(&self, id: TransactionId) -> StoreResult<()> {
+        tracing::debug!("Deleting transaction: {:?}", id);
+        self.0.remove_deposit(id);
+        self.0.reserved_ids.remove(&id);
+        self.0.dispute_counts.remove(&id);
+        Ok(())
+    }
+
+    /// Drops the stored [`Transaction`], if any, but keeps its id reserved.
+    #[instrument(skip(self))]
+    async fn reserve_transaction_id(&self, id: TransactionId) -> StoreResult<()> {
+        tracing::debug!("Reserving transaction id: {:?}", id);
+        // the id is reserved first, so it's never free in between
+        self.0.reserved_ids.insert(id);
+        self.0.remove_deposit(id);
+        self.0.dispute_counts.remove(&id);
+        Ok(())
+    }
+
+    /// Sets the [`DisputeState`] of a [`Transaction`].
+    /// Every time a [`Transaction`] gets into [`DisputeState::Open`], its dispute counter is incremented.
+    #[instrument(skip(self))]
+    async fn set_dispute_state(&self, id: TransactionId, state: DisputeState) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} dispute state to {:?}", id, state);
+        if let Some(mut transaction) = self.0.deposits.get_mut(&id) {
+            if state == DisputeState::Open && transaction.dispute_state() != Some(state) {
+                *self.0.dispute_counts.entry(id).or_default() += 1;
+            }
+            self.0.track_open_disputes(
+                transaction.info().client_id,
+                transaction.is_under_dispute(),
+                state.is_under_dispute(),
+            );
+            transaction.set_dispute_state(state);
+        }
+        Ok(())
+    }
+
+    /// Sets the [`Amount`] held by a partial dispute of a [`Transaction`], or [`None`] if the whole amount is disputed.
+    #[instrument(skip(self))]
+    async fn set_disputed_amount(
+        &self,
+        id: TransactionId,
+        amount: Option<Amount>,
+    ) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} disputed amount to {:?}", id, amount);
+        if let Some(mut transaction) = self.0.deposits.get_mut(&id) {
+            transaction.set_disputed_amount(amount);
+        }
+        Ok(())
+    }
+
+    /// Sets the [`Amount`] of a stored [`Transaction`] in place.
+    #[instrument(skip(self))]
+    async fn set_transaction_amount(&self, id: TransactionId, amount: Amount) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} amount to {}", id, amount);
+        if let Some(mut transaction) = self.0.deposits.get_mut(&id) {
+            if let Some(stored) = transaction.amount_mut() {
+                *stored = amount;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns how many times a [`Transaction`] has been disputed.
+    /// If the [`Transaction`] doesn't exist, it returns an [`StoreError::NotFound`].
+    #[instrument(skip(self))]
+    async fn dispute_count(&self, id: TransactionId) -> StoreResult<u32> {
+        tracing::debug!("Getting dispute count of transaction {}", id);
+        if !self.0.deposits.contains_key(&id) {
+            tracing::error!("Transaction {} not found", id);
+            return Err(StoreError::NotFound { id });
+        }
+        Ok(self
+            .0
+            .dispute_counts
+            .get(&id)
+            .map(|count| *count)
+            .unwrap_or_default())
+    }
+
+    /// Keeps a [`Transaction`] that was rejected but should be retained for later reconciliation.
+    #[instrument(skip(self))]
+    async fn add_pending_transaction(&self, transaction: Transaction) -> StoreResult<()> {
+        tracing::debug!("Adding pending transaction: {:?}", transaction);
+        self.0.pending.insert(transaction.info().id, transaction);
+        Ok(())
+    }
+
+    /// Returns the pending [`Transaction`], sorted by id.
+    #[instrument(skip(self))]
+    async fn get_pending_transactions(&self) -> StoreResult<Vec<Transaction>> {
+        let mut pending = self
+            .0
+            .pending
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|transaction| transaction.info().id);
+        Ok(pending)
+    }
+
+    /// Returns the stored [`Transaction`] of the client, sorted by id.
+    #[instrument(skip(self))]
+    async fn get_transactions_for_client(&self, client: ClientId) -> StoreResult<Vec<Transaction>> {
+        tracing::debug!("Getting transactions of client {}", client);
+        let mut transactions = self
+            .0
+            .deposits
+            .iter()
+            .filter(|entry| entry.value().info().client_id == client)
+            .map(|entry| entry.value().clone())
+            .collect::<Vec<_>>();
+        transactions.sort_by_key(|transaction| transaction.info().id);
+        Ok(transactions)
+    }
+
+    /// Gets the current state of the [`Account`], or [`None`] if it doesn't exist.
+    #[instrument(skip(self))]
+    async fn get_account(&self, id: ClientId) -> StoreResult<Option<Account>> {
+        tracing::debug!("Getting account: {}", id);
+        Ok(self.0.accounts.get(&id).map(|account| account.clone()))
+    }
+
+    /// Gets the current state of the [`Account`] and the [`Transaction`] with the given id.
+    #[instrument(skip(self))]
+    async fn get_account_and_transaction(
+        &self,
+        client: ClientId,
+        id: TransactionId,
+    ) -> StoreResult<(Account, Option<Transaction>)> {
+        tracing::debug!("Getting account {} and transaction {}", client, id);
+        let account = self.get_or_default_account(client).await?;
+        let transaction = self.0.deposits.get(&id).map(|deposit| deposit.clone());
+        Ok((account, transaction))
+    }
+
+    /// Updates the state of the [`Account`].
+    /// If the [`Account`] does not exist, it will create the [`Account`].
+    /// Returns whether the [`Account`] was created or updated.
+    #[instrument(skip(self))]
+    async fn upsert_account(&self, account: &Account) -> StoreResult<UpsertOutcome> {
+        tracing::debug!("Upserting account: {:?}", account);
+        Ok(
+            match self.0.accounts.insert(account.client, account.clone()) {
+                Some(_) => UpsertOutcome::Updated,
+                None => UpsertOutcome::Created,
+            },
+        )
+    }
+
+    /// Returns the current state of clients accounts, sorted by client.
+    #[instrument(skip(self))]
+    async fn get_all_accounts(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>> {
+        let mut accounts = self
+            .0
+            .accounts
+            .iter()
+            .map(|entry| entry.value().clone())
+            .collect::<Vec<_>>();
+        accounts.sort_unstable_by_key(|account| account.client);
+        Ok(Box::pin(futures::stream::iter(accounts)))
+    }
+
+    /// Returns the ids of all the clients with an [`Account`], sorted.
+    #[instrument(skip(self))]
+    async fn get_client_ids(&self) -> StoreResult<Vec<ClientId>> {
+        tracing::debug!("Getting client ids");
+        let mut client_ids = self
+            .0
+            .accounts
+            .iter()
+            .map(|entry| *entry.key())
+            .collect::<Vec<_>>();
+        client_ids.sort_unstable();
+        Ok(client_ids)
+    }
+
+    /// Returns the deposits and withdrawals which are currently under dispute, sorted by id.
+    #[instrument(skip(self))]
+    async fn get_disputed_transactions(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Transaction> + Send>>> {
+        let mut disputed = self
+            .0
+            .deposits
+            .iter()
+            .filter(|entry| entry.value().is_under_dispute())
+            .map(|entry| entry.value().clone())
+            .collect::<Vec<_>>();
+        disputed.sort_by_key(|deposit| deposit.info().id);
+        Ok(Box::pin(futures::stream::iter(disputed)))
+    }
+
+    /// Returns how many deposits and withdrawals of the client are currently under dispute.
+    /// They're counted as their dispute state changes, so nothing is scanned.
+    #[instrument(skip(self))]
+    async fn open_disputes_count(&self, client: ClientId) -> StoreResult<u32> {
+        tracing::debug!("Counting open disputes of client {}", client);
+        Ok(self
+            .0
+            .open_disputes
+            .get(&client)
+            .map(|count| *count)
+            .unwrap_or_default())
+    }
+
+    /// Checks that the held funds of every [`Account`] equal the sum of its under dispute deposits and withdrawals.
+    #[instrument(skip(self))]
+    async fn verify_dispute_consistency(&self) -> StoreResult<Vec<DisputeMismatch>> {
+        tracing::debug!("Verifying dispute consistency");
+        let mut disputed: HashMap<ClientId, Amount> = HashMap::new();
+        self.0
+            .deposits
+            .iter()
+            .filter(|entry| entry.value().is_under_dispute())
+            .for_each(|entry| {
+                if let Some(amount) = entry.value().disputed_amount() {
+                    *disputed.entry(entry.value().info().client_id).or_default() += amount;
+                }
+            });
+
+        let held = self
+            .0
+            .accounts
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().held))
+            .collect::<HashMap<_, _>>();
+
+        let mut clients = held
+            .keys()
+            .chain(disputed.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        clients.sort_unstable();
+        clients.dedup();
+
+        let mismatches = clients
+            .into_iter()
+            .filter_map(|client| {
+                let held = held.get(&client).copied().unwrap_or_default();
+                let disputed = disputed.get(&client).copied().unwrap_or_default();
+                (held != disputed).then_some(DisputeMismatch {
+                    client,
+                    held,
+                    disputed,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !mismatches.is_empty() {
+            tracing::error!("Inconsistent disputes found: {:?}", mismatches);
+        }
+
+        Ok(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use rust_decimal::Decimal;
+
+    #[tokio::test]
+    async fn create_transaction_fails_if_it_already_exists() {
+        let store = DashMapStore::new();
+        store
+            .create_transaction(Transaction::deposit(1, 1, Decimal::ONE))
+            .await
+            .unwrap();
+        let result = store
+            .create_transaction(Transaction::deposit(1, 1, Decimal::ONE))
+            .await;
+        assert_eq!(result, Err(StoreError::AlreadyExists { id: 1 }));
+    }
+
+    #[tokio::test]
+    async fn create_transaction_fails_if_an_adjustment_already_exists() {
+        let store = DashMapStore::new();
+        let adjustment = Transaction::adjustment(1, 1, Decimal::ONE, "fee refund");
+        store.create_transaction(adjustment.clone()).await.unwrap();
+
+        assert_eq!(
+            store.create_transaction(adjustment).await,
+            Err(StoreError::AlreadyExists { id: 1 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::open_account(1, 2, false))
+                .await,
+            Err(StoreError::AlreadyExists { id: 1 })
+        );
+        assert_eq!(
+            store.get_transaction(1).await,
+            Err(StoreError::NotFound { id: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn get_all_accounts_are_sorted_by_client() {
+        let store = DashMapStore::new();
+        for client in [3, 1, 2] {
+            store.upsert_account(&Account::new(client)).await.unwrap();
+        }
+
+        let result = store
+            .get_all_accounts()
+            .await
+            .unwrap()
+            .map(|account| account.client)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn upsert_account_reports_the_outcome() {
+        let store = DashMapStore::new();
+        let account = Account::new(1);
+        assert_eq!(
+            store.upsert_account(&account).await,
+            Ok(UpsertOutcome::Created)
+        );
+        assert_eq!(
+            store.upsert_account(&account).await,
+            Ok(UpsertOutcome::Updated)
+        );
+        assert_eq!(store.get_account(1).await, Ok(Some(account)));
+        assert_eq!(store.get_account(2).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn open_disputes_count_stays_in_sync_with_the_dispute_states() {
+        let store = DashMapStore::new();
+        for (id, client) in [(1, 1), (2, 1), (3, 2)] {
+            store
+                .create_transaction(Transaction::deposit(id, client, Decimal::ONE))
+                .await
+                .unwrap();
+        }
+        store
+            .create_transaction(Transaction::deposit_under_dispute(4, 1, Decimal::ONE))
+            .await
+            .unwrap();
+        assert_eq!(store.open_disputes_count(1).await, Ok(1));
+
+        let steps = [
+            (1, DisputeState::Open, 2),
+            (1, DisputeState::Open, 2),
+            (2, DisputeState::Open, 3),
+            (1, DisputeState::UnderReview, 3),
+            (1, DisputeState::Resolved, 2),
+            (2, DisputeState::ChargedBack, 1),
+            (1, DisputeState::Open, 2),
+        ];
+        for (id, state, expected) in steps {
+            store.set_dispute_state(id, state).await.unwrap();
+            assert_eq!(store.open_disputes_count(1).await, Ok(expected));
+        }
+        assert_eq!(store.open_disputes_count(2).await, Ok(0));
+
+        store.delete_transaction(4).await.unwrap();
+        store.reserve_transaction_id(1).await.unwrap();
+        assert_eq!(store.open_disputes_count(1).await, Ok(0));
+        // dispute states of transactions which are not stored are not counted
+        store
+            .set_dispute_state(5, DisputeState::Open)
+            .await
+            .unwrap();
+        assert_eq!(store.open_disputes_count(1).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn set_dispute_state_counts_disputes() {
+        let store = DashMapStore::new();
+        store
+            .create_transaction(Transaction::deposit(1, 1, Decimal::ONE))
+            .await
+            .unwrap();
+        store
+            .set_dispute_state(1, DisputeState::Open)
+            .await
+            .unwrap();
+        store
+            .set_dispute_state(1, DisputeState::Open)
+            .await
+            .unwrap();
+        store
+            .set_dispute_state(1, DisputeState::Resolved)
+            .await
+            .unwrap();
+        store
+            .set_dispute_state(1, DisputeState::Open)
+            .await
+            .unwrap();
+        assert_eq!(store.dispute_count(1).await, Ok(2));
+        assert_eq!(
+            store.dispute_count(2).await,
+            Err(StoreError::NotFound { id: 2 })
+        );
+    }
+}
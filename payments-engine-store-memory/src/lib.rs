@@ -3,6 +3,17 @@
 //! Useful for simple storage and for testing.
 //!
 //! Use the `testing` feature to enable some handy methods for testing purposes.
+//!
+//! Use the `dashmap` feature to enable [`DashMapStore`], a sharded alternative to [`MemoryStore`]
+//! which scales better when many clients are processed concurrently.
+//!
+//! The state of the stores can be persisted as a versioned [`StoreSnapshot`].
+#[cfg(feature = "dashmap")]
+mod dashmap_store;
 mod memory_store;
+mod snapshot;
 
+#[cfg(feature = "dashmap")]
+pub use dashmap_store::DashMapStore;
 pub use memory_store::MemoryStore;
+pub use snapshot::{StoreSnapshot, SNAPSHOT_VERSION};
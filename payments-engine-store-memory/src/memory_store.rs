@@ -1,12 +1,15 @@
+use crate::StoreSnapshot;
 use async_trait::async_trait;
+use futures::StreamExt;
 use payments_engine_core::{
     account::Account,
-    common::ClientId,
-    store::{Store, StoreError, StoreResult},
-    transaction::{Transaction, TransactionId},
+    common::{Amount, ClientId},
+    store::{DisputeMismatch, Store, StoreError, StoreResult, UpsertOutcome},
+    transaction::{DisputeState, Transaction, TransactionId},
 };
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Bound::{Excluded, Unbounded},
     ops::Deref,
     pin::Pin,
     sync::{Arc, RwLock},
@@ -19,12 +22,16 @@ use tracing::instrument;
 /// Note that [`MemoryStore`] can be safely shared across different threads as it uses an inner [`std::sync::Arc`]. This basically means that whenever you clone a [`MemoryStore`] you´re using `Arc::clone()` under the hood.
 ///
 /// # Important
-/// This store only cares about [`Transaction::Dispute`] transactions so all the other variants are not really stored.
+/// This store only cares about [`Transaction::Deposit`] transactions so all the other variants are not really stored.
+/// Storing [`Transaction::Withdrawal`] transactions too can be enabled with [`MemoryStore::storing_withdrawals`].
+/// This is required to dispute withdrawals, as disputes on transactions that are not stored are ignored.
+/// Otherwise, only the ids of the withdrawals are kept, so replayed withdrawals are rejected too.
+/// The ids of adjustments and account openings and closings are kept as well, so they can't be replayed either.
 ///
 /// # Testing:
 ///
 /// The inner hashmaps are exposed for testing purposes.
-/// There's also a convenient flag to enable/disable error while upserting accounts.
+/// There are also convenient flags to enable/disable errors while upserting accounts, getting all of them and deleting transactions.
 #[derive(Debug, Default)]
 pub struct MemoryStore(Arc<Inner>);
 
@@ -43,6 +50,90 @@ impl MemoryStore {
     ) -> Self {
         Self(Arc::new(Inner::seeded(deposits, accounts)))
     }
+
+    /// Creates a new [`MemoryStore`] that stores [`Transaction::Withdrawal`] transactions along with the deposits,
+    /// so they can be looked up later, e.g. to dispute them.
+    #[must_use]
+    pub fn storing_withdrawals() -> Self {
+        Self(Arc::new(Inner {
+            store_withdrawals: true,
+            ..Inner::default()
+        }))
+    }
+
+    /// Creates a new [`MemoryStore`] with the state of the given [`StoreSnapshot`].
+    #[must_use]
+    pub fn from_snapshot(snapshot: StoreSnapshot) -> Self {
+        let by_id = |transactions: Vec<Transaction>| {
+            transactions
+                .into_iter()
+                .map(|transaction| (transaction.info().id, transaction))
+                .collect::<HashMap<_, _>>()
+        };
+        Self(Arc::new(Inner {
+            open_disputes: RwLock::new(open_disputes_by_client(&snapshot.deposits)),
+            deposits: RwLock::new(by_id(snapshot.deposits)),
+            accounts: Arc::new(RwLock::new(
+                snapshot
+                    .accounts
+                    .into_iter()
+                    .map(|account| (account.client, account))
+                    .collect(),
+            )),
+            dispute_counts: RwLock::new(snapshot.dispute_counts.into_iter().collect()),
+            pending: RwLock::new(by_id(snapshot.pending)),
+            reserved_ids: RwLock::new(snapshot.reserved_ids.into_iter().collect()),
+            store_withdrawals: snapshot.store_withdrawals,
+            ..Inner::default()
+        }))
+    }
+
+    /// Takes a [`StoreSnapshot`] of the whole state of the [`MemoryStore`],
+    /// which can be persisted and restored later with [`MemoryStore::from_snapshot`].
+    pub fn snapshot(&self) -> StoreResult<StoreSnapshot> {
+        let access_error = |e: String| {
+            tracing::error!("Error while taking the snapshot: {}", e);
+            StoreError::AccessError(e)
+        };
+        let deposits = self
+            .deposits
+            .read()
+            .map_err(|e| access_error(e.to_string()))?;
+        let accounts = self
+            .accounts
+            .read()
+            .map_err(|e| access_error(e.to_string()))?;
+        let dispute_counts = self
+            .dispute_counts
+            .read()
+            .map_err(|e| access_error(e.to_string()))?;
+        let pending = self
+            .pending
+            .read()
+            .map_err(|e| access_error(e.to_string()))?;
+        let reserved_ids = self
+            .reserved_ids
+            .read()
+            .map_err(|e| access_error(e.to_string()))?;
+
+        let mut reserved_ids = reserved_ids.iter().copied().collect::<Vec<_>>();
+        reserved_ids.sort_unstable();
+        let mut pending = pending.values().cloned().collect::<Vec<_>>();
+        pending.sort_by_key(|transaction| transaction.info().id);
+        Ok(StoreSnapshot {
+            dispute_counts: dispute_counts
+                .iter()
+                .map(|(id, count)| (*id, *count))
+                .collect(),
+            pending,
+            reserved_ids,
+            store_withdrawals: self.store_withdrawals,
+            ..StoreSnapshot::new(
+                deposits.values().cloned().collect(),
+                accounts.values().cloned().collect(),
+            )
+        })
+    }
 }
 
 impl Clone for MemoryStore {
@@ -70,9 +161,10 @@ impl Store for MemoryStore {
 
     /// Creates a new [`Transaction`] and returns it.
     /// If the [`Transaction`] already exists, it returns an [`StoreError::AlreadyExists`].
-    /// Note that this method is only storing [`Transaction::Deposit`] transactions.
-    /// That's mainly because disputes, resolutions and chargebacks are only related to diposits,
-    /// so it makes no sense to store withdrawals or any other kind of [`Transaction`].
+    /// Only [`Transaction::Deposit`] transactions are stored, as they're the ones disputes refer to,
+    /// plus withdrawals if the store was created with [`MemoryStore::storing_withdrawals`].
+    /// The ids of the rest of transactions not referencing another one are reserved instead,
+    /// so duplicates are rejected anyway.
     #[instrument(skip(self))]
     async fn create_transaction(&self, transaction: Transaction) -> StoreResult<Transaction> {
         self.0.create_transaction(transaction).await
@@ -84,46 +176,141 @@ impl Store for MemoryStore {
         self.0.delete_transaction(id).await
     }
 
-    /// Sets a [`Transaction`] under dispute.
+    /// Drops the stored [`Transaction`], if any, but keeps its id reserved.
+    #[instrument(skip(self))]
+    async fn reserve_transaction_id(&self, id: TransactionId) -> StoreResult<()> {
+        self.0.reserve_transaction_id(id).await
+    }
+
+    /// Sets the [`DisputeState`] of a [`Transaction`].
+    #[instrument(skip(self))]
+    async fn set_dispute_state(&self, id: TransactionId, state: DisputeState) -> StoreResult<()> {
+        self.0.set_dispute_state(id, state).await
+    }
+
+    /// Sets the [`Amount`] held by a partial dispute of a [`Transaction`].
     #[instrument(skip(self))]
-    async fn set_transaction_under_dispute(
+    async fn set_disputed_amount(
         &self,
         id: TransactionId,
-        under_dispute: bool,
+        amount: Option<Amount>,
     ) -> StoreResult<()> {
-        self.0
-            .set_transaction_under_dispute(id, under_dispute)
-            .await
+        self.0.set_disputed_amount(id, amount).await
+    }
+
+    /// Sets the [`Amount`] of a stored [`Transaction`] in place.
+    #[instrument(skip(self))]
+    async fn set_transaction_amount(&self, id: TransactionId, amount: Amount) -> StoreResult<()> {
+        self.0.set_transaction_amount(id, amount).await
+    }
+
+    /// Returns how many times a [`Transaction`] has been disputed.
+    #[instrument(skip(self))]
+    async fn dispute_count(&self, id: TransactionId) -> StoreResult<u32> {
+        self.0.dispute_count(id).await
+    }
+
+    /// Keeps a [`Transaction`] that was rejected but should be retained for later reconciliation.
+    #[instrument(skip(self))]
+    async fn add_pending_transaction(&self, transaction: Transaction) -> StoreResult<()> {
+        self.0.add_pending_transaction(transaction).await
+    }
+
+    /// Returns the pending [`Transaction`], sorted by id.
+    #[instrument(skip(self))]
+    async fn get_pending_transactions(&self) -> StoreResult<Vec<Transaction>> {
+        self.0.get_pending_transactions().await
     }
 
-    /// Toggles the under dispute flag
+    /// Returns the stored [`Transaction`] of the client, sorted by id.
     #[instrument(skip(self))]
-    async fn toggle_under_dispute(&self, id: TransactionId) -> StoreResult<()> {
-        self.0.toggle_under_dispute(id).await
+    async fn get_transactions_for_client(&self, client: ClientId) -> StoreResult<Vec<Transaction>> {
+        self.0.get_transactions_for_client(client).await
     }
 
-    /// Gets the current state of the [`Account`].
-    /// If the [`Account`] does not exist, it will return an empty [`Account`].
-    /// Note that the account is not created in the [`Store`] yet.
+    /// Gets the current state of the [`Account`], or [`None`] if it doesn't exist.
     #[instrument(skip(self))]
-    async fn get_account(&self, id: ClientId) -> StoreResult<Account> {
+    async fn get_account(&self, id: ClientId) -> StoreResult<Option<Account>> {
         self.0.get_account(id).await
     }
 
+    /// Gets the current state of the [`Account`] and the [`Transaction`] with the given id.
+    #[instrument(skip(self))]
+    async fn get_account_and_transaction(
+        &self,
+        client: ClientId,
+        id: TransactionId,
+    ) -> StoreResult<(Account, Option<Transaction>)> {
+        self.0.get_account_and_transaction(client, id).await
+    }
+
     /// Updates the state of the [`Account`].
     /// If the [`Account`] does not exist, it will create the [`Account`].
     #[instrument(skip(self))]
-    async fn upsert_account(&self, account: &Account) -> StoreResult<()> {
+    async fn upsert_account(&self, account: &Account) -> StoreResult<UpsertOutcome> {
         self.0.upsert_account(account).await
     }
 
-    /// Returns the current state of clients accounts.
+    /// Seeds the store with the given accounts. Existing accounts are replaced.
+    #[instrument(skip(self, accounts))]
+    async fn load_accounts(&self, accounts: Vec<Account>) -> StoreResult<()> {
+        self.0.load_accounts(accounts).await
+    }
+
+    /// Returns the current state of clients accounts, sorted by client.
+    /// Every [`Account`] is cloned as the stream gets polled.
     #[instrument(skip(self))]
     async fn get_all_accounts(
         &self,
     ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>> {
         self.0.get_all_accounts().await
     }
+
+    /// Returns up to `limit` [`Account`] sorted by client, starting right after the `after` client.
+    #[instrument(skip(self))]
+    async fn get_accounts_page(
+        &self,
+        after: Option<ClientId>,
+        limit: usize,
+    ) -> StoreResult<Vec<Account>> {
+        self.0.get_accounts_page(after, limit).await
+    }
+
+    /// Returns up to `limit` [`Account`] sorted by client, skipping the first `offset` ones.
+    #[instrument(skip(self))]
+    async fn get_accounts_at_offset(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> StoreResult<Vec<Account>> {
+        self.0.get_accounts_at_offset(offset, limit).await
+    }
+
+    /// Returns the ids of all the clients with an [`Account`], sorted.
+    #[instrument(skip(self))]
+    async fn get_client_ids(&self) -> StoreResult<Vec<ClientId>> {
+        self.0.get_client_ids().await
+    }
+
+    /// Returns the deposits and withdrawals which are currently under dispute.
+    #[instrument(skip(self))]
+    async fn get_disputed_transactions(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Transaction> + Send>>> {
+        self.0.get_disputed_transactions().await
+    }
+
+    /// Returns how many deposits and withdrawals of the client are currently under dispute.
+    #[instrument(skip(self))]
+    async fn open_disputes_count(&self, client: ClientId) -> StoreResult<u32> {
+        self.0.open_disputes_count(client).await
+    }
+
+    /// Checks that the held funds of every [`Account`] equal the sum of its under dispute deposits and withdrawals.
+    #[instrument(skip(self))]
+    async fn verify_dispute_consistency(&self) -> StoreResult<Vec<DisputeMismatch>> {
+        self.0.verify_dispute_consistency().await
+    }
 }
 
 /// Inner implementation of the [`MemoryStore`]
@@ -131,8 +318,21 @@ impl Store for MemoryStore {
 pub struct Inner {
     #[cfg(any(test, feature = "testing"))]
     enable_upsert_account_failure: RwLock<bool>,
+    #[cfg(any(test, feature = "testing"))]
+    enable_get_all_accounts_failure: RwLock<bool>,
+    #[cfg(any(test, feature = "testing"))]
+    enable_delete_transaction_failure: RwLock<bool>,
     deposits: RwLock<HashMap<TransactionId, Transaction>>,
-    accounts: RwLock<HashMap<ClientId, Account>>,
+    /// shared with the streams of [`Store::get_all_accounts`], so they can clone the accounts lazily.
+    accounts: Arc<RwLock<BTreeMap<ClientId, Account>>>,
+    dispute_counts: RwLock<HashMap<TransactionId, u32>>,
+    pending: RwLock<HashMap<TransactionId, Transaction>>,
+    /// ids of the transactions which are not stored, e.g. withdrawals or adjustments, to reject duplicates.
+    reserved_ids: RwLock<HashSet<TransactionId>>,
+    /// number of deposits and withdrawals under dispute by client,
+    /// updated along with the disputed state of the transactions.
+    open_disputes: RwLock<HashMap<ClientId, u32>>,
+    store_withdrawals: bool,
 }
 
 impl Inner {
@@ -149,11 +349,42 @@ impl Inner {
         accounts: Option<HashMap<ClientId, Account>>,
     ) -> Self {
         Self {
+            open_disputes: RwLock::new(open_disputes_by_client(
+                deposits.iter().flat_map(HashMap::values),
+            )),
             deposits: RwLock::new(deposits.unwrap_or_default()),
-            accounts: RwLock::new(accounts.unwrap_or_default()),
+            accounts: Arc::new(RwLock::new(
+                accounts.unwrap_or_default().into_iter().collect(),
+            )),
+            dispute_counts: RwLock::new(HashMap::new()),
+            pending: RwLock::new(HashMap::new()),
+            reserved_ids: RwLock::new(HashSet::new()),
+            store_withdrawals: false,
             #[cfg(any(test, feature = "testing"))]
             enable_upsert_account_failure: RwLock::new(false),
+            #[cfg(any(test, feature = "testing"))]
+            enable_get_all_accounts_failure: RwLock::new(false),
+            #[cfg(any(test, feature = "testing"))]
+            enable_delete_transaction_failure: RwLock::new(false),
+        }
+    }
+
+    /// Updates the open disputes of the client when one of its transactions gets in or out of dispute.
+    fn track_open_disputes(&self, client: ClientId, was: bool, is: bool) -> StoreResult<()> {
+        if was == is {
+            return Ok(());
+        }
+        let mut open_disputes = self
+            .open_disputes
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?;
+        let count = open_disputes.entry(client).or_default();
+        if is {
+            *count += 1;
+        } else {
+            *count = count.saturating_sub(1);
         }
+        Ok(())
     }
 
     #[cfg(any(test, feature = "testing"))]
@@ -162,7 +393,7 @@ impl Inner {
     }
 
     #[cfg(any(test, feature = "testing"))]
-    pub const fn accounts(&self) -> &RwLock<HashMap<ClientId, Account>> {
+    pub fn accounts(&self) -> &RwLock<BTreeMap<ClientId, Account>> {
         &self.accounts
     }
 
@@ -202,15 +433,54 @@ impl Inner {
     pub fn enable_upsert_account_failure(&self) -> bool {
         *self.enable_upsert_account_failure.read().unwrap()
     }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub fn set_enable_get_all_accounts_failure(&self, enable: bool) {
+        self.enable_get_all_accounts_failure
+            .write()
+            .map(|mut failure| {
+                *failure = enable;
+            })
+            .unwrap();
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub fn enable_get_all_accounts_failure(&self) -> bool {
+        *self.enable_get_all_accounts_failure.read().unwrap()
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub fn set_enable_delete_transaction_failure(&self, enable: bool) {
+        self.enable_delete_transaction_failure
+            .write()
+            .map(|mut failure| {
+                *failure = enable;
+            })
+            .unwrap();
+    }
+
+    #[cfg(any(test, feature = "testing"))]
+    pub fn enable_delete_transaction_failure(&self) -> bool {
+        *self.enable_delete_transaction_failure.read().unwrap()
+    }
 }
 
 impl Default for Inner {
     fn default() -> Self {
         Self {
             deposits: RwLock::new(HashMap::new()),
-            accounts: RwLock::new(HashMap::new()),
+            accounts: Arc::default(),
+            dispute_counts: RwLock::new(HashMap::new()),
+            pending: RwLock::new(HashMap::new()),
+            reserved_ids: RwLock::new(HashSet::new()),
+            open_disputes: RwLock::new(HashMap::new()),
+            store_withdrawals: false,
             #[cfg(any(test, feature = "testing"))]
             enable_upsert_account_failure: RwLock::new(false),
+            #[cfg(any(test, feature = "testing"))]
+            enable_get_all_accounts_failure: RwLock::new(false),
+            #[cfg(any(test, feature = "testing"))]
+            enable_delete_transaction_failure: RwLock::new(false),
         }
     }
 }
@@ -242,103 +512,253 @@ impl Store for Inner {
 
     /// Creates a new [`Transaction`] and returns it.
     /// If the [`Transaction`] already exists, it returns an [`StoreError::AlreadyExists`].
-    /// Note that this method is only storing [`Transaction::Deposit`] transactions.
-    /// That's mainly because disputes, resolutions and chargebacks are only related to diposits,
-    /// so it makes no sense to store withdrawals or any other kind of [`Transaction`].
+    /// Only [`Transaction::Deposit`] transactions are stored, as they're the ones disputes refer to,
+    /// plus withdrawals if the store was created with [`MemoryStore::storing_withdrawals`].
+    /// The ids of the rest of transactions not referencing another one, e.g. adjustments,
+    /// are kept in the reserved ids instead, so duplicates are rejected anyway.
     #[instrument(skip(self))]
     async fn create_transaction(&self, transaction: Transaction) -> StoreResult<Transaction> {
         tracing::debug!("Creating transaction: {:?}", transaction);
-        if let Transaction::Deposit { .. } = transaction {
-            let result = self
-                .deposits
-                .write()
-                .map_err(|e| StoreError::AccessError(e.to_string()))
-                .and_then(|mut deposits| {
-                    let transaction_id = transaction.info().id;
-                    if let std::collections::hash_map::Entry::Vacant(e) =
-                        deposits.entry(transaction_id)
-                    {
-                        e.insert(transaction.clone());
-                        Ok(transaction)
-                    } else {
-                        Err(StoreError::AlreadyExists { id: transaction_id })
-                    }
-                });
-
-            if result.is_err() {
-                tracing::error!("Error while trying to create transaction: {:?}", result);
-            }
+        if transaction.references_transaction() {
+            return Ok(transaction);
+        }
+        let is_stored = match transaction {
+            Transaction::Deposit { .. } => true,
+            Transaction::Withdrawal { .. } => self.store_withdrawals,
+            _ => false,
+        };
+        let transaction_id = transaction.info().id;
+        let result = self
+            .deposits
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))
+            .and_then(|mut deposits| {
+                let mut reserved_ids = self
+                    .reserved_ids
+                    .write()
+                    .map_err(|e| StoreError::AccessError(e.to_string()))?;
+                if deposits.contains_key(&transaction_id) || reserved_ids.contains(&transaction_id)
+                {
+                    return Err(StoreError::AlreadyExists { id: transaction_id });
+                }
+                if is_stored {
+                    self.track_open_disputes(
+                        transaction.info().client_id,
+                        false,
+                        transaction.is_under_dispute(),
+                    )?;
+                    deposits.insert(transaction_id, transaction.clone());
+                } else {
+                    reserved_ids.insert(transaction_id);
+                }
+                Ok(transaction)
+            });
 
-            result
-        } else {
-            Ok(transaction)
+        if result.is_err() {
+            tracing::error!("Error while trying to create transaction: {:?}", result);
         }
+
+        result
     }
 
     /// Deletes a [`Transaction`].
     #[instrument(skip(self))]
     async fn delete_transaction(&self, id: TransactionId) -> StoreResult<()> {
         tracing::debug!("Deleting transaction: {:?}", id);
-        self.deposits
+        #[cfg(any(test, feature = "testing"))]
+        {
+            if self.enable_delete_transaction_failure() {
+                return Err(StoreError::AccessError("Test Error".to_string()));
+            }
+        }
+        let removed = self
+            .deposits
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?
+            .remove(&id);
+        if let Some(transaction) = removed {
+            self.track_open_disputes(
+                transaction.info().client_id,
+                transaction.is_under_dispute(),
+                false,
+            )?;
+        }
+        self.reserved_ids
             .write()
             .map_err(|e| StoreError::AccessError(e.to_string()))
-            .map(|mut deposits| {
-                deposits.remove(&id);
+            .map(|mut reserved_ids| {
+                reserved_ids.remove(&id);
+            })?;
+        self.dispute_counts
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))
+            .map(|mut dispute_counts| {
+                dispute_counts.remove(&id);
+            })
+    }
+
+    /// Drops the stored [`Transaction`], if any, but keeps its id reserved.
+    /// The id is kept along with the ids of the rest of transactions which are not stored.
+    #[instrument(skip(self))]
+    async fn reserve_transaction_id(&self, id: TransactionId) -> StoreResult<()> {
+        tracing::debug!("Reserving transaction id: {:?}", id);
+        let mut deposits = self
+            .deposits
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?;
+        self.reserved_ids
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?
+            .insert(id);
+        if let Some(transaction) = deposits.remove(&id) {
+            self.track_open_disputes(
+                transaction.info().client_id,
+                transaction.is_under_dispute(),
+                false,
+            )?;
+        }
+        self.dispute_counts
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))
+            .map(|mut dispute_counts| {
+                dispute_counts.remove(&id);
             })
     }
 
-    /// Sets a [`Transaction`] under dispute.
+    /// Sets the [`DisputeState`] of a [`Transaction`].
+    /// Every time a [`Transaction`] gets into [`DisputeState::Open`], its dispute counter is incremented.
+    #[instrument(skip(self))]
+    async fn set_dispute_state(&self, id: TransactionId, state: DisputeState) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} dispute state to {:?}", id, state);
+        let mut deposits = self
+            .deposits
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?;
+        if let Some(transaction) = deposits.get_mut(&id) {
+            if state == DisputeState::Open && transaction.dispute_state() != Some(state) {
+                *self
+                    .dispute_counts
+                    .write()
+                    .map_err(|e| StoreError::AccessError(e.to_string()))?
+                    .entry(id)
+                    .or_default() += 1;
+            }
+            self.track_open_disputes(
+                transaction.info().client_id,
+                transaction.is_under_dispute(),
+                state.is_under_dispute(),
+            )?;
+            transaction.set_dispute_state(state);
+        }
+        Ok(())
+    }
+
+    /// Sets the [`Amount`] held by a partial dispute of a [`Transaction`], or [`None`] if the whole amount is disputed.
     #[instrument(skip(self))]
-    async fn set_transaction_under_dispute(
+    async fn set_disputed_amount(
         &self,
         id: TransactionId,
-        under_dispute: bool,
+        amount: Option<Amount>,
     ) -> StoreResult<()> {
-        tracing::debug!(
-            "Setting transaction {} under dispute to {}",
-            id,
-            under_dispute
-        );
+        tracing::debug!("Setting transaction {} disputed amount to {:?}", id, amount);
         self.deposits
             .write()
             .map_err(|e| StoreError::AccessError(e.to_string()))
             .map(|mut deposits| {
                 if let Some(transaction) = deposits.get_mut(&id) {
-                    transaction.set_under_dispute(under_dispute);
+                    transaction.set_disputed_amount(amount);
                 }
             })
     }
 
-    /// Toggles the under dispute flag
+    /// Sets the [`Amount`] of a stored [`Transaction`] in place.
     #[instrument(skip(self))]
-    async fn toggle_under_dispute(&self, id: TransactionId) -> StoreResult<()> {
-        tracing::debug!("Toggling under dispute for transaction {}", id);
+    async fn set_transaction_amount(&self, id: TransactionId, amount: Amount) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} amount to {}", id, amount);
         self.deposits
             .write()
             .map_err(|e| StoreError::AccessError(e.to_string()))
             .map(|mut deposits| {
-                if let Some(transaction) = deposits.get_mut(&id) {
-                    transaction.toggle_under_dispute();
+                if let Some(stored) = deposits.get_mut(&id).and_then(Transaction::amount_mut) {
+                    *stored = amount;
                 }
             })
     }
 
-    /// Gets the current state of the [`Account`].
-    /// If the [`Account`] does not exist, it will return an empty [`Account`].
-    /// Note that the account is not created in the [`Store`] yet.
+    /// Returns how many times a [`Transaction`] has been disputed.
+    /// If the [`Transaction`] doesn't exist, it returns an [`StoreError::NotFound`].
+    #[instrument(skip(self))]
+    async fn dispute_count(&self, id: TransactionId) -> StoreResult<u32> {
+        tracing::debug!("Getting dispute count of transaction {}", id);
+        let deposits = self
+            .deposits
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?;
+        if !deposits.contains_key(&id) {
+            tracing::error!("Transaction {} not found", id);
+            return Err(StoreError::NotFound { id });
+        }
+        let count = self
+            .dispute_counts
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?
+            .get(&id)
+            .copied()
+            .unwrap_or_default();
+        Ok(count)
+    }
+
+    /// Keeps a [`Transaction`] that was rejected but should be retained for later reconciliation.
+    #[instrument(skip(self))]
+    async fn add_pending_transaction(&self, transaction: Transaction) -> StoreResult<()> {
+        tracing::debug!("Adding pending transaction: {:?}", transaction);
+        self.pending
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))
+            .map(|mut pending| {
+                pending.insert(transaction.info().id, transaction);
+            })
+    }
+
+    /// Returns the pending [`Transaction`], sorted by id.
+    #[instrument(skip(self))]
+    async fn get_pending_transactions(&self) -> StoreResult<Vec<Transaction>> {
+        let mut pending = self
+            .pending
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|transaction| transaction.info().id);
+        Ok(pending)
+    }
+
+    /// Returns the stored [`Transaction`] of the client, sorted by id.
+    #[instrument(skip(self))]
+    async fn get_transactions_for_client(&self, client: ClientId) -> StoreResult<Vec<Transaction>> {
+        tracing::debug!("Getting transactions of client {}", client);
+        let mut transactions = self
+            .deposits
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?
+            .values()
+            .filter(|transaction| transaction.info().client_id == client)
+            .cloned()
+            .collect::<Vec<_>>();
+        transactions.sort_by_key(|transaction| transaction.info().id);
+        Ok(transactions)
+    }
+
+    /// Gets the current state of the [`Account`], or [`None`] if it doesn't exist.
     #[instrument(skip(self))]
-    async fn get_account(&self, id: ClientId) -> StoreResult<Account> {
+    async fn get_account(&self, id: ClientId) -> StoreResult<Option<Account>> {
         tracing::debug!("Getting account: {}", id);
         let result = self
             .accounts
             .read()
             .map_err(|e| StoreError::AccessError(e.to_string()))
-            .map(|accounts| {
-                accounts
-                    .get(&id)
-                    .cloned()
-                    .unwrap_or_else(|| Account::new(id))
-            });
+            .map(|accounts| accounts.get(&id).cloned());
 
         if result.is_err() {
             tracing::error!("Error while getting account: {:?}", result);
@@ -347,10 +767,44 @@ impl Store for Inner {
         result
     }
 
+    /// Gets the current state of the [`Account`] and the [`Transaction`] with the given id.
+    /// Both maps are read at the same time, so the result is consistent.
+    #[instrument(skip(self))]
+    async fn get_account_and_transaction(
+        &self,
+        client: ClientId,
+        id: TransactionId,
+    ) -> StoreResult<(Account, Option<Transaction>)> {
+        tracing::debug!("Getting account {} and transaction {}", client, id);
+        let result = self
+            .accounts
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))
+            .and_then(|accounts| {
+                self.deposits
+                    .read()
+                    .map_err(|e| StoreError::AccessError(e.to_string()))
+                    .map(|deposits| {
+                        let account = accounts
+                            .get(&client)
+                            .cloned()
+                            .unwrap_or_else(|| Account::new(client));
+                        (account, deposits.get(&id).cloned())
+                    })
+            });
+
+        if result.is_err() {
+            tracing::error!("Error while getting account and transaction: {:?}", result);
+        }
+
+        result
+    }
+
     /// Updates the state of the [`Account`].
     /// If the [`Account`] does not exist, it will create the [`Account`].
+    /// Returns whether the [`Account`] was created or updated.
     #[instrument(skip(self))]
-    async fn upsert_account(&self, account: &Account) -> StoreResult<()> {
+    async fn upsert_account(&self, account: &Account) -> StoreResult<UpsertOutcome> {
         tracing::debug!("Upserting account: {:?}", account);
         #[cfg(any(test, feature = "testing"))]
         {
@@ -362,8 +816,15 @@ impl Store for Inner {
             .accounts
             .write()
             .map_err(|e| StoreError::AccessError(e.to_string()))
-            .map(|mut accounts| {
-                accounts.insert(account.client, account.clone());
+            .map(|mut accounts| match accounts.entry(account.client) {
+                std::collections::btree_map::Entry::Occupied(mut e) => {
+                    e.insert(account.clone());
+                    UpsertOutcome::Updated
+                }
+                std::collections::btree_map::Entry::Vacant(e) => {
+                    e.insert(account.clone());
+                    UpsertOutcome::Created
+                }
             });
 
         if result.is_err() {
@@ -373,24 +834,212 @@ impl Store for Inner {
         result
     }
 
-    /// Returns the current state of clients accounts.
+    /// Seeds the store with the given accounts. Existing accounts are replaced.
+    /// All of them are inserted while holding the lock, so they're loaded at once.
+    #[instrument(skip(self, accounts))]
+    async fn load_accounts(&self, accounts: Vec<Account>) -> StoreResult<()> {
+        tracing::debug!("Loading {} accounts", accounts.len());
+        #[cfg(any(test, feature = "testing"))]
+        {
+            if self.enable_upsert_account_failure() {
+                return Err(StoreError::AccessError("Test Error".to_string()));
+            }
+        }
+        let result = self
+            .accounts
+            .write()
+            .map_err(|e| StoreError::AccessError(e.to_string()))
+            .map(|mut stored| {
+                stored.extend(
+                    accounts
+                        .into_iter()
+                        .map(|account| (account.client, account)),
+                );
+            });
+
+        if result.is_err() {
+            tracing::error!("Error while loading accounts: {:?}", result);
+        }
+
+        result
+    }
+
+    /// Returns the current state of clients accounts, sorted by client.
+    /// Only the client ids are copied upfront, every [`Account`] is cloned as the stream gets polled,
+    /// so the accounts updated meanwhile are streamed with their latest state.
     #[instrument(skip(self))]
     async fn get_all_accounts(
         &self,
     ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>> {
-        let result = self
+        #[cfg(any(test, feature = "testing"))]
+        {
+            if self.enable_get_all_accounts_failure() {
+                return Err(StoreError::AccessError("Test Error".to_string()));
+            }
+        }
+        let clients = self.get_client_ids().await?;
+        let accounts = Arc::clone(&self.accounts);
+        let stream = futures::stream::iter(clients).filter_map(move |client| {
+            let account = match accounts.read() {
+                Ok(accounts) => accounts.get(&client).cloned(),
+                Err(e) => {
+                    tracing::error!("Error while getting account {}: {}", client, e);
+                    None
+                }
+            };
+            futures::future::ready(account)
+        });
+        Ok(Box::pin(stream))
+    }
+
+    /// Returns up to `limit` [`Account`] sorted by client, starting right after the `after` client.
+    /// The accounts are kept sorted by client, so the page is a range of them and nothing else is visited.
+    #[instrument(skip(self))]
+    async fn get_accounts_page(
+        &self,
+        after: Option<ClientId>,
+        limit: usize,
+    ) -> StoreResult<Vec<Account>> {
+        tracing::debug!("Getting {} accounts after {:?}", limit, after);
+        let accounts = self
+            .accounts
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?;
+        let start = after.map_or(Unbounded, Excluded);
+        Ok(accounts
+            .range((start, Unbounded))
+            .take(limit)
+            .map(|(_, account)| account.clone())
+            .collect())
+    }
+
+    /// Returns up to `limit` [`Account`] sorted by client, skipping the first `offset` ones.
+    /// Only the accounts of the page are cloned.
+    #[instrument(skip(self))]
+    async fn get_accounts_at_offset(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> StoreResult<Vec<Account>> {
+        tracing::debug!("Getting {} accounts at offset {}", limit, offset);
+        let accounts = self
+            .accounts
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?;
+        Ok(accounts
+            .values()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect())
+    }
+
+    /// Returns the ids of all the clients with an [`Account`], sorted.
+    #[instrument(skip(self))]
+    async fn get_client_ids(&self) -> StoreResult<Vec<ClientId>> {
+        tracing::debug!("Getting client ids");
+        Ok(self
             .accounts
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?
+            .keys()
+            .copied()
+            .collect())
+    }
+
+    /// Returns the deposits and withdrawals which are currently under dispute, sorted by id.
+    #[instrument(skip(self))]
+    async fn get_disputed_transactions(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Transaction> + Send>>> {
+        let mut disputed = self
+            .deposits
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?
+            .values()
+            .filter(|deposit| deposit.is_under_dispute())
+            .cloned()
+            .collect::<Vec<_>>();
+        disputed.sort_by_key(|deposit| deposit.info().id);
+        Ok(Box::pin(futures::stream::iter(disputed)))
+    }
+
+    /// Returns how many deposits and withdrawals of the client are currently under dispute.
+    /// They're counted as their dispute state changes, so nothing is scanned.
+    #[instrument(skip(self))]
+    async fn open_disputes_count(&self, client: ClientId) -> StoreResult<u32> {
+        tracing::debug!("Counting open disputes of client {}", client);
+        self.open_disputes
             .read()
             .map_err(|e| StoreError::AccessError(e.to_string()))
-            .map(|accounts| {
-                Box::pin(futures::stream::iter(
-                    accounts.values().cloned().collect::<Vec<_>>(),
-                ))
-            })?;
-        Ok(result)
+            .map(|open_disputes| open_disputes.get(&client).copied().unwrap_or_default())
+    }
+
+    /// Checks that the held funds of every [`Account`] equal the sum of its under dispute deposits and withdrawals.
+    #[instrument(skip(self))]
+    async fn verify_dispute_consistency(&self) -> StoreResult<Vec<DisputeMismatch>> {
+        tracing::debug!("Verifying dispute consistency");
+        let mut disputed: HashMap<ClientId, Amount> = HashMap::new();
+        self.deposits
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?
+            .values()
+            .filter(|transaction| transaction.is_under_dispute())
+            .for_each(|transaction| {
+                if let Some(amount) = transaction.disputed_amount() {
+                    *disputed.entry(transaction.info().client_id).or_default() += amount;
+                }
+            });
+
+        let accounts = self
+            .accounts
+            .read()
+            .map_err(|e| StoreError::AccessError(e.to_string()))?;
+
+        let mut clients = accounts
+            .keys()
+            .chain(disputed.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        clients.sort_unstable();
+        clients.dedup();
+
+        let mismatches = clients
+            .into_iter()
+            .filter_map(|client| {
+                let held = accounts.get(&client).map_or(Amount::ZERO, |a| a.held);
+                let disputed = disputed.get(&client).copied().unwrap_or_default();
+                (held != disputed).then_some(DisputeMismatch {
+                    client,
+                    held,
+                    disputed,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !mismatches.is_empty() {
+            tracing::error!("Inconsistent disputes found: {:?}", mismatches);
+        }
+
+        Ok(mismatches)
     }
 }
 
+/// Counts the transactions under dispute of every client.
+fn open_disputes_by_client<'a>(
+    transactions: impl IntoIterator<Item = &'a Transaction>,
+) -> HashMap<ClientId, u32> {
+    let mut open_disputes = HashMap::new();
+    for transaction in transactions {
+        if transaction.is_under_dispute() {
+            *open_disputes
+                .entry(transaction.info().client_id)
+                .or_default() += 1;
+        }
+    }
+    open_disputes
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -413,10 +1062,66 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn get_transaction_returns_not_found_if_transaction_does_not_exist() {
-        let transaction_id = 1;
-        let transaction = Transaction::deposit(transaction_id, 1, dec!(1.0001));
-        let mut deposits = HashMap::new();
+    async fn get_all_accounts_are_sorted_by_client() {
+        let accounts = [3, 1, 2]
+            .into_iter()
+            .map(|client| (client, Account::new(client)))
+            .collect::<HashMap<_, _>>();
+        let store = MemoryStore::seeded(None, Some(accounts));
+
+        let result = store
+            .get_all_accounts()
+            .await
+            .unwrap()
+            .map(|account| account.client)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result, vec![1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn get_all_accounts_streams_large_stores() {
+        let accounts = (1..=50_000)
+            .rev()
+            .map(|client| (client, Account::seeded(client, dec!(1.5), dec!(0), false)))
+            .collect::<HashMap<_, _>>();
+        let store = MemoryStore::seeded(None, Some(accounts));
+
+        let result = store
+            .get_all_accounts()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result.len(), 50_000);
+        assert!(result.iter().zip(1..).all(
+            |(account, client)| *account == Account::seeded(client, dec!(1.5), dec!(0), false)
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_all_accounts_streams_the_latest_state_of_the_accounts() {
+        let store = MemoryStore::new();
+        store.upsert_account(&Account::new(1)).await.unwrap();
+        store.upsert_account(&Account::new(2)).await.unwrap();
+
+        let stream = store.get_all_accounts().await.unwrap();
+        let updated = Account::seeded(2, dec!(10), dec!(0), true);
+        store.upsert_account(&updated).await.unwrap();
+
+        assert_eq!(
+            stream.collect::<Vec<_>>().await,
+            vec![Account::new(1), updated]
+        );
+    }
+
+    #[tokio::test]
+    async fn get_transaction_returns_not_found_if_transaction_does_not_exist() {
+        let transaction_id = 1;
+        let transaction = Transaction::deposit(transaction_id, 1, dec!(1.0001));
+        let mut deposits = HashMap::new();
         deposits.insert(transaction_id, transaction.clone());
 
         let store = MemoryStore::seeded(Some(deposits), None);
@@ -495,6 +1200,73 @@ mod tests {
         assert!(chargeback.is_err());
     }
 
+    #[tokio::test]
+    async fn create_transaction_rejects_duplicated_ids_of_any_kind() {
+        let store = MemoryStore::new();
+        let adjustment = Transaction::adjustment(1, 1, dec!(1), "fee refund");
+        store.create_transaction(adjustment.clone()).await.unwrap();
+        store
+            .create_transaction(Transaction::open_account(2, 2, false))
+            .await
+            .unwrap();
+        store
+            .create_transaction(Transaction::close_account(3, 2))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.create_transaction(adjustment).await,
+            Err(StoreError::AlreadyExists { id: 1 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::deposit(2, 1, dec!(1)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 2 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::close_account(3, 2))
+                .await,
+            Err(StoreError::AlreadyExists { id: 3 })
+        );
+        // but only their ids are kept
+        assert_eq!(store.transactions_len(), 0);
+    }
+
+    #[tokio::test]
+    async fn create_transaction_does_not_save_withdrawals_by_default() {
+        let store = MemoryStore::new();
+        let withdrawal = Transaction::withdrawal(1, 1, dec!(1.0001));
+
+        store.create_transaction(withdrawal).await.unwrap();
+
+        assert!(store.get_transaction(1).await.is_err());
+        assert_eq!(store.transactions_len(), 0);
+    }
+
+    #[tokio::test]
+    async fn create_transaction_saves_withdrawals_if_enabled() {
+        let store = MemoryStore::storing_withdrawals();
+        let deposit = Transaction::deposit(1, 1, dec!(2));
+        let withdrawal = Transaction::withdrawal(2, 1, dec!(1.0001));
+
+        store.create_transaction(deposit.clone()).await.unwrap();
+        store.create_transaction(withdrawal.clone()).await.unwrap();
+        store
+            .create_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(store.get_transaction(1).await.unwrap(), deposit);
+        assert_eq!(store.get_transaction(2).await.unwrap(), withdrawal);
+        assert_eq!(store.transactions_len(), 2);
+        assert_eq!(
+            store.create_transaction(withdrawal).await,
+            Err(StoreError::AlreadyExists { id: 2 })
+        );
+    }
+
     #[tokio::test]
     async fn delete_transaction_works() {
         let txs = vec![
@@ -516,6 +1288,168 @@ mod tests {
         assert!(store.delete_transaction(1).await.is_ok());
     }
 
+    #[tokio::test]
+    async fn get_transactions_for_client_only_returns_their_transactions() {
+        let store = MemoryStore::storing_withdrawals();
+        let transactions = vec![
+            Transaction::deposit(3, 1, dec!(3)),
+            Transaction::deposit(1, 1, dec!(1)),
+            Transaction::deposit(2, 2, dec!(2)),
+            Transaction::withdrawal(4, 1, dec!(1)),
+        ];
+        for transaction in transactions {
+            store.create_transaction(transaction).await.unwrap();
+        }
+        store
+            .add_pending_transaction(Transaction::deposit(5, 1, dec!(5)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_transactions_for_client(1).await,
+            Ok(vec![
+                Transaction::deposit(1, 1, dec!(1)),
+                Transaction::deposit(3, 1, dec!(3)),
+                Transaction::withdrawal(4, 1, dec!(1)),
+            ])
+        );
+        assert_eq!(
+            store.get_transactions_for_client(2).await,
+            Ok(vec![Transaction::deposit(2, 2, dec!(2))])
+        );
+        assert_eq!(store.get_transactions_for_client(3).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn duplicated_withdrawals_are_rejected_even_if_not_stored() {
+        let store = MemoryStore::new();
+        store
+            .create_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        store
+            .create_transaction(Transaction::withdrawal(2, 1, dec!(5)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store
+                .create_transaction(Transaction::withdrawal(2, 1, dec!(5)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 2 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::withdrawal(1, 1, dec!(5)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 1 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::deposit(2, 1, dec!(5)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 2 })
+        );
+        assert_eq!(
+            store.get_transaction(2).await,
+            Err(StoreError::NotFound { id: 2 })
+        );
+
+        // rolled back withdrawals can be retried
+        store.delete_transaction(2).await.unwrap();
+        assert!(store
+            .create_transaction(Transaction::withdrawal(2, 1, dec!(5)))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn restored_snapshots_behave_like_the_original_store() {
+        let store = MemoryStore::new();
+        store
+            .create_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        store
+            .create_transaction(Transaction::deposit(2, 2, dec!(5)))
+            .await
+            .unwrap();
+        store
+            .create_transaction(Transaction::withdrawal(3, 1, dec!(4)))
+            .await
+            .unwrap();
+        store
+            .set_dispute_state(1, DisputeState::Open)
+            .await
+            .unwrap();
+        store
+            .upsert_account(&Account::seeded(1, dec!(6), dec!(10), false))
+            .await
+            .unwrap();
+        store
+            .upsert_account(&Account::seeded(2, dec!(5), dec!(0), false))
+            .await
+            .unwrap();
+        store
+            .add_pending_transaction(Transaction::deposit(4, 2, dec!(1)))
+            .await
+            .unwrap();
+
+        let snapshot = store.snapshot().unwrap();
+        let json = snapshot.to_json().unwrap();
+        let restored = MemoryStore::from_snapshot(StoreSnapshot::from_json(&json).unwrap());
+
+        assert_eq!(restored.snapshot(), Ok(snapshot));
+        assert_eq!(
+            restored.get_transaction(1).await,
+            store.get_transaction(1).await
+        );
+        assert_eq!(restored.dispute_count(1).await, Ok(1));
+        assert_eq!(
+            restored.get_account(1).await,
+            Ok(Some(Account::seeded(1, dec!(6), dec!(10), false)))
+        );
+        assert_eq!(
+            restored.get_pending_transactions().await,
+            Ok(vec![Transaction::deposit(4, 2, dec!(1))])
+        );
+        assert_eq!(
+            restored
+                .create_transaction(Transaction::withdrawal(3, 1, dec!(4)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 3 })
+        );
+        // both stores are independent
+        restored.delete_transaction(2).await.unwrap();
+        assert!(store.get_transaction(2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn pending_transactions_are_kept_apart() {
+        let store = MemoryStore::new();
+
+        store
+            .add_pending_transaction(Transaction::deposit(2, 1, dec!(2)))
+            .await
+            .unwrap();
+        store
+            .add_pending_transaction(Transaction::deposit(1, 1, dec!(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_pending_transactions().await,
+            Ok(vec![
+                Transaction::deposit(1, 1, dec!(1)),
+                Transaction::deposit(2, 1, dec!(2)),
+            ])
+        );
+        assert_eq!(
+            store.get_transaction(1).await,
+            Err(StoreError::NotFound { id: 1 })
+        );
+    }
+
     #[tokio::test]
     async fn get_account_works() {
         let account = Account::seeded(1, dec!(10.3001), dec!(5.40), false);
@@ -526,14 +1460,13 @@ mod tests {
 
         let result = store.get_account(account.client).await;
 
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), account);
+        assert_eq!(result, Ok(Some(account)));
     }
 
     #[tokio::test]
-    async fn get_account_returns_new_account_if_not_exists_but_does_not_create_it() {
+    async fn get_or_default_account_returns_new_account_if_not_exists_but_does_not_create_it() {
         let store = MemoryStore::new();
-        let result = store.get_account(1).await;
+        let result = store.get_or_default_account(1).await;
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), Account::new(1));
@@ -547,12 +1480,11 @@ mod tests {
 
         let result = store.upsert_account(&account).await;
 
-        assert!(result.is_ok());
+        assert_eq!(result, Ok(UpsertOutcome::Created));
 
         let result = store.get_account(1).await;
 
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), account);
+        assert_eq!(result, Ok(Some(account)));
         assert_eq!(store.accounts_len(), 1);
     }
 
@@ -567,12 +1499,394 @@ mod tests {
 
         let result = store.upsert_account(&update).await;
 
-        assert!(result.is_ok());
+        assert_eq!(result, Ok(UpsertOutcome::Updated));
 
         let result = store.get_account(1).await;
 
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), update);
+        assert_eq!(result, Ok(Some(update)));
         assert_eq!(store.accounts_len(), 1);
     }
+
+    #[tokio::test]
+    async fn load_accounts_inserts_and_replaces_accounts() {
+        let mut accounts = HashMap::new();
+        accounts.insert(1, Account::new(1));
+        let store = MemoryStore::seeded(None, Some(accounts));
+        let loaded = vec![
+            Account::seeded(1, dec!(10), dec!(2), true),
+            Account::seeded(2, dec!(3.5), Amount::ZERO, false),
+        ];
+
+        store.load_accounts(loaded.clone()).await.unwrap();
+
+        assert_eq!(store.accounts_len(), 2);
+        assert_eq!(store.get_accounts_page(None, 10).await, Ok(loaded));
+    }
+
+    #[tokio::test]
+    async fn upsert_accounts_upserts_the_whole_batch() {
+        let store = MemoryStore::new();
+        store.upsert_account(&Account::new(1)).await.unwrap();
+        let batch = vec![
+            Account::seeded(1, dec!(10), dec!(2), false),
+            Account::seeded(2, dec!(3.5), Amount::ZERO, true),
+            Account::new(3),
+        ];
+
+        store.upsert_accounts(&batch).await.unwrap();
+
+        assert_eq!(store.accounts_len(), 3);
+        for account in batch {
+            assert_eq!(store.get_account(account.client).await, Ok(Some(account)));
+        }
+    }
+
+    #[tokio::test]
+    async fn upsert_accounts_fails_if_the_store_fails() {
+        let store = MemoryStore::new();
+        store.set_enable_upsert_account_failure(true);
+
+        let result = store.upsert_accounts(&[Account::new(1)]).await;
+
+        assert!(matches!(result, Err(StoreError::AccessError(_))));
+        assert_eq!(store.accounts_len(), 0);
+    }
+
+    #[tokio::test]
+    async fn upsert_account_reports_created_only_on_first_upsert() {
+        let store = MemoryStore::new();
+
+        let outcomes = vec![
+            store.upsert_account(&Account::new(1)).await,
+            store.upsert_account(&Account::new(2)).await,
+            store
+                .upsert_account(&Account::seeded(1, dec!(1), Amount::ZERO, false))
+                .await,
+            store.upsert_account(&Account::new(1)).await,
+        ];
+
+        assert_eq!(
+            outcomes,
+            vec![
+                Ok(UpsertOutcome::Created),
+                Ok(UpsertOutcome::Created),
+                Ok(UpsertOutcome::Updated),
+                Ok(UpsertOutcome::Updated),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dispute_count_is_incremented_on_every_dispute() {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit(1, 1, dec!(1)));
+        let store = MemoryStore::seeded(Some(deposits), None);
+
+        assert_eq!(store.dispute_count(1).await, Ok(0));
+
+        store
+            .set_dispute_state(1, DisputeState::Open)
+            .await
+            .unwrap();
+        assert_eq!(store.dispute_count(1).await, Ok(1));
+
+        store
+            .set_dispute_state(1, DisputeState::Resolved)
+            .await
+            .unwrap();
+        assert_eq!(store.dispute_count(1).await, Ok(1));
+
+        store
+            .set_dispute_state(1, DisputeState::Open)
+            .await
+            .unwrap();
+        assert_eq!(store.dispute_count(1).await, Ok(2));
+    }
+
+    #[tokio::test]
+    async fn set_disputed_amount_works() {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit(1, 1, dec!(100)));
+        let store = MemoryStore::seeded(Some(deposits), None);
+
+        store.set_disputed_amount(1, Some(dec!(30))).await.unwrap();
+
+        assert_eq!(
+            store.get_transaction(1).await.unwrap().disputed_amount(),
+            Some(dec!(30))
+        );
+    }
+
+    #[tokio::test]
+    async fn set_transaction_amount_updates_stored_transactions_only() {
+        let store = MemoryStore::storing_withdrawals();
+        store
+            .create_transaction(Transaction::withdrawal(1, 1, dec!(25)))
+            .await
+            .unwrap();
+
+        store.set_transaction_amount(1, dec!(10)).await.unwrap();
+        store.set_transaction_amount(2, dec!(10)).await.unwrap();
+
+        assert_eq!(
+            store.get_transaction(1).await,
+            Ok(Transaction::withdrawal(1, 1, dec!(10)))
+        );
+        assert_eq!(
+            store.get_transaction(2).await,
+            Err(StoreError::NotFound { id: 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn dispute_count_returns_not_found_if_transaction_does_not_exist() {
+        let store = MemoryStore::new();
+
+        let result = store.dispute_count(1).await;
+
+        assert_eq!(result, Err(StoreError::NotFound { id: 1 }));
+    }
+
+    #[tokio::test]
+    async fn get_accounts_page_returns_empty_page_if_no_accounts() {
+        let store = MemoryStore::new();
+
+        let result = store.get_accounts_page(None, 10).await;
+
+        assert_eq!(result, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn get_accounts_page_iterates_in_chunks() {
+        let mut accounts = HashMap::new();
+        for client in [5, 1, 4, 2, 3] {
+            accounts.insert(client, Account::new(client));
+        }
+        let store = MemoryStore::seeded(None, Some(accounts));
+
+        // full pages
+        let first = store.get_accounts_page(None, 2).await.unwrap();
+        assert_eq!(first, vec![Account::new(1), Account::new(2)]);
+        let second = store.get_accounts_page(Some(2), 2).await.unwrap();
+        assert_eq!(second, vec![Account::new(3), Account::new(4)]);
+        // partial page
+        let third = store.get_accounts_page(Some(4), 2).await.unwrap();
+        assert_eq!(third, vec![Account::new(5)]);
+        // nothing else
+        let fourth = store.get_accounts_page(Some(5), 2).await.unwrap();
+        assert_eq!(fourth, vec![]);
+    }
+
+    #[tokio::test]
+    async fn get_accounts_at_offset_returns_stable_pages_without_gaps() {
+        let mut accounts = HashMap::new();
+        for client in [5, 1, 4, 2, 3] {
+            accounts.insert(client, Account::new(client));
+        }
+        let store = MemoryStore::seeded(None, Some(accounts));
+
+        let mut clients = vec![];
+        for offset in (0..6).step_by(2) {
+            let page = store.get_accounts_at_offset(offset, 2).await.unwrap();
+            // same page on every call
+            assert_eq!(
+                store.get_accounts_at_offset(offset, 2).await,
+                Ok(page.clone())
+            );
+            clients.extend(page.into_iter().map(|account| account.client));
+        }
+
+        assert_eq!(clients, vec![1, 2, 3, 4, 5]);
+        assert_eq!(store.get_accounts_at_offset(5, 2).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn get_client_ids_returns_sorted_ids() {
+        let mut accounts = HashMap::new();
+        accounts.insert(3, Account::new(3));
+        accounts.insert(1, Account::new(1));
+        accounts.insert(2, Account::new(2));
+        let store = MemoryStore::seeded(None, Some(accounts));
+
+        let result = store.get_client_ids().await;
+
+        assert_eq!(result, Ok(vec![1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn open_disputes_count_stays_in_sync_with_the_dispute_states() {
+        let store = MemoryStore::new();
+        for (id, client) in [(1, 1), (2, 1), (3, 2)] {
+            store
+                .create_transaction(Transaction::deposit(id, client, dec!(1)))
+                .await
+                .unwrap();
+        }
+        store
+            .create_transaction(Transaction::deposit_under_dispute(4, 1, dec!(1)))
+            .await
+            .unwrap();
+        assert_eq!(store.open_disputes_count(1).await, Ok(1));
+
+        let steps = [
+            (1, DisputeState::Open, 2),
+            (1, DisputeState::Open, 2),
+            (2, DisputeState::Open, 3),
+            (1, DisputeState::UnderReview, 3),
+            (1, DisputeState::Resolved, 2),
+            (2, DisputeState::ChargedBack, 1),
+            (1, DisputeState::Open, 2),
+        ];
+        for (id, state, expected) in steps {
+            store.set_dispute_state(id, state).await.unwrap();
+            assert_eq!(store.open_disputes_count(1).await, Ok(expected));
+        }
+        assert_eq!(store.open_disputes_count(2).await, Ok(0));
+
+        store.delete_transaction(4).await.unwrap();
+        store.reserve_transaction_id(1).await.unwrap();
+        assert_eq!(store.open_disputes_count(1).await, Ok(0));
+        // dispute states of transactions which are not stored are not counted
+        store
+            .set_dispute_state(5, DisputeState::Open)
+            .await
+            .unwrap();
+        assert_eq!(store.open_disputes_count(1).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn get_disputed_transactions_only_streams_disputed_deposits() {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit(1, 1, dec!(1)));
+        deposits.insert(2, Transaction::deposit_under_dispute(2, 1, dec!(2)));
+        deposits.insert(3, Transaction::deposit(3, 2, dec!(3)));
+        deposits.insert(4, Transaction::deposit_under_dispute(4, 2, dec!(4)));
+        let store = MemoryStore::seeded(Some(deposits), None);
+        store
+            .set_dispute_state(3, DisputeState::Resolved)
+            .await
+            .unwrap();
+
+        let disputed = store
+            .get_disputed_transactions()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            disputed,
+            vec![
+                Transaction::deposit_under_dispute(2, 1, dec!(2)),
+                Transaction::deposit_under_dispute(4, 2, dec!(4)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_dispute_consistency_returns_no_mismatches_if_consistent() {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit_under_dispute(1, 1, dec!(10)));
+        deposits.insert(2, Transaction::deposit_under_dispute(2, 1, dec!(5.5)));
+        deposits.insert(3, Transaction::deposit(3, 1, dec!(100)));
+        deposits.insert(4, Transaction::deposit(4, 2, dec!(20)));
+        let mut accounts = HashMap::new();
+        accounts.insert(1, Account::seeded(1, dec!(100), dec!(15.5), false));
+        accounts.insert(2, Account::seeded(2, dec!(20), Amount::ZERO, false));
+
+        let store = MemoryStore::seeded(Some(deposits), Some(accounts));
+
+        let result = store.verify_dispute_consistency().await;
+
+        assert_eq!(result.unwrap(), vec![]);
+    }
+
+    #[tokio::test]
+    async fn verify_dispute_consistency_reports_mismatches() {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit_under_dispute(1, 1, dec!(10)));
+        deposits.insert(2, Transaction::deposit(2, 2, dec!(20)));
+        deposits.insert(3, Transaction::deposit_under_dispute(3, 3, dec!(30)));
+        let mut accounts = HashMap::new();
+        // held should be 10
+        accounts.insert(1, Account::seeded(1, Amount::ZERO, dec!(5), false));
+        // held should be 0
+        accounts.insert(2, Account::seeded(2, dec!(10), dec!(10), false));
+        // account 3 was never persisted
+
+        let store = MemoryStore::seeded(Some(deposits), Some(accounts));
+
+        let result = store.verify_dispute_consistency().await;
+
+        assert_eq!(
+            result.unwrap(),
+            vec![
+                DisputeMismatch {
+                    client: 1,
+                    held: dec!(5),
+                    disputed: dec!(10),
+                },
+                DisputeMismatch {
+                    client: 2,
+                    held: dec!(10),
+                    disputed: Amount::ZERO,
+                },
+                DisputeMismatch {
+                    client: 3,
+                    held: Amount::ZERO,
+                    disputed: dec!(30),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn get_account_returns_none_if_account_does_not_exist() {
+        let mut accounts = HashMap::new();
+        accounts.insert(1, Account::new(1));
+        let store = MemoryStore::seeded(None, Some(accounts));
+
+        assert_eq!(store.get_account(1).await, Ok(Some(Account::new(1))));
+        assert_eq!(store.get_account(2).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn get_account_and_transaction_works() {
+        let transaction = Transaction::deposit(1, 1, dec!(10));
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
+        let mut deposits = HashMap::new();
+        deposits.insert(1, transaction.clone());
+        let mut accounts = HashMap::new();
+        accounts.insert(1, account.clone());
+
+        let store = MemoryStore::seeded(Some(deposits), Some(accounts));
+
+        let result = store.get_account_and_transaction(1, 1).await;
+        assert_eq!(result.unwrap(), (account.clone(), Some(transaction)));
+
+        let result = store.get_account_and_transaction(1, 2).await;
+        assert_eq!(result.unwrap(), (account, None));
+
+        let result = store.get_account_and_transaction(2, 2).await;
+        assert_eq!(result.unwrap(), (Account::new(2), None));
+    }
+
+    #[tokio::test]
+    async fn set_dispute_state_works() {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit(1, 1, dec!(10)));
+
+        let store = MemoryStore::seeded(Some(deposits), None);
+
+        store
+            .set_dispute_state(1, DisputeState::UnderReview)
+            .await
+            .unwrap();
+
+        let result = store.get_transaction(1).await.unwrap();
+        assert_eq!(result.dispute_state(), Some(DisputeState::UnderReview));
+
+        // setting the state of a non-existing transaction should not fail
+        assert!(store.set_dispute_state(2, DisputeState::Open).await.is_ok());
+    }
 }
@@ -0,0 +1,149 @@
+use payments_engine_core::{
+    account::Account,
+    migration::{load_versioned, StoreMigration},
+    store::{StoreError, StoreResult},
+    transaction::{Transaction, TransactionId},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+/// Current version of the [`StoreSnapshot`] format.
+/// It must be bumped, along with a new entry in [`MIGRATIONS`], whenever the format changes.
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Upgrades of the older versions of the [`StoreSnapshot`] format, in order.
+const MIGRATIONS: [&dyn StoreMigration; 1] = [&AddDisputesAndPending];
+
+/// Upgrades the version 1, which only held the deposits and the accounts,
+/// adding the dispute counts, the pending transactions and the withdrawal ids with their empty defaults.
+struct AddDisputesAndPending;
+
+impl StoreMigration for AddDisputesAndPending {
+    fn source_version(&self) -> u32 {
+        1
+    }
+
+    fn migrate(&self, mut state: Value) -> StoreResult<Value> {
+        let snapshot = state.as_object_mut().ok_or_else(|| {
+            tracing::error!("Snapshot of version 1 is not an object");
+            StoreError::MigrationError("snapshot is not an object".to_string())
+        })?;
+        snapshot.insert("dispute_counts".to_string(), Value::Object(Map::new()));
+        snapshot.insert("pending".to_string(), Value::Array(Vec::new()));
+        snapshot.insert("reserved_ids".to_string(), Value::Array(Vec::new()));
+        snapshot.insert("store_withdrawals".to_string(), Value::Bool(false));
+        Ok(state)
+    }
+}
+
+/// Serializable state of an in-memory store, tagged with the version of its format
+/// so snapshots written by an older engine can still be loaded.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoreSnapshot {
+    /// Version of the format, see [`SNAPSHOT_VERSION`].
+    pub version: u32,
+    /// The stored deposits and withdrawals, sorted by id.
+    pub deposits: Vec<Transaction>,
+    /// The accounts, sorted by client.
+    pub accounts: Vec<Account>,
+    /// How many times every transaction has been disputed.
+    pub dispute_counts: BTreeMap<TransactionId, u32>,
+    /// The pending transactions, sorted by id.
+    pub pending: Vec<Transaction>,
+    /// The reserved ids of the transactions which are not stored, e.g. withdrawals or adjustments, sorted.
+    pub reserved_ids: Vec<TransactionId>,
+    /// Whether withdrawals are stored along with the deposits.
+    pub store_withdrawals: bool,
+}
+
+impl StoreSnapshot {
+    /// Creates a new [`StoreSnapshot`] of the current version with the given deposits and accounts.
+    #[must_use]
+    pub fn new(mut deposits: Vec<Transaction>, mut accounts: Vec<Account>) -> Self {
+        deposits.sort_by_key(|deposit| deposit.info().id);
+        accounts.sort_by_key(|account| account.client);
+        Self {
+            version: SNAPSHOT_VERSION,
+            deposits,
+            accounts,
+            dispute_counts: BTreeMap::new(),
+            pending: Vec::new(),
+            reserved_ids: Vec::new(),
+            store_withdrawals: false,
+        }
+    }
+
+    /// Serializes the [`StoreSnapshot`] as JSON.
+    pub fn to_json(&self) -> StoreResult<String> {
+        serde_json::to_string(self).map_err(|e| {
+            tracing::error!("Error while serializing the snapshot: {}", e);
+            StoreError::AccessError(e.to_string())
+        })
+    }
+
+    /// Deserializes a [`StoreSnapshot`] from JSON, upgrading it to the [`SNAPSHOT_VERSION`] if it's older.
+    pub fn from_json(json: &str) -> StoreResult<Self> {
+        load_versioned(json, SNAPSHOT_VERSION, &MIGRATIONS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use payments_engine_core::dec;
+
+    #[test]
+    fn snapshots_round_trip_as_json() {
+        let snapshot = StoreSnapshot::new(
+            vec![
+                Transaction::deposit(2, 1, dec!(5)),
+                Transaction::deposit(1, 1, dec!(10)),
+            ],
+            vec![Account::seeded(1, dec!(15), dec!(0), false)],
+        );
+
+        let json = snapshot.to_json().unwrap();
+
+        assert_eq!(snapshot.deposits[0], Transaction::deposit(1, 1, dec!(10)));
+        assert_eq!(StoreSnapshot::from_json(&json), Ok(snapshot));
+    }
+
+    #[test]
+    fn snapshots_of_version_1_are_migrated() {
+        let deposit = serde_json::to_string(&Transaction::deposit(1, 1, dec!(10))).unwrap();
+        let json = format!(r#"{{"version":1,"deposits":[{}],"accounts":[]}}"#, deposit);
+
+        let snapshot = StoreSnapshot::from_json(&json).unwrap();
+
+        assert_eq!(snapshot.version, SNAPSHOT_VERSION);
+        assert_eq!(
+            snapshot.deposits,
+            vec![Transaction::deposit(1, 1, dec!(10))]
+        );
+        assert!(snapshot.dispute_counts.is_empty());
+        assert!(snapshot.pending.is_empty());
+        assert!(snapshot.reserved_ids.is_empty());
+        assert!(!snapshot.store_withdrawals);
+    }
+
+    #[test]
+    fn snapshots_of_the_current_version_must_have_every_field() {
+        let json = r#"{"version":2,"deposits":[],"accounts":[]}"#;
+
+        assert!(matches!(
+            StoreSnapshot::from_json(json),
+            Err(StoreError::MigrationError(_))
+        ));
+    }
+
+    #[test]
+    fn snapshots_of_newer_versions_are_rejected() {
+        let json = r#"{"version":3,"deposits":[],"accounts":[]}"#;
+
+        assert!(matches!(
+            StoreSnapshot::from_json(json),
+            Err(StoreError::MigrationError(_))
+        ));
+    }
+}
@@ -0,0 +1,10 @@
+//! Redis ['`Store`'] implementation.
+//!
+//! Useful to run several engine workers against a shared state, as every worker sees the same transactions and accounts.
+//! Note that the clients must be partitioned across the workers, as accounts are not updated atomically.
+//!
+//! The integration tests need a running Redis server, so they're only run with the `integration-tests` feature.
+//! The server is read from the `REDIS_URL` environment variable, `redis://127.0.0.1:6379` by default.
+mod redis_store;
+
+pub use redis_store::RedisStore;
@@ -0,0 +1,1064 @@
+use async_trait::async_trait;
+use futures::StreamExt;
+use payments_engine_core::{
+    account::Account,
+    common::{Amount, ClientId},
+    store::{DisputeMismatch, Store, StoreError, StoreResult, UpsertOutcome},
+    transaction::{DisputeState, Transaction, TransactionId},
+};
+use redis::{aio::MultiplexedConnection, AsyncCommands, Script};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    pin::Pin,
+};
+use tracing::instrument;
+
+/// Prefix of the keys used by default.
+const DEFAULT_PREFIX: &str = "payments";
+
+/// Number of keys requested per `SCAN` while streaming the accounts.
+const SCAN_COUNT: usize = 100;
+
+// fields of the transaction hashes. the scripts below use them too.
+const TRANSACTION_FIELD: &str = "transaction";
+const DISPUTE_STATE_FIELD: &str = "dispute_state";
+const DISPUTED_AMOUNT_FIELD: &str = "disputed_amount";
+const DISPUTE_COUNT_FIELD: &str = "dispute_count";
+
+/// Sets the dispute state of an existing transaction, incrementing its dispute counter if it gets opened
+/// and keeping the set of disputed transactions up to date, all at once.
+///
+/// - `KEYS`: the transaction hash and the set of disputed transactions.
+/// - `ARGV`: the new state, the open state, the transaction id and `1` if the new state is under dispute.
+const SET_DISPUTE_STATE_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+if ARGV[1] == ARGV[2] and redis.call('HGET', KEYS[1], 'dispute_state') ~= ARGV[2] then
+    redis.call('HINCRBY', KEYS[1], 'dispute_count', 1)
+end
+redis.call('HSET', KEYS[1], 'dispute_state', ARGV[1])
+if ARGV[4] == '1' then
+    redis.call('SADD', KEYS[2], ARGV[3])
+else
+    redis.call('SREM', KEYS[2], ARGV[3])
+end
+return 1
+";
+
+/// Sets the disputed amount of an existing transaction.
+///
+/// - `KEYS`: the transaction hash.
+/// - `ARGV`: the new disputed amount.
+const SET_DISPUTED_AMOUNT_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+redis.call('HSET', KEYS[1], 'disputed_amount', ARGV[1])
+return 1
+";
+
+/// Replaces the serialized transaction of an existing transaction hash, keeping its dispute fields.
+///
+/// - `KEYS`: the transaction hash.
+/// - `ARGV`: the new serialized transaction.
+const SET_TRANSACTION_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+    return 0
+end
+redis.call('HSET', KEYS[1], 'transaction', ARGV[1])
+return 1
+";
+
+/// Redis implementation of the Store trait.
+///
+/// Several [`RedisStore`] connected to the same server and using the same prefix share the same state.
+/// Note that cloning a [`RedisStore`] shares the same multiplexed connection.
+///
+/// # Concurrency
+/// Accounts and dispute states are written blindly, without checking what was read before,
+/// so two engines updating the same client at once may lose one of the updates,
+/// e.g. both could dispute or charge back the same deposit.
+/// Engines sharing a store must therefore process disjoint sets of clients,
+/// e.g. by partitioning the transactions by client across them.
+/// Only the id reservations are atomic, so a transaction is never processed twice.
+///
+/// Every key starts with the prefix, `payments` by default:
+/// - `{prefix}:id:{id}` reserves the id of every transaction not referencing another one with `SET NX`, so duplicates are rejected.
+/// - `{prefix}:tx:{id}` is a hash with the stored transaction and its dispute state, disputed amount and dispute count.
+/// - `{prefix}:client:{client}:tx` is the set of ids of the stored transactions of a client.
+/// - `{prefix}:disputed` is the set of ids of the transactions under dispute.
+/// - `{prefix}:account:{client}` holds the account of a client.
+/// - `{prefix}:pending` is a hash with the pending transactions by id.
+///
+/// Accounts and transactions are serialized as JSON. Updating an account needs Redis 6.2 or newer.
+///
+/// # Important
+/// Like the `MemoryStore`, this store only cares about [`Transaction::Deposit`] transactions,
+/// so all the other variants are not really stored.
+/// Storing [`Transaction::Withdrawal`] transactions too can be enabled with [`RedisStore::with_withdrawals`].
+/// Otherwise, only the ids of the withdrawals are kept, so replayed withdrawals are rejected too.
+/// The ids of adjustments and account openings and closings are kept as well, so they can't be replayed either.
+#[derive(Clone)]
+pub struct RedisStore {
+    connection: MultiplexedConnection,
+    keys: Keys,
+    store_withdrawals: bool,
+}
+
+impl fmt::Debug for RedisStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisStore")
+            .field("keys", &self.keys)
+            .field("store_withdrawals", &self.store_withdrawals)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RedisStore {
+    /// Connects to the Redis server at the given url, e.g. `redis://127.0.0.1:6379`.
+    pub async fn connect(url: &str) -> StoreResult<Self> {
+        let client = redis::Client::open(url).map_err(access_error)?;
+        let connection = client
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| {
+                tracing::error!("Error while connecting to Redis: {}", e);
+                access_error(e)
+            })?;
+        Ok(Self {
+            connection,
+            keys: Keys::new(DEFAULT_PREFIX),
+            store_withdrawals: false,
+        })
+    }
+
+    /// Uses the given prefix for all the keys, so several stores can share the same Redis server.
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.keys = Keys::new(prefix);
+        self
+    }
+
+    /// Stores [`Transaction::Withdrawal`] transactions too, so they can be disputed.
+    #[must_use]
+    pub const fn with_withdrawals(mut self, enabled: bool) -> Self {
+        self.store_withdrawals = enabled;
+        self
+    }
+
+    /// Deletes all the keys of the store's prefix, leaving it empty.
+    #[instrument(skip(self))]
+    pub async fn clear(&self) -> StoreResult<()> {
+        tracing::debug!("Clearing the store");
+        let mut connection = self.connection.clone();
+        let keys = scan_all(&mut connection, &self.keys.all()).await?;
+        if !keys.is_empty() {
+            connection.del::<_, ()>(keys).await.map_err(access_error)?;
+        }
+        Ok(())
+    }
+
+    /// Gets the stored transactions with the given ids, sorted by id.
+    /// Transactions deleted in between are skipped.
+    async fn get_transactions(&self, ids: Vec<TransactionId>) -> StoreResult<Vec<Transaction>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut pipe = redis::pipe();
+        for id in &ids {
+            pipe.hgetall(self.keys.transaction(*id));
+        }
+        let fields: Vec<HashMap<String, String>> = pipe
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(access_error)?;
+        let mut transactions = fields
+            .into_iter()
+            .filter_map(|fields| transaction_from_fields(fields).transpose())
+            .collect::<StoreResult<Vec<_>>>()?;
+        transactions.sort_by_key(|transaction| transaction.info().id);
+        Ok(transactions)
+    }
+
+    /// Removes the stored transaction, and its id reservation unless `keep_id` is set.
+    async fn remove_transaction(&self, id: TransactionId, keep_id: bool) -> StoreResult<()> {
+        let transaction = match self.get_transaction(id).await {
+            Ok(transaction) => Some(transaction),
+            Err(StoreError::NotFound { .. }) => None,
+            Err(e) => return Err(e),
+        };
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        if keep_id {
+            pipe.set(self.keys.id(id), 1).ignore();
+        } else {
+            pipe.del(self.keys.id(id)).ignore();
+        }
+        pipe.del(self.keys.transaction(id))
+            .ignore()
+            .srem(self.keys.disputed(), id)
+            .ignore();
+        if let Some(transaction) = transaction {
+            pipe.srem(
+                self.keys.client_transactions(transaction.info().client_id),
+                id,
+            )
+            .ignore();
+        }
+        pipe.query_async(&mut self.connection.clone())
+            .await
+            .map_err(access_error)
+    }
+}
+
+#[async_trait]
+impl Store for RedisStore {
+    /// Gets a transaction by its id.
+    /// If it doesn't exist, it returns an [`StoreError::NotFound].
+    #[instrument(skip(self))]
+    async fn get_transaction(&self, id: TransactionId) -> StoreResult<Transaction> {
+        tracing::debug!("Getting transaction {}", id);
+        let result = self
+            .connection
+            .clone()
+            .hgetall(self.keys.transaction(id))
+            .await
+            .map_err(access_error)
+            .and_then(transaction_from_fields)
+            .and_then(|transaction| transaction.ok_or(StoreError::NotFound { id }));
+
+        if result.is_err() {
+            tracing::error!("Error while getting transaction: {:?}", result);
+        }
+
+        result
+    }
+
+    /// Creates a new [`Transaction`] and returns it.
+    /// If the [`Transaction`] already exists, it returns an [`StoreError::AlreadyExists`].
+    /// Withdrawals are stored too if the store was created with [`RedisStore::with_withdrawals`].
+    /// Only the ids of the rest of transactions not referencing another one are kept.
+    #[instrument(skip(self))]
+    async fn create_transaction(&self, transaction: Transaction) -> StoreResult<Transaction> {
+        tracing::debug!("Creating transaction: {:?}", transaction);
+        let is_stored = match transaction {
+            Transaction::Deposit { .. } => true,
+            Transaction::Withdrawal { .. } => self.store_withdrawals,
+            _ if transaction.references_transaction() => return Ok(transaction),
+            _ => false,
+        };
+        let info = transaction.info().clone();
+        let mut connection = self.connection.clone();
+
+        let reserved: Option<String> = redis::cmd("SET")
+            .arg(self.keys.id(info.id))
+            .arg(1)
+            .arg("NX")
+            .query_async(&mut connection)
+            .await
+            .map_err(access_error)?;
+        if reserved.is_none() {
+            tracing::error!("Transaction {} already exists", info.id);
+            return Err(StoreError::AlreadyExists { id: info.id });
+        }
+        if !is_stored {
+            return Ok(transaction);
+        }
+
+        let dispute_state = transaction.dispute_state().unwrap_or_default();
+        let fields = [
+            (TRANSACTION_FIELD, to_json(&transaction)?),
+            (DISPUTE_STATE_FIELD, to_json(&dispute_state)?),
+            (
+                DISPUTED_AMOUNT_FIELD,
+                to_json(&disputed_amount(&transaction))?,
+            ),
+            (DISPUTE_COUNT_FIELD, "0".to_string()),
+        ];
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hset_multiple(self.keys.transaction(info.id), &fields)
+            .ignore()
+            .sadd(self.keys.client_transactions(info.client_id), info.id)
+            .ignore();
+        if dispute_state.is_under_dispute() {
+            pipe.sadd(self.keys.disputed(), info.id).ignore();
+        }
+        let result = pipe
+            .query_async::<()>(&mut connection)
+            .await
+            .map_err(access_error);
+
+        if result.is_err() {
+            tracing::error!("Error while trying to create transaction: {:?}", result);
+        }
+
+        result.map(|()| transaction)
+    }
+
+    /// Deletes a [`Transaction`].
+    #[instrument(skip(self))]
+    async fn delete_transaction(&self, id: TransactionId) -> StoreResult<()> {
+        tracing::debug!("Deleting transaction: {:?}", id);
+        self.remove_transaction(id, false).await
+    }
+
+    /// Drops the stored [`Transaction`], if any, but keeps its id reserved.
+    #[instrument(skip(self))]
+    async fn reserve_transaction_id(&self, id: TransactionId) -> StoreResult<()> {
+        tracing::debug!("Reserving transaction id: {:?}", id);
+        self.remove_transaction(id, true).await
+    }
+
+    /// Sets the [`DisputeState`] of a [`Transaction`].
+    /// Every time a [`Transaction`] gets into [`DisputeState::Open`], its dispute counter is incremented.
+    /// Both are updated atomically by a Lua script.
+    #[instrument(skip(self))]
+    async fn set_dispute_state(&self, id: TransactionId, state: DisputeState) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} dispute state to {:?}", id, state);
+        Script::new(SET_DISPUTE_STATE_SCRIPT)
+            .key(self.keys.transaction(id))
+            .key(self.keys.disputed())
+            .arg(to_json(&state)?)
+            .arg(to_json(&DisputeState::Open)?)
+            .arg(id)
+            .arg(u8::from(state.is_under_dispute()))
+            .invoke_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(access_error)
+    }
+
+    /// Sets the [`Amount`] held by a partial dispute of a [`Transaction`], or [`None`] if the whole amount is disputed.
+    #[instrument(skip(self))]
+    async fn set_disputed_amount(
+        &self,
+        id: TransactionId,
+        amount: Option<Amount>,
+    ) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} disputed amount to {:?}", id, amount);
+        Script::new(SET_DISPUTED_AMOUNT_SCRIPT)
+            .key(self.keys.transaction(id))
+            .arg(to_json(&amount)?)
+            .invoke_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(access_error)
+    }
+
+    /// Sets the [`Amount`] of a stored [`Transaction`] in place.
+    /// The hash is only written if it still exists, so deleted transactions are not brought back.
+    #[instrument(skip(self))]
+    async fn set_transaction_amount(&self, id: TransactionId, amount: Amount) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} amount to {}", id, amount);
+        let mut transaction = match self.get_transaction(id).await {
+            Ok(transaction) => transaction,
+            Err(StoreError::NotFound { .. }) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let Some(stored) = transaction.amount_mut() else {
+            return Ok(());
+        };
+        *stored = amount;
+        Script::new(SET_TRANSACTION_SCRIPT)
+            .key(self.keys.transaction(id))
+            .arg(to_json(&transaction)?)
+            .invoke_async::<()>(&mut self.connection.clone())
+            .await
+            .map_err(access_error)
+    }
+
+    /// Returns how many times a [`Transaction`] has been disputed.
+    /// If the [`Transaction`] doesn't exist, it returns an [`StoreError::NotFound`].
+    #[instrument(skip(self))]
+    async fn dispute_count(&self, id: TransactionId) -> StoreResult<u32> {
+        tracing::debug!("Getting dispute count of transaction {}", id);
+        let count: Option<u32> = self
+            .connection
+            .clone()
+            .hget(self.keys.transaction(id), DISPUTE_COUNT_FIELD)
+            .await
+            .map_err(access_error)?;
+        count.ok_or_else(|| {
+            tracing::error!("Transaction {} not found", id);
+            StoreError::NotFound { id }
+        })
+    }
+
+    /// Keeps a [`Transaction`] that was rejected but should be retained for later reconciliation.
+    #[instrument(skip(self))]
+    async fn add_pending_transaction(&self, transaction: Transaction) -> StoreResult<()> {
+        tracing::debug!("Adding pending transaction: {:?}", transaction);
+        self.connection
+            .clone()
+            .hset(
+                self.keys.pending(),
+                transaction.info().id,
+                to_json(&transaction)?,
+            )
+            .await
+            .map_err(access_error)
+    }
+
+    /// Returns the pending [`Transaction`], sorted by id.
+    #[instrument(skip(self))]
+    async fn get_pending_transactions(&self) -> StoreResult<Vec<Transaction>> {
+        let pending: Vec<String> = self
+            .connection
+            .clone()
+            .hvals(self.keys.pending())
+            .await
+            .map_err(access_error)?;
+        let mut pending = pending
+            .iter()
+            .map(|json| from_json::<Transaction>(json))
+            .collect::<StoreResult<Vec<_>>>()?;
+        pending.sort_by_key(|transaction| transaction.info().id);
+        Ok(pending)
+    }
+
+    /// Returns the stored [`Transaction`] of the client, sorted by id.
+    #[instrument(skip(self))]
+    async fn get_transactions_for_client(&self, client: ClientId) -> StoreResult<Vec<Transaction>> {
+        tracing::debug!("Getting transactions of client {}", client);
+        let ids: Vec<TransactionId> = self
+            .connection
+            .clone()
+            .smembers(self.keys.client_transactions(client))
+            .await
+            .map_err(access_error)?;
+        self.get_transactions(ids).await
+    }
+
+    /// Gets the current state of the [`Account`], or [`None`] if it doesn't exist.
+    #[instrument(skip(self))]
+    async fn get_account(&self, id: ClientId) -> StoreResult<Option<Account>> {
+        tracing::debug!("Getting account: {}", id);
+        let account: Option<String> = self
+            .connection
+            .clone()
+            .get(self.keys.account(id))
+            .await
+            .map_err(access_error)?;
+        account.as_deref().map(from_json).transpose()
+    }
+
+    /// Gets the current state of the [`Account`] and the [`Transaction`] with the given id in one round-trip.
+    #[instrument(skip(self))]
+    async fn get_account_and_transaction(
+        &self,
+        client: ClientId,
+        id: TransactionId,
+    ) -> StoreResult<(Account, Option<Transaction>)> {
+        tracing::debug!("Getting account {} and transaction {}", client, id);
+        let (account, fields): (Option<String>, HashMap<String, String>) = redis::pipe()
+            .get(self.keys.account(client))
+            .hgetall(self.keys.transaction(id))
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(access_error)?;
+        let account = account
+            .as_deref()
+            .map(from_json)
+            .transpose()?
+            .unwrap_or_else(|| Account::new(client));
+        Ok((account, transaction_from_fields(fields)?))
+    }
+
+    /// Updates the state of the [`Account`].
+    /// If the [`Account`] does not exist, it will create the [`Account`].
+    /// Returns whether the [`Account`] was created or updated.
+    #[instrument(skip(self))]
+    async fn upsert_account(&self, account: &Account) -> StoreResult<UpsertOutcome> {
+        tracing::debug!("Upserting account: {:?}", account);
+        let previous: Option<String> = redis::cmd("SET")
+            .arg(self.keys.account(account.client))
+            .arg(to_json(account)?)
+            .arg("GET")
+            .query_async(&mut self.connection.clone())
+            .await
+            .map_err(access_error)?;
+        Ok(match previous {
+            Some(_) => UpsertOutcome::Updated,
+            None => UpsertOutcome::Created,
+        })
+    }
+
+    /// Seeds the [`Store`] with the given accounts in a single `MSET`.
+    /// Existing accounts are replaced.
+    #[instrument(skip(self, accounts))]
+    async fn load_accounts(&self, accounts: Vec<Account>) -> StoreResult<()> {
+        tracing::debug!("Loading {} accounts", accounts.len());
+        if accounts.is_empty() {
+            return Ok(());
+        }
+        let accounts = accounts
+            .iter()
+            .map(|account| Ok((self.keys.account(account.client), to_json(account)?)))
+            .collect::<StoreResult<Vec<_>>>()?;
+        self.connection
+            .clone()
+            .mset(&accounts)
+            .await
+            .map_err(access_error)
+    }
+
+    /// Returns the current state of clients accounts, scanning the account keys.
+    /// The accounts are not sorted.
+    ///
+    /// The whole scan runs before the stream is returned,
+    /// so any error is reported instead of silently cutting the stream short.
+    #[instrument(skip(self))]
+    async fn get_all_accounts(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>> {
+        let mut connection = self.connection.clone();
+        let pattern = self.keys.accounts();
+        let mut accounts = Vec::new();
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let (next, page) = scan_accounts(&mut connection, &pattern, cursor)
+                .await
+                .map_err(|e| {
+                    tracing::error!("Error while scanning accounts: {:?}", e);
+                    e
+                })?;
+            // SCAN may return the same key more than once
+            accounts.extend(
+                page.into_iter()
+                    .filter(|account| seen.insert(account.client)),
+            );
+            // a zero cursor means the scan is complete
+            if next == 0 {
+                return Ok(Box::pin(futures::stream::iter(accounts)));
+            }
+            cursor = next;
+        }
+    }
+
+    /// Returns the ids of all the clients with an [`Account`], sorted.
+    #[instrument(skip(self))]
+    async fn get_client_ids(&self) -> StoreResult<Vec<ClientId>> {
+        tracing::debug!("Getting client ids");
+        let keys = scan_all(&mut self.connection.clone(), &self.keys.accounts()).await?;
+        let mut client_ids = keys
+            .iter()
+            .filter_map(|key| self.keys.client_of_account(key))
+            .collect::<Vec<_>>();
+        client_ids.sort_unstable();
+        client_ids.dedup();
+        Ok(client_ids)
+    }
+
+    /// Returns the deposits and withdrawals which are currently under dispute, sorted by id.
+    #[instrument(skip(self))]
+    async fn get_disputed_transactions(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Transaction> + Send>>> {
+        let ids: Vec<TransactionId> = self
+            .connection
+            .clone()
+            .smembers(self.keys.disputed())
+            .await
+            .map_err(access_error)?;
+        let disputed = self.get_transactions(ids).await?;
+        Ok(Box::pin(futures::stream::iter(disputed)))
+    }
+
+    /// Checks that the held funds of every [`Account`] equal the sum of its under dispute deposits and withdrawals.
+    #[instrument(skip(self))]
+    async fn verify_dispute_consistency(&self) -> StoreResult<Vec<DisputeMismatch>> {
+        tracing::debug!("Verifying dispute consistency");
+        let mut disputed: HashMap<ClientId, Amount> = HashMap::new();
+        let mut transactions = self.get_disputed_transactions().await?;
+        while let Some(transaction) = transactions.next().await {
+            if let Some(amount) = transaction.disputed_amount() {
+                *disputed.entry(transaction.info().client_id).or_default() += amount;
+            }
+        }
+
+        let held = self
+            .get_all_accounts()
+            .await?
+            .map(|account| (account.client, account.held))
+            .collect::<HashMap<_, _>>()
+            .await;
+
+        let mut clients = held
+            .keys()
+            .chain(disputed.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        clients.sort_unstable();
+        clients.dedup();
+
+        let mismatches = clients
+            .into_iter()
+            .filter_map(|client| {
+                let held = held.get(&client).copied().unwrap_or_default();
+                let disputed = disputed.get(&client).copied().unwrap_or_default();
+                (held != disputed).then_some(DisputeMismatch {
+                    client,
+                    held,
+                    disputed,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !mismatches.is_empty() {
+            tracing::error!("Inconsistent disputes found: {:?}", mismatches);
+        }
+
+        Ok(mismatches)
+    }
+}
+
+/// Builds the keys of a [`RedisStore`] from its prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Keys {
+    prefix: String,
+}
+
+impl Keys {
+    fn new(prefix: impl Into<String>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    fn id(&self, id: TransactionId) -> String {
+        format!("{}:id:{}", self.prefix, id)
+    }
+
+    fn transaction(&self, id: TransactionId) -> String {
+        format!("{}:tx:{}", self.prefix, id)
+    }
+
+    fn client_transactions(&self, client: ClientId) -> String {
+        format!("{}:client:{}:tx", self.prefix, client)
+    }
+
+    fn disputed(&self) -> String {
+        format!("{}:disputed", self.prefix)
+    }
+
+    fn pending(&self) -> String {
+        format!("{}:pending", self.prefix)
+    }
+
+    fn account(&self, client: ClientId) -> String {
+        format!("{}:account:{}", self.prefix, client)
+    }
+
+    /// Pattern matching the keys of all the accounts.
+    fn accounts(&self) -> String {
+        format!("{}:account:*", self.prefix)
+    }
+
+    /// Pattern matching all the keys.
+    fn all(&self) -> String {
+        format!("{}:*", self.prefix)
+    }
+
+    /// Parses the client of an account key.
+    fn client_of_account(&self, key: &str) -> Option<ClientId> {
+        key.strip_prefix(&self.prefix)?
+            .strip_prefix(":account:")?
+            .parse()
+            .ok()
+    }
+}
+
+/// Scans a page of the account keys from the given cursor and fetches them with a single `MGET`.
+/// Returns the cursor of the next page along with the accounts, skipping the ones deleted in between.
+async fn scan_accounts(
+    connection: &mut MultiplexedConnection,
+    pattern: &str,
+    cursor: u64,
+) -> StoreResult<(u64, Vec<Account>)> {
+    let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+        .arg(cursor)
+        .arg("MATCH")
+        .arg(pattern)
+        .arg("COUNT")
+        .arg(SCAN_COUNT)
+        .query_async(connection)
+        .await
+        .map_err(access_error)?;
+    if keys.is_empty() {
+        return Ok((next, Vec::new()));
+    }
+    let accounts: Vec<Option<String>> = redis::cmd("MGET")
+        .arg(&keys)
+        .query_async(connection)
+        .await
+        .map_err(access_error)?;
+    let accounts = accounts
+        .iter()
+        .flatten()
+        .map(|json| from_json(json))
+        .collect::<StoreResult<Vec<_>>>()?;
+    Ok((next, accounts))
+}
+
+/// Scans all the keys matching the given pattern.
+async fn scan_all(
+    connection: &mut MultiplexedConnection,
+    pattern: &str,
+) -> StoreResult<Vec<String>> {
+    let mut keys = Vec::new();
+    let mut cursor = 0;
+    loop {
+        let (next, page): (u64, Vec<String>) = redis::cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT)
+            .query_async(connection)
+            .await
+            .map_err(access_error)?;
+        keys.extend(page);
+        if next == 0 {
+            return Ok(keys);
+        }
+        cursor = next;
+    }
+}
+
+/// Builds the [`Transaction`] from the fields of its hash, or [`None`] if there are no fields as it doesn't exist.
+fn transaction_from_fields(fields: HashMap<String, String>) -> StoreResult<Option<Transaction>> {
+    let Some(transaction) = fields.get(TRANSACTION_FIELD) else {
+        return Ok(None);
+    };
+    let mut transaction: Transaction = from_json(transaction)?;
+    if let Some(state) = fields.get(DISPUTE_STATE_FIELD) {
+        transaction.set_dispute_state(from_json(state)?);
+    }
+    if let Some(amount) = fields.get(DISPUTED_AMOUNT_FIELD) {
+        transaction.set_disputed_amount(from_json(amount)?);
+    }
+    Ok(Some(transaction))
+}
+
+/// Returns the [`Amount`] held by a partial dispute, or [`None`] if the whole amount is disputed.
+const fn disputed_amount(transaction: &Transaction) -> Option<Amount> {
+    match transaction {
+        Transaction::Deposit {
+            disputed_amount, ..
+        }
+        | Transaction::Withdrawal {
+            disputed_amount, ..
+        } => *disputed_amount,
+        _ => None,
+    }
+}
+
+fn to_json<T: serde::Serialize + ?Sized>(value: &T) -> StoreResult<String> {
+    serde_json::to_string(value).map_err(access_error)
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(json: &str) -> StoreResult<T> {
+    serde_json::from_str(json).map_err(access_error)
+}
+
+fn access_error(e: impl fmt::Display) -> StoreError {
+    StoreError::AccessError(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn keys_start_with_the_prefix() {
+        let keys = Keys::new("workers");
+
+        assert_eq!(keys.id(1), "workers:id:1");
+        assert_eq!(keys.transaction(1), "workers:tx:1");
+        assert_eq!(keys.client_transactions(2), "workers:client:2:tx");
+        assert_eq!(keys.disputed(), "workers:disputed");
+        assert_eq!(keys.pending(), "workers:pending");
+        assert_eq!(keys.account(2), "workers:account:2");
+        assert_eq!(keys.accounts(), "workers:account:*");
+        assert_eq!(keys.all(), "workers:*");
+    }
+
+    #[test]
+    fn clients_are_parsed_from_account_keys() {
+        let keys = Keys::new("workers");
+
+        assert_eq!(keys.client_of_account("workers:account:42"), Some(42));
+        assert_eq!(keys.client_of_account("workers:account:x"), None);
+        assert_eq!(keys.client_of_account("others:account:42"), None);
+        assert_eq!(keys.client_of_account("workers:tx:42"), None);
+    }
+
+    #[test]
+    fn transactions_are_built_from_their_fields() {
+        let transaction = Transaction::deposit(1, 2, Decimal::TEN);
+        let fields = HashMap::from([
+            (
+                TRANSACTION_FIELD.to_string(),
+                to_json(&transaction).unwrap(),
+            ),
+            (
+                DISPUTE_STATE_FIELD.to_string(),
+                to_json(&DisputeState::Open).unwrap(),
+            ),
+            (
+                DISPUTED_AMOUNT_FIELD.to_string(),
+                to_json(&Some(Decimal::ONE)).unwrap(),
+            ),
+            (DISPUTE_COUNT_FIELD.to_string(), "1".to_string()),
+        ]);
+
+        let mut expected = transaction;
+        expected.set_dispute_state(DisputeState::Open);
+        expected.set_disputed_amount(Some(Decimal::ONE));
+
+        assert_eq!(transaction_from_fields(fields), Ok(Some(expected)));
+        assert_eq!(transaction_from_fields(HashMap::new()), Ok(None));
+    }
+
+    #[cfg(feature = "integration-tests")]
+    mod integration {
+        use super::*;
+        use payments_engine::Engine;
+        use payments_engine_core::engine::Engine as CoreEngine;
+
+        const DEFAULT_URL: &str = "redis://127.0.0.1:6379";
+
+        /// Connects to the test server with a prefix of its own, so tests can run in parallel.
+        async fn store(name: &str) -> RedisStore {
+            let url = std::env::var("REDIS_URL").unwrap_or_else(|_| DEFAULT_URL.to_string());
+            let store = RedisStore::connect(&url)
+                .await
+                .unwrap()
+                .with_prefix(format!("payments-test:{}:{}", std::process::id(), name));
+            store.clear().await.unwrap();
+            store
+        }
+
+        #[tokio::test]
+        async fn create_transaction_fails_if_it_already_exists() {
+            let store = store("already-exists").await;
+            let deposit = Transaction::deposit(1, 1, Decimal::ONE);
+
+            assert_eq!(
+                store.create_transaction(deposit.clone()).await,
+                Ok(deposit.clone())
+            );
+            assert_eq!(
+                store.create_transaction(deposit.clone()).await,
+                Err(StoreError::AlreadyExists { id: 1 })
+            );
+            assert_eq!(store.get_transaction(1).await, Ok(deposit));
+            assert_eq!(
+                store.get_transaction(2).await,
+                Err(StoreError::NotFound { id: 2 })
+            );
+        }
+
+        #[tokio::test]
+        async fn create_transaction_fails_if_an_adjustment_already_exists() {
+            let store = store("adjustment-exists").await;
+            let adjustment = Transaction::adjustment(1, 1, Decimal::ONE, "fee refund");
+
+            store.create_transaction(adjustment.clone()).await.unwrap();
+            assert_eq!(
+                store.create_transaction(adjustment).await,
+                Err(StoreError::AlreadyExists { id: 1 })
+            );
+            assert_eq!(
+                store.get_transaction(1).await,
+                Err(StoreError::NotFound { id: 1 })
+            );
+        }
+
+        #[tokio::test]
+        async fn withdrawal_ids_are_kept_even_if_not_stored() {
+            let store = store("withdrawal-ids").await;
+
+            store
+                .create_transaction(Transaction::withdrawal(1, 1, Decimal::ONE))
+                .await
+                .unwrap();
+
+            assert_eq!(
+                store.get_transaction(1).await,
+                Err(StoreError::NotFound { id: 1 })
+            );
+            assert_eq!(
+                store
+                    .create_transaction(Transaction::deposit(1, 1, Decimal::ONE))
+                    .await,
+                Err(StoreError::AlreadyExists { id: 1 })
+            );
+
+            store.delete_transaction(1).await.unwrap();
+
+            assert!(store
+                .create_transaction(Transaction::deposit(1, 1, Decimal::ONE))
+                .await
+                .is_ok());
+        }
+
+        #[tokio::test]
+        async fn set_dispute_state_counts_disputes() {
+            let store = store("dispute-counts").await;
+            store
+                .create_transaction(Transaction::deposit(1, 1, Decimal::ONE))
+                .await
+                .unwrap();
+
+            store
+                .set_dispute_state(1, DisputeState::Open)
+                .await
+                .unwrap();
+            store
+                .set_dispute_state(1, DisputeState::Open)
+                .await
+                .unwrap();
+
+            assert_eq!(store.dispute_count(1).await, Ok(1));
+            assert_eq!(
+                store
+                    .get_disputed_transactions()
+                    .await
+                    .unwrap()
+                    .count()
+                    .await,
+                1
+            );
+
+            store
+                .set_dispute_state(1, DisputeState::Resolved)
+                .await
+                .unwrap();
+            store
+                .set_dispute_state(1, DisputeState::Open)
+                .await
+                .unwrap();
+            store
+                .set_disputed_amount(1, Some(Decimal::ONE))
+                .await
+                .unwrap();
+
+            assert_eq!(store.dispute_count(1).await, Ok(2));
+            let transaction = store.get_transaction(1).await.unwrap();
+            assert_eq!(transaction.dispute_state(), Some(DisputeState::Open));
+            assert_eq!(transaction.disputed_amount(), Some(Decimal::ONE));
+            assert_eq!(
+                store.dispute_count(2).await,
+                Err(StoreError::NotFound { id: 2 })
+            );
+        }
+
+        #[tokio::test]
+        async fn upsert_account_reports_the_outcome() {
+            let store = store("upsert").await;
+            let account = Account::new(1);
+
+            assert_eq!(
+                store.upsert_account(&account).await,
+                Ok(UpsertOutcome::Created)
+            );
+            assert_eq!(
+                store.upsert_account(&account).await,
+                Ok(UpsertOutcome::Updated)
+            );
+            assert_eq!(store.get_account(1).await, Ok(Some(account)));
+            assert_eq!(store.get_account(2).await, Ok(None));
+        }
+
+        #[tokio::test]
+        async fn get_all_accounts_scans_every_account() {
+            let store = store("all-accounts").await;
+            store
+                .load_accounts((1..=250).map(Account::new).collect())
+                .await
+                .unwrap();
+
+            let mut clients = store
+                .get_all_accounts()
+                .await
+                .unwrap()
+                .map(|account| account.client)
+                .collect::<Vec<_>>()
+                .await;
+            clients.sort_unstable();
+
+            assert_eq!(clients, (1..=250).collect::<Vec<_>>());
+            assert_eq!(store.get_client_ids().await, Ok(clients));
+        }
+
+        #[tokio::test]
+        async fn transactions_and_pending_ones_are_sorted_by_id() {
+            let store = store("sorted").await;
+            for id in [3, 1, 2] {
+                store
+                    .create_transaction(Transaction::deposit(id, 1, Decimal::ONE))
+                    .await
+                    .unwrap();
+                store
+                    .add_pending_transaction(Transaction::deposit(id + 10, 2, Decimal::ONE))
+                    .await
+                    .unwrap();
+            }
+
+            let ids = store
+                .get_transactions_for_client(1)
+                .await
+                .unwrap()
+                .iter()
+                .map(|transaction| transaction.info().id)
+                .collect::<Vec<_>>();
+            let pending = store
+                .get_pending_transactions()
+                .await
+                .unwrap()
+                .iter()
+                .map(|transaction| transaction.info().id)
+                .collect::<Vec<_>>();
+
+            assert_eq!(ids, vec![1, 2, 3]);
+            assert_eq!(pending, vec![11, 12, 13]);
+            assert_eq!(store.get_transactions_for_client(2).await, Ok(vec![]));
+        }
+
+        #[tokio::test]
+        async fn engines_sharing_the_store_see_the_same_accounts() {
+            let store = store("shared").await;
+            let first = Engine::new(store.clone());
+            let second = Engine::new(store.clone());
+
+            first
+                .process_transaction(Transaction::deposit(1, 1, Decimal::TEN))
+                .await
+                .unwrap();
+            second
+                .process_transaction(Transaction::withdrawal(2, 1, Decimal::ONE))
+                .await
+                .unwrap();
+            second
+                .process_transaction(Transaction::dispute(1, 1))
+                .await
+                .unwrap();
+
+            let account = first.get_account(1).await.unwrap();
+
+            assert_eq!(account.held, Decimal::TEN);
+            assert_eq!(account.total, Decimal::from(9));
+            assert_eq!(store.verify_dispute_consistency().await, Ok(vec![]));
+            assert_eq!(
+                first
+                    .process_transaction(Transaction::deposit(1, 1, Decimal::TEN))
+                    .await,
+                Err(StoreError::AlreadyExists { id: 1 }.into())
+            );
+        }
+    }
+}
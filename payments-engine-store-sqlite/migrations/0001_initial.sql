@@ -0,0 +1,18 @@
+CREATE TABLE transactions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    kind TEXT NOT NULL,
+    client INTEGER NOT NULL,
+    amount TEXT NOT NULL,
+    dispute_state TEXT NOT NULL,
+    dispute_count INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX transactions_dispute_state ON transactions (dispute_state);
+
+CREATE TABLE accounts (
+    client INTEGER PRIMARY KEY NOT NULL,
+    available TEXT NOT NULL,
+    held TEXT NOT NULL,
+    total TEXT NOT NULL,
+    locked INTEGER NOT NULL
+);
@@ -0,0 +1,7 @@
+CREATE TABLE pending_transactions (
+    id INTEGER PRIMARY KEY NOT NULL,
+    kind TEXT NOT NULL,
+    client INTEGER NOT NULL,
+    amount TEXT NOT NULL,
+    dispute_state TEXT NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE transactions ADD COLUMN timestamp INTEGER;
+ALTER TABLE pending_transactions ADD COLUMN timestamp INTEGER;
@@ -0,0 +1 @@
+CREATE INDEX transactions_client ON transactions (client);
@@ -0,0 +1,3 @@
+CREATE TABLE reserved_ids (
+    id INTEGER PRIMARY KEY NOT NULL
+);
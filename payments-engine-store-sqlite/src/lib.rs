@@ -0,0 +1,9 @@
+//! SQLite ['`Store`'] implementation.
+//!
+//! Useful for long-running services, as the transactions and accounts survive restarts.
+//!
+//! The schema is created and migrated automatically when the database is opened.
+mod migrations;
+mod sqlite_store;
+
+pub use sqlite_store::SqliteStore;
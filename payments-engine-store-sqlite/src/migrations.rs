@@ -0,0 +1,47 @@
+use rusqlite::Connection;
+
+/// Migrations of the schema, in order.
+/// The number of applied migrations is tracked with the `user_version` pragma.
+const MIGRATIONS: [&str; 6] = [
+    include_str!("../migrations/0001_initial.sql"),
+    include_str!("../migrations/0002_pending_transactions.sql"),
+    include_str!("../migrations/0003_transaction_timestamps.sql"),
+    include_str!("../migrations/0004_disputed_amounts.sql"),
+    include_str!("../migrations/0005_transactions_client.sql"),
+    include_str!("../migrations/0006_reserved_ids.sql"),
+];
+
+/// Applies the pending migrations in a single transaction.
+pub fn migrate(connection: &mut Connection) -> rusqlite::Result<()> {
+    let applied: usize = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let pending = MIGRATIONS.iter().skip(applied).collect::<Vec<_>>();
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    tracing::info!("Applying {} migrations", pending.len());
+    let transaction = connection.transaction()?;
+    for migration in pending {
+        transaction.execute_batch(migration)?;
+    }
+    transaction.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrations_are_applied_only_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+
+        migrate(&mut connection).unwrap();
+        migrate(&mut connection).unwrap();
+
+        let version: usize = connection
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+}
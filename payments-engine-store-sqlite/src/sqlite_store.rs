@@ -0,0 +1,1483 @@
+use crate::migrations::migrate;
+use async_trait::async_trait;
+use payments_engine_core::{
+    account::Account,
+    common::{Amount, ClientId},
+    store::{DisputeMismatch, Store, StoreError, StoreResult, UpsertOutcome},
+    transaction::{DisputeState, Transaction, TransactionId, TransactionInfo},
+};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension, Row};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+use tracing::instrument;
+
+/// Number of accounts fetched per query while streaming them.
+const ACCOUNTS_PAGE_SIZE: usize = 100;
+
+/// SQLite implementation of the Store trait.
+///
+/// Note that [`SqliteStore`] can be safely shared across different threads as it uses an inner [`std::sync::Arc`].
+/// Queries are run in the blocking thread pool of Tokio, so a Tokio runtime is required.
+///
+/// # Important
+/// Like the `MemoryStore`, this store only cares about [`Transaction::Deposit`] transactions,
+/// so all the other variants are not really stored.
+/// Storing [`Transaction::Withdrawal`] transactions too can be enabled with [`SqliteStore::with_withdrawals`].
+/// Otherwise, only the ids of the withdrawals are kept, so replayed withdrawals are rejected too.
+/// The ids of adjustments and account openings and closings are kept as well, so they can't be replayed either.
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+    store_withdrawals: bool,
+}
+
+impl SqliteStore {
+    /// Opens the SQLite database at the given path, creating it if it doesn't exist.
+    /// Pending migrations are applied.
+    pub fn open(path: impl AsRef<Path>) -> StoreResult<Self> {
+        Self::from_connection(Connection::open(path).map_err(access_error)?)
+    }
+
+    /// Opens a new in-memory SQLite database. Everything is lost once the store is dropped.
+    pub fn open_in_memory() -> StoreResult<Self> {
+        Self::from_connection(Connection::open_in_memory().map_err(access_error)?)
+    }
+
+    fn from_connection(mut connection: Connection) -> StoreResult<Self> {
+        migrate(&mut connection).map_err(access_error)?;
+        Ok(Self {
+            connection: Arc::new(Mutex::new(connection)),
+            store_withdrawals: false,
+        })
+    }
+
+    /// Stores [`Transaction::Withdrawal`] transactions too, so they can be disputed.
+    #[must_use]
+    pub const fn with_withdrawals(mut self, enabled: bool) -> Self {
+        self.store_withdrawals = enabled;
+        self
+    }
+
+    /// Runs the given closure with the connection in the blocking thread pool.
+    async fn run<T, F>(&self, f: F) -> StoreResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> StoreResult<T> + Send + 'static,
+    {
+        let connection = Arc::clone(&self.connection);
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection
+                .lock()
+                .map_err(|e| StoreError::AccessError(e.to_string()))?;
+            f(&mut connection)
+        })
+        .await
+        .map_err(|e| StoreError::UnknownError(e.to_string()))?
+    }
+
+    /// Reserves the id of a transaction which is not stored, e.g. a withdrawal or an adjustment, so it can't be reused.
+    async fn create_transaction_id(&self, transaction: Transaction) -> StoreResult<Transaction> {
+        let id = transaction.info().id;
+        let result = self
+            .run(move |connection| {
+                if id_exists(connection, "transactions", id)? {
+                    return Err(StoreError::AlreadyExists { id });
+                }
+                connection
+                    .execute("INSERT INTO reserved_ids (id) VALUES (?1)", [id])
+                    .map_err(|e| match e.sqlite_error_code() {
+                        Some(ErrorCode::ConstraintViolation) => StoreError::AlreadyExists { id },
+                        _ => access_error(e),
+                    })
+            })
+            .await;
+
+        if result.is_err() {
+            tracing::error!("Error while trying to create transaction: {:?}", result);
+        }
+
+        result.map(|_| transaction)
+    }
+}
+
+#[async_trait]
+impl Store for SqliteStore {
+    /// Gets a transaction by its id.
+    /// If it doesn't exist, it returns an [`StoreError::NotFound].
+    #[instrument(skip(self))]
+    async fn get_transaction(&self, id: TransactionId) -> StoreResult<Transaction> {
+        tracing::debug!("Getting transaction {}", id);
+        let result = self
+            .run(move |connection| {
+                get_transaction(connection, id)?.ok_or(StoreError::NotFound { id })
+            })
+            .await;
+
+        if result.is_err() {
+            tracing::error!("Error while getting transaction: {:?}", result);
+        }
+
+        result
+    }
+
+    /// Creates a new [`Transaction`] and returns it.
+    /// If the [`Transaction`] already exists, it returns an [`StoreError::AlreadyExists`].
+    /// The uniqueness of the ids is enforced by the primary key of the table.
+    /// Note that this method is only storing [`Transaction::Deposit`] transactions,
+    /// and [`Transaction::Withdrawal`] ones if enabled with [`SqliteStore::with_withdrawals`].
+    /// Only the ids of the rest of transactions not referencing another one are kept.
+    #[instrument(skip(self))]
+    async fn create_transaction(&self, transaction: Transaction) -> StoreResult<Transaction> {
+        tracing::debug!("Creating transaction: {:?}", transaction);
+        let (kind, amount, dispute_state, disputed_amount) = match transaction_to_sql(&transaction)
+        {
+            Some(("withdrawal", ..)) if !self.store_withdrawals => {
+                return self.create_transaction_id(transaction).await
+            }
+            Some(columns) => columns,
+            None if transaction.references_transaction() => return Ok(transaction),
+            None => return self.create_transaction_id(transaction).await,
+        };
+        let info = transaction.info().clone();
+        let result = self
+            .run(move |connection| {
+                if id_exists(connection, "reserved_ids", info.id)? {
+                    return Err(StoreError::AlreadyExists { id: info.id });
+                }
+                connection
+                    .execute(
+                        "INSERT INTO transactions (id, kind, client, amount, dispute_state, timestamp, disputed_amount)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            info.id,
+                            kind,
+                            info.client_id,
+                            amount.to_string(),
+                            dispute_state_to_sql(dispute_state),
+                            info.timestamp,
+                            disputed_amount.map(|amount| amount.to_string())
+                        ],
+                    )
+                    .map_err(|e| match e.sqlite_error_code() {
+                        Some(ErrorCode::ConstraintViolation) => {
+                            StoreError::AlreadyExists { id: info.id }
+                        }
+                        _ => access_error(e),
+                    })
+            })
+            .await;
+
+        if result.is_err() {
+            tracing::error!("Error while trying to create transaction: {:?}", result);
+        }
+
+        result.map(|_| transaction)
+    }
+
+    /// Deletes a [`Transaction`].
+    #[instrument(skip(self))]
+    async fn delete_transaction(&self, id: TransactionId) -> StoreResult<()> {
+        tracing::debug!("Deleting transaction: {:?}", id);
+        self.run(move |connection| {
+            connection
+                .execute("DELETE FROM transactions WHERE id = ?1", [id])
+                .map_err(access_error)?;
+            connection
+                .execute("DELETE FROM reserved_ids WHERE id = ?1", [id])
+                .map_err(access_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Drops the stored [`Transaction`], if any, but keeps its id reserved.
+    #[instrument(skip(self))]
+    async fn reserve_transaction_id(&self, id: TransactionId) -> StoreResult<()> {
+        tracing::debug!("Reserving transaction id: {:?}", id);
+        self.run(move |connection| {
+            let transaction = connection.transaction().map_err(access_error)?;
+            transaction
+                .execute("DELETE FROM transactions WHERE id = ?1", [id])
+                .map_err(access_error)?;
+            transaction
+                .execute("INSERT OR IGNORE INTO reserved_ids (id) VALUES (?1)", [id])
+                .map_err(access_error)?;
+            transaction.commit().map_err(access_error)
+        })
+        .await
+    }
+
+    /// Sets the [`DisputeState`] of a [`Transaction`].
+    /// Every time a [`Transaction`] gets into [`DisputeState::Open`], its dispute counter is incremented.
+    #[instrument(skip(self))]
+    async fn set_dispute_state(&self, id: TransactionId, state: DisputeState) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} dispute state to {:?}", id, state);
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "UPDATE transactions SET
+                        dispute_count = dispute_count + (?2 = 'Open' AND dispute_state != 'Open'),
+                        dispute_state = ?2
+                     WHERE id = ?1",
+                    params![id, dispute_state_to_sql(state)],
+                )
+                .map_err(access_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sets the [`Amount`] held by a partial dispute of a [`Transaction`], or [`None`] if the whole amount is disputed.
+    #[instrument(skip(self))]
+    async fn set_disputed_amount(
+        &self,
+        id: TransactionId,
+        amount: Option<Amount>,
+    ) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} disputed amount to {:?}", id, amount);
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "UPDATE transactions SET disputed_amount = ?2 WHERE id = ?1",
+                    params![id, amount.map(|amount| amount.to_string())],
+                )
+                .map_err(access_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Sets the [`Amount`] of a stored [`Transaction`] in place.
+    #[instrument(skip(self))]
+    async fn set_transaction_amount(&self, id: TransactionId, amount: Amount) -> StoreResult<()> {
+        tracing::debug!("Setting transaction {} amount to {}", id, amount);
+        self.run(move |connection| {
+            connection
+                .execute(
+                    "UPDATE transactions SET amount = ?2 WHERE id = ?1",
+                    params![id, amount.to_string()],
+                )
+                .map_err(access_error)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Returns how many times a [`Transaction`] has been disputed.
+    /// If the [`Transaction`] doesn't exist, it returns an [`StoreError::NotFound`].
+    #[instrument(skip(self))]
+    async fn dispute_count(&self, id: TransactionId) -> StoreResult<u32> {
+        tracing::debug!("Getting dispute count of transaction {}", id);
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    "SELECT dispute_count FROM transactions WHERE id = ?1",
+                    [id],
+                    |row| row.get(0),
+                )
+                .optional()
+                .map_err(access_error)?
+                .ok_or(StoreError::NotFound { id })
+        })
+        .await
+    }
+
+    /// Keeps a [`Transaction`] that was rejected but should be retained for later reconciliation.
+    /// Pending transactions are kept in their own table, so they can't be referenced by disputes.
+    /// Note that only [`Transaction::Deposit`] and [`Transaction::Withdrawal`] transactions can be kept.
+    #[instrument(skip(self))]
+    async fn add_pending_transaction(&self, transaction: Transaction) -> StoreResult<()> {
+        tracing::debug!("Adding pending transaction: {:?}", transaction);
+        let Some((kind, amount, dispute_state, disputed_amount)) = transaction_to_sql(&transaction)
+        else {
+            return Ok(());
+        };
+        let info = transaction.info().clone();
+        let result = self
+            .run(move |connection| {
+                connection
+                    .execute(
+                        "INSERT OR REPLACE INTO pending_transactions (id, kind, client, amount, dispute_state, timestamp, disputed_amount)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                        params![
+                            info.id,
+                            kind,
+                            info.client_id,
+                            amount.to_string(),
+                            dispute_state_to_sql(dispute_state),
+                            info.timestamp,
+                            disputed_amount.map(|amount| amount.to_string())
+                        ],
+                    )
+                    .map_err(access_error)
+            })
+            .await;
+
+        if result.is_err() {
+            tracing::error!(
+                "Error while trying to add pending transaction: {:?}",
+                result
+            );
+        }
+
+        result.map(|_| ())
+    }
+
+    /// Returns the pending [`Transaction`], sorted by id.
+    #[instrument(skip(self))]
+    async fn get_pending_transactions(&self) -> StoreResult<Vec<Transaction>> {
+        tracing::debug!("Getting pending transactions");
+        self.run(|connection| {
+            let mut statement = connection
+                .prepare(
+                    "SELECT id, kind, client, amount, dispute_state, timestamp, disputed_amount FROM pending_transactions
+                     ORDER BY id",
+                )
+                .map_err(access_error)?;
+            let transactions = statement
+                .query_map([], transaction_from_row)
+                .map_err(access_error)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(access_error)?;
+            Ok(transactions)
+        })
+        .await
+    }
+
+    /// Returns the stored [`Transaction`] of the client, sorted by id.
+    #[instrument(skip(self))]
+    async fn get_transactions_for_client(&self, client: ClientId) -> StoreResult<Vec<Transaction>> {
+        tracing::debug!("Getting transactions of client {}", client);
+        self.run(move |connection| {
+            let mut statement = connection
+                .prepare(
+                    "SELECT id, kind, client, amount, dispute_state, timestamp, disputed_amount FROM transactions
+                     WHERE client = ?1 ORDER BY id",
+                )
+                .map_err(access_error)?;
+            let transactions = statement
+                .query_map([client], transaction_from_row)
+                .map_err(access_error)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(access_error)?;
+            Ok(transactions)
+        })
+        .await
+    }
+
+    /// Gets the current state of the [`Account`], or [`None`] if it doesn't exist.
+    #[instrument(skip(self))]
+    async fn get_account(&self, id: ClientId) -> StoreResult<Option<Account>> {
+        tracing::debug!("Getting account: {}", id);
+        let result = self
+            .run(move |connection| get_account(connection, id))
+            .await;
+
+        if result.is_err() {
+            tracing::error!("Error while getting account: {:?}", result);
+        }
+
+        result
+    }
+
+    /// Gets the current state of the [`Account`] and the [`Transaction`] with the given id.
+    /// Both are read while holding the connection, so the result is consistent.
+    #[instrument(skip(self))]
+    async fn get_account_and_transaction(
+        &self,
+        client: ClientId,
+        id: TransactionId,
+    ) -> StoreResult<(Account, Option<Transaction>)> {
+        tracing::debug!("Getting account {} and transaction {}", client, id);
+        let result = self
+            .run(move |connection| {
+                let account =
+                    get_account(connection, client)?.unwrap_or_else(|| Account::new(client));
+                Ok((account, get_transaction(connection, id)?))
+            })
+            .await;
+
+        if result.is_err() {
+            tracing::error!("Error while getting account and transaction: {:?}", result);
+        }
+
+        result
+    }
+
+    /// Updates the state of the [`Account`].
+    /// If the [`Account`] does not exist, it will create the [`Account`].
+    /// Returns whether the [`Account`] was created or updated.
+    #[instrument(skip(self))]
+    async fn upsert_account(&self, account: &Account) -> StoreResult<UpsertOutcome> {
+        tracing::debug!("Upserting account: {:?}", account);
+        let account = account.clone();
+        let result = self
+            .run(move |connection| {
+                let transaction = connection.transaction().map_err(access_error)?;
+                let exists = transaction
+                    .query_row(
+                        "SELECT EXISTS(SELECT 1 FROM accounts WHERE client = ?1)",
+                        [account.client],
+                        |row| row.get::<_, bool>(0),
+                    )
+                    .map_err(access_error)?;
+                transaction
+                    .execute(
+                        "INSERT OR REPLACE INTO accounts (client, available, held, total, locked)
+                         VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![
+                            account.client,
+                            account.available.to_string(),
+                            account.held.to_string(),
+                            account.total.to_string(),
+                            account.locked
+                        ],
+                    )
+                    .map_err(access_error)?;
+                transaction.commit().map_err(access_error)?;
+                Ok(if exists {
+                    UpsertOutcome::Updated
+                } else {
+                    UpsertOutcome::Created
+                })
+            })
+            .await;
+
+        if result.is_err() {
+            tracing::error!("Error while trying to create an account: {:?}", result);
+        }
+
+        result
+    }
+
+    /// Seeds the store with the given accounts. Existing accounts are replaced.
+    /// All of them are inserted in a single SQL transaction, so either all or none are loaded.
+    #[instrument(skip(self, accounts))]
+    async fn load_accounts(&self, accounts: Vec<Account>) -> StoreResult<()> {
+        tracing::debug!("Loading {} accounts", accounts.len());
+        let result = self
+            .run(move |connection| {
+                let transaction = connection.transaction().map_err(access_error)?;
+                for account in &accounts {
+                    transaction
+                        .execute(
+                            "INSERT OR REPLACE INTO accounts (client, available, held, total, locked)
+                             VALUES (?1, ?2, ?3, ?4, ?5)",
+                            params![
+                                account.client,
+                                account.available.to_string(),
+                                account.held.to_string(),
+                                account.total.to_string(),
+                                account.locked
+                            ],
+                        )
+                        .map_err(access_error)?;
+                }
+                transaction.commit().map_err(access_error)
+            })
+            .await;
+
+        if result.is_err() {
+            tracing::error!("Error while loading accounts: {:?}", result);
+        }
+
+        result
+    }
+
+    /// Returns the current state of clients accounts, sorted by client.
+    /// The accounts are fetched lazily in pages while the stream is consumed.
+    /// If a page can't be fetched, the error is logged and the stream ends.
+    #[instrument(skip(self))]
+    async fn get_all_accounts(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>> {
+        // fetching the first page eagerly, so access errors are reported
+        let first_page = self.get_accounts_page(None, ACCOUNTS_PAGE_SIZE).await?;
+        let more = first_page.len() == ACCOUNTS_PAGE_SIZE;
+        let state = (self.clone(), VecDeque::from(first_page), more, None);
+        let stream = futures::stream::unfold(
+            state,
+            |(store, mut page, mut more, last): (
+                Self,
+                VecDeque<Account>,
+                bool,
+                Option<ClientId>,
+            )| async move {
+                if page.is_empty() {
+                    // a page which is not full means there's nothing else to fetch
+                    if !more {
+                        return None;
+                    }
+                    let accounts = match store.get_accounts_page(last, ACCOUNTS_PAGE_SIZE).await {
+                        Ok(accounts) => accounts,
+                        Err(e) => {
+                            tracing::error!("Error while streaming accounts: {:?}", e);
+                            return None;
+                        }
+                    };
+                    more = accounts.len() == ACCOUNTS_PAGE_SIZE;
+                    page = VecDeque::from(accounts);
+                }
+                let account = page.pop_front()?;
+                let last = Some(account.client);
+                Some((account, (store, page, more, last)))
+            },
+        );
+        Ok(Box::pin(stream))
+    }
+
+    /// Returns up to `limit` [`Account`] sorted by client, starting right after the `after` client.
+    #[instrument(skip(self))]
+    async fn get_accounts_page(
+        &self,
+        after: Option<ClientId>,
+        limit: usize,
+    ) -> StoreResult<Vec<Account>> {
+        tracing::debug!("Getting {} accounts after {:?}", limit, after);
+        self.run(move |connection| {
+            let mut statement = connection
+                .prepare(
+                    "SELECT client, available, held, total, locked FROM accounts
+                     WHERE ?1 IS NULL OR client > ?1 ORDER BY client LIMIT ?2",
+                )
+                .map_err(access_error)?;
+            let accounts = statement
+                .query_map(params![after, limit], account_from_row)
+                .map_err(access_error)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(access_error)?;
+            Ok(accounts)
+        })
+        .await
+    }
+
+    /// Returns up to `limit` [`Account`] sorted by client, skipping the first `offset` ones.
+    #[instrument(skip(self))]
+    async fn get_accounts_at_offset(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> StoreResult<Vec<Account>> {
+        tracing::debug!("Getting {} accounts at offset {}", limit, offset);
+        self.run(move |connection| {
+            let mut statement = connection
+                .prepare(
+                    "SELECT client, available, held, total, locked FROM accounts
+                     ORDER BY client LIMIT ?1 OFFSET ?2",
+                )
+                .map_err(access_error)?;
+            let accounts = statement
+                .query_map(params![limit, offset], account_from_row)
+                .map_err(access_error)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(access_error)?;
+            Ok(accounts)
+        })
+        .await
+    }
+
+    /// Returns the ids of all the clients with an [`Account`], sorted.
+    #[instrument(skip(self))]
+    async fn get_client_ids(&self) -> StoreResult<Vec<ClientId>> {
+        tracing::debug!("Getting client ids");
+        self.run(|connection| {
+            let mut statement = connection
+                .prepare("SELECT client FROM accounts ORDER BY client")
+                .map_err(access_error)?;
+            let client_ids = statement
+                .query_map([], |row| row.get(0))
+                .map_err(access_error)?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .map_err(access_error)?;
+            Ok(client_ids)
+        })
+        .await
+    }
+
+    /// Returns the deposits and withdrawals which are currently under dispute, sorted by id.
+    #[instrument(skip(self))]
+    async fn get_disputed_transactions(
+        &self,
+    ) -> StoreResult<Pin<Box<dyn futures::Stream<Item = Transaction> + Send>>> {
+        let disputed = self.run(get_disputed_transactions).await?;
+        Ok(Box::pin(futures::stream::iter(disputed)))
+    }
+
+    /// Returns how many deposits and withdrawals of the client are currently under dispute.
+    #[instrument(skip(self))]
+    async fn open_disputes_count(&self, client: ClientId) -> StoreResult<u32> {
+        tracing::debug!("Counting open disputes of client {}", client);
+        self.run(move |connection| {
+            connection
+                .query_row(
+                    "SELECT COUNT(*) FROM transactions
+                     WHERE client = ?1 AND dispute_state IN ('Open', 'UnderReview')",
+                    [client],
+                    |row| row.get(0),
+                )
+                .map_err(access_error)
+        })
+        .await
+    }
+
+    /// Checks that the held funds of every [`Account`] equal the sum of its under dispute deposits and withdrawals.
+    #[instrument(skip(self))]
+    async fn verify_dispute_consistency(&self) -> StoreResult<Vec<DisputeMismatch>> {
+        tracing::debug!("Verifying dispute consistency");
+        let (transactions, held) = self
+            .run(|connection| {
+                let transactions = get_disputed_transactions(connection)?;
+                let mut statement = connection
+                    .prepare("SELECT client, held FROM accounts")
+                    .map_err(access_error)?;
+                let held = statement
+                    .query_map([], |row| {
+                        Ok((row.get::<_, ClientId>(0)?, amount_from_row(row, 1)?))
+                    })
+                    .map_err(access_error)?
+                    .collect::<rusqlite::Result<HashMap<_, _>>>()
+                    .map_err(access_error)?;
+                Ok((transactions, held))
+            })
+            .await?;
+
+        let mut disputed: HashMap<ClientId, Amount> = HashMap::new();
+        for transaction in &transactions {
+            if let Some(amount) = transaction.disputed_amount() {
+                *disputed.entry(transaction.info().client_id).or_default() += amount;
+            }
+        }
+
+        let mut clients = held
+            .keys()
+            .chain(disputed.keys())
+            .copied()
+            .collect::<Vec<_>>();
+        clients.sort_unstable();
+        clients.dedup();
+
+        let mismatches = clients
+            .into_iter()
+            .filter_map(|client| {
+                let held = held.get(&client).copied().unwrap_or_default();
+                let disputed = disputed.get(&client).copied().unwrap_or_default();
+                (held != disputed).then_some(DisputeMismatch {
+                    client,
+                    held,
+                    disputed,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !mismatches.is_empty() {
+            tracing::warn!(?mismatches, "Dispute inconsistencies found");
+        }
+
+        Ok(mismatches)
+    }
+}
+
+fn access_error(e: rusqlite::Error) -> StoreError {
+    StoreError::AccessError(e.to_string())
+}
+
+fn get_transaction(connection: &Connection, id: TransactionId) -> StoreResult<Option<Transaction>> {
+    connection
+        .query_row(
+            "SELECT id, kind, client, amount, dispute_state, timestamp, disputed_amount FROM transactions WHERE id = ?1",
+            [id],
+            transaction_from_row,
+        )
+        .optional()
+        .map_err(access_error)
+}
+
+fn get_account(connection: &Connection, client: ClientId) -> StoreResult<Option<Account>> {
+    connection
+        .query_row(
+            "SELECT client, available, held, total, locked FROM accounts WHERE client = ?1",
+            [client],
+            account_from_row,
+        )
+        .optional()
+        .map_err(access_error)
+}
+
+/// Returns whether the given table has a row with the given id.
+fn id_exists(connection: &Connection, table: &str, id: TransactionId) -> StoreResult<bool> {
+    connection
+        .query_row(
+            &format!("SELECT EXISTS(SELECT 1 FROM {} WHERE id = ?1)", table),
+            [id],
+            |row| row.get(0),
+        )
+        .map_err(access_error)
+}
+
+fn get_disputed_transactions(connection: &mut Connection) -> StoreResult<Vec<Transaction>> {
+    let mut statement = connection
+        .prepare(
+            "SELECT id, kind, client, amount, dispute_state, timestamp, disputed_amount FROM transactions
+             WHERE dispute_state IN ('Open', 'UnderReview') ORDER BY id",
+        )
+        .map_err(access_error)?;
+    let transactions = statement
+        .query_map([], transaction_from_row)
+        .map_err(access_error)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(access_error)?;
+    Ok(transactions)
+}
+
+/// Returns the kind, amount, dispute state and disputed amount columns of the storable transactions.
+fn transaction_to_sql(
+    transaction: &Transaction,
+) -> Option<(&'static str, Amount, DisputeState, Option<Amount>)> {
+    match *transaction {
+        Transaction::Deposit {
+            amount,
+            dispute_state,
+            disputed_amount,
+            ..
+        } => Some(("deposit", amount, dispute_state, disputed_amount)),
+        Transaction::Withdrawal {
+            amount,
+            dispute_state,
+            disputed_amount,
+            ..
+        } => Some(("withdrawal", amount, dispute_state, disputed_amount)),
+        _ => None,
+    }
+}
+
+fn transaction_from_row(row: &Row<'_>) -> rusqlite::Result<Transaction> {
+    let info = TransactionInfo::new(row.get(0)?, row.get(2)?).with_timestamp(row.get(5)?);
+    let amount = amount_from_row(row, 3)?;
+    let dispute_state = dispute_state_from_sql(&row.get::<_, String>(4)?)
+        .ok_or_else(|| invalid_column(4, "Unknown dispute state"))?;
+    let disputed_amount = match row.get::<_, Option<String>>(6)? {
+        Some(_) => Some(amount_from_row(row, 6)?),
+        None => None,
+    };
+    match row.get::<_, String>(1)?.as_str() {
+        "deposit" => Ok(Transaction::Deposit {
+            info,
+            amount,
+            dispute_state,
+            disputed_amount,
+        }),
+        "withdrawal" => Ok(Transaction::Withdrawal {
+            info,
+            amount,
+            dispute_state,
+            disputed_amount,
+        }),
+        _ => Err(invalid_column(1, "Unknown transaction kind")),
+    }
+}
+
+fn account_from_row(row: &Row<'_>) -> rusqlite::Result<Account> {
+    Ok(Account {
+        client: row.get(0)?,
+        available: amount_from_row(row, 1)?,
+        held: amount_from_row(row, 2)?,
+        total: amount_from_row(row, 3)?,
+        locked: row.get(4)?,
+    })
+}
+
+/// Amounts are stored as text to keep their full precision.
+fn amount_from_row(row: &Row<'_>, index: usize) -> rusqlite::Result<Amount> {
+    row.get::<_, String>(index)?
+        .parse::<Amount>()
+        .map_err(|e| invalid_column(index, &e.to_string()))
+}
+
+fn invalid_column(index: usize, message: &str) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(
+        index,
+        rusqlite::types::Type::Text,
+        message.to_string().into(),
+    )
+}
+
+const fn dispute_state_to_sql(state: DisputeState) -> &'static str {
+    match state {
+        DisputeState::Undisputed => "Undisputed",
+        DisputeState::Open => "Open",
+        DisputeState::UnderReview => "UnderReview",
+        DisputeState::Resolved => "Resolved",
+        DisputeState::ChargedBack => "ChargedBack",
+    }
+}
+
+fn dispute_state_from_sql(state: &str) -> Option<DisputeState> {
+    match state {
+        "Undisputed" => Some(DisputeState::Undisputed),
+        "Open" => Some(DisputeState::Open),
+        "UnderReview" => Some(DisputeState::UnderReview),
+        "Resolved" => Some(DisputeState::Resolved),
+        "ChargedBack" => Some(DisputeState::ChargedBack),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use payments_engine_core::dec;
+
+    /// Opens an in-memory store with the given deposits and accounts.
+    async fn seeded(deposits: Vec<Transaction>, accounts: Vec<Account>) -> SqliteStore {
+        let store = SqliteStore::open_in_memory().unwrap();
+        for deposit in deposits {
+            store.create_transaction(deposit).await.unwrap();
+        }
+        for account in accounts {
+            store.upsert_account(&account).await.unwrap();
+        }
+        store
+    }
+
+    #[tokio::test]
+    async fn get_transaction_works() {
+        let tx = Transaction::deposit(1, 1, dec!(10.3001));
+        let store = seeded(vec![tx.clone()], vec![]).await;
+
+        let result = store.get_transaction(1).await;
+
+        assert_eq!(result, Ok(tx));
+    }
+
+    #[tokio::test]
+    async fn get_transaction_returns_not_found_if_transaction_does_not_exist() {
+        let store = seeded(vec![Transaction::deposit(1, 1, dec!(10))], vec![]).await;
+
+        let result = store.get_transaction(2).await;
+
+        assert_eq!(result, Err(StoreError::NotFound { id: 2 }));
+    }
+
+    #[tokio::test]
+    async fn create_transaction_works() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let tx = Transaction::deposit_under_dispute(1, 1, dec!(10.3001));
+
+        let result = store.create_transaction(tx.clone()).await;
+
+        assert_eq!(result, Ok(tx.clone()));
+        assert_eq!(store.get_transaction(1).await, Ok(tx));
+    }
+
+    #[tokio::test]
+    async fn max_ids_round_trip() {
+        let tx = Transaction::deposit(TransactionId::MAX, ClientId::MAX, dec!(1));
+        let account = Account::seeded(ClientId::MAX, dec!(1), Amount::ZERO, false);
+        let store = seeded(vec![tx.clone()], vec![account.clone()]).await;
+
+        assert_eq!(store.get_transaction(TransactionId::MAX).await, Ok(tx));
+        assert_eq!(store.get_account(ClientId::MAX).await, Ok(Some(account)));
+        assert_eq!(store.get_client_ids().await, Ok(vec![ClientId::MAX]));
+    }
+
+    #[tokio::test]
+    async fn create_transaction_returns_already_exists_if_transaction_already_exists() {
+        let store = seeded(vec![Transaction::deposit(1, 1, dec!(10))], vec![]).await;
+
+        let result = store
+            .create_transaction(Transaction::deposit(1, 2, dec!(20)))
+            .await;
+
+        assert_eq!(result, Err(StoreError::AlreadyExists { id: 1 }));
+    }
+
+    #[tokio::test]
+    async fn create_transaction_only_saves_deposits() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let transactions = vec![
+            Transaction::withdrawal(1, 1, dec!(10)),
+            Transaction::dispute(2, 1),
+            Transaction::resolve(3, 1),
+            Transaction::chargeback(4, 1),
+        ];
+
+        for tx in transactions {
+            assert_eq!(store.create_transaction(tx.clone()).await, Ok(tx));
+        }
+
+        for id in 1..=4 {
+            assert_eq!(
+                store.get_transaction(id).await,
+                Err(StoreError::NotFound { id })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn create_transaction_rejects_duplicated_adjustments() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let adjustment = Transaction::adjustment(1, 1, dec!(1), "fee refund");
+        store.create_transaction(adjustment.clone()).await.unwrap();
+
+        assert_eq!(
+            store.create_transaction(adjustment).await,
+            Err(StoreError::AlreadyExists { id: 1 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::deposit(1, 1, dec!(1)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 1 })
+        );
+        assert_eq!(
+            store.get_transaction(1).await,
+            Err(StoreError::NotFound { id: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn create_transaction_saves_withdrawals_if_enabled() {
+        let store = SqliteStore::open_in_memory()
+            .unwrap()
+            .with_withdrawals(true);
+        let tx = Transaction::withdrawal(1, 1, dec!(10));
+
+        store.create_transaction(tx.clone()).await.unwrap();
+
+        assert_eq!(store.get_transaction(1).await, Ok(tx));
+    }
+
+    #[tokio::test]
+    async fn delete_transaction_works() {
+        let store = seeded(vec![Transaction::deposit(1, 1, dec!(10))], vec![]).await;
+
+        let result = store.delete_transaction(1).await;
+
+        assert!(result.is_ok());
+        assert_eq!(
+            store.get_transaction(1).await,
+            Err(StoreError::NotFound { id: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn get_transactions_for_client_only_returns_their_transactions() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let transactions = vec![
+            Transaction::deposit(3, 1, dec!(3)),
+            Transaction::deposit(1, 1, dec!(1)),
+            Transaction::deposit(2, 2, dec!(2)),
+        ];
+        for transaction in transactions {
+            store.create_transaction(transaction).await.unwrap();
+        }
+        store
+            .add_pending_transaction(Transaction::deposit(5, 1, dec!(5)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_transactions_for_client(1).await,
+            Ok(vec![
+                Transaction::deposit(1, 1, dec!(1)),
+                Transaction::deposit(3, 1, dec!(3)),
+            ])
+        );
+        assert_eq!(
+            store.get_transactions_for_client(2).await,
+            Ok(vec![Transaction::deposit(2, 2, dec!(2))])
+        );
+        assert_eq!(store.get_transactions_for_client(3).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn duplicated_withdrawals_are_rejected_even_if_not_stored() {
+        let store = seeded(vec![Transaction::deposit(1, 1, dec!(10))], vec![]).await;
+        store
+            .create_transaction(Transaction::withdrawal(2, 1, dec!(5)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store
+                .create_transaction(Transaction::withdrawal(2, 1, dec!(5)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 2 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::withdrawal(1, 1, dec!(5)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 1 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::deposit(2, 1, dec!(5)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 2 })
+        );
+
+        // rolled back withdrawals can be retried
+        store.delete_transaction(2).await.unwrap();
+        assert!(store
+            .create_transaction(Transaction::withdrawal(2, 1, dec!(5)))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn pending_transactions_are_kept_apart() {
+        let store = SqliteStore::open_in_memory().unwrap();
+
+        store
+            .add_pending_transaction(Transaction::deposit(2, 1, dec!(2)))
+            .await
+            .unwrap();
+        store
+            .add_pending_transaction(Transaction::deposit(1, 1, dec!(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_pending_transactions().await,
+            Ok(vec![
+                Transaction::deposit(1, 1, dec!(1)),
+                Transaction::deposit(2, 1, dec!(2)),
+            ])
+        );
+        assert_eq!(
+            store.get_transaction(1).await,
+            Err(StoreError::NotFound { id: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn timestamps_are_persisted() {
+        let deposit = Transaction::deposit(1, 1, dec!(1)).with_timestamp(Some(1_700_000_000));
+        let pending = Transaction::deposit(2, 1, dec!(1)).with_timestamp(Some(1_700_000_001));
+        let store = seeded(vec![deposit.clone()], vec![]).await;
+        store
+            .add_pending_transaction(pending.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(store.get_transaction(1).await, Ok(deposit));
+        assert_eq!(store.get_pending_transactions().await, Ok(vec![pending]));
+    }
+
+    #[tokio::test]
+    async fn get_account_works() {
+        let account = Account::seeded(1, dec!(10.3001), dec!(5.40), false);
+        let store = seeded(vec![], vec![account.clone()]).await;
+
+        let result = store.get_account(1).await;
+
+        assert_eq!(result, Ok(Some(account)));
+    }
+
+    #[tokio::test]
+    async fn get_or_default_account_returns_new_account_if_not_exists_but_does_not_create_it() {
+        let store = SqliteStore::open_in_memory().unwrap();
+
+        let result = store.get_or_default_account(1).await;
+
+        assert_eq!(result, Ok(Account::new(1)));
+        assert_eq!(store.get_client_ids().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn get_account_returns_none_if_account_does_not_exist() {
+        let store = seeded(vec![], vec![Account::new(1)]).await;
+
+        assert_eq!(store.get_account(1).await, Ok(Some(Account::new(1))));
+        assert_eq!(store.get_account(2).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn get_account_and_transaction_works() {
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, true);
+        let tx = Transaction::deposit(1, 1, dec!(10));
+        let store = seeded(vec![tx.clone()], vec![account.clone()]).await;
+
+        assert_eq!(
+            store.get_account_and_transaction(1, 1).await,
+            Ok((account, Some(tx)))
+        );
+        assert_eq!(
+            store.get_account_and_transaction(2, 2).await,
+            Ok((Account::new(2), None))
+        );
+    }
+
+    #[tokio::test]
+    async fn upsert_account_creates_new_account_if_does_not_exist() {
+        let store = SqliteStore::open_in_memory().unwrap();
+        let account = Account::seeded(1, dec!(10.3001), dec!(5.40), false);
+
+        let result = store.upsert_account(&account).await;
+
+        assert_eq!(result, Ok(UpsertOutcome::Created));
+        assert_eq!(store.get_account(1).await, Ok(Some(account)));
+    }
+
+    #[tokio::test]
+    async fn upsert_account_updates_account_if_exists() {
+        let store = seeded(
+            vec![],
+            vec![Account::seeded(1, dec!(10.3001), dec!(5.40), false)],
+        )
+        .await;
+        let update = Account::seeded(1, dec!(5), dec!(5.40), true);
+
+        let result = store.upsert_account(&update).await;
+
+        assert_eq!(result, Ok(UpsertOutcome::Updated));
+        assert_eq!(store.get_account(1).await, Ok(Some(update)));
+        assert_eq!(store.get_client_ids().await, Ok(vec![1]));
+    }
+
+    #[tokio::test]
+    async fn dispute_count_is_incremented_on_every_dispute() {
+        let store = seeded(vec![Transaction::deposit(1, 1, dec!(1))], vec![]).await;
+
+        assert_eq!(store.dispute_count(1).await, Ok(0));
+
+        store
+            .set_dispute_state(1, DisputeState::Open)
+            .await
+            .unwrap();
+        assert_eq!(store.dispute_count(1).await, Ok(1));
+
+        store
+            .set_dispute_state(1, DisputeState::Resolved)
+            .await
+            .unwrap();
+        assert_eq!(store.dispute_count(1).await, Ok(1));
+
+        store
+            .set_dispute_state(1, DisputeState::Open)
+            .await
+            .unwrap();
+        assert_eq!(store.dispute_count(1).await, Ok(2));
+        assert_eq!(
+            store.get_transaction(1).await,
+            Ok(Transaction::deposit_under_dispute(1, 1, dec!(1)))
+        );
+    }
+
+    #[tokio::test]
+    async fn set_disputed_amount_works() {
+        let store = seeded(vec![Transaction::deposit(1, 1, dec!(100))], vec![]).await;
+
+        store.set_disputed_amount(1, Some(dec!(30))).await.unwrap();
+        let mut expected = Transaction::deposit(1, 1, dec!(100));
+        expected.set_disputed_amount(Some(dec!(30)));
+        assert_eq!(store.get_transaction(1).await, Ok(expected));
+
+        store.set_disputed_amount(1, None).await.unwrap();
+        assert_eq!(
+            store.get_transaction(1).await,
+            Ok(Transaction::deposit(1, 1, dec!(100)))
+        );
+    }
+
+    #[tokio::test]
+    async fn set_transaction_amount_works() {
+        let store = seeded(vec![Transaction::deposit(1, 1, dec!(100))], vec![]).await;
+
+        store.set_transaction_amount(1, dec!(30)).await.unwrap();
+
+        assert_eq!(
+            store.get_transaction(1).await,
+            Ok(Transaction::deposit(1, 1, dec!(30)))
+        );
+    }
+
+    #[tokio::test]
+    async fn dispute_count_returns_not_found_if_transaction_does_not_exist() {
+        let store = SqliteStore::open_in_memory().unwrap();
+
+        let result = store.dispute_count(1).await;
+
+        assert_eq!(result, Err(StoreError::NotFound { id: 1 }));
+    }
+
+    #[tokio::test]
+    async fn load_accounts_inserts_and_replaces_accounts() {
+        let store = seeded(vec![], vec![Account::new(1)]).await;
+        let accounts = vec![
+            Account::seeded(1, dec!(10), dec!(2), true),
+            Account::seeded(2, dec!(3.5), Amount::ZERO, false),
+        ];
+
+        store.load_accounts(accounts.clone()).await.unwrap();
+
+        assert_eq!(store.get_accounts_page(None, 10).await, Ok(accounts));
+    }
+
+    #[tokio::test]
+    async fn get_all_accounts_streams_every_page() {
+        let accounts = (1..=250)
+            .rev()
+            .map(|client| Account::seeded(client, dec!(1), Amount::ZERO, false))
+            .collect::<Vec<_>>();
+        let store = seeded(vec![], accounts).await;
+
+        let result = store
+            .get_all_accounts()
+            .await
+            .unwrap()
+            .map(|account| account.client)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result, (1..=250).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn get_all_accounts_streams_a_full_single_page() {
+        let accounts = (1..=100).map(Account::new).collect::<Vec<_>>();
+        let store = seeded(vec![], accounts).await;
+
+        let result = store
+            .get_all_accounts()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(result.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn get_accounts_page_iterates_in_chunks() {
+        let store = seeded(
+            vec![],
+            [5, 1, 4, 2, 3].into_iter().map(Account::new).collect(),
+        )
+        .await;
+
+        assert_eq!(store.get_accounts_page(None, 10).await.unwrap().len(), 5);
+        assert_eq!(
+            store.get_accounts_page(None, 2).await,
+            Ok(vec![Account::new(1), Account::new(2)])
+        );
+        assert_eq!(
+            store.get_accounts_page(Some(2), 2).await,
+            Ok(vec![Account::new(3), Account::new(4)])
+        );
+        assert_eq!(
+            store.get_accounts_page(Some(4), 2).await,
+            Ok(vec![Account::new(5)])
+        );
+        assert_eq!(store.get_accounts_page(Some(5), 2).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn get_accounts_at_offset_returns_stable_pages_without_gaps() {
+        let store = seeded(
+            vec![],
+            [5, 1, 4, 2, 3].into_iter().map(Account::new).collect(),
+        )
+        .await;
+
+        let mut clients = vec![];
+        for offset in (0..6).step_by(2) {
+            let page = store.get_accounts_at_offset(offset, 2).await.unwrap();
+            assert_eq!(
+                store.get_accounts_at_offset(offset, 2).await,
+                Ok(page.clone())
+            );
+            clients.extend(page.into_iter().map(|account| account.client));
+        }
+
+        assert_eq!(clients, vec![1, 2, 3, 4, 5]);
+        assert_eq!(store.get_accounts_at_offset(5, 2).await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn get_client_ids_returns_sorted_ids() {
+        let store = seeded(
+            vec![],
+            vec![Account::new(3), Account::new(1), Account::new(2)],
+        )
+        .await;
+
+        let result = store.get_client_ids().await;
+
+        assert_eq!(result, Ok(vec![1, 2, 3]));
+    }
+
+    #[tokio::test]
+    async fn get_disputed_transactions_only_streams_disputed_deposits() {
+        let store = seeded(
+            vec![
+                Transaction::deposit(1, 1, dec!(1)),
+                Transaction::deposit_under_dispute(2, 1, dec!(2)),
+                Transaction::deposit(3, 2, dec!(3)),
+                Transaction::deposit_under_dispute(4, 2, dec!(4)),
+            ],
+            vec![],
+        )
+        .await;
+        store
+            .set_dispute_state(3, DisputeState::Resolved)
+            .await
+            .unwrap();
+
+        let disputed = store
+            .get_disputed_transactions()
+            .await
+            .unwrap()
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            disputed,
+            vec![
+                Transaction::deposit_under_dispute(2, 1, dec!(2)),
+                Transaction::deposit_under_dispute(4, 2, dec!(4)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn open_disputes_count_only_counts_the_client_disputes() {
+        let store = seeded(
+            vec![
+                Transaction::deposit_under_dispute(1, 1, dec!(1)),
+                Transaction::deposit(2, 1, dec!(2)),
+                Transaction::deposit_under_dispute(3, 1, dec!(3)),
+                Transaction::deposit_under_dispute(4, 2, dec!(4)),
+            ],
+            vec![],
+        )
+        .await;
+        store
+            .set_dispute_state(3, DisputeState::UnderReview)
+            .await
+            .unwrap();
+
+        assert_eq!(store.open_disputes_count(1).await, Ok(2));
+        assert_eq!(store.open_disputes_count(2).await, Ok(1));
+        assert_eq!(store.open_disputes_count(3).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn verify_dispute_consistency_returns_no_mismatches_if_consistent() {
+        let store = seeded(
+            vec![
+                Transaction::deposit_under_dispute(1, 1, dec!(10)),
+                Transaction::deposit_under_dispute(2, 1, dec!(5.5)),
+                Transaction::deposit(3, 1, dec!(100)),
+                Transaction::deposit(4, 2, dec!(20)),
+            ],
+            vec![
+                Account::seeded(1, dec!(100), dec!(15.5), false),
+                Account::seeded(2, dec!(20), Amount::ZERO, false),
+            ],
+        )
+        .await;
+
+        let result = store.verify_dispute_consistency().await;
+
+        assert_eq!(result, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn verify_dispute_consistency_reports_mismatches() {
+        let store = seeded(
+            vec![
+                Transaction::deposit_under_dispute(1, 1, dec!(10)),
+                Transaction::deposit(2, 2, dec!(20)),
+                Transaction::deposit_under_dispute(3, 3, dec!(30)),
+            ],
+            vec![
+                // held should be 10
+                Account::seeded(1, Amount::ZERO, dec!(5), false),
+                // held should be 0
+                Account::seeded(2, dec!(10), dec!(10), false),
+                // account 3 was never persisted
+            ],
+        )
+        .await;
+
+        let result = store.verify_dispute_consistency().await;
+
+        assert_eq!(
+            result,
+            Ok(vec![
+                DisputeMismatch {
+                    client: 1,
+                    held: dec!(5),
+                    disputed: dec!(10),
+                },
+                DisputeMismatch {
+                    client: 2,
+                    held: dec!(10),
+                    disputed: Amount::ZERO,
+                },
+                DisputeMismatch {
+                    client: 3,
+                    held: Amount::ZERO,
+                    disputed: dec!(30),
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn works_with_the_engine() {
+        use payments_engine::Engine;
+        use payments_engine_core::engine::Engine as CoreEngine;
+
+        let store = SqliteStore::open_in_memory().unwrap();
+        let engine = Engine::new(store.clone());
+
+        for tx in [
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::deposit(2, 1, dec!(5)),
+            Transaction::withdrawal(3, 1, dec!(2)),
+            Transaction::dispute(2, 1),
+            Transaction::chargeback(2, 1),
+        ] {
+            engine.process_transaction(tx).await.unwrap();
+        }
+
+        let report = engine.report().await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(
+            report,
+            vec![Account::seeded(1, dec!(8), Amount::ZERO, true)]
+        );
+        assert_eq!(
+            store.get_transaction(2).await.unwrap().dispute_state(),
+            Some(DisputeState::ChargedBack)
+        );
+    }
+
+    #[tokio::test]
+    async fn data_survives_reopening_the_database() {
+        let path = std::env::temp_dir().join(format!(
+            "payments-engine-store-sqlite-{}.db",
+            std::process::id()
+        ));
+        let account = Account::seeded(1, dec!(10.12345), dec!(2), true);
+        let tx = Transaction::deposit_under_dispute(1, 1, dec!(2));
+        {
+            let store = SqliteStore::open(&path).unwrap();
+            store.create_transaction(tx.clone()).await.unwrap();
+            store.upsert_account(&account).await.unwrap();
+        }
+
+        let store = SqliteStore::open(&path).unwrap();
+        let result = store.get_account_and_transaction(1, 1).await;
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(result, Ok((account, Some(tx))));
+    }
+}
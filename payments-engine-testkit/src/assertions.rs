@@ -0,0 +1,51 @@
+use payments_engine_core::{account::Account, common::Amount};
+
+/// Asserts the balances of an [`Account`].
+/// The total is expected to be the sum of the available and held funds.
+///
+/// # Panics
+///
+/// Panics if any of the balances doesn't match.
+#[track_caller]
+pub fn assert_account_balance(account: &Account, available: Amount, held: Amount, locked: bool) {
+    assert_eq!(
+        account.available, available,
+        "Unexpected available funds for client {}",
+        account.client
+    );
+    assert_eq!(
+        account.held, held,
+        "Unexpected held funds for client {}",
+        account.client
+    );
+    assert_eq!(
+        account.total,
+        available + held,
+        "Unexpected total funds for client {}",
+        account.client
+    );
+    assert_eq!(
+        account.locked, locked,
+        "Unexpected locked state for client {}",
+        account.client
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use payments_engine_core::dec;
+
+    #[test]
+    fn assert_account_balance_works() {
+        let account = Account::seeded(1, dec!(10), dec!(5), true);
+        assert_account_balance(&account, dec!(10), dec!(5), true);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected held funds for client 1")]
+    fn assert_account_balance_panics_on_mismatch() {
+        let account = Account::seeded(1, dec!(10), dec!(5), false);
+        assert_account_balance(&account, dec!(10), dec!(4), false);
+    }
+}
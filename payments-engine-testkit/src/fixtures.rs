@@ -0,0 +1,82 @@
+use payments_engine_core::{
+    account::Account,
+    common::{Amount, ClientId},
+    transaction::{Transaction, TransactionId},
+};
+use payments_engine_store_memory::MemoryStore;
+use std::collections::HashMap;
+
+/// Builder of seeded [`MemoryStore`] instances.
+#[derive(Debug, Default)]
+pub struct StoreBuilder {
+    deposits: HashMap<TransactionId, Transaction>,
+    accounts: HashMap<ClientId, Account>,
+}
+
+impl StoreBuilder {
+    /// Creates a new empty [`StoreBuilder`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Seeds a [`Transaction::Deposit`].
+    #[must_use]
+    pub fn deposit(mut self, id: TransactionId, client: ClientId, amount: Amount) -> Self {
+        self.deposits
+            .insert(id, Transaction::deposit(id, client, amount));
+        self
+    }
+
+    /// Seeds a [`Transaction::Deposit`] which is under dispute.
+    #[must_use]
+    pub fn disputed_deposit(mut self, id: TransactionId, client: ClientId, amount: Amount) -> Self {
+        self.deposits
+            .insert(id, Transaction::deposit_under_dispute(id, client, amount));
+        self
+    }
+
+    /// Seeds an [`Account`] with the given balances.
+    #[must_use]
+    pub fn account(
+        mut self,
+        client: ClientId,
+        available: Amount,
+        held: Amount,
+        locked: bool,
+    ) -> Self {
+        self.accounts
+            .insert(client, Account::seeded(client, available, held, locked));
+        self
+    }
+
+    /// Builds the seeded [`MemoryStore`].
+    #[must_use]
+    pub fn build(self) -> MemoryStore {
+        MemoryStore::seeded(Some(self.deposits), Some(self.accounts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use payments_engine_core::{dec, store::Store};
+
+    #[tokio::test]
+    async fn store_builder_seeds_the_store() {
+        let store = StoreBuilder::new()
+            .deposit(1, 1, dec!(10))
+            .disputed_deposit(2, 1, dec!(5))
+            .account(1, dec!(10), dec!(5), false)
+            .build();
+
+        assert_eq!(
+            store.get_transaction(2).await.unwrap(),
+            Transaction::deposit_under_dispute(2, 1, dec!(5))
+        );
+        assert_eq!(
+            store.get_or_default_account(1).await.unwrap(),
+            Account::seeded(1, dec!(10), dec!(5), false)
+        );
+    }
+}
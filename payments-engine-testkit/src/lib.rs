@@ -0,0 +1,13 @@
+//! Test utilities for [payments-engine].
+//!
+//! It exposes:
+//! - [`StoreBuilder`] to create seeded stores.
+//! - [`assert_account_balance`] to check the balances of an [`Account`](payments_engine_core::account::Account).
+//! - [`Scenario`] to feed a sequence of transactions to an engine and check the final report.
+mod assertions;
+mod fixtures;
+mod scenario;
+
+pub use assertions::assert_account_balance;
+pub use fixtures::StoreBuilder;
+pub use scenario::Scenario;
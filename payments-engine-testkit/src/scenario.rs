@@ -0,0 +1,138 @@
+use futures::StreamExt;
+use payments_engine::Engine;
+use payments_engine_core::{
+    account::Account, engine::Engine as CoreEngine, store::Store, transaction::Transaction,
+};
+use payments_engine_store_memory::MemoryStore;
+
+/// Feeds a sequence of [`Transaction`] to an [`Engine`] and checks the final report.
+///
+/// Errors while processing the transactions are traced and ignored, as the CLI does.
+pub struct Scenario<S: Store> {
+    engine: Engine<S>,
+    transactions: Vec<Transaction>,
+    expected: Vec<Account>,
+}
+
+impl Default for Scenario<MemoryStore> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scenario<MemoryStore> {
+    /// Creates a new [`Scenario`] using an empty [`MemoryStore`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::with_engine(Engine::new(MemoryStore::default()))
+    }
+}
+
+impl<S: Store> Scenario<S> {
+    /// Creates a new [`Scenario`] using the given [`Engine`].
+    #[must_use]
+    pub fn with_engine(engine: Engine<S>) -> Self {
+        Self {
+            engine,
+            transactions: Vec::new(),
+            expected: Vec::new(),
+        }
+    }
+
+    /// Adds a [`Transaction`] to the sequence.
+    #[must_use]
+    pub fn transaction(mut self, transaction: Transaction) -> Self {
+        self.transactions.push(transaction);
+        self
+    }
+
+    /// Adds several transactions to the sequence.
+    #[must_use]
+    pub fn transactions(mut self, transactions: impl IntoIterator<Item = Transaction>) -> Self {
+        self.transactions.extend(transactions);
+        self
+    }
+
+    /// Adds an [`Account`] to the expected report.
+    #[must_use]
+    pub fn expect(mut self, account: Account) -> Self {
+        self.expected.push(account);
+        self
+    }
+
+    /// Processes the sequence and returns the report sorted by client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the report can't be generated.
+    pub async fn run(self) -> Vec<Account> {
+        for transaction in self.transactions {
+            if let Err(e) = self.engine.process_transaction(transaction).await {
+                tracing::info!(error=?e, "Scenario transaction failed: {}", e);
+            }
+        }
+        let mut report = self
+            .engine
+            .report()
+            .await
+            .expect("report to be generated")
+            .collect::<Vec<_>>()
+            .await;
+        report.sort_by_key(|account| account.client);
+        report
+    }
+
+    /// Processes the sequence and asserts that the report matches the expected accounts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the report doesn't match the expected accounts.
+    pub async fn check(mut self) {
+        let mut expected = std::mem::take(&mut self.expected);
+        expected.sort_by_key(|account| account.client);
+        let report = self.run().await;
+        assert_eq!(report, expected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assert_account_balance, StoreBuilder};
+    use payments_engine_core::{common::Amount, dec};
+
+    #[tokio::test]
+    async fn full_dispute_lifecycle() {
+        Scenario::new()
+            .transactions([
+                Transaction::deposit(1, 1, dec!(100)),
+                Transaction::deposit(2, 1, dec!(50)),
+                Transaction::withdrawal(3, 1, dec!(20)),
+                Transaction::dispute(1, 1),
+                Transaction::resolve(1, 1),
+                Transaction::dispute(2, 1),
+                Transaction::chargeback(2, 1),
+                // ignored, the account is locked
+                Transaction::deposit(4, 1, dec!(10)),
+            ])
+            .expect(Account::seeded(1, dec!(80), Amount::ZERO, true))
+            .check()
+            .await;
+    }
+
+    #[tokio::test]
+    async fn scenarios_can_start_from_a_seeded_store() {
+        let store = StoreBuilder::new()
+            .disputed_deposit(1, 1, dec!(10))
+            .account(1, dec!(5), dec!(10), false)
+            .build();
+
+        let report = Scenario::with_engine(Engine::new(store))
+            .transaction(Transaction::resolve(1, 1))
+            .run()
+            .await;
+
+        assert_eq!(report.len(), 1);
+        assert_account_balance(&report[0], dec!(15), Amount::ZERO, false);
+    }
+}
@@ -0,0 +1,91 @@
+//! Compares the throughput of [`MemoryStore`] and [`DashMapStore`] when many clients are processed concurrently.
+//!
+//! Every client runs its own sequence of deposits, withdrawals and disputes in a separate task,
+//! so the only contention comes from the store.
+//!
+//! Run it with `cargo bench -p payments-engine --bench store_contention -- [clients] [transactions per client]`.
+use payments_engine::Engine;
+use payments_engine_core::{
+    common::ClientId,
+    dec,
+    engine::Engine as CoreEngine,
+    store::Store,
+    transaction::{Transaction, TransactionId},
+};
+use payments_engine_store_memory::{DashMapStore, MemoryStore};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_CLIENTS: u16 = 64;
+const DEFAULT_TRANSACTIONS_PER_CLIENT: u32 = 2_000;
+
+/// Builds the transactions of a client. Ids never collide across clients.
+fn client_workload(client: ClientId, transactions: u32) -> Vec<Transaction> {
+    let base = TransactionId::from(client) * transactions;
+    (0..transactions)
+        .map(|i| {
+            let id = base + i;
+            match i % 10 {
+                3 | 7 => Transaction::withdrawal(id, client, dec!(2.5)),
+                5 => Transaction::dispute(id - 1, client),
+                6 => Transaction::resolve(id - 2, client),
+                _ => Transaction::deposit(id, client, dec!(10)),
+            }
+        })
+        .collect()
+}
+
+async fn run<S: Store + 'static>(store: S, clients: u16, transactions: u32) -> Duration {
+    let engine = Arc::new(Engine::new(store));
+    let start = Instant::now();
+    let handles = (1..=clients)
+        .map(|client| {
+            let engine = Arc::clone(&engine);
+            tokio::spawn(async move {
+                for transaction in client_workload(client, transactions) {
+                    let _ = engine.process_transaction(transaction).await;
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for handle in handles {
+        handle.await.expect("client task panicked");
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration, total: u64) {
+    #[allow(clippy::cast_precision_loss)]
+    let throughput = total as f64 / elapsed.as_secs_f64();
+    println!("{name:<12} {elapsed:>12.2?} {throughput:>14.0} tx/s");
+}
+
+#[tokio::main(flavor = "multi_thread")]
+async fn main() {
+    let mut args = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"));
+    let clients = args
+        .next()
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_CLIENTS);
+    let transactions = args
+        .next()
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(DEFAULT_TRANSACTIONS_PER_CLIENT);
+    let total = u64::from(clients) * u64::from(transactions);
+
+    println!("{clients} concurrent clients, {transactions} transactions each");
+    report(
+        "RwLock",
+        run(MemoryStore::new(), clients, transactions).await,
+        total,
+    );
+    report(
+        "DashMap",
+        run(DashMapStore::new(), clients, transactions).await,
+        total,
+    );
+}
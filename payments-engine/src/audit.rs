@@ -0,0 +1,145 @@
+use payments_engine_core::{
+    account::Account, audit::AuditLog, engine::EngineResult, transaction::Transaction,
+};
+use std::sync::{Arc, Mutex};
+
+/// An attempted [`Transaction`] recorded by an [`AuditLog`], along with its outcome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    /// The attempted [`Transaction`].
+    pub transaction: Transaction,
+    /// The resulting [`Account`] or the error that rejected the [`Transaction`].
+    pub outcome: EngineResult<Account>,
+    /// The id of the batch the [`Transaction`] belongs to, if the log was tagged with one.
+    pub batch_id: Option<String>,
+}
+
+/// In-Memory [`AuditLog`] implementation.
+///
+/// Cloning a [`MemoryAuditLog`] shares the same records, so a clone can be kept to read them
+/// once the log is handed over to the engine. Records can't be modified or removed.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryAuditLog {
+    records: Arc<Mutex<Vec<AuditRecord>>>,
+    batch_id: Option<String>,
+}
+
+impl MemoryAuditLog {
+    /// Creates a new empty [`MemoryAuditLog`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tags every record with the given batch id, so they can be correlated to their run.
+    #[must_use]
+    pub fn with_batch_id(mut self, batch_id: impl Into<String>) -> Self {
+        self.batch_id = Some(batch_id.into());
+        self
+    }
+
+    /// Returns a copy of all the records, in the order they were recorded.
+    #[must_use]
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records
+            .lock()
+            .map(|records| records.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the attempted transactions, in the order they were recorded, e.g. to [`Engine::replay`](crate::Engine::replay) them.
+    #[must_use]
+    pub fn transactions(&self) -> Vec<Transaction> {
+        self.records()
+            .into_iter()
+            .map(|record| record.transaction)
+            .collect()
+    }
+}
+
+/// [`AuditLog`] implementation emitting every record as a tracing event under the `audit` target.
+///
+/// Nothing is buffered: every event is emitted as soon as the transaction is attempted,
+/// within the current span, so it carries its fields, e.g. the `batch_id` of the run.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingAuditLog;
+
+impl AuditLog for TracingAuditLog {
+    fn record(&self, transaction: &Transaction, outcome: &EngineResult<Account>) {
+        let info = transaction.info();
+        match outcome {
+            Ok(account) => tracing::info!(
+                target: "audit",
+                tx = info.id,
+                client = info.client_id,
+                ?account,
+                "Transaction applied"
+            ),
+            Err(e) => tracing::info!(
+                target: "audit",
+                tx = info.id,
+                client = info.client_id,
+                error = %e,
+                "Transaction rejected"
+            ),
+        }
+    }
+}
+
+impl AuditLog for MemoryAuditLog {
+    fn record(&self, transaction: &Transaction, outcome: &EngineResult<Account>) {
+        match self.records.lock() {
+            Ok(mut records) => records.push(AuditRecord {
+                transaction: transaction.clone(),
+                outcome: outcome.clone(),
+                batch_id: self.batch_id.clone(),
+            }),
+            Err(e) => tracing::error!("Error while recording the transaction: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use payments_engine_core::{dec, engine::EngineError};
+
+    #[test]
+    fn records_are_kept_in_order_and_shared_by_clones() {
+        let log = MemoryAuditLog::new();
+        let clone = log.clone();
+        let deposit = Transaction::deposit(1, 1, dec!(10));
+        let withdrawal = Transaction::withdrawal(2, 1, dec!(20));
+
+        log.record(&deposit, &Ok(Account::seeded(1, dec!(10), dec!(0), false)));
+        log.record(&withdrawal, &Err(EngineError::InsufficientAvailableFunds));
+
+        assert_eq!(
+            clone.records(),
+            vec![
+                AuditRecord {
+                    transaction: deposit,
+                    outcome: Ok(Account::seeded(1, dec!(10), dec!(0), false)),
+                    batch_id: None,
+                },
+                AuditRecord {
+                    transaction: withdrawal,
+                    outcome: Err(EngineError::InsufficientAvailableFunds),
+                    batch_id: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn records_are_tagged_with_the_batch_id() {
+        let log = MemoryAuditLog::new().with_batch_id("batch-42");
+
+        log.record(
+            &Transaction::deposit(1, 1, dec!(10)),
+            &Ok(Account::seeded(1, dec!(10), dec!(0), false)),
+        );
+
+        assert_eq!(log.records()[0].batch_id.as_deref(), Some("batch-42"));
+    }
+}
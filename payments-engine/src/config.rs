@@ -0,0 +1,141 @@
+use payments_engine_core::common::Amount;
+
+/// Policy to apply when a disputed deposit exceeds the available funds,
+/// e.g. because part of it was already withdrawn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DisputePolicy {
+    /// Rejects the dispute with an [`EngineError::InsufficientAvailableFunds`](payments_engine_core::engine::EngineError::InsufficientAvailableFunds) error.
+    #[default]
+    Strict,
+    /// Holds only the available funds, driving them to zero.
+    /// The remainder that couldn't be held is flagged in the logs.
+    /// The held amount is recorded, so resolving or charging back the dispute releases exactly that amount.
+    HoldAvailable,
+    /// Holds the full amount of the deposit, letting the available funds go negative.
+    AllowNegative,
+}
+
+/// Policy to apply when a resolved deposit exceeds the held funds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResolvePolicy {
+    /// Rejects the resolve with an [`EngineError::InsufficientHeldFunds`](payments_engine_core::engine::EngineError::InsufficientHeldFunds) error.
+    #[default]
+    Strict,
+    /// Releases up to the held funds, i.e. `min(held, deposit amount)`.
+    PartialRelease,
+}
+
+/// Policy to apply to the stored deposits, withdrawals and adjustments when they fail.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RollbackPolicy {
+    /// Always deletes the failed transaction from the store.
+    #[default]
+    Always,
+    /// Keeps the ids of the transactions rejected because the account is locked in the store,
+    /// so they can't be reused. The transactions themselves are dropped,
+    /// so they don't affect the balances and can't be disputed.
+    /// Any other failed transaction is deleted.
+    KeepOnLocked,
+}
+
+/// Configuration of the business rules applied by the [`Engine`](crate::Engine).
+///
+/// The default configuration keeps the standard behavior.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineConfig {
+    /// Requires disputes to be reviewed before they can be charged back,
+    /// so charging back an open dispute fails with an `EngineError::InvalidDisputeTransition`.
+    /// The rest of the transitions are always validated.
+    ///
+    /// Disabled by default, so open disputes can be directly charged back:
+    /// the standard input has no reviews at all, so enforcing it would reject every chargeback of those feeds.
+    /// Workflows with a review step should enable it.
+    pub require_dispute_review: bool,
+    /// Policy for disputes exceeding the available funds. [`DisputePolicy::Strict`] by default.
+    pub dispute_policy: DisputePolicy,
+    /// Shorthand for [`DisputePolicy::AllowNegative`]: disputes hold the full amount of the deposit
+    /// even if the funds were already withdrawn, driving the available funds negative.
+    /// It overrides the `dispute_policy` when enabled. Disabled by default.
+    pub allow_negative_on_dispute: bool,
+    /// Policy for resolves exceeding the held funds. [`ResolvePolicy::Strict`] by default.
+    pub resolve_policy: ResolvePolicy,
+    /// Persists the [`Account`](payments_engine_core::account::Account) even if the transaction is ignored
+    /// because its referenced transaction doesn't exist, so the client appears in the reports.
+    /// Enabled by default.
+    pub persist_on_ignore: bool,
+    /// How far below zero the available funds can go on withdrawals.
+    /// Withdrawals succeed as long as `available - amount >= -overdraft_limit`. Zero by default.
+    pub overdraft_limit: Amount,
+    /// Normalizes the amounts of the transactions to the given decimal places before they're stored,
+    /// and the balances after every transaction is applied, rounding or padding them,
+    /// so the scale of the internal state is predictable.
+    /// Amounts which can't be represented with that many decimal places are rejected.
+    /// Note that `rust_decimal` supports up to 28 decimal places. Disabled by default.
+    pub working_precision: Option<u32>,
+    /// Keeps the deposits rejected because the account is locked as pending transactions,
+    /// so they can be reconciled later. They never affect the balances. Disabled by default.
+    pub retain_locked_deposits: bool,
+    /// Also rejects disputes, reviews, resolves and chargebacks of sanctioned clients.
+    /// Deposits, withdrawals and adjustments are always rejected. Disabled by default.
+    pub block_sanctioned_disputes: bool,
+    /// Withdrawals of at least this amount publish an [`EngineEvent::LargeWithdrawal`](crate::EngineEvent::LargeWithdrawal).
+    /// Disabled by default.
+    pub large_withdrawal_threshold: Option<Amount>,
+    /// Maximum number of simultaneous open disputes of a client, including the ones under review.
+    /// New disputes beyond it are rejected. There's no limit by default.
+    pub max_open_disputes: Option<u32>,
+    /// Policy for failed deposits, withdrawals and adjustments. [`RollbackPolicy::Always`] by default.
+    pub rollback_policy: RollbackPolicy,
+    /// Rejects deposits and withdrawals above this amount, e.g. to catch fat-finger errors.
+    /// There's no limit by default.
+    pub max_amount: Option<Amount>,
+    /// Rejects resolves and chargebacks of transactions which are not under dispute
+    /// with an [`EngineError::NotUnderDispute`](payments_engine_core::engine::EngineError::NotUnderDispute) error,
+    /// as they may point to an upstream bug. They're ignored by default.
+    pub strict_dispute_refs: bool,
+    /// Checks that the `total` of the [`Account`](payments_engine_core::account::Account) equals `available + held`
+    /// after every transaction is applied. Otherwise, the transaction is rolled back with an
+    /// [`EngineError::IntegrityViolation`](payments_engine_core::engine::EngineError::IntegrityViolation)
+    /// instead of persisting the corrupt account. It's meant for debugging, so it's disabled by default.
+    pub check_integrity: bool,
+    /// Withdrawals exceeding the available funds debit whatever is available instead of being rejected,
+    /// e.g. for sweeps. The stored withdrawal keeps the withdrawn amount, so disputes hold what was actually debited.
+    /// See [`Engine::process_transaction_with_withdrawn`](crate::Engine::process_transaction_with_withdrawn).
+    /// Disabled by default.
+    pub allow_partial_withdrawal: bool,
+}
+
+impl EngineConfig {
+    /// Returns the [`DisputePolicy`] in effect, taking [`EngineConfig::allow_negative_on_dispute`] into account.
+    #[must_use]
+    pub const fn effective_dispute_policy(&self) -> DisputePolicy {
+        if self.allow_negative_on_dispute {
+            DisputePolicy::AllowNegative
+        } else {
+            self.dispute_policy
+        }
+    }
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            require_dispute_review: false,
+            dispute_policy: DisputePolicy::default(),
+            allow_negative_on_dispute: false,
+            resolve_policy: ResolvePolicy::default(),
+            persist_on_ignore: true,
+            overdraft_limit: Amount::ZERO,
+            working_precision: None,
+            retain_locked_deposits: false,
+            block_sanctioned_disputes: false,
+            large_withdrawal_threshold: None,
+            max_open_disputes: None,
+            rollback_policy: RollbackPolicy::default(),
+            max_amount: None,
+            strict_dispute_refs: false,
+            check_integrity: false,
+            allow_partial_withdrawal: false,
+        }
+    }
+}
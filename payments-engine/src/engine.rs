@@ -1,17 +1,57 @@
+use crate::{
+    events::EVENTS_CAPACITY, DisputePolicy, EngineConfig, EngineEvent, MonotonicIdGenerator,
+    ResolvePolicy, RollbackPolicy,
+};
 use async_trait::async_trait;
+use futures::StreamExt;
 use payments_engine_core::{
-    account::Account,
-    common::Amount,
-    engine::{Engine as CoreEngine, EngineError, EngineResult},
-    store::{Store, StoreError},
-    transaction::{Transaction, TransactionInfo},
+    account::{vanishes_at_display_precision, Account},
+    audit::AuditLog,
+    common::{Amount, ClientId},
+    engine::{Engine as CoreEngine, EngineError, EngineResult, ProcessSummary},
+    id_generator::IdGenerator,
+    sanctions::SanctionsChecker,
+    store::{Store, UpsertOutcome},
+    transaction::{DisputeState, Transaction, TransactionId, TransactionInfo},
+};
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
 };
-use std::pin::Pin;
+use tokio::sync::broadcast;
 use tracing::instrument;
+
+/// Maximum scale supported by [`Amount`].
+const MAX_WORKING_PRECISION: u32 = 28;
+
 /// The [`Engine`] is responsible for processing all the transactions.
 /// It also provides a way to get the current state of all the accounts.
 pub struct Engine<S: Store> {
     store: S,
+    config: EngineConfig,
+    id_generator: Box<dyn IdGenerator>,
+    sanctions_checker: Option<Box<dyn SanctionsChecker>>,
+    audit_log: Option<Box<dyn AuditLog>>,
+    processed_count: AtomicU64,
+    clients_count: AtomicU64,
+    events: broadcast::Sender<EngineEvent>,
+    /// Clients whose transactions couldn't be rolled back, so their state may be inconsistent.
+    inconsistent_clients: Mutex<HashSet<ClientId>>,
+}
+
+/// Outcome of a processed [`Transaction`].
+#[derive(Debug)]
+struct Processed {
+    /// The resulting [`Account`].
+    account: Account,
+    /// Whether the transaction was ignored because its referenced transaction doesn't exist.
+    ignored: bool,
+    /// Amount debited if it's a withdrawal.
+    withdrawn: Option<Amount>,
 }
 
 #[async_trait]
@@ -19,9 +59,355 @@ impl<S: Store> CoreEngine for Engine<S> {
     /// Processes the given [`Transaction`] and returns the resulting state of the [`Account`]
     #[instrument(skip(self))]
     async fn process_transaction(&self, transaction: Transaction) -> EngineResult<Account> {
+        self.processed_count.fetch_add(1, Ordering::Relaxed);
+        self.process(transaction, false).await
+    }
+
+    /// Processes the given transactions and returns a [`ProcessSummary`] of the outcomes.
+    /// Transactions referencing a transaction that doesn't exist are counted as ignored.
+    #[instrument(skip(self, transactions))]
+    async fn process_transactions<T>(&self, transactions: T) -> ProcessSummary
+    where
+        T: futures::Stream<Item = Transaction> + Send + Unpin + 'async_trait,
+    {
+        self.process_stream(transactions, false).await.0
+    }
+
+    /// Processes the given transactions until one of them is rejected, returning its error.
+    /// Transactions referencing a transaction that doesn't exist are counted as ignored, not rejected.
+    #[instrument(skip(self, transactions))]
+    async fn try_process_transactions<T>(&self, transactions: T) -> EngineResult<ProcessSummary>
+    where
+        T: futures::Stream<Item = Transaction> + Send + Unpin + 'async_trait,
+    {
+        match self.process_stream(transactions, true).await {
+            (summary, None) => Ok(summary),
+            (_, Some(e)) => Err(e),
+        }
+    }
+
+    /// Returns the current state of clients accounts.
+    #[instrument(skip(self))]
+    async fn report(&self) -> EngineResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>> {
+        let stream = self.store.get_all_accounts().await?;
+        Ok(stream)
+    }
+
+    /// Returns a page of up to `limit` [`Account`] sorted by client, skipping the first `offset` ones.
+    #[instrument(skip(self))]
+    async fn report_page(&self, offset: usize, limit: usize) -> EngineResult<Vec<Account>> {
+        let accounts = self.store.get_accounts_at_offset(offset, limit).await?;
+        Ok(accounts)
+    }
+
+    /// Returns the ids of all the clients with an [`Account`].
+    #[instrument(skip(self))]
+    async fn client_ids(&self) -> EngineResult<Vec<ClientId>> {
+        let client_ids = self.store.get_client_ids().await?;
+        Ok(client_ids)
+    }
+
+    /// Returns the current state of the client's [`Account`] if it exists.
+    #[instrument(skip(self))]
+    async fn get_account(&self, client: ClientId) -> EngineResult<Account> {
+        self.store.get_account(client).await?.ok_or_else(|| {
+            tracing::error!("Account of client {} doesn't exist", client);
+            EngineError::AccountNotFound { client }
+        })
+    }
+}
+
+impl<S: Store> Engine<S> {
+    /// Creates a new [`Engine`] with the given [`Store`].
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            config: EngineConfig::default(),
+            id_generator: Box::new(MonotonicIdGenerator::default()),
+            sanctions_checker: None,
+            audit_log: None,
+            processed_count: AtomicU64::new(0),
+            clients_count: AtomicU64::new(0),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+            inconsistent_clients: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Returns the number of transactions processed through [`CoreEngine::process_transaction`],
+    /// [`CoreEngine::process_transactions`] and [`CoreEngine::try_process_transactions`], regardless of their success.
+    #[must_use]
+    pub fn processed_count(&self) -> u64 {
+        self.processed_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of distinct clients whose [`Account`] was created by this [`Engine`].
+    /// Accounts that already existed in the [`Store`] are not counted.
+    #[must_use]
+    pub fn clients_count(&self) -> u64 {
+        self.clients_count.load(Ordering::Relaxed)
+    }
+
+    /// Subscribes to the [`EngineEvent`] published from now on.
+    /// Publishing never blocks the processing, so subscribers lagging behind miss the oldest events.
+    #[must_use]
+    pub fn subscribe(&self) -> broadcast::Receiver<EngineEvent> {
+        self.events.subscribe()
+    }
+
+    /// Sets the [`EngineConfig`] with the business rules to apply.
+    #[must_use]
+    pub fn with_config(mut self, config: EngineConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replaces the [`IdGenerator`] used to mint the ids of synthetic transactions.
+    #[must_use]
+    pub fn with_id_generator(mut self, id_generator: impl IdGenerator + 'static) -> Self {
+        self.id_generator = Box::new(id_generator);
+        self
+    }
+
+    /// Sets the [`SanctionsChecker`] consulted before applying any transaction.
+    /// The money movements of sanctioned clients are rejected with an [`EngineError::SanctionedClient`],
+    /// so their balances stay as they are. There's no checker by default.
+    #[must_use]
+    pub fn with_sanctions_checker(mut self, checker: impl SanctionsChecker + 'static) -> Self {
+        self.sanctions_checker = Some(Box::new(checker));
+        self
+    }
+
+    /// Sets the [`AuditLog`] where every attempted transaction is recorded along with its outcome,
+    /// including the rejected ones. There's no audit log by default.
+    #[must_use]
+    pub fn with_audit_log(mut self, audit_log: impl AuditLog + 'static) -> Self {
+        self.audit_log = Some(Box::new(audit_log));
+        self
+    }
+
+    /// Applies a manual [`Transaction::Adjustment`] to the client's [`Account`].
+    /// A positive amount credits the account while a negative one debits it, as long as there are enough available funds.
+    /// The id of the adjustment is minted by the [`IdGenerator`].
+    /// It returns an [`EngineError::TransactionIdSpaceExhausted`] if there are no ids left.
+    ///
+    /// Adjustments respect the locked-account rules unless `admin` is set,
+    /// in which case they can be applied to locked accounts too.
+    #[instrument(skip(self, reason))]
+    pub async fn adjust(
+        &self,
+        client: ClientId,
+        amount: Amount,
+        reason: impl Into<String> + Send,
+        admin: bool,
+    ) -> EngineResult<Account> {
+        let Some(id) = self.id_generator.next_id() else {
+            tracing::error!(
+                "No transaction ids left for the adjustment of client {}",
+                client
+            );
+            return Err(EngineError::TransactionIdSpaceExhausted);
+        };
+        let adjustment = Transaction::Adjustment {
+            info: TransactionInfo::new(id, client),
+            amount,
+            reason: reason.into(),
+        };
+        self.process(adjustment, admin).await
+    }
+
+    /// Unlocks the client's [`Account`], e.g. after a chargeback was reviewed,
+    /// so it can process transactions again.
+    /// It returns an [`EngineError::AccountNotFound`] if the account doesn't exist, instead of creating an empty one.
+    ///
+    /// Note that closed accounts are just locked, so unlocking them reopens them too.
+    #[instrument(skip(self))]
+    pub async fn unlock_account(&self, client: ClientId) -> EngineResult<Account> {
+        let Some(mut account) = self.store.get_account(client).await? else {
+            tracing::error!("Tried to unlock the non existing account {}", client);
+            return Err(EngineError::AccountNotFound { client });
+        };
+        account.locked = false;
+        self.store.upsert_account(&account).await?;
+        tracing::info!("Account {} unlocked", client);
+        Ok(account)
+    }
+
+    /// Returns the clients whose held funds don't match the sum of their under dispute deposits and withdrawals,
+    /// e.g. accounts holding funds without any active dispute.
+    /// This is a diagnostic to detect data integrity issues.
+    #[instrument(skip(self))]
+    pub async fn find_orphaned_holds(&self) -> EngineResult<Vec<ClientId>> {
+        let mismatches = self.store.verify_dispute_consistency().await?;
+        for mismatch in &mismatches {
+            tracing::warn!(?mismatch, "Orphaned hold found");
+        }
+        Ok(mismatches.into_iter().map(|m| m.client).collect())
+    }
+
+    /// Returns the clients flagged as inconsistent because a rollback of their transactions failed, sorted.
+    /// Their transactions are rejected with an [`EngineError::AccountInInconsistentState`]
+    /// until they're cleared with [`Engine::clear_inconsistent_state`].
+    #[must_use]
+    pub fn inconsistent_clients(&self) -> Vec<ClientId> {
+        let mut clients = self
+            .inconsistent_clients
+            .lock()
+            .map(|clients| clients.iter().copied().collect::<Vec<_>>())
+            .unwrap_or_default();
+        clients.sort_unstable();
+        clients
+    }
+
+    /// Clears the inconsistent flag of the client, e.g. once its [`Account`] has been reconciled,
+    /// so its transactions are processed again.
+    /// Returns whether the client was flagged.
+    pub fn clear_inconsistent_state(&self, client: ClientId) -> bool {
+        let cleared = self
+            .inconsistent_clients
+            .lock()
+            .is_ok_and(|mut clients| clients.remove(&client));
+        if cleared {
+            tracing::info!("Inconsistent state of account {} cleared", client);
+        }
+        cleared
+    }
+
+    /// Flags the client as inconsistent after a failed rollback.
+    fn flag_inconsistent(&self, client: ClientId) {
+        tracing::error!("Flagging account {} as inconsistent", client);
+        match self.inconsistent_clients.lock() {
+            Ok(mut clients) => {
+                clients.insert(client);
+            }
+            Err(e) => tracing::error!("Error while flagging the account: {}", e),
+        }
+    }
+
+    /// Returns the deposits rejected because their account was locked,
+    /// retained when [`EngineConfig::retain_locked_deposits`] is enabled.
+    #[instrument(skip(self))]
+    pub async fn pending_deposits(&self) -> EngineResult<Vec<Transaction>> {
+        Ok(self.store.get_pending_transactions().await?)
+    }
+
+    /// Rebuilds the state of the accounts from scratch by processing the given transactions in order,
+    /// e.g. the ones of an [`AuditLog`], and returns a [`ProcessSummary`] of the outcomes.
+    /// Unlike [`CoreEngine::report`], which only reads the current state, this one writes it.
+    ///
+    /// The [`Store`] must be empty, e.g. a fresh one, otherwise an [`EngineError::ReplayIntoNonEmptyStore`] is returned.
+    /// Note that the transactions go through the regular rules, so admin adjustments of locked accounts are rejected.
+    #[instrument(skip(self, transactions))]
+    pub async fn replay(
+        &self,
+        transactions: impl futures::Stream<Item = Transaction> + Send + Unpin,
+    ) -> EngineResult<ProcessSummary> {
+        if !self.store.get_client_ids().await?.is_empty() {
+            tracing::error!("Tried to replay the transactions into a non empty store");
+            return Err(EngineError::ReplayIntoNonEmptyStore);
+        }
+        let summary = self.process_transactions(transactions).await;
+        tracing::info!(?summary, "Transactions replayed");
+        Ok(summary)
+    }
+
+    /// Processes the given [`Transaction`].
+    /// If `bypass_lock` is set, the transaction will be applied even if the account is locked.
+    async fn process(&self, transaction: Transaction, bypass_lock: bool) -> EngineResult<Account> {
+        self.process_with_outcome(transaction, bypass_lock)
+            .await
+            .map(|processed| processed.account)
+    }
+
+    /// Processes the given [`Transaction`] and returns the resulting [`Account`],
+    /// along with the amount debited if it's a withdrawal.
+    /// It's lower than the requested one if the withdrawal was partially filled,
+    /// see [`EngineConfig::allow_partial_withdrawal`].
+    #[instrument(skip(self))]
+    pub async fn process_transaction_with_withdrawn(
+        &self,
+        transaction: Transaction,
+    ) -> EngineResult<(Account, Option<Amount>)> {
+        self.processed_count.fetch_add(1, Ordering::Relaxed);
+        self.process_with_outcome(transaction, false)
+            .await
+            .map(|processed| (processed.account, processed.withdrawn))
+    }
+
+    /// Processes the given transactions, counting their outcomes in a [`ProcessSummary`].
+    /// Rejected transactions are counted and skipped, unless `stop_on_error` is set,
+    /// in which case the first rejection stops the stream and its error is returned along with the summary.
+    async fn process_stream<T>(
+        &self,
+        mut transactions: T,
+        stop_on_error: bool,
+    ) -> (ProcessSummary, Option<EngineError>)
+    where
+        T: futures::Stream<Item = Transaction> + Send + Unpin,
+    {
+        let mut summary = ProcessSummary::default();
+        while let Some(transaction) = transactions.next().await {
+            self.processed_count.fetch_add(1, Ordering::Relaxed);
+            match self.process_with_outcome(transaction, false).await {
+                Ok(Processed { ignored: true, .. }) => summary.ignored += 1,
+                Ok(Processed { ignored: false, .. }) => summary.applied += 1,
+                Err(e) if stop_on_error => {
+                    tracing::error!(error=?e, ?summary, "Stopping at the rejected transaction: {}", e);
+                    return (summary, Some(e));
+                }
+                Err(e) => {
+                    tracing::error!(error=?e, "Error processing transaction: {}", e);
+                    summary.rejected += 1;
+                }
+            }
+        }
+        (summary, None)
+    }
+
+    /// Processes the given [`Transaction`] and returns its [`Processed`] outcome.
+    /// The attempt is recorded in the [`AuditLog`], if any.
+    async fn process_with_outcome(
+        &self,
+        transaction: Transaction,
+        bypass_lock: bool,
+    ) -> EngineResult<Processed> {
+        let Some(audit_log) = &self.audit_log else {
+            return self.process_attempt(transaction, bypass_lock).await;
+        };
+        let attempted = transaction.clone();
+        let result = self.process_attempt(transaction, bypass_lock).await;
+        let outcome = match &result {
+            Ok(processed) => Ok(processed.account.clone()),
+            Err(e) => Err(e.clone()),
+        };
+        audit_log.record(&attempted, &outcome);
+        result
+    }
+
+    /// Processes the given [`Transaction`] and returns its [`Processed`] outcome, without auditing it.
+    async fn process_attempt(
+        &self,
+        mut transaction: Transaction,
+        bypass_lock: bool,
+    ) -> EngineResult<Processed> {
         tracing::debug!("Processing transaction: {:?}", transaction);
         // validate transaction state
         let transaction_info = transaction.info().clone();
+        // make sure synthetic transactions never reuse this id
+        self.id_generator.observe(transaction_info.id);
+        if self
+            .inconsistent_clients
+            .lock()
+            .is_ok_and(|clients| clients.contains(&transaction_info.client_id))
+        {
+            tracing::error!(
+                "Transaction with id {} rejected as account {} may be inconsistent",
+                transaction_info.id,
+                transaction_info.client_id
+            );
+            return Err(EngineError::AccountInInconsistentState {
+                client: transaction_info.client_id,
+            });
+        }
         if transaction.has_negative_amount() {
             tracing::error!(
                 "Transaction with id {} has negative amount",
@@ -31,18 +417,93 @@ impl<S: Store> CoreEngine for Engine<S> {
                 id: transaction_info.id,
             });
         }
+        if let (
+            Some(limit),
+            Transaction::Deposit { amount, .. } | Transaction::Withdrawal { amount, .. },
+        ) = (self.config.max_amount, &transaction)
+        {
+            if *amount > limit {
+                tracing::error!(
+                    "Transaction with id {} has amount {} above the limit of {}",
+                    transaction_info.id,
+                    amount,
+                    limit
+                );
+                return Err(EngineError::AmountExceedsLimit {
+                    id: transaction_info.id,
+                    amount: *amount,
+                    limit,
+                });
+            }
+        }
+
+        // sanctioned clients can't move money, even with admin rights.
+        // disputes are only blocked if configured, as they don't bring new money in or out.
+        let blocked =
+            !transaction.references_transaction() || self.config.block_sanctioned_disputes;
+        if blocked
+            && self
+                .sanctions_checker
+                .as_ref()
+                .is_some_and(|checker| checker.is_sanctioned(transaction_info.client_id))
+        {
+            tracing::error!(
+                "Transaction with id {} rejected as client {} is sanctioned",
+                transaction_info.id,
+                transaction_info.client_id
+            );
+            return Err(EngineError::SanctionedClient {
+                client: transaction_info.client_id,
+            });
+        }
+
+        // the amount is rounded once before being stored,
+        // so the stored transaction always matches what it moved in the account.
+        if let (Some(places), Some(amount)) =
+            (self.config.working_precision, transaction.amount_mut())
+        {
+            *amount = rescale_checked(*amount, places)?;
+        }
+
+        // tiny amounts are kept with their full precision internally,
+        // but they will be displayed as zero in the reports.
+        if transaction
+            .amount()
+            .is_some_and(vanishes_at_display_precision)
+        {
+            tracing::warn!(
+                "Transaction with id {} has an amount below the display precision",
+                transaction_info.id
+            );
+        }
 
         // storing the transaction in the store.
         // note that duplicated transactions are not allowed and
         // the store will return an error if the transaction already exists.
         let transaction = self.store.create_transaction(transaction).await?;
-
-        let transaction_result: EngineResult<Account> = async {
-            // get info about the account from the store
-            let mut account = self.store.get_account(transaction_info.client_id).await?;
+        // dispute state of the referenced transaction before applying the transaction.
+        // it will be used to rollback the dispute state if needed.
+        let mut previous_dispute_state = None;
+
+        let transaction_result: EngineResult<Processed> = async {
+            // get info about the account from the store.
+            // disputes, reviews, resolves and chargebacks also need the referenced transaction,
+            // so we get both in one go.
+            let (mut account, ref_transaction) = if transaction.references_transaction() {
+                self.store
+                    .get_account_and_transaction(transaction_info.client_id, transaction_info.id)
+                    .await?
+            } else {
+                (
+                    self.store
+                        .get_or_default_account(transaction_info.client_id)
+                        .await?,
+                    None,
+                )
+            };
 
             // is the account locked?
-            if account.locked {
+            if account.locked && !bypass_lock {
                 tracing::error!(
                     "Tried to apply transaction with id {} to a locked account {}",
                     transaction_info.id,
@@ -56,54 +517,156 @@ impl<S: Store> CoreEngine for Engine<S> {
 
             // apply the transaction to the account in memory.
             // note that there might be a mutationn of the ref transaction
-            // in case of disputes, resolves and chargebacks.
-            self.apply_transaction(&mut account, &transaction).await?;
+            // in case of disputes, reviews, resolves and chargebacks.
+            previous_dispute_state = ref_transaction
+                .as_ref()
+                .and_then(Transaction::dispute_state);
+            let ignored = ref_transaction.is_none() && transaction.references_transaction();
+            let available = account.available;
+            self.apply_transaction(&mut account, &transaction, ref_transaction)
+                .await?;
+            let withdrawn = matches!(transaction, Transaction::Withdrawal { .. })
+                .then(|| available - account.available);
+            if let Some(places) = self.config.working_precision {
+                // same rescaling as padded displays, but applied to the internal state
+                account.available = rescale_checked(account.available, places)?;
+                account.held = rescale_checked(account.held, places)?;
+                account.total = rescale_checked(account.total, places)?;
+            }
+            if self.config.check_integrity && !account.is_consistent() {
+                tracing::error!(account = ?account, "Account total is inconsistent, not persisting it");
+                return Err(EngineError::IntegrityViolation {
+                    client: account.client,
+                });
+            }
+
+            if ignored && !self.config.persist_on_ignore {
+                tracing::debug!(
+                    "Not persisting account {} as transaction {} was ignored",
+                    account.client,
+                    transaction_info.id
+                );
+                return Ok(Processed {
+                    account,
+                    ignored,
+                    withdrawn,
+                });
+            }
+
+            // partially filled withdrawals are stored with the withdrawn amount instead of the requested one
+            let transaction = match (&transaction, withdrawn) {
+                (Transaction::Withdrawal { amount, .. }, Some(withdrawn)) if *amount != withdrawn => {
+                    self.replace_partial_withdrawal(&transaction, withdrawn)
+                        .await?
+                }
+                _ => transaction.clone(),
+            };
 
             // save the account back to the store
-            self.store
+            let outcome = self
+                .store
                 .upsert_account(&account)
                 .await
                 .map_err(EngineError::TransactionNotCommited)?;
+            if outcome == UpsertOutcome::Created {
+                self.clients_count.fetch_add(1, Ordering::Relaxed);
+            }
+            if !ignored {
+                self.publish_events(&account, &transaction);
+            }
 
-            Ok(account)
+            Ok(Processed {
+                account,
+                ignored,
+                withdrawn,
+            })
         }
         .await;
 
         tracing::debug!("Transaction processed: {:?}", transaction_result);
 
         match transaction_result {
-            Ok(account) => Ok(account),
+            Ok(result) => Ok(result),
             Err(e) => {
                 // let's rollback the stored transaction.
-                // NOTE: if the account is frozen we're rolling back all the transactions.
-                // this could be easily changed by excluding LockedAccount errors.
-                // For now, it seems like a sensible behavior due the simple implementation that we're aiming for.
+                // NOTE: by default, if the account is frozen we're rolling back all the transactions.
+                // the rollback policy allows keeping the ones rejected because of the locked account.
                 // IMPORTANT:
-                // we're only rolling back deposit and withdrawals.
-                // for the rest of transactions we're rolling back the transaction under_dispute flag in case the transaction didn't commit
+                // we're only rolling back deposits, withdrawals and adjustments.
+                // for the rest of transactions we're rolling back the referenced transaction dispute state in case the transaction didn't commit
                 match transaction {
-                    Transaction::Deposit { .. } | Transaction::Withdrawal { .. } => {
+                    Transaction::Deposit { .. }
+                    | Transaction::Withdrawal { .. }
+                    | Transaction::Adjustment { .. }
+                    | Transaction::OpenAccount { .. }
+                    | Transaction::CloseAccount { .. } => {
                         // rolling back
-                        tracing::warn!("Rolling back transaction for tx {}", transaction_info.id);
-                        if let Err(e) = self.store.delete_transaction(transaction_info.id).await {
+                        let rollback = if matches!(e, EngineError::LockedAccount { .. })
+                            && self.config.rollback_policy == RollbackPolicy::KeepOnLocked
+                        {
+                            // only the id is kept, the transaction must never be disputable
+                            tracing::warn!(
+                                "Keeping the id of transaction {} of locked account {}",
+                                transaction_info.id,
+                                transaction_info.client_id
+                            );
+                            self.store.reserve_transaction_id(transaction_info.id).await
+                        } else {
+                            tracing::warn!(
+                                "Rolling back transaction for tx {}",
+                                transaction_info.id
+                            );
+                            self.store.delete_transaction(transaction_info.id).await
+                        };
+                        if let Err(e) = rollback {
                             tracing::error!(
                                 "CRITICAL: Failed to rollback transaction: {}",
                                 transaction_info.id
                             );
+                            self.flag_inconsistent(transaction_info.client_id);
                             return Err(EngineError::Store(e));
                         }
+                        if matches!(transaction, Transaction::Deposit { .. })
+                            && matches!(e, EngineError::LockedAccount { .. })
+                            && self.config.retain_locked_deposits
+                        {
+                            tracing::warn!(
+                                "Retaining deposit {} of locked account {} as pending",
+                                transaction_info.id,
+                                transaction_info.client_id
+                            );
+                            self.store.add_pending_transaction(transaction).await?;
+                        }
                     }
                     Transaction::Dispute { .. }
+                    | Transaction::Review { .. }
                     | Transaction::Resolve { .. }
                     | Transaction::ChargeBack { .. } => {
                         // Rollback disputed state in the store if the error comes from the upsert_account layer
-                        if let EngineError::TransactionNotCommited(_) = e {
-                            // change the under_dispute_state
+                        // or if the account was not persisted because it was corrupt
+                        if let (
+                            EngineError::TransactionNotCommited(_)
+                            | EngineError::IntegrityViolation { .. },
+                            Some(state),
+                        ) = (&e, previous_dispute_state)
+                        {
+                            // restore the previous dispute state
                             tracing::warn!(
                                 "Rolling back transaction dispute state for tx {}",
                                 transaction_info.id
                             );
-                            self.store.toggle_under_dispute(transaction_info.id).await?;
+                            if let Err(e) = self
+                                .store
+                                .set_dispute_state(transaction_info.id, state)
+                                .await
+                            {
+                                tracing::error!(
+                                    "CRITICAL: Failed to rollback dispute state of transaction: {}",
+                                    transaction_info.id
+                                );
+                                self.flag_inconsistent(transaction_info.client_id);
+                                return Err(EngineError::Store(e));
+                            }
                         }
                     }
                 };
@@ -113,31 +676,64 @@ impl<S: Store> CoreEngine for Engine<S> {
         }
     }
 
-    /// Returns the current state of clients accounts.
-    #[instrument(skip(self))]
-    async fn report(&self) -> EngineResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>> {
-        let stream = self.store.get_all_accounts().await?;
-        Ok(stream)
-    }
-}
-
-impl<S: Store> Engine<S> {
-    /// Creates a new [`Engine`] with the given [`Store`].
-    pub fn new(store: S) -> Self {
-        Self { store }
+    /// Publishes the [`EngineEvent`] caused by a committed [`Transaction`], if any.
+    fn publish_events(&self, account: &Account, transaction: &Transaction) {
+        let TransactionInfo { id, client_id, .. } = *transaction.info();
+        let event = match transaction {
+            Transaction::ChargeBack { .. } if account.locked => EngineEvent::AccountLocked {
+                client: client_id,
+                tx: id,
+            },
+            Transaction::Withdrawal { amount, .. }
+                if self
+                    .config
+                    .large_withdrawal_threshold
+                    .is_some_and(|threshold| *amount >= threshold) =>
+            {
+                EngineEvent::LargeWithdrawal {
+                    client: client_id,
+                    tx: id,
+                    amount: *amount,
+                }
+            }
+            Transaction::Dispute { .. } => EngineEvent::DisputeOpened {
+                client: client_id,
+                tx: id,
+            },
+            _ => return,
+        };
+        // it only fails if there are no subscribers
+        if self.events.send(event).is_err() {
+            tracing::trace!("No subscribers for the engine events");
+        }
     }
 
     async fn apply_transaction(
         &self,
         account: &mut Account,
         transaction: &Transaction,
+        ref_transaction: Option<Transaction>,
     ) -> EngineResult<()> {
         match transaction {
             Transaction::Deposit { amount, .. } => self.deposit(account, amount).await,
             Transaction::Withdrawal { amount, .. } => self.withdrawal(account, amount).await,
-            Transaction::Dispute { info } => self.dispute(account, info).await,
-            Transaction::Resolve { info } => self.resolve(account, info).await,
-            Transaction::ChargeBack { info } => self.chargeback(account, info).await,
+            Transaction::Dispute { info, amount } => {
+                self.dispute(account, info, *amount, ref_transaction).await
+            }
+            Transaction::Review { info } => self.review(account, info, ref_transaction).await,
+            Transaction::Resolve { info } => self.resolve(account, info, ref_transaction).await,
+            Transaction::ChargeBack { info } => {
+                self.chargeback(account, info, ref_transaction).await
+            }
+            Transaction::Adjustment {
+                info,
+                amount,
+                reason,
+            } => self.adjustment(account, info, amount, reason).await,
+            Transaction::OpenAccount { info, locked } => {
+                self.open_account(account, info, *locked).await
+            }
+            Transaction::CloseAccount { info } => self.close_account(account, info).await,
         }
     }
 
@@ -148,182 +744,477 @@ impl<S: Store> Engine<S> {
     }
 
     async fn withdrawal(&self, account: &mut Account, amount: &Amount) -> EngineResult<()> {
-        if account.available < *amount {
+        // withdrawals can only draw from the available funds, held funds are never touched.
+        // accounts can be overdrawn up to the configured limit
+        let withdrawable = (account.available + self.config.overdraft_limit).max(Amount::ZERO);
+        let amount = if *amount <= withdrawable {
+            amount
+        } else if self.config.allow_partial_withdrawal {
+            tracing::warn!(?account, %amount, "Partially filling the withdrawal");
+            &withdrawable
+        } else {
             tracing::error!(?account, "Insufficient available funds");
             return Err(EngineError::InsufficientAvailableFunds);
-        }
+        };
+        let consistent = account.total == account.available + account.held;
         account.available -= amount;
         account.total -= amount;
+        debug_assert!(
+            !consistent || account.total == account.available + account.held,
+            "withdrawals must keep the total as the sum of the available and held funds"
+        );
+        Ok(())
+    }
+
+    /// Updates the stored [`Transaction::Withdrawal`] in place with the withdrawn amount, returning it.
+    /// The id stays reserved all along, so no other transaction can take it in between.
+    async fn replace_partial_withdrawal(
+        &self,
+        transaction: &Transaction,
+        withdrawn: Amount,
+    ) -> EngineResult<Transaction> {
+        let mut partial = transaction.clone();
+        if let Some(amount) = partial.amount_mut() {
+            *amount = withdrawn;
+        }
+        self.store
+            .set_transaction_amount(partial.info().id, withdrawn)
+            .await?;
+        Ok(partial)
+    }
+
+    async fn adjustment(
+        &self,
+        account: &mut Account,
+        info: &TransactionInfo,
+        amount: &Amount,
+        reason: &str,
+    ) -> EngineResult<()> {
+        if account.available + amount < Amount::ZERO {
+            tracing::error!(?account, "Insufficient available funds for adjustment");
+            return Err(EngineError::InsufficientAvailableFunds);
+        }
+        account.available += amount;
+        account.total += amount;
+        tracing::info!(
+            target: "audit",
+            tx = info.id,
+            client = info.client_id,
+            %amount,
+            reason,
+            "Adjustment applied"
+        );
+        Ok(())
+    }
+
+    async fn open_account(
+        &self,
+        account: &mut Account,
+        info: &TransactionInfo,
+        locked: bool,
+    ) -> EngineResult<()> {
+        if self.store.get_account(account.client).await?.is_some() {
+            tracing::error!(?account, "Can't open an account which already exists");
+            return Err(EngineError::AccountAlreadyExists {
+                client: account.client,
+            });
+        }
+        account.locked = locked;
+        tracing::info!(
+            target: "audit",
+            tx = info.id,
+            client = info.client_id,
+            locked,
+            "Account opened"
+        );
+        Ok(())
+    }
+
+    async fn close_account(
+        &self,
+        account: &mut Account,
+        info: &TransactionInfo,
+    ) -> EngineResult<()> {
+        if self.store.get_account(account.client).await?.is_none() {
+            tracing::error!(?account, "Can't close an account which doesn't exist");
+            return Err(EngineError::AccountNotFound {
+                client: account.client,
+            });
+        }
+        if !account.total.is_zero() {
+            tracing::error!(?account, "Can't close an account with a nonzero total");
+            return Err(EngineError::NonZeroBalanceOnClose {
+                client: account.client,
+                total: account.total,
+            });
+        }
+        account.locked = true;
+        tracing::info!(
+            target: "audit",
+            tx = info.id,
+            client = info.client_id,
+            "Account closed"
+        );
         Ok(())
     }
 
-    async fn dispute(&self, account: &mut Account, info: &TransactionInfo) -> EngineResult<()> {
+    /// Checks that the dispute of the given [`Transaction`] can move from one [`DisputeState`] to another.
+    fn validate_dispute_transition(
+        &self,
+        id: TransactionId,
+        from: DisputeState,
+        to: DisputeState,
+    ) -> EngineResult<()> {
+        if from.can_transition_to(to, self.config.require_dispute_review) {
+            Ok(())
+        } else {
+            tracing::error!(
+                "Invalid dispute transition for tx {}: {:?} -> {:?}",
+                id,
+                from,
+                to
+            );
+            Err(EngineError::InvalidDisputeTransition { id, from, to })
+        }
+    }
+
+    /// Disputes the referenced transaction,
+    /// holding only the `partial_amount` if given, or the whole amount otherwise.
+    async fn dispute(
+        &self,
+        account: &mut Account,
+        info: &TransactionInfo,
+        partial_amount: Option<Amount>,
+        ref_transaction: Option<Transaction>,
+    ) -> EngineResult<()> {
         // if no ref, ignore
-        let ref_transaction = self.store.get_transaction(info.id).await;
         match ref_transaction {
-            Err(StoreError::NotFound { id }) => {
-                tracing::info!("Ignoring dispute for transaction {}. No ref found", id);
+            None => {
+                tracing::info!("Ignoring dispute for transaction {}. No ref found", info.id);
                 Ok(())
             }
-            Err(e) => Err(EngineError::Store(e)),
-            Ok(ref_tx) => {
-                if let Transaction::Deposit {
-                    info,
-                    amount,
-                    under_dispute,
-                } = ref_tx
-                {
-                    if account.client != info.client_id {
-                        return Err(wrong_client_error(account, &info));
-                    } else if under_dispute {
-                        tracing::error!(?account, "Double dispute for tx {}", info.id);
-                        return Err(EngineError::DoubleDispute { id: info.id });
-                    } else if account.available < amount {
-                        tracing::error!(?account, "Insufficient available funds");
-                        return Err(EngineError::InsufficientAvailableFunds);
+            Some(ref_tx) => {
+                let is_withdrawal = matches!(ref_tx, Transaction::Withdrawal { .. });
+                let (info, amount, dispute_state) = disputable(ref_tx)?;
+                if account.client != info.client_id {
+                    return Err(wrong_client_error(account, &info));
+                } else if dispute_state.is_under_dispute() {
+                    tracing::error!(?account, "Double dispute for tx {}", info.id);
+                    return Err(EngineError::DoubleDispute { id: info.id });
+                }
+                self.validate_dispute_transition(info.id, dispute_state, DisputeState::Open)?;
+                if partial_amount.is_some_and(|partial| partial > amount) {
+                    tracing::error!(?account, "Dispute exceeds the amount of tx {}", info.id);
+                    return Err(EngineError::DisputeExceedsTransaction { id: info.id });
+                }
+                if let Some(max) = self.config.max_open_disputes {
+                    if self.store.open_disputes_count(account.client).await? >= max {
+                        tracing::error!(?account, "Too many open disputes for tx {}", info.id);
+                        return Err(EngineError::TooManyOpenDisputes {
+                            client: account.client,
+                        });
                     }
-                    // if everything is fine: update the account
-                    account.available -= amount;
+                }
+                let amount = partial_amount.unwrap_or(amount);
+                // disputing a zero amount would hold nothing but could still lock the account on chargeback
+                if amount.is_zero() {
+                    tracing::error!(?account, "Zero amount dispute for tx {}", info.id);
+                    return Err(EngineError::ZeroAmountDispute { id: info.id });
+                }
+                let mut held = amount;
+                if is_withdrawal {
+                    // the withdrawn funds are held until the dispute is settled
                     account.held += amount;
-                    // set to under dispute
-                    self.store
-                        .set_transaction_under_dispute(info.id, true)
-                        .await?;
+                    account.total += amount;
                 } else {
-                    tracing::error!("Reference transaction {} is not a Deposit", info.id);
-                    return Err(EngineError::WrongTransactionRef { id: info.id });
+                    if account.available < amount {
+                        match self.config.effective_dispute_policy() {
+                            DisputePolicy::Strict => {
+                                tracing::error!(?account, "Insufficient available funds");
+                                return Err(EngineError::InsufficientAvailableFunds);
+                            }
+                            DisputePolicy::HoldAvailable => {
+                                held = account.available.max(Amount::ZERO);
+                                tracing::warn!(
+                                    ?account,
+                                    remainder = %(amount - held),
+                                    "Dispute for tx {} exceeds the available funds. Holding only the available funds",
+                                    info.id
+                                );
+                            }
+                            DisputePolicy::AllowNegative => {
+                                tracing::warn!(
+                                    ?account,
+                                    "Dispute for tx {} exceeds the available funds. Available funds will be negative",
+                                    info.id
+                                );
+                            }
+                        }
+                    }
+                    // if everything is fine: update the account
+                    account.available -= held;
+                    account.held += held;
                 }
+                // open the dispute, remembering the held amount for the resolve or chargeback.
+                // with [`DisputePolicy::HoldAvailable`] it may be less than the disputed one.
+                let held_amount = if held == amount {
+                    partial_amount
+                } else {
+                    Some(held)
+                };
+                self.store.set_disputed_amount(info.id, held_amount).await?;
+                self.store
+                    .set_dispute_state(info.id, DisputeState::Open)
+                    .await?;
 
                 Ok(())
             }
         }
     }
 
-    async fn resolve(&self, account: &mut Account, info: &TransactionInfo) -> EngineResult<()> {
+    async fn review(
+        &self,
+        account: &mut Account,
+        info: &TransactionInfo,
+        ref_transaction: Option<Transaction>,
+    ) -> EngineResult<()> {
         // if no ref, ignore
-        let ref_transaction = self.store.get_transaction(info.id).await;
         match ref_transaction {
-            Err(StoreError::NotFound { id }) => {
-                tracing::info!("Ignoring resolve for transaction {}. No ref found", id);
+            None => {
+                tracing::info!("Ignoring review for transaction {}. No ref found", info.id);
                 Ok(())
             }
-            Err(e) => Err(EngineError::Store(e)),
-            Ok(ref_tx) => {
-                if let Transaction::Deposit {
-                    info,
-                    amount,
-                    under_dispute,
-                } = ref_tx
-                {
-                    if account.client != info.client_id {
-                        return Err(wrong_client_error(account, &info));
-                    } else if account.held < amount {
-                        tracing::error!(?account, "Insufficient held funds");
-                        return Err(EngineError::InsufficientHeldFunds);
-                    } else if !under_dispute {
-                        tracing::info!(
-                            "Ignoring resolve for transaction {}. Not under dispute",
-                            info.id
-                        );
-                        return Ok(());
-                    }
-                    // if everything is fine: update the account
-                    account.held -= amount;
-                    account.available += amount;
-                    // set to not under dispute
-                    self.store
-                        .set_transaction_under_dispute(info.id, false)
-                        .await?;
-                } else {
-                    tracing::error!("Reference transaction {} is not a Deposit", info.id);
-                    return Err(EngineError::WrongTransactionRef { id: info.id });
+            Some(ref_tx) => {
+                let (info, _, dispute_state) = disputable(ref_tx)?;
+                if account.client != info.client_id {
+                    return Err(wrong_client_error(account, &info));
                 }
+                self.validate_dispute_transition(
+                    info.id,
+                    dispute_state,
+                    DisputeState::UnderReview,
+                )?;
+                // reviews don't change the funds
+                self.store
+                    .set_dispute_state(info.id, DisputeState::UnderReview)
+                    .await?;
 
                 Ok(())
             }
         }
     }
 
-    async fn chargeback(&self, account: &mut Account, info: &TransactionInfo) -> EngineResult<()> {
+    async fn resolve(
+        &self,
+        account: &mut Account,
+        info: &TransactionInfo,
+        ref_transaction: Option<Transaction>,
+    ) -> EngineResult<()> {
         // if no ref, ignore
-        let ref_transaction = self.store.get_transaction(info.id).await;
         match ref_transaction {
-            Err(StoreError::NotFound { id }) => {
-                tracing::info!("Ignoring chargeback for transaction {}. No ref found", id);
+            None => {
+                tracing::info!("Ignoring resolve for transaction {}. No ref found", info.id);
                 Ok(())
             }
-            Err(e) => Err(EngineError::Store(e)),
-            Ok(ref_tx) => {
-                if let Transaction::Deposit {
-                    info,
-                    amount,
-                    under_dispute,
-                } = ref_tx
-                {
-                    if account.client != info.client_id {
-                        return Err(wrong_client_error(account, &info));
-                    } else if account.held < amount {
-                        tracing::error!(?account, "Insufficient held funds");
-                        return Err(EngineError::InsufficientHeldFunds);
-                    } else if !under_dispute {
-                        tracing::info!(
-                            "Ignoring chargeback for transaction {}. Not under dispute",
-                            info.id
-                        );
-                        return Ok(());
-                    }
-                    // if everything is fine: update the account
-                    account.held -= amount;
+            Some(ref_tx) => {
+                let is_withdrawal = matches!(ref_tx, Transaction::Withdrawal { .. });
+                // only the recorded amount was held
+                let disputed_amount = ref_tx.disputed_amount();
+                let (info, amount, dispute_state) = disputable(ref_tx)?;
+                let amount = disputed_amount.unwrap_or(amount);
+                let amount = match self.config.resolve_policy {
+                    ResolvePolicy::PartialRelease => amount.min(account.held),
+                    ResolvePolicy::Strict => amount,
+                };
+                if account.client != info.client_id {
+                    return Err(wrong_client_error(account, &info));
+                } else if self.config.strict_dispute_refs && !dispute_state.is_under_dispute() {
+                    tracing::error!("Transaction {} is not under dispute", info.id);
+                    return Err(EngineError::NotUnderDispute { id: info.id });
+                } else if account.held.is_zero() && !amount.is_zero() {
+                    tracing::error!(?account, "No held funds");
+                    return Err(EngineError::NoHeldFunds {
+                        client: account.client,
+                    });
+                } else if account.held < amount {
+                    tracing::error!(?account, "Insufficient held funds");
+                    return Err(EngineError::InsufficientHeldFunds);
+                } else if !dispute_state.is_under_dispute() {
+                    tracing::info!(
+                        "Ignoring resolve for transaction {}. Not under dispute",
+                        info.id
+                    );
+                    return Ok(());
+                }
+                self.validate_dispute_transition(info.id, dispute_state, DisputeState::Resolved)?;
+                // if everything is fine: update the account
+                account.held -= amount;
+                if is_withdrawal {
+                    // the withdrawal stands, so the held funds are debited again
                     account.total -= amount;
-                    account.locked = true;
-                    // set to not under dispute
-                    self.store
-                        .set_transaction_under_dispute(info.id, false)
-                        .await?;
                 } else {
-                    tracing::error!("Reference transaction {} is not a Deposit", info.id);
-                    return Err(EngineError::WrongTransactionRef { id: info.id });
+                    account.available += amount;
                 }
+                // close the dispute
+                self.store
+                    .set_dispute_state(info.id, DisputeState::Resolved)
+                    .await?;
 
                 Ok(())
             }
         }
     }
-}
-
-fn wrong_client_error(account: &Account, info: &TransactionInfo) -> EngineError {
-    tracing::error!(
-        ?account,
-        ?info,
-        "Referenced transaction {} is not for the same client",
-        info.id
-    );
-    EngineError::TransactionRefWrongClient {
-        id: info.id,
-        client: info.client_id,
-        wrong_client: account.client,
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    async fn chargeback(
+        &self,
+        account: &mut Account,
+        info: &TransactionInfo,
+        ref_transaction: Option<Transaction>,
+    ) -> EngineResult<()> {
+        // if no ref, ignore
+        match ref_transaction {
+            None => {
+                tracing::info!(
+                    "Ignoring chargeback for transaction {}. No ref found",
+                    info.id
+                );
+                Ok(())
+            }
+            Some(ref_tx) => {
+                let is_withdrawal = matches!(ref_tx, Transaction::Withdrawal { .. });
+                // only the recorded amount was held
+                let disputed_amount = ref_tx.disputed_amount();
+                let (info, amount, dispute_state) = disputable(ref_tx)?;
+                let amount = disputed_amount.unwrap_or(amount);
+                if account.client != info.client_id {
+                    return Err(wrong_client_error(account, &info));
+                } else if self.config.strict_dispute_refs && !dispute_state.is_under_dispute() {
+                    tracing::error!("Transaction {} is not under dispute", info.id);
+                    return Err(EngineError::NotUnderDispute { id: info.id });
+                } else if account.held.is_zero() && !amount.is_zero() {
+                    tracing::error!(?account, "No held funds");
+                    return Err(EngineError::NoHeldFunds {
+                        client: account.client,
+                    });
+                } else if account.held < amount {
+                    tracing::error!(?account, "Insufficient held funds");
+                    return Err(EngineError::InsufficientHeldFunds);
+                } else if !dispute_state.is_under_dispute() {
+                    tracing::info!(
+                        "Ignoring chargeback for transaction {}. Not under dispute",
+                        info.id
+                    );
+                    return Ok(());
+                }
+                self.validate_dispute_transition(
+                    info.id,
+                    dispute_state,
+                    DisputeState::ChargedBack,
+                )?;
+                // if everything is fine: update the account
+                account.held -= amount;
+                if is_withdrawal {
+                    // the debit is reversed, so the held funds go back to the client
+                    account.available += amount;
+                } else {
+                    account.total -= amount;
+                }
+                account.locked = true;
+                // close the dispute
+                self.store
+                    .set_dispute_state(info.id, DisputeState::ChargedBack)
+                    .await?;
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Returns the info, amount and [`DisputeState`] of a disputable transaction,
+/// i.e. a [`Transaction::Deposit`] or a [`Transaction::Withdrawal`].
+fn disputable(ref_tx: Transaction) -> EngineResult<(TransactionInfo, Amount, DisputeState)> {
+    match ref_tx {
+        Transaction::Deposit {
+            info,
+            amount,
+            dispute_state,
+            ..
+        }
+        | Transaction::Withdrawal {
+            info,
+            amount,
+            dispute_state,
+            ..
+        } => Ok((info, amount, dispute_state)),
+        _ => {
+            let id = ref_tx.info().id;
+            tracing::error!(
+                "Reference transaction {} is neither a Deposit nor a Withdrawal",
+                id
+            );
+            Err(EngineError::WrongTransactionRef { id })
+        }
+    }
+}
+
+fn wrong_client_error(account: &Account, info: &TransactionInfo) -> EngineError {
+    tracing::error!(
+        ?account,
+        ?info,
+        "Referenced transaction {} is not for the same client",
+        info.id
+    );
+    EngineError::TransactionRefWrongClient {
+        id: info.id,
+        client: info.client_id,
+        wrong_client: account.client,
+    }
+}
+
+/// Rescales the amount to exactly the given decimal places, rounding or padding it.
+/// It returns an [`EngineError::PrecisionOverflow`] if the amount can't be represented with them.
+fn rescale_checked(amount: Amount, places: u32) -> EngineResult<Amount> {
+    let places = places.min(MAX_WORKING_PRECISION);
+    let mut rescaled = amount;
+    rescaled.rescale(places);
+    if rescaled.scale() != places {
+        tracing::error!(
+            "Amount {} can't be rescaled to {} decimal places",
+            amount,
+            places
+        );
+        return Err(EngineError::PrecisionOverflow { amount, places });
+    }
+    Ok(rescaled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MemoryAuditLog, SanctionsList};
+    use futures::StreamExt;
     use payments_engine_core::dec;
-    use payments_engine_core::transaction::TransactionId;
+    use payments_engine_core::store::StoreError;
     use payments_engine_store_memory::MemoryStore;
     use std::collections::HashMap;
 
     /// Asserts that a particular deposit is under a particular dispute state.
     fn assert_under_dispute(store: &MemoryStore, id: TransactionId, under_dispute_state: bool) {
-        let deposits = store.deposits().read().unwrap();
-        if let Some(&Transaction::Deposit { under_dispute, .. }) = deposits.get(&id) {
-            assert_eq!(under_dispute, under_dispute_state);
-        } else {
-            panic!("Deposit not found");
-        }
+        assert_eq!(
+            dispute_state(store, id).is_under_dispute(),
+            under_dispute_state
+        );
     }
 
-    // test the public api
+    /// Returns the dispute state of a particular deposit or withdrawal.
+    fn dispute_state(store: &MemoryStore, id: TransactionId) -> DisputeState {
+        let deposits = store.deposits().read().unwrap();
+        deposits
+            .get(&id)
+            .and_then(Transaction::dispute_state)
+            .expect("Deposit or withdrawal not found")
+    }
 
     #[tokio::test]
     async fn no_transaction_must_be_applied_if_the_account_is_locked() {
@@ -460,15 +1351,15 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn on_dispute_the_referenced_tx_must_be_a_deposit() {
+    async fn on_dispute_the_referenced_tx_must_be_a_deposit_or_a_withdrawal() {
         // this case is not really possible in InMemoryStore
         // but it's useful to recreate it in case we use other kind of stores.
         let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
         let mut deposits = HashMap::new();
         deposits.insert(1, Transaction::deposit(1, 1, dec!(10)));
-        // inserting a withdrawal directly.
+        // inserting an adjustment directly.
         // this won't even happen with memory store, but it's useful to test the engine
-        deposits.insert(2, Transaction::withdrawal(2, 1, dec!(1)));
+        deposits.insert(2, Transaction::adjustment(2, 1, dec!(1), "fee correction"));
         let store = MemoryStore::seeded(Some(deposits), None);
         store.upsert_account(&account).await.unwrap();
 
@@ -479,7 +1370,7 @@ mod tests {
         assert_eq!(store.accounts_len(), 1);
 
         let engine = Engine::new(store.clone());
-        // referencing a withdrawal
+        // referencing an adjustment
         let dispute = Transaction::dispute(2, 1);
         let err = engine.process_transaction(dispute).await.unwrap_err();
         // it should error
@@ -635,15 +1526,15 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn on_resolve_the_referenced_tx_must_be_a_deposit() {
+    async fn on_resolve_the_referenced_tx_must_be_a_deposit_or_a_withdrawal() {
         // this case is not really possible in InMemoryStore
         // but it's useful to recreate it in case we use other kind of stores.
         let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
         let mut deposits = HashMap::new();
         deposits.insert(1, Transaction::deposit_under_dispute(1, 1, dec!(10)));
-        // inserting a withdrawal directly.
+        // inserting an adjustment directly.
         // this won't even happen with memory store, but it's useful to test the engine
-        deposits.insert(2, Transaction::withdrawal(2, 1, dec!(1)));
+        deposits.insert(2, Transaction::adjustment(2, 1, dec!(1), "fee correction"));
         let store = MemoryStore::seeded(Some(deposits), None);
         store.upsert_account(&account).await.unwrap();
 
@@ -655,7 +1546,7 @@ mod tests {
         assert_under_dispute(&store, 1, true);
 
         let engine = Engine::new(store.clone());
-        // referencing a withdrawal
+        // referencing an adjustment
         let resolve = Transaction::resolve(2, 1);
         let err = engine.process_transaction(resolve).await.unwrap_err();
         // it should error
@@ -862,15 +1753,15 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn on_chargeback_the_referenced_tx_must_be_a_deposit() {
+    async fn on_chargeback_the_referenced_tx_must_be_a_deposit_or_a_withdrawal() {
         // this case is not really possible in InMemoryStore
         // but it's useful to recreate it in case we use other kind of stores.
         let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
         let mut deposits = HashMap::new();
         deposits.insert(1, Transaction::deposit(1, 1, dec!(10)));
-        // inserting a withdrawal directly.
+        // inserting an adjustment directly.
         // this won't even happen with memory store, but it's useful to test the engine
-        deposits.insert(2, Transaction::withdrawal(2, 1, dec!(1)));
+        deposits.insert(2, Transaction::adjustment(2, 1, dec!(1), "fee correction"));
         let store = MemoryStore::seeded(Some(deposits), None);
         store.upsert_account(&account).await.unwrap();
 
@@ -881,7 +1772,7 @@ mod tests {
         assert_eq!(store.accounts_len(), 1);
 
         let engine = Engine::new(store.clone());
-        // referencing a withdrawal
+        // referencing an adjustment
         let chargeback = Transaction::chargeback(2, 1);
         let err = engine.process_transaction(chargeback).await.unwrap_err();
         // it should error
@@ -927,89 +1818,2351 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn rollback_transaction_under_dispute_state_if_tx_is_not_commited() {
+    async fn reviewed_disputes_can_be_charged_back_when_review_is_required() {
         let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
         let mut deposits = HashMap::new();
         deposits.insert(1, Transaction::deposit(1, 1, dec!(10)));
-        let store = payments_engine_store_memory::MemoryStore::seeded(Some(deposits), None);
-
+        let store = MemoryStore::seeded(Some(deposits), None);
         store.upsert_account(&account).await.unwrap();
 
-        assert_eq!(account.available, dec!(10));
-        assert_eq!(account.held, Amount::ZERO);
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            require_dispute_review: true,
+            ..EngineConfig::default()
+        });
+
+        engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(dispute_state(&store, 1), DisputeState::Open);
+
+        let account = engine
+            .process_transaction(Transaction::review(1, 1))
+            .await
+            .unwrap();
+        // reviews don't change the funds
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, dec!(10));
         assert_eq!(account.total, dec!(10));
-        assert_eq!(store.transactions_len(), 1);
-        assert_eq!(store.accounts_len(), 1);
+        assert_eq!(dispute_state(&store, 1), DisputeState::UnderReview);
 
-        // provoke a failure when saving the account.
-        // this will cause the transaction to be rolled back
-        store.set_enable_upsert_account_failure(true);
+        let account = engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, Amount::ZERO);
+        assert!(account.locked);
+        assert_eq!(dispute_state(&store, 1), DisputeState::ChargedBack);
+    }
 
-        let engine = Engine::new(store.clone());
+    #[tokio::test]
+    async fn on_chargeback_error_if_review_is_required_and_dispute_is_not_reviewed() {
+        let account = Account::seeded(1, Amount::ZERO, dec!(10), false);
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit_under_dispute(1, 1, dec!(10)));
+        let store = MemoryStore::seeded(Some(deposits), None);
+        store.upsert_account(&account).await.unwrap();
 
-        // test dispute rollback
-        let dispute = Transaction::dispute(1, 1);
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            require_dispute_review: true,
+            ..EngineConfig::default()
+        });
+        let err = engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            EngineError::InvalidDisputeTransition {
+                id: 1,
+                from: DisputeState::Open,
+                to: DisputeState::ChargedBack,
+            }
+        );
+        assert_eq!(dispute_state(&store, 1), DisputeState::Open);
+        assert_eq!(store.get_or_default_account(1).await.unwrap(), account);
+    }
+
+    #[tokio::test]
+    async fn on_review_error_if_ref_transaction_is_not_under_dispute() {
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit(1, 1, dec!(10)));
+        let store = MemoryStore::seeded(Some(deposits), None);
+        store.upsert_account(&account).await.unwrap();
 
+        let engine = Engine::new(store.clone());
         let err = engine
-            .process_transaction(dispute.clone())
+            .process_transaction(Transaction::review(1, 1))
             .await
             .unwrap_err();
 
         assert_eq!(
             err,
-            EngineError::TransactionNotCommited(StoreError::AccessError("Test Error".to_string()))
+            EngineError::InvalidDisputeTransition {
+                id: 1,
+                from: DisputeState::Undisputed,
+                to: DisputeState::UnderReview,
+            }
         );
+        assert_eq!(dispute_state(&store, 1), DisputeState::Undisputed);
+    }
 
-        assert_eq!(account.available, dec!(10));
-        assert_eq!(account.held, Amount::ZERO);
-        assert_eq!(account.total, dec!(10));
+    #[tokio::test]
+    async fn resolved_deposits_can_be_disputed_again() {
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit(1, 1, dec!(10)));
+        let store = MemoryStore::seeded(Some(deposits), None);
+        store.upsert_account(&account).await.unwrap();
 
-        assert_under_dispute(&store, 1, false);
+        let engine = Engine::new(store.clone());
+        for tx in [
+            Transaction::dispute(1, 1),
+            Transaction::resolve(1, 1),
+            Transaction::dispute(1, 1),
+        ] {
+            engine.process_transaction(tx).await.unwrap();
+        }
+        assert_eq!(dispute_state(&store, 1), DisputeState::Open);
 
-        // test resolve rollback
-        store.set_enable_upsert_account_failure(false);
+        let account = engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .await
+            .unwrap();
+        assert!(account.locked);
+        assert_eq!(dispute_state(&store, 1), DisputeState::ChargedBack);
+    }
 
-        let account = engine.process_transaction(dispute).await.unwrap();
+    #[tokio::test]
+    async fn find_orphaned_holds_reports_held_funds_without_disputes() {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit_under_dispute(1, 1, dec!(10)));
+        deposits.insert(2, Transaction::deposit(2, 2, dec!(10)));
+        let store = MemoryStore::seeded(Some(deposits), None);
+        // consistent account
+        store
+            .upsert_account(&Account::seeded(1, Amount::ZERO, dec!(10), false))
+            .await
+            .unwrap();
+        // held funds without any active dispute
+        store
+            .upsert_account(&Account::seeded(2, Amount::ZERO, dec!(10), false))
+            .await
+            .unwrap();
 
-        assert_eq!(account.available, Amount::ZERO);
-        assert_eq!(account.held, dec!(10));
-        assert_eq!(account.total, dec!(10));
+        let engine = Engine::new(store);
+        let orphaned = engine.find_orphaned_holds().await.unwrap();
 
-        assert_under_dispute(&store, 1, true);
+        assert_eq!(orphaned, vec![2]);
+    }
 
-        store.set_enable_upsert_account_failure(true);
+    /// Deposits 100, withdraws 60 and disputes the deposit using the given [`DisputePolicy`].
+    async fn dispute_after_partial_withdrawal(
+        dispute_policy: DisputePolicy,
+    ) -> (Engine<MemoryStore>, EngineResult<Account>) {
+        dispute_after_withdrawal(dispute_policy, dec!(60)).await
+    }
 
-        let err = engine
+    /// Deposits 100, withdraws the given amount and disputes the deposit using the given [`DisputePolicy`].
+    async fn dispute_after_withdrawal(
+        dispute_policy: DisputePolicy,
+        withdrawn: Amount,
+    ) -> (Engine<MemoryStore>, EngineResult<Account>) {
+        let engine = Engine::new(MemoryStore::default()).with_config(EngineConfig {
+            dispute_policy,
+            ..EngineConfig::default()
+        });
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(100)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(2, 1, withdrawn))
+            .await
+            .unwrap();
+        let result = engine.process_transaction(Transaction::dispute(1, 1)).await;
+        (engine, result)
+    }
+
+    #[tokio::test]
+    async fn strict_policy_rejects_disputes_exceeding_available_funds() {
+        let (engine, result) = dispute_after_partial_withdrawal(DisputePolicy::Strict).await;
+
+        assert_eq!(result.unwrap_err(), EngineError::InsufficientAvailableFunds);
+        let account = engine.store.get_or_default_account(1).await.unwrap();
+        assert_eq!(account.available, dec!(40));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_under_dispute(&engine.store, 1, false);
+    }
+
+    #[tokio::test]
+    async fn hold_available_policy_holds_only_the_available_funds() {
+        let (engine, result) = dispute_after_partial_withdrawal(DisputePolicy::HoldAvailable).await;
+
+        let account = result.unwrap();
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, dec!(40));
+        assert_eq!(account.total, dec!(40));
+        assert_under_dispute(&engine.store, 1, true);
+
+        // resolving releases what was held
+        let account = engine
             .process_transaction(Transaction::resolve(1, 1))
             .await
-            .unwrap_err();
+            .unwrap();
+        assert_eq!(account.available, dec!(40));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, dec!(40));
+    }
+
+    #[tokio::test]
+    async fn hold_available_policy_releases_only_what_each_dispute_held() {
+        let engine = Engine::new(MemoryStore::default()).with_config(EngineConfig {
+            dispute_policy: DisputePolicy::HoldAvailable,
+            ..EngineConfig::default()
+        });
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(100)),
+            Transaction::deposit(2, 1, dec!(50)),
+            Transaction::dispute(2, 1),
+            Transaction::withdrawal(3, 1, dec!(100)),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).await.unwrap();
+        }
 
+        // nothing is available, so nothing is held
+        let account = engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(account, Account::seeded(1, Amount::ZERO, dec!(50), false));
         assert_eq!(
-            err,
-            EngineError::TransactionNotCommited(StoreError::AccessError("Test Error".to_string()))
+            engine
+                .store
+                .get_transaction(1)
+                .await
+                .unwrap()
+                .disputed_amount(),
+            Some(Amount::ZERO)
         );
 
+        let account = engine
+            .process_transaction(Transaction::resolve(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(account, Account::seeded(1, Amount::ZERO, dec!(50), false));
+
+        let account = engine
+            .process_transaction(Transaction::resolve(2, 1))
+            .await
+            .unwrap();
+        assert_eq!(account, Account::seeded(1, dec!(50), Amount::ZERO, false));
+    }
+
+    #[tokio::test]
+    async fn allow_negative_policy_holds_the_full_amount() {
+        let (engine, result) = dispute_after_partial_withdrawal(DisputePolicy::AllowNegative).await;
+
+        let account = result.unwrap();
+        assert_eq!(account.available, dec!(-60));
+        assert_eq!(account.held, dec!(100));
+        assert_eq!(account.total, dec!(40));
+        assert_under_dispute(&engine.store, 1, true);
+    }
+
+    #[tokio::test]
+    async fn strict_policy_rejects_disputes_of_fully_withdrawn_deposits() {
+        let (engine, result) = dispute_after_withdrawal(DisputePolicy::Strict, dec!(100)).await;
+
+        assert_eq!(result.unwrap_err(), EngineError::InsufficientAvailableFunds);
+        let account = engine.store.get_or_default_account(1).await.unwrap();
         assert_eq!(account.available, Amount::ZERO);
-        assert_eq!(account.held, dec!(10));
-        assert_eq!(account.total, dec!(10));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_under_dispute(&engine.store, 1, false);
+    }
 
-        assert_under_dispute(&store, 1, true);
+    #[tokio::test]
+    async fn allow_negative_policy_holds_fully_withdrawn_deposits() {
+        let (engine, result) =
+            dispute_after_withdrawal(DisputePolicy::AllowNegative, dec!(100)).await;
+
+        let account = result.unwrap();
+        assert_eq!(account.available, dec!(-100));
+        assert_eq!(account.held, dec!(100));
+        assert_eq!(account.total, Amount::ZERO);
+        assert_under_dispute(&engine.store, 1, true);
+
+        // the chargeback takes the held funds back, leaving the debt
+        let account = engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(account.available, dec!(-100));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, dec!(-100));
+        assert!(account.locked);
+    }
+
+    #[tokio::test]
+    async fn allow_negative_on_dispute_holds_fully_withdrawn_deposits() {
+        let engine = Engine::new(MemoryStore::default()).with_config(EngineConfig {
+            allow_negative_on_dispute: true,
+            ..EngineConfig::default()
+        });
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(100)),
+            Transaction::withdrawal(2, 1, dec!(100)),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).await.unwrap();
+        }
+
+        let account = engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::seeded(1, dec!(-100), dec!(100), false));
+        assert_under_dispute(&engine.store, 1, true);
+    }
+
+    #[tokio::test]
+    async fn disputes_of_withdrawn_deposits_are_rejected_without_allow_negative_on_dispute() {
+        let engine = Engine::new(MemoryStore::default());
+        assert!(!engine.config.allow_negative_on_dispute);
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(100)),
+            Transaction::withdrawal(2, 1, dec!(100)),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).await.unwrap();
+        }
+
+        let result = engine.process_transaction(Transaction::dispute(1, 1)).await;
+
+        assert_eq!(result.unwrap_err(), EngineError::InsufficientAvailableFunds);
+        assert_under_dispute(&engine.store, 1, false);
+    }
+
+    #[tokio::test]
+    async fn zero_amount_deposits_cannot_be_disputed_nor_charged_back() {
+        let engine = Engine::new(MemoryStore::default());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, Amount::ZERO))
+            .await
+            .unwrap();
 
-        // test chargeback rollback
         let err = engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap_err();
+        assert_eq!(err, EngineError::ZeroAmountDispute { id: 1 });
+        assert_under_dispute(&engine.store, 1, false);
+
+        let account = engine
             .process_transaction(Transaction::chargeback(1, 1))
             .await
+            .unwrap();
+        assert!(!account.locked);
+        assert_eq!(account, Account::new(1));
+    }
+
+    #[tokio::test]
+    async fn processed_count_tracks_every_call() {
+        let engine = Engine::new(MemoryStore::default());
+        let calls = (1..=10).map(|id| {
+            // odd ids are withdrawals without funds, so they fail
+            let tx = if id % 2 == 0 {
+                Transaction::deposit(id, 1, dec!(1))
+            } else {
+                Transaction::withdrawal(id, 2, dec!(1))
+            };
+            engine.process_transaction(tx)
+        });
+
+        let results = futures::future::join_all(calls).await;
+
+        assert_eq!(results.iter().filter(|r| r.is_err()).count(), 5);
+        assert_eq!(engine.processed_count(), 10);
+    }
+
+    /// Two disputed deposits of 10 and 30 whose held funds drifted to 20.
+    fn drifted_held_store() -> MemoryStore {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit_under_dispute(1, 1, dec!(10)));
+        deposits.insert(2, Transaction::deposit_under_dispute(2, 1, dec!(30)));
+        let mut accounts = HashMap::new();
+        accounts.insert(1, Account::seeded(1, Amount::ZERO, dec!(20), false));
+        MemoryStore::seeded(Some(deposits), Some(accounts))
+    }
+
+    #[tokio::test]
+    async fn on_resolve_error_if_held_drifted_below_the_deposit_amount() {
+        let engine = Engine::new(drifted_held_store());
+
+        let account = engine
+            .process_transaction(Transaction::resolve(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(account.held, dec!(10));
+
+        let err = engine
+            .process_transaction(Transaction::resolve(2, 1))
+            .await
             .unwrap_err();
+        assert_eq!(err, EngineError::InsufficientHeldFunds);
+        assert_under_dispute(&engine.store, 2, true);
+    }
 
-        assert_eq!(
-            err,
-            EngineError::TransactionNotCommited(StoreError::AccessError("Test Error".to_string()))
-        );
+    #[tokio::test]
+    async fn on_resolve_release_up_to_the_held_funds_with_partial_release() {
+        let engine = Engine::new(drifted_held_store()).with_config(EngineConfig {
+            resolve_policy: ResolvePolicy::PartialRelease,
+            ..EngineConfig::default()
+        });
 
-        assert_eq!(account.available, Amount::ZERO);
+        let account = engine
+            .process_transaction(Transaction::resolve(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(account.available, dec!(10));
         assert_eq!(account.held, dec!(10));
-        assert_eq!(account.total, dec!(10));
 
+        let account = engine
+            .process_transaction(Transaction::resolve(2, 1))
+            .await
+            .unwrap();
+        assert_eq!(account.available, dec!(20));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, dec!(20));
+        assert_under_dispute(&engine.store, 1, false);
+        assert_under_dispute(&engine.store, 2, false);
+    }
+
+    #[tokio::test]
+    async fn on_resolve_and_chargeback_error_if_account_has_no_held_funds() {
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit_under_dispute(1, 1, dec!(10)));
+        // the account was never persisted
+        let store = MemoryStore::seeded(Some(deposits), None);
+        let engine = Engine::new(store.clone());
+
+        for tx in [Transaction::resolve(1, 1), Transaction::chargeback(1, 1)] {
+            let err = engine.process_transaction(tx).await.unwrap_err();
+            assert_eq!(err, EngineError::NoHeldFunds { client: 1 });
+        }
+
+        // still under dispute
         assert_under_dispute(&store, 1, true);
+        assert_eq!(
+            store.get_or_default_account(1).await.unwrap(),
+            Account::new(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn accounts_are_persisted_on_ignored_transactions_by_default() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone());
+
+        engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(store.accounts_len(), 1);
+        assert_eq!(
+            engine.report().await.unwrap().next().await,
+            Some(Account::new(1))
+        );
+    }
+
+    #[tokio::test]
+    async fn accounts_are_not_persisted_on_ignored_transactions_if_disabled() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            persist_on_ignore: false,
+            ..EngineConfig::default()
+        });
+
+        let account = engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::new(1));
+        assert_eq!(store.accounts_len(), 0);
+        assert_eq!(engine.report().await.unwrap().next().await, None);
+    }
+
+    #[tokio::test]
+    async fn dispute_count_is_tracked_across_dispute_cycles() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone());
+
+        for tx in [
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::dispute(1, 1),
+            Transaction::resolve(1, 1),
+            Transaction::dispute(1, 1),
+        ] {
+            engine.process_transaction(tx).await.unwrap();
+        }
+
+        assert_eq!(store.dispute_count(1).await, Ok(2));
+        assert_eq!(dispute_state(&store, 1), DisputeState::Open);
+    }
+
+    #[tokio::test]
+    async fn withdrawal_disputes_hold_the_withdrawn_amount() {
+        let store = MemoryStore::storing_withdrawals();
+        let engine = Engine::new(store.clone());
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(4)))
+            .await
+            .unwrap();
+        let account = engine
+            .process_transaction(Transaction::dispute(2, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::seeded(1, dec!(6), dec!(4), false));
+        assert_eq!(dispute_state(&store, 2), DisputeState::Open);
+    }
+
+    #[tokio::test]
+    async fn resolving_a_withdrawal_dispute_keeps_the_debit() {
+        let store = MemoryStore::storing_withdrawals();
+        let engine = Engine::new(store.clone());
+
+        for tx in [
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::withdrawal(2, 1, dec!(4)),
+            Transaction::dispute(2, 1),
+        ] {
+            engine.process_transaction(tx).await.unwrap();
+        }
+        let account = engine
+            .process_transaction(Transaction::resolve(2, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::seeded(1, dec!(6), Amount::ZERO, false));
+        assert_eq!(dispute_state(&store, 2), DisputeState::Resolved);
+    }
+
+    #[tokio::test]
+    async fn charging_back_a_withdrawal_dispute_reverses_the_debit() {
+        let store = MemoryStore::storing_withdrawals();
+        let engine = Engine::new(store.clone());
+
+        for tx in [
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::withdrawal(2, 1, dec!(4)),
+            Transaction::dispute(2, 1),
+        ] {
+            engine.process_transaction(tx).await.unwrap();
+        }
+        let account = engine
+            .process_transaction(Transaction::chargeback(2, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::seeded(1, dec!(10), Amount::ZERO, true));
+        assert_eq!(dispute_state(&store, 2), DisputeState::ChargedBack);
+        assert_eq!(
+            engine.find_orphaned_holds().await.unwrap(),
+            Vec::<ClientId>::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn withdrawal_disputes_are_ignored_if_withdrawals_are_not_stored() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone());
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(4)))
+            .await
+            .unwrap();
+        let account = engine
+            .process_transaction(Transaction::dispute(2, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::seeded(1, dec!(6), Amount::ZERO, false));
+    }
+
+    #[tokio::test]
+    async fn withdrawals_within_the_overdraft_limit_are_allowed() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            overdraft_limit: dec!(50),
+            ..EngineConfig::default()
+        });
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        let account = engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(60)))
+            .await
+            .unwrap();
+
+        assert_eq!(account.available, dec!(-50));
+        assert_eq!(account.total, dec!(-50));
+    }
+
+    #[tokio::test]
+    async fn withdrawals_beyond_the_overdraft_limit_fail() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            overdraft_limit: dec!(50),
+            ..EngineConfig::default()
+        });
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        let err = engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(60.0001)))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, EngineError::InsufficientAvailableFunds);
+        assert_eq!(
+            store.get_or_default_account(1).await.unwrap(),
+            Account::seeded(1, dec!(10), Amount::ZERO, false)
+        );
+    }
+
+    #[tokio::test]
+    async fn clients_count_only_counts_new_accounts() {
+        let mut accounts = HashMap::new();
+        accounts.insert(3, Account::new(3));
+        let store = MemoryStore::seeded(None, Some(accounts));
+        let engine = Engine::new(store.clone());
+
+        for tx in [
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::deposit(2, 2, dec!(10)),
+            Transaction::withdrawal(3, 1, dec!(5)),
+            Transaction::deposit(4, 3, dec!(10)),
+        ] {
+            engine.process_transaction(tx).await.unwrap();
+        }
+
+        assert_eq!(engine.clients_count(), 2);
+        assert_eq!(engine.processed_count(), 4);
+    }
+
+    #[tokio::test]
+    async fn balances_keep_the_working_precision() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            working_precision: Some(4),
+            ..EngineConfig::default()
+        });
+
+        let transactions = [
+            Transaction::deposit(1, 1, dec!(1.23456789)),
+            Transaction::deposit(2, 1, dec!(2)),
+            Transaction::withdrawal(3, 1, dec!(0.00001)),
+            Transaction::deposit(4, 1, dec!(0.1)),
+            Transaction::dispute(4, 1),
+            Transaction::resolve(4, 1),
+        ];
+        for tx in transactions {
+            let account = engine.process_transaction(tx).await.unwrap();
+            for amount in [account.available, account.held, account.total] {
+                assert_eq!(amount.scale(), 4);
+            }
+        }
+
+        let account = store.get_or_default_account(1).await.unwrap();
+        assert_eq!(account.available.to_string(), "3.3346");
+        assert_eq!(account.held.to_string(), "0.0000");
+        assert_eq!(account.total.to_string(), "3.3346");
+    }
+
+    #[tokio::test]
+    async fn transactions_are_rounded_to_the_working_precision_before_being_stored() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            working_precision: Some(4),
+            ..EngineConfig::default()
+        });
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(1.23454)))
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_transaction(1).await.unwrap().amount(),
+            Some(dec!(1.2345))
+        );
+
+        let account = engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(account.available.to_string(), "0.0000");
+        assert_eq!(account.held.to_string(), "1.2345");
+    }
+
+    #[tokio::test]
+    async fn amounts_beyond_the_working_precision_are_rejected() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            working_precision: Some(4),
+            ..EngineConfig::default()
+        });
+
+        let err = engine
+            .process_transaction(Transaction::deposit(1, 1, Amount::MAX))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            EngineError::PrecisionOverflow {
+                amount: Amount::MAX,
+                places: 4
+            }
+        );
+        assert_eq!(
+            store.get_transaction(1).await,
+            Err(StoreError::NotFound { id: 1 })
+        );
+        assert_eq!(store.get_account(1).await, Ok(None));
+    }
+
+    #[tokio::test]
+    async fn balances_keep_their_scale_without_working_precision() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone());
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(1.23456789)))
+            .await
+            .unwrap();
+        let account = engine
+            .process_transaction(Transaction::deposit(2, 1, dec!(2)))
+            .await
+            .unwrap();
+
+        assert_eq!(account.available.to_string(), "3.23456789");
+    }
+
+    #[tokio::test]
+    async fn process_transactions_summarizes_the_outcomes() {
+        let store = MemoryStore::default();
+        let engine = Engine::new(store.clone());
+
+        let summary = engine
+            .process_transactions(futures::stream::iter(vec![
+                Transaction::deposit(1, 1, dec!(10)),
+                Transaction::withdrawal(2, 1, dec!(20)),
+                Transaction::dispute(3, 1),
+                Transaction::dispute(1, 1),
+                Transaction::deposit(1, 1, dec!(10)),
+                Transaction::resolve(1, 1),
+            ]))
+            .await;
+
+        assert_eq!(
+            summary,
+            ProcessSummary {
+                applied: 3,
+                rejected: 2,
+                ignored: 1,
+            }
+        );
+        assert_eq!(engine.processed_count(), 6);
+        assert_eq!(
+            store.get_or_default_account(1).await.unwrap(),
+            Account::seeded(1, dec!(10), Amount::ZERO, false)
+        );
+    }
+
+    async fn locked_store() -> MemoryStore {
+        let store = MemoryStore::new();
+        store
+            .upsert_account(&Account::seeded(1, dec!(10), Amount::ZERO, true))
+            .await
+            .unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn deposits_rejected_by_locked_accounts_are_rolled_back_by_default() {
+        let store = locked_store().await;
+        let engine = Engine::new(store.clone());
+
+        let err = engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(5)))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, EngineError::LockedAccount { id: 1, tx: 1 });
+        assert_eq!(
+            store.get_transaction(1).await,
+            Err(StoreError::NotFound { id: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn deposits_rejected_by_locked_accounts_are_kept_if_configured() {
+        let store = locked_store().await;
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            rollback_policy: RollbackPolicy::KeepOnLocked,
+            ..EngineConfig::default()
+        });
+
+        let err = engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(5)))
+            .await
+            .unwrap_err();
+        assert_eq!(err, EngineError::LockedAccount { id: 1, tx: 1 });
+        // only the id is kept, so it can't be reused
+        assert_eq!(
+            store.get_transaction(1).await,
+            Err(StoreError::NotFound { id: 1 })
+        );
+        assert_eq!(
+            store
+                .create_transaction(Transaction::deposit(1, 2, dec!(5)))
+                .await,
+            Err(StoreError::AlreadyExists { id: 1 })
+        );
+        assert_eq!(engine.get_account(1).await.unwrap().available, dec!(10));
+
+        // other errors are still rolled back
+        store.set_enable_upsert_account_failure(true);
+        engine
+            .process_transaction(Transaction::deposit(2, 2, dec!(5)))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            store.get_transaction(2).await,
+            Err(StoreError::NotFound { id: 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn deposits_kept_on_locked_accounts_can_not_be_disputed_after_unlocking() {
+        let store = locked_store().await;
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            rollback_policy: RollbackPolicy::KeepOnLocked,
+            retain_locked_deposits: true,
+            ..EngineConfig::default()
+        });
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(5)))
+            .await
+            .unwrap_err();
+        // the retention is still honored
+        assert_eq!(
+            store.get_pending_transactions().await,
+            Ok(vec![Transaction::deposit(1, 1, dec!(5))])
+        );
+
+        engine.unlock_account(1).await.unwrap();
+        let summary = engine
+            .process_transactions(futures::stream::iter(vec![
+                Transaction::dispute(1, 1),
+                Transaction::chargeback(1, 1),
+            ]))
+            .await;
+        assert_eq!(summary.ignored, 2);
+        let account = engine.get_account(1).await.unwrap();
+        assert_eq!(account.available, dec!(10));
+        assert_eq!(account.held, Amount::ZERO);
+        assert!(!account.locked);
+    }
+
+    #[tokio::test]
+    async fn deposits_rejected_by_locked_accounts_are_retained_as_pending_if_enabled() {
+        let store = locked_store().await;
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            retain_locked_deposits: true,
+            ..EngineConfig::default()
+        });
+
+        assert_eq!(
+            engine
+                .process_transaction(Transaction::deposit(2, 1, dec!(5)))
+                .await,
+            Err(EngineError::LockedAccount { id: 1, tx: 2 })
+        );
+        assert_eq!(
+            engine
+                .process_transaction(Transaction::withdrawal(3, 1, dec!(5)))
+                .await,
+            Err(EngineError::LockedAccount { id: 1, tx: 3 })
+        );
+
+        assert_eq!(
+            engine.pending_deposits().await,
+            Ok(vec![Transaction::deposit(2, 1, dec!(5))])
+        );
+        // balances are untouched and the deposit can't be disputed
+        assert_eq!(
+            store.get_or_default_account(1).await,
+            Ok(Account::seeded(1, dec!(10), Amount::ZERO, true))
+        );
+        assert!(store.get_transaction(2).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn deposits_rejected_by_locked_accounts_are_not_retained_by_default() {
+        let store = locked_store().await;
+        let engine = Engine::new(store.clone());
+
+        assert!(engine
+            .process_transaction(Transaction::deposit(2, 1, dec!(5)))
+            .await
+            .is_err());
+
+        assert_eq!(engine.pending_deposits().await, Ok(vec![]));
+        assert_eq!(
+            store.get_or_default_account(1).await,
+            Ok(Account::seeded(1, dec!(10), Amount::ZERO, true))
+        );
+    }
+
+    #[tokio::test]
+    async fn report_page_returns_every_account_once() {
+        let engine = Engine::new(MemoryStore::new());
+        for client in [3, 1, 2] {
+            engine
+                .process_transaction(Transaction::deposit(client.into(), client, dec!(1)))
+                .await
+                .unwrap();
+        }
+
+        let first = engine.report_page(0, 2).await.unwrap();
+        let second = engine.report_page(2, 2).await.unwrap();
+
+        assert_eq!(
+            first
+                .iter()
+                .chain(&second)
+                .map(|a| a.client)
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(engine.report_page(0, 2).await, Ok(first));
+    }
+
+    #[tokio::test]
+    async fn unlocked_accounts_can_process_transactions_again() {
+        let store = locked_store().await;
+        let engine = Engine::new(store.clone());
+
+        let account = engine.unlock_account(1).await.unwrap();
+
+        assert!(!account.locked);
+        assert_eq!(store.get_or_default_account(1).await, Ok(account));
+        assert_eq!(
+            engine
+                .process_transaction(Transaction::deposit(2, 1, dec!(5)))
+                .await,
+            Ok(Account::seeded(1, dec!(15), Amount::ZERO, false))
+        );
+    }
+
+    #[tokio::test]
+    async fn unlock_account_errors_if_account_does_not_exist() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+
+        assert_eq!(
+            engine.unlock_account(1).await,
+            Err(EngineError::AccountNotFound { client: 1 })
+        );
+        assert_eq!(store.get_client_ids().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn money_movements_of_sanctioned_clients_are_rejected() {
+        let store = MemoryStore::new();
+        store
+            .upsert_account(&Account::seeded(1, dec!(10), Amount::ZERO, false))
+            .await
+            .unwrap();
+        let engine = Engine::new(store.clone()).with_sanctions_checker(SanctionsList::new([1]));
+        let sanctioned = || EngineError::SanctionedClient { client: 1 };
+
+        assert_eq!(
+            engine
+                .process_transaction(Transaction::deposit(1, 1, dec!(5)))
+                .await,
+            Err(sanctioned())
+        );
+        assert_eq!(
+            engine
+                .process_transaction(Transaction::withdrawal(2, 1, dec!(5)))
+                .await,
+            Err(sanctioned())
+        );
+        assert_eq!(
+            engine.adjust(1, dec!(5), "bonus", true).await,
+            Err(sanctioned())
+        );
+        // the balances are frozen and nothing was stored
+        assert_eq!(
+            store.get_or_default_account(1).await,
+            Ok(Account::seeded(1, dec!(10), Amount::ZERO, false))
+        );
+        assert!(store.get_transaction(1).await.is_err());
+
+        // other clients proceed normally
+        assert_eq!(
+            engine
+                .process_transaction(Transaction::deposit(3, 2, dec!(5)))
+                .await,
+            Ok(Account::seeded(2, dec!(5), Amount::ZERO, false))
+        );
+    }
+
+    #[tokio::test]
+    async fn disputes_of_sanctioned_clients_are_only_rejected_if_configured() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+
+        let allowing = Engine::new(store.clone()).with_sanctions_checker(SanctionsList::new([1]));
+        assert!(allowing
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .is_ok());
+        assert_under_dispute(&store, 1, true);
+
+        let blocking = Engine::new(store.clone())
+            .with_sanctions_checker(SanctionsList::new([1]))
+            .with_config(EngineConfig {
+                block_sanctioned_disputes: true,
+                ..EngineConfig::default()
+            });
+        assert_eq!(
+            blocking
+                .process_transaction(Transaction::resolve(1, 1))
+                .await,
+            Err(EngineError::SanctionedClient { client: 1 })
+        );
+        assert_under_dispute(&store, 1, true);
+    }
+
+    #[tokio::test]
+    async fn account_locked_event_is_published_on_chargeback() {
+        let engine = Engine::new(MemoryStore::new());
+        let mut events = engine.subscribe();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            events.try_recv(),
+            Ok(EngineEvent::DisputeOpened { client: 1, tx: 1 })
+        );
+        assert_eq!(
+            events.try_recv(),
+            Ok(EngineEvent::AccountLocked { client: 1, tx: 1 })
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn large_withdrawal_event_is_published_if_threshold_is_reached() {
+        let engine = Engine::new(MemoryStore::new()).with_config(EngineConfig {
+            large_withdrawal_threshold: Some(dec!(100)),
+            ..EngineConfig::default()
+        });
+        let mut events = engine.subscribe();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(500)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(99)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(3, 1, dec!(100)))
+            .await
+            .unwrap();
+        // rejected transactions publish nothing
+        assert!(engine
+            .process_transaction(Transaction::withdrawal(4, 1, dec!(1000)))
+            .await
+            .is_err());
+
+        assert_eq!(
+            events.try_recv(),
+            Ok(EngineEvent::LargeWithdrawal {
+                client: 1,
+                tx: 3,
+                amount: dec!(100)
+            })
+        );
+        assert!(events.try_recv().is_err());
+    }
+
+    async fn partially_disputed_engine() -> (MemoryStore, Engine<MemoryStore>) {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(100)))
+            .await
+            .unwrap();
+        let account = engine
+            .process_transaction(Transaction::partial_dispute(1, 1, dec!(30)))
+            .await
+            .unwrap();
+        assert_eq!(account, Account::seeded(1, dec!(70), dec!(30), false));
+        (store, engine)
+    }
+
+    #[tokio::test]
+    async fn on_partial_dispute_only_the_disputed_amount_is_held() {
+        let (store, engine) = partially_disputed_engine().await;
+
+        assert_under_dispute(&store, 1, true);
+        assert_eq!(engine.find_orphaned_holds().await, Ok(vec![]));
+    }
+
+    #[tokio::test]
+    async fn on_partial_dispute_error_if_amount_exceeds_the_transaction() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(100)))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            engine
+                .process_transaction(Transaction::partial_dispute(1, 1, dec!(100.01)))
+                .await,
+            Err(EngineError::DisputeExceedsTransaction { id: 1 })
+        );
+        assert_under_dispute(&store, 1, false);
+    }
+
+    #[tokio::test]
+    async fn on_partial_resolve_only_the_disputed_amount_is_released() {
+        let (store, engine) = partially_disputed_engine().await;
+
+        let account = engine
+            .process_transaction(Transaction::resolve(1, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::seeded(1, dec!(100), Amount::ZERO, false));
+        assert_eq!(dispute_state(&store, 1), DisputeState::Resolved);
+    }
+
+    #[tokio::test]
+    async fn on_partial_chargeback_only_the_disputed_amount_is_reversed() {
+        let (store, engine) = partially_disputed_engine().await;
+
+        let account = engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::seeded(1, dec!(70), Amount::ZERO, true));
+        assert_eq!(dispute_state(&store, 1), DisputeState::ChargedBack);
+    }
+
+    #[tokio::test]
+    async fn full_disputes_after_partial_ones_hold_the_whole_amount() {
+        let (_, engine) = partially_disputed_engine().await;
+        engine
+            .process_transaction(Transaction::resolve(1, 1))
+            .await
+            .unwrap();
+
+        let account = engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account, Account::seeded(1, Amount::ZERO, dec!(100), false));
+    }
+
+    #[tokio::test]
+    async fn disputes_beyond_the_max_open_disputes_are_rejected_until_one_is_resolved() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            max_open_disputes: Some(2),
+            ..EngineConfig::default()
+        });
+        for id in 1..=3 {
+            engine
+                .process_transaction(Transaction::deposit(id, 1, dec!(10)))
+                .await
+                .unwrap();
+        }
+        engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::dispute(2, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::dispute(3, 1)).await,
+            Err(EngineError::TooManyOpenDisputes { client: 1 })
+        );
+        assert_under_dispute(&store, 3, false);
+
+        // resolving frees a slot
+        engine
+            .process_transaction(Transaction::resolve(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(
+            engine.process_transaction(Transaction::dispute(3, 1)).await,
+            Ok(Account::seeded(1, dec!(10), dec!(20), false))
+        );
+    }
+
+    #[tokio::test]
+    async fn memory_and_dashmap_stores_end_up_in_the_same_state() {
+        async fn run<S: Store>(store: S) -> Vec<Account> {
+            let engine = Engine::new(store);
+            for client in 1..=8 {
+                let base = TransactionId::from(client) * 100;
+                for i in 0..100 {
+                    let id = base + i;
+                    let transaction = match i % 10 {
+                        3 | 7 => Transaction::withdrawal(id, client, dec!(2.5)),
+                        5 => Transaction::dispute(id - 1, client),
+                        6 => Transaction::resolve(id - 2, client),
+                        8 if client % 2 == 0 => Transaction::dispute(id - 8, client),
+                        9 if client % 2 == 0 => Transaction::chargeback(id - 9, client),
+                        _ => Transaction::deposit(id, client, dec!(10)),
+                    };
+                    let _ = engine.process_transaction(transaction).await;
+                }
+            }
+            let mut accounts = engine.report().await.unwrap().collect::<Vec<_>>().await;
+            accounts.sort_by_key(|account| account.client);
+            accounts
+        }
+
+        let memory = run(MemoryStore::new()).await;
+        let dashmap = run(payments_engine_store_memory::DashMapStore::new()).await;
+        assert_eq!(memory.len(), 8);
+        assert_eq!(memory, dashmap);
+    }
+
+    #[tokio::test]
+    async fn withdrawals_cannot_draw_from_held_funds() {
+        let engine = Engine::new(MemoryStore::new());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(100)))
+            .await
+            .unwrap();
+        let account = engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .await
+            .unwrap();
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, dec!(100));
+        assert_eq!(account.total, dec!(100));
+
+        for (id, amount) in [(2, dec!(0.0001)), (3, dec!(1)), (4, dec!(100))] {
+            let err = engine
+                .process_transaction(Transaction::withdrawal(id, 1, amount))
+                .await
+                .unwrap_err();
+            assert_eq!(err, EngineError::InsufficientAvailableFunds);
+        }
+
+        let account = engine.get_account(1).await.unwrap();
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, dec!(100));
+        assert_eq!(account.total, dec!(100));
+    }
+
+    #[tokio::test]
+    async fn withdrawals_only_draw_from_available_funds() {
+        let engine = Engine::new(MemoryStore::new());
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(100)),
+            Transaction::deposit(2, 1, dec!(50)),
+            Transaction::dispute(2, 1),
+            Transaction::withdrawal(3, 1, dec!(100)),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).await.unwrap();
+        }
+
+        let account = engine.get_account(1).await.unwrap();
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, dec!(50));
+        assert_eq!(account.total, dec!(50));
+
+        let err = engine
+            .process_transaction(Transaction::withdrawal(4, 1, dec!(0.0001)))
+            .await
+            .unwrap_err();
+        assert_eq!(err, EngineError::InsufficientAvailableFunds);
+    }
+
+    #[tokio::test]
+    async fn deposits_up_to_the_max_amount_are_allowed() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            max_amount: Some(dec!(1000)),
+            ..EngineConfig::default()
+        });
+
+        let account = engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(1000)))
+            .await
+            .unwrap();
+        assert_eq!(account.available, dec!(1000));
+
+        let err = engine
+            .process_transaction(Transaction::deposit(2, 1, dec!(1000.0001)))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            EngineError::AmountExceedsLimit {
+                id: 2,
+                amount: dec!(1000.0001),
+                limit: dec!(1000),
+            }
+        );
+        // it should not change the account nor store the transaction
+        assert_eq!(engine.get_account(1).await.unwrap().available, dec!(1000));
+        assert_eq!(
+            store.get_transaction(2).await,
+            Err(StoreError::NotFound { id: 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn withdrawals_up_to_the_max_amount_are_allowed() {
+        let mut accounts = HashMap::new();
+        accounts.insert(1, Account::seeded(1, dec!(5000), Amount::ZERO, false));
+        let store = MemoryStore::seeded(None, Some(accounts));
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            max_amount: Some(dec!(1000)),
+            ..EngineConfig::default()
+        });
+
+        let account = engine
+            .process_transaction(Transaction::withdrawal(1, 1, dec!(1000)))
+            .await
+            .unwrap();
+        assert_eq!(account.available, dec!(4000));
+
+        let err = engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(1001)))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err,
+            EngineError::AmountExceedsLimit {
+                id: 2,
+                amount: dec!(1001),
+                limit: dec!(1000),
+            }
+        );
+        // it should not change the account
+        let account = engine.get_account(1).await.unwrap();
+        assert_eq!(account.available, dec!(4000));
+        assert_eq!(account.total, dec!(4000));
+    }
+
+    #[tokio::test]
+    async fn transactions_with_the_max_ids_are_processed() {
+        let engine = Engine::new(MemoryStore::new());
+        let client = ClientId::MAX;
+
+        engine
+            .process_transaction(Transaction::deposit(TransactionId::MAX, client, dec!(10)))
+            .await
+            .unwrap();
+        let account = engine
+            .process_transaction(Transaction::dispute(TransactionId::MAX, client))
+            .await
+            .unwrap();
+        assert_eq!(account.client, ClientId::MAX);
+        assert_eq!(account.held, dec!(10));
+
+        engine
+            .process_transaction(Transaction::resolve(TransactionId::MAX, client))
+            .await
+            .unwrap();
+        let account = engine
+            .process_transaction(Transaction::withdrawal(
+                TransactionId::MAX - 1,
+                client,
+                dec!(4),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(account.available, dec!(6));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(engine.client_ids().await.unwrap(), vec![ClientId::MAX]);
+    }
+
+    #[tokio::test]
+    async fn adjustments_fail_once_the_id_space_is_exhausted() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        engine
+            .process_transaction(Transaction::deposit(TransactionId::MAX, 1, dec!(10)))
+            .await
+            .unwrap();
+
+        let err = engine
+            .adjust(1, dec!(1), "goodwill", false)
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, EngineError::TransactionIdSpaceExhausted);
+        assert_eq!(engine.get_account(1).await.unwrap().total, dec!(10));
+        assert_eq!(store.transactions_len(), 1);
+    }
+
+    async fn engine_holding_funds_of_another_deposit(
+        strict_dispute_refs: bool,
+    ) -> Engine<MemoryStore> {
+        let engine = Engine::new(MemoryStore::new()).with_config(EngineConfig {
+            strict_dispute_refs,
+            ..EngineConfig::default()
+        });
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(20)),
+            Transaction::deposit(2, 1, dec!(50)),
+            Transaction::dispute(2, 1),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).await.unwrap();
+        }
+        engine
+    }
+
+    #[tokio::test]
+    async fn resolves_and_chargebacks_of_undisputed_deposits_are_ignored_by_default() {
+        let engine = engine_holding_funds_of_another_deposit(false).await;
+
+        for transaction in [Transaction::resolve(1, 1), Transaction::chargeback(1, 1)] {
+            let account = engine.process_transaction(transaction).await.unwrap();
+            assert_eq!(account.available, dec!(20));
+            assert_eq!(account.held, dec!(50));
+            assert!(!account.locked);
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_and_chargebacks_of_undisputed_deposits_fail_if_strict() {
+        let engine = engine_holding_funds_of_another_deposit(true).await;
+
+        for transaction in [Transaction::resolve(1, 1), Transaction::chargeback(1, 1)] {
+            let err = engine.process_transaction(transaction).await.unwrap_err();
+            assert_eq!(err, EngineError::NotUnderDispute { id: 1 });
+        }
+        let account = engine.get_account(1).await.unwrap();
+        assert_eq!(account.available, dec!(20));
+        assert_eq!(account.held, dec!(50));
+        assert!(!account.locked);
+
+        // even if there are no held funds at all
+        engine
+            .process_transaction(Transaction::deposit(3, 2, dec!(10)))
+            .await
+            .unwrap();
+        let err = engine
+            .process_transaction(Transaction::resolve(3, 2))
+            .await
+            .unwrap_err();
+        assert_eq!(err, EngineError::NotUnderDispute { id: 3 });
+    }
+
+    #[tokio::test]
+    async fn replayed_withdrawals_are_only_applied_once() {
+        let engine = Engine::new(MemoryStore::new());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(100)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(30)))
+            .await
+            .unwrap();
+
+        let result = engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(30)))
+            .await;
+
+        assert_eq!(
+            result,
+            Err(EngineError::Store(StoreError::AlreadyExists { id: 2 }))
+        );
+        let account = engine.get_account(1).await.unwrap();
+        assert_eq!(account.available, dec!(70));
+        assert_eq!(account.total, dec!(70));
+    }
+
+    #[tokio::test]
+    async fn engines_can_resume_from_a_store_snapshot() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(100)),
+            Transaction::deposit(2, 1, dec!(50)),
+            Transaction::dispute(2, 1),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).await.unwrap();
+        }
+
+        let restored = Engine::new(MemoryStore::from_snapshot(store.snapshot().unwrap()));
+
+        for engine in [&engine, &restored] {
+            let account = engine
+                .process_transaction(Transaction::resolve(2, 1))
+                .await
+                .unwrap();
+            assert_eq!(account.available, dec!(150));
+            assert_eq!(account.held, dec!(0));
+            let result = engine
+                .process_transaction(Transaction::deposit(1, 1, dec!(100)))
+                .await;
+            assert_eq!(
+                result,
+                Err(EngineError::Store(StoreError::AlreadyExists { id: 1 }))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_are_flagged_as_inconsistent_if_a_rollback_fails() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        store.set_enable_upsert_account_failure(true);
+        store.set_enable_delete_transaction_failure(true);
+
+        let result = engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await;
+
+        assert_eq!(
+            result,
+            Err(EngineError::Store(StoreError::AccessError(
+                "Test Error".to_string()
+            )))
+        );
+        assert_eq!(engine.inconsistent_clients(), vec![1]);
+
+        store.set_enable_upsert_account_failure(false);
+        store.set_enable_delete_transaction_failure(false);
+        assert_eq!(
+            engine
+                .process_transaction(Transaction::deposit(2, 1, dec!(10)))
+                .await,
+            Err(EngineError::AccountInInconsistentState { client: 1 })
+        );
+        // other clients are not affected
+        assert!(engine
+            .process_transaction(Transaction::deposit(3, 2, dec!(10)))
+            .await
+            .is_ok());
+
+        assert!(engine.clear_inconsistent_state(1));
+        assert!(!engine.clear_inconsistent_state(1));
+        assert!(engine.inconsistent_clients().is_empty());
+        let account = engine
+            .process_transaction(Transaction::deposit(2, 1, dec!(10)))
+            .await
+            .unwrap();
+        assert_eq!(account.available, dec!(10));
+    }
+
+    #[tokio::test]
+    async fn clients_are_not_flagged_if_the_rollback_succeeds() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        store.set_enable_upsert_account_failure(true);
+
+        assert!(engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .is_err());
+
+        assert!(engine.inconsistent_clients().is_empty());
+        store.set_enable_upsert_account_failure(false);
+        assert!(engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn single_accounts_can_be_read_without_the_report() {
+        let engine = Engine::new(MemoryStore::new());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::deposit(2, 2, dec!(20)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(3, 2, dec!(5)))
+            .await
+            .unwrap();
+
+        let account = engine.get_account(2).await.unwrap();
+
+        assert_eq!(account, Account::seeded(2, dec!(15), dec!(0), false));
+        assert_eq!(
+            engine.get_account(3).await,
+            Err(EngineError::AccountNotFound { client: 3 })
+        );
+    }
+
+    /// Engine whose store has a corrupt account 1, with a total of 20 instead of 10 + 5.
+    async fn engine_with_a_corrupt_account(
+        check_integrity: bool,
+    ) -> (Engine<MemoryStore>, MemoryStore) {
+        let store = MemoryStore::new();
+        store
+            .create_transaction(Transaction::deposit_under_dispute(1, 1, dec!(5)))
+            .await
+            .unwrap();
+        let mut account = Account::seeded(1, dec!(10), dec!(5), false);
+        account.total = dec!(20);
+        store.upsert_account(&account).await.unwrap();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            check_integrity,
+            ..EngineConfig::default()
+        });
+        (engine, store)
+    }
+
+    #[tokio::test]
+    async fn corrupt_accounts_are_persisted_if_integrity_is_not_checked() {
+        let (engine, _) = engine_with_a_corrupt_account(false).await;
+
+        let account = engine
+            .process_transaction(Transaction::deposit(2, 1, dec!(1)))
+            .await
+            .unwrap();
+
+        assert_eq!(account.total, dec!(21));
+        assert!(!account.is_consistent());
+    }
+
+    #[tokio::test]
+    async fn corrupt_accounts_are_not_persisted_if_integrity_is_checked() {
+        let (engine, store) = engine_with_a_corrupt_account(true).await;
+        let corrupt = store.get_account(1).await.unwrap();
+
+        let result = engine
+            .process_transaction(Transaction::deposit(2, 1, dec!(1)))
+            .await;
+
+        assert_eq!(result, Err(EngineError::IntegrityViolation { client: 1 }));
+        assert_eq!(store.get_account(1).await.unwrap(), corrupt);
+        assert_eq!(
+            store.get_transaction(2).await,
+            Err(StoreError::NotFound { id: 2 })
+        );
+
+        // the dispute state of the referenced transaction is rolled back too
+        let result = engine.process_transaction(Transaction::resolve(1, 1)).await;
+
+        assert_eq!(result, Err(EngineError::IntegrityViolation { client: 1 }));
+        assert_eq!(store.get_account(1).await.unwrap(), corrupt);
+        assert!(store.get_transaction(1).await.unwrap().is_under_dispute());
+    }
+
+    async fn engine_with_partial_withdrawals(
+        available: Amount,
+    ) -> (Engine<MemoryStore>, MemoryStore) {
+        let store = MemoryStore::storing_withdrawals();
+        let engine = Engine::new(store.clone()).with_config(EngineConfig {
+            allow_partial_withdrawal: true,
+            ..EngineConfig::default()
+        });
+        if available > Amount::ZERO {
+            engine
+                .process_transaction(Transaction::deposit(1, 1, available))
+                .await
+                .unwrap();
+        }
+        (engine, store)
+    }
+
+    #[tokio::test]
+    async fn withdrawals_within_the_available_funds_are_fully_filled() {
+        let (engine, store) = engine_with_partial_withdrawals(dec!(10)).await;
+
+        let (account, withdrawn) = engine
+            .process_transaction_with_withdrawn(Transaction::withdrawal(2, 1, dec!(10)))
+            .await
+            .unwrap();
+
+        assert_eq!(withdrawn, Some(dec!(10)));
+        assert_eq!(account, Account::seeded(1, dec!(0), dec!(0), false));
+        assert_eq!(
+            store.get_transaction(2).await,
+            Ok(Transaction::withdrawal(2, 1, dec!(10)))
+        );
+    }
+
+    #[tokio::test]
+    async fn withdrawals_exceeding_the_available_funds_are_partially_filled() {
+        let (engine, store) = engine_with_partial_withdrawals(dec!(10)).await;
+
+        let (account, withdrawn) = engine
+            .process_transaction_with_withdrawn(Transaction::withdrawal(2, 1, dec!(25)))
+            .await
+            .unwrap();
+
+        assert_eq!(withdrawn, Some(dec!(10)));
+        assert_eq!(account, Account::seeded(1, dec!(0), dec!(0), false));
+        assert_eq!(
+            store.get_transaction(2).await,
+            Ok(Transaction::withdrawal(2, 1, dec!(10)))
+        );
+
+        // disputes hold what was actually withdrawn
+        let account = engine
+            .process_transaction(Transaction::dispute(2, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account.held, dec!(10));
+    }
+
+    #[tokio::test]
+    async fn withdrawals_without_available_funds_withdraw_nothing() {
+        let (engine, _) = engine_with_partial_withdrawals(Amount::ZERO).await;
+
+        let (account, withdrawn) = engine
+            .process_transaction_with_withdrawn(Transaction::withdrawal(1, 1, dec!(5)))
+            .await
+            .unwrap();
+
+        assert_eq!(withdrawn, Some(Amount::ZERO));
+        assert_eq!(account, Account::new(1));
+    }
+
+    #[tokio::test]
+    async fn withdrawals_exceeding_the_available_funds_fail_by_default() {
+        let engine = Engine::new(MemoryStore::new());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+
+        let result = engine
+            .process_transaction_with_withdrawn(Transaction::withdrawal(2, 1, dec!(25)))
+            .await;
+
+        assert_eq!(result, Err(EngineError::InsufficientAvailableFunds));
+        assert_eq!(
+            engine
+                .process_transaction_with_withdrawn(Transaction::deposit(3, 1, dec!(1)))
+                .await,
+            Ok((Account::seeded(1, dec!(11), dec!(0), false), None))
+        );
+    }
+
+    #[tokio::test]
+    async fn every_attempted_transaction_is_audited_with_its_outcome() {
+        let audit_log = MemoryAuditLog::new();
+        let engine = Engine::new(MemoryStore::new()).with_audit_log(audit_log.clone());
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::withdrawal(2, 1, dec!(20)),
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::dispute(3, 1),
+            Transaction::withdrawal(4, 1, dec!(-1)),
+        ];
+
+        engine
+            .process_transactions(futures::stream::iter(transactions.clone()))
+            .await;
+        engine.adjust(1, dec!(5), "goodwill", false).await.unwrap();
+
+        let records = audit_log.records();
+        let outcomes = records
+            .iter()
+            .map(|record| record.outcome.clone())
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            records[..5]
+                .iter()
+                .map(|record| record.transaction.clone())
+                .collect::<Vec<_>>(),
+            transactions
+        );
+        assert!(matches!(
+            records[5].transaction,
+            Transaction::Adjustment { .. }
+        ));
+        assert_eq!(
+            outcomes,
+            vec![
+                Ok(Account::seeded(1, dec!(10), dec!(0), false)),
+                Err(EngineError::InsufficientAvailableFunds),
+                Err(EngineError::Store(StoreError::AlreadyExists { id: 1 })),
+                Ok(Account::seeded(1, dec!(10), dec!(0), false)),
+                Err(EngineError::NegativeAmountTransaction { id: 4 }),
+                Ok(Account::seeded(1, dec!(15), dec!(0), false)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rollback_transaction_under_dispute_state_if_tx_is_not_commited() {
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
+        let mut deposits = HashMap::new();
+        deposits.insert(1, Transaction::deposit(1, 1, dec!(10)));
+        let store = payments_engine_store_memory::MemoryStore::seeded(Some(deposits), None);
+
+        store.upsert_account(&account).await.unwrap();
+
+        assert_eq!(account.available, dec!(10));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, dec!(10));
+        assert_eq!(store.transactions_len(), 1);
+        assert_eq!(store.accounts_len(), 1);
+
+        // provoke a failure when saving the account.
+        // this will cause the transaction to be rolled back
+        store.set_enable_upsert_account_failure(true);
+
+        let engine = Engine::new(store.clone());
+
+        // test dispute rollback
+        let dispute = Transaction::dispute(1, 1);
+
+        let err = engine
+            .process_transaction(dispute.clone())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            EngineError::TransactionNotCommited(StoreError::AccessError("Test Error".to_string()))
+        );
+
+        assert_eq!(account.available, dec!(10));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, dec!(10));
+
+        assert_under_dispute(&store, 1, false);
+
+        // test resolve rollback
+        store.set_enable_upsert_account_failure(false);
+
+        let account = engine.process_transaction(dispute).await.unwrap();
+
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, dec!(10));
+        assert_eq!(account.total, dec!(10));
+
+        assert_under_dispute(&store, 1, true);
+
+        store.set_enable_upsert_account_failure(true);
+
+        let err = engine
+            .process_transaction(Transaction::resolve(1, 1))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            EngineError::TransactionNotCommited(StoreError::AccessError("Test Error".to_string()))
+        );
+
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, dec!(10));
+        assert_eq!(account.total, dec!(10));
+
+        assert_under_dispute(&store, 1, true);
+
+        // test chargeback rollback
+        let err = engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            EngineError::TransactionNotCommited(StoreError::AccessError("Test Error".to_string()))
+        );
+
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.held, dec!(10));
+        assert_eq!(account.total, dec!(10));
+
+        assert_under_dispute(&store, 1, true);
+    }
+
+    #[tokio::test]
+    async fn on_positive_adjustment_available_and_total_must_be_increased() {
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
+        let store = MemoryStore::new();
+        store.upsert_account(&account).await.unwrap();
+
+        let engine = Engine::new(store.clone());
+        let account = engine
+            .adjust(1, dec!(5), "bank fee refund", false)
+            .await
+            .unwrap();
+
+        assert_eq!(account.available, dec!(15));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, dec!(15));
+    }
+
+    #[tokio::test]
+    async fn on_negative_adjustment_available_and_total_must_be_decreased() {
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
+        let store = MemoryStore::new();
+        store.upsert_account(&account).await.unwrap();
+
+        let engine = Engine::new(store.clone());
+        let adjustment = Transaction::adjustment(1, 1, dec!(-4), "duplicated deposit");
+        let account = engine.process_transaction(adjustment).await.unwrap();
+
+        assert_eq!(account.available, dec!(6));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, dec!(6));
+    }
+
+    #[tokio::test]
+    async fn replayed_adjustments_are_rejected() {
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, false);
+        let store = MemoryStore::new();
+        store.upsert_account(&account).await.unwrap();
+
+        let engine = Engine::new(store.clone());
+        let adjustment = Transaction::adjustment(1, 1, dec!(1), "bank fee refund");
+        let account = engine
+            .process_transaction(adjustment.clone())
+            .await
+            .unwrap();
+        assert_eq!(account.available, dec!(11));
+
+        let err = engine.process_transaction(adjustment).await.unwrap_err();
+        assert_eq!(err, EngineError::Store(StoreError::AlreadyExists { id: 1 }));
+        assert_eq!(engine.get_account(1).await.unwrap().available, dec!(11));
+    }
+
+    #[tokio::test]
+    async fn on_negative_adjustment_error_if_it_underflows_available_funds() {
+        let account = Account::seeded(1, dec!(10), dec!(5), false);
+        let store = MemoryStore::new();
+        store.upsert_account(&account).await.unwrap();
+
+        let engine = Engine::new(store.clone());
+        let adjustment = Transaction::adjustment(1, 1, dec!(-11), "duplicated deposit");
+        let err = engine.process_transaction(adjustment).await.unwrap_err();
+
+        assert_eq!(err, EngineError::InsufficientAvailableFunds);
+        // it should not change the account
+        let account = store.get_or_default_account(1).await.unwrap();
+        assert_eq!(account.available, dec!(10));
+        assert_eq!(account.held, dec!(5));
+        assert_eq!(account.total, dec!(15));
+    }
+
+    #[tokio::test]
+    async fn adjustments_respect_locked_accounts_unless_admin() {
+        let account = Account::seeded(1, dec!(10), Amount::ZERO, true);
+        let store = MemoryStore::new();
+        store.upsert_account(&account).await.unwrap();
+
+        let engine = Engine::new(store.clone());
+
+        let err = engine
+            .adjust(1, dec!(-10), "closing balance", false)
+            .await
+            .unwrap_err();
+        assert_eq!(err, EngineError::LockedAccount { id: 1, tx: 1 });
+
+        let account = engine
+            .adjust(1, dec!(-10), "closing balance", true)
+            .await
+            .unwrap();
+        assert_eq!(account.available, Amount::ZERO);
+        assert_eq!(account.total, Amount::ZERO);
+        assert!(account.locked);
+    }
+
+    #[tokio::test]
+    async fn try_process_transactions_stops_at_the_first_rejected_transaction() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::dispute(9, 1),
+            Transaction::withdrawal(2, 1, dec!(20)),
+            Transaction::deposit(3, 1, dec!(5)),
+        ];
+
+        let err = engine
+            .try_process_transactions(futures::stream::iter(transactions.clone()))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, EngineError::InsufficientAvailableFunds);
+        assert_eq!(engine.processed_count(), 3);
+        assert_eq!(
+            store.get_account(1).await,
+            Ok(Some(Account::seeded(1, dec!(10), Amount::ZERO, false)))
+        );
+
+        let summary = Engine::new(MemoryStore::new())
+            .try_process_transactions(futures::stream::iter(
+                transactions.into_iter().take(2).collect::<Vec<_>>(),
+            ))
+            .await;
+        assert_eq!(
+            summary,
+            Ok(ProcessSummary {
+                applied: 1,
+                rejected: 0,
+                ignored: 1,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn accounts_can_be_opened_locked_or_unlocked() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+
+        let unlocked = engine
+            .process_transaction(Transaction::open_account(1, 1, false))
+            .await
+            .unwrap();
+        let locked = engine
+            .process_transaction(Transaction::open_account(2, 2, true))
+            .await
+            .unwrap();
+
+        assert_eq!(unlocked, Account::new(1));
+        assert_eq!(locked, Account::seeded(2, Amount::ZERO, Amount::ZERO, true));
+        assert_eq!(store.get_account(1).await, Ok(Some(unlocked)));
+        assert_eq!(store.get_account(2).await, Ok(Some(locked)));
+        assert_eq!(engine.clients_count(), 2);
+        // the locked account can't be used until it's unlocked
+        let err = engine
+            .process_transaction(Transaction::deposit(3, 2, dec!(10)))
+            .await
+            .unwrap_err();
+        assert_eq!(err, EngineError::LockedAccount { id: 2, tx: 3 });
+    }
+
+    #[tokio::test]
+    async fn existing_accounts_cannot_be_opened_again() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+
+        let err = engine
+            .process_transaction(Transaction::open_account(2, 1, true))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, EngineError::AccountAlreadyExists { client: 1 });
+        assert_eq!(
+            store.get_account(1).await,
+            Ok(Some(Account::seeded(1, dec!(10), Amount::ZERO, false)))
+        );
+    }
+
+    #[tokio::test]
+    async fn accounts_with_zero_total_can_be_closed() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(2, 1, dec!(10)))
+            .await
+            .unwrap();
+
+        let account = engine
+            .process_transaction(Transaction::close_account(3, 1))
+            .await
+            .unwrap();
+
+        assert_eq!(account.total, Amount::ZERO);
+        assert!(account.locked);
+        assert_eq!(store.get_account(1).await, Ok(Some(account)));
+        let err = engine
+            .process_transaction(Transaction::deposit(4, 1, dec!(1)))
+            .await
+            .unwrap_err();
+        assert_eq!(err, EngineError::LockedAccount { id: 1, tx: 4 });
+    }
+
+    #[tokio::test]
+    async fn accounts_with_nonzero_total_cannot_be_closed() {
+        let store = MemoryStore::new();
+        store
+            .upsert_account(&Account::seeded(1, dec!(5), dec!(2), false))
+            .await
+            .unwrap();
+        let engine = Engine::new(store.clone());
+
+        let err = engine
+            .process_transaction(Transaction::close_account(1, 1))
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            err,
+            EngineError::NonZeroBalanceOnClose {
+                client: 1,
+                total: dec!(7)
+            }
+        );
+        let account = store.get_account(1).await.unwrap().unwrap();
+        assert!(!account.locked);
+    }
+
+    #[tokio::test]
+    async fn accounts_which_do_not_exist_cannot_be_closed() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+
+        let err = engine
+            .process_transaction(Transaction::close_account(1, 7))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err, EngineError::AccountNotFound { client: 7 });
+        assert_eq!(store.get_account(7).await, Ok(None));
+        // the client can still open its account afterwards
+        let account = engine
+            .process_transaction(Transaction::open_account(2, 7, false))
+            .await
+            .unwrap();
+        assert!(!account.locked);
+    }
+
+    #[tokio::test]
+    async fn replaying_the_audited_transactions_rebuilds_the_same_accounts() {
+        let audit_log = MemoryAuditLog::new();
+        let engine = Engine::new(MemoryStore::new()).with_audit_log(audit_log.clone());
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::deposit(2, 2, dec!(20)),
+            Transaction::withdrawal(3, 1, dec!(4)),
+            Transaction::withdrawal(4, 2, dec!(50)),
+            Transaction::dispute(2, 2),
+            Transaction::chargeback(2, 2),
+            Transaction::deposit(5, 2, dec!(1)),
+            Transaction::deposit(6, 3, dec!(7.5)),
+            Transaction::dispute(6, 3),
+        ];
+        let summary = engine
+            .process_transactions(futures::stream::iter(transactions))
+            .await;
+        let expected = engine.report().await.unwrap().collect::<Vec<_>>().await;
+
+        let replayed = Engine::new(MemoryStore::new());
+        let result = replayed
+            .replay(futures::stream::iter(audit_log.transactions()))
+            .await;
+
+        assert_eq!(result, Ok(summary));
+        assert_eq!(
+            replayed.report().await.unwrap().collect::<Vec<_>>().await,
+            expected
+        );
+    }
+
+    #[tokio::test]
+    async fn transactions_cannot_be_replayed_into_a_non_empty_store() {
+        let store = MemoryStore::new();
+        store.upsert_account(&Account::new(1)).await.unwrap();
+        let engine = Engine::new(store.clone());
+
+        let result = engine
+            .replay(futures::stream::iter(vec![Transaction::deposit(
+                1,
+                1,
+                dec!(10),
+            )]))
+            .await;
+
+        assert_eq!(result, Err(EngineError::ReplayIntoNonEmptyStore));
+        assert_eq!(store.get_account(1).await, Ok(Some(Account::new(1))));
+    }
+
+    #[tokio::test]
+    async fn amounts_below_display_precision_are_retained_internally() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+
+        let account = engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(0.00001)))
+            .await
+            .unwrap();
+
+        assert_eq!(account.available, dec!(0.00001));
+        assert_eq!(account.total, dec!(0.00001));
+
+        // it's displayed as zero
+        let mut displayed = account.clone();
+        displayed.to_max_display_precision();
+        assert!(displayed.available.is_zero());
+        assert!(displayed.total.is_zero());
+    }
+
+    #[tokio::test]
+    async fn amounts_below_display_precision_accumulate_internally() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+
+        for id in 1..=10 {
+            engine
+                .process_transaction(Transaction::deposit(id, 1, dec!(0.00001)))
+                .await
+                .unwrap();
+        }
+        let account = engine
+            .process_transaction(Transaction::withdrawal(11, 1, dec!(0.00002)))
+            .await
+            .unwrap();
+
+        assert_eq!(account.available, dec!(0.00008));
+        assert_eq!(account.held, Amount::ZERO);
+        assert_eq!(account.total, dec!(0.00008));
+
+        let mut displayed = account.clone();
+        displayed.to_max_display_precision();
+        assert_eq!(displayed.available, dec!(0.0001));
+        assert_eq!(displayed.total, dec!(0.0001));
+    }
+
+    #[tokio::test]
+    async fn synthetic_transaction_ids_do_not_collide_with_input_ids() {
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+
+        for id in 1..=100 {
+            engine
+                .process_transaction(Transaction::deposit(id, 1, dec!(1)))
+                .await
+                .unwrap();
+        }
+
+        let generator = MonotonicIdGenerator::default();
+        let engine = engine.with_id_generator(generator);
+
+        // the new generator didn't see the seeded input range yet
+        for id in 101..=150 {
+            engine
+                .process_transaction(Transaction::deposit(id, 1, dec!(1)))
+                .await
+                .unwrap();
+        }
+
+        let account = engine
+            .adjust(1, dec!(-1), "correction", false)
+            .await
+            .unwrap();
+        assert_eq!(account.total, dec!(149));
+
+        let next = engine.id_generator.next_id().unwrap();
+        assert!(next > 151, "generated id {} collides with the input", next);
+        assert_eq!(store.transactions_len(), 150);
+    }
+
+    #[tokio::test]
+    async fn later_input_transactions_can_take_the_ids_generated_mid_stream() {
+        let engine = Engine::new(MemoryStore::new());
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        // the generator only saw the id 1, so the adjustment takes the id 2
+        engine.adjust(1, dec!(1), "goodwill", false).await.unwrap();
+
+        let result = engine
+            .process_transaction(Transaction::deposit(2, 1, dec!(5)))
+            .await;
+
+        assert_eq!(
+            result.unwrap_err(),
+            EngineError::Store(StoreError::AlreadyExists { id: 2 })
+        );
+    }
+
+    #[tokio::test]
+    async fn generators_starting_after_the_input_range_do_not_collide_with_later_input() {
+        let engine = Engine::new(MemoryStore::new())
+            .with_id_generator(MonotonicIdGenerator::starting_after(2));
+        engine
+            .process_transaction(Transaction::deposit(1, 1, dec!(10)))
+            .await
+            .unwrap();
+        engine.adjust(1, dec!(1), "goodwill", false).await.unwrap();
+
+        let account = engine
+            .process_transaction(Transaction::deposit(2, 1, dec!(5)))
+            .await
+            .unwrap();
+
+        assert_eq!(account.total, dec!(16));
     }
 }
@@ -0,0 +1,23 @@
+use payments_engine_core::{
+    common::{Amount, ClientId},
+    transaction::TransactionId,
+};
+
+/// Maximum number of events buffered for every subscriber.
+/// Lagging subscribers miss the oldest events once it's exceeded.
+pub(crate) const EVENTS_CAPACITY: usize = 1024;
+
+/// Events published by the [`Engine`](crate::Engine) once a transaction has been committed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EngineEvent {
+    /// The account got locked by a chargeback.
+    AccountLocked { client: ClientId, tx: TransactionId },
+    /// A withdrawal reached the [`EngineConfig::large_withdrawal_threshold`](crate::EngineConfig::large_withdrawal_threshold).
+    LargeWithdrawal {
+        client: ClientId,
+        tx: TransactionId,
+        amount: Amount,
+    },
+    /// A deposit or a withdrawal got disputed.
+    DisputeOpened { client: ClientId, tx: TransactionId },
+}
@@ -0,0 +1,77 @@
+use payments_engine_core::{id_generator::IdGenerator, transaction::TransactionId};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// [`IdGenerator`] implementation based on a monotonic counter.
+///
+/// The counter always starts above the max observed input id,
+/// so generated ids never collide with the ones seen so far.
+/// Once [`TransactionId::MAX`] is reached, the counter saturates and no more ids are generated.
+///
+/// Note that it only knows the input ids observed so far, not the ones still to come.
+/// An id generated mid-stream, e.g. by an adjustment, can be taken by a later input transaction,
+/// which is then rejected as a duplicate. If the input range is known upfront,
+/// use [`MonotonicIdGenerator::starting_after`] with its max id, so ids are always generated above it.
+#[derive(Debug, Default)]
+pub struct MonotonicIdGenerator {
+    last: AtomicU32,
+}
+
+impl MonotonicIdGenerator {
+    /// Creates a new [`MonotonicIdGenerator`] starting above the given [`TransactionId`].
+    #[must_use]
+    pub const fn starting_after(id: TransactionId) -> Self {
+        Self {
+            last: AtomicU32::new(id),
+        }
+    }
+}
+
+impl IdGenerator for MonotonicIdGenerator {
+    fn observe(&self, id: TransactionId) {
+        self.last.fetch_max(id, Ordering::SeqCst);
+    }
+
+    fn next_id(&self) -> Option<TransactionId> {
+        self.last
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+                last.checked_add(1)
+            })
+            .ok()
+            .map(|last| last + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_ids_are_monotonic() {
+        let generator = MonotonicIdGenerator::default();
+        assert_eq!(generator.next_id(), Some(1));
+        assert_eq!(generator.next_id(), Some(2));
+        assert_eq!(generator.next_id(), Some(3));
+    }
+
+    #[test]
+    fn generated_ids_start_above_the_max_observed_id() {
+        let generator = MonotonicIdGenerator::starting_after(10);
+        generator.observe(20);
+        generator.observe(15);
+        assert_eq!(generator.next_id(), Some(21));
+        generator.observe(5);
+        assert_eq!(generator.next_id(), Some(22));
+    }
+
+    #[test]
+    fn generated_ids_saturate_at_the_max_id() {
+        let generator = MonotonicIdGenerator::starting_after(TransactionId::MAX - 1);
+        assert_eq!(generator.next_id(), Some(TransactionId::MAX));
+        assert_eq!(generator.next_id(), None);
+        assert_eq!(generator.next_id(), None);
+
+        let generator = MonotonicIdGenerator::default();
+        generator.observe(TransactionId::MAX);
+        assert_eq!(generator.next_id(), None);
+    }
+}
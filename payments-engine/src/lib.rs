@@ -1,6 +1,19 @@
 #![allow(clippy::module_name_repetitions)]
 
 //! Implementation of the [`payments-engine-core::Engine`] trait.
+mod audit;
+mod config;
 mod engine;
+mod events;
+mod id_generator;
+pub mod reversal;
+mod sanctions;
+mod timing;
 
+pub use audit::{AuditRecord, MemoryAuditLog, TracingAuditLog};
+pub use config::{DisputePolicy, EngineConfig, ResolvePolicy, RollbackPolicy};
 pub use engine::*;
+pub use events::EngineEvent;
+pub use id_generator::MonotonicIdGenerator;
+pub use sanctions::SanctionsList;
+pub use timing::{LatencyHistogram, TimingEngine};
@@ -0,0 +1,169 @@
+use futures::{Stream, StreamExt};
+use payments_engine_core::{
+    engine::{EngineError, EngineResult},
+    id_generator::IdGenerator,
+    transaction::Transaction,
+};
+
+/// Returns the inverse of the given [`Transaction`], if it's reversible.
+///
+/// These are the reversible kinds:
+/// - [`Transaction::Deposit`] is reverted by a [`Transaction::Withdrawal`] of the same amount.
+/// - [`Transaction::Withdrawal`] is reverted by a [`Transaction::Deposit`] of the same amount.
+/// - [`Transaction::Adjustment`] is reverted by an adjustment with the opposite amount.
+/// - [`Transaction::Dispute`] is reverted by a [`Transaction::Resolve`] of the same deposit.
+/// - [`Transaction::Resolve`] is reverted by a [`Transaction::Dispute`] of the same deposit.
+///
+/// [`Transaction::ChargeBack`] is not reversible, as it permanently locks the account, so [`None`] is returned.
+/// Neither is [`Transaction::Review`], as a dispute can't go back from review,
+/// nor [`Transaction::OpenAccount`], as accounts can't be deleted,
+/// nor [`Transaction::CloseAccount`], as closed accounts are locked.
+///
+/// The inverse of deposits, withdrawals and adjustments are new transactions, so their ids are minted by the [`IdGenerator`].
+/// If it has no ids left, an [`EngineError::TransactionIdSpaceExhausted`] is returned.
+pub fn inverse(
+    transaction: &Transaction,
+    id_generator: &dyn IdGenerator,
+) -> EngineResult<Option<Transaction>> {
+    let next_id = || {
+        id_generator.next_id().ok_or_else(|| {
+            tracing::error!(
+                "No transaction ids left for the reversal of transaction {}",
+                transaction.info().id
+            );
+            EngineError::TransactionIdSpaceExhausted
+        })
+    };
+    let inverse = match transaction {
+        Transaction::Deposit { info, amount, .. } => {
+            Some(Transaction::withdrawal(next_id()?, info.client_id, *amount))
+        }
+        Transaction::Withdrawal { info, amount, .. } => {
+            Some(Transaction::deposit(next_id()?, info.client_id, *amount))
+        }
+        Transaction::Adjustment { info, amount, .. } => Some(Transaction::adjustment(
+            next_id()?,
+            info.client_id,
+            -*amount,
+            format!("Reversal of transaction {}", info.id),
+        )),
+        Transaction::Dispute { info, .. } => Some(Transaction::resolve(info.id, info.client_id)),
+        Transaction::Resolve { info } => Some(Transaction::dispute(info.id, info.client_id)),
+        Transaction::Review { .. }
+        | Transaction::ChargeBack { .. }
+        | Transaction::OpenAccount { .. }
+        | Transaction::CloseAccount { .. } => None,
+    };
+    Ok(inverse)
+}
+
+/// Produces the inverse of the given stream of processed transactions, in reverse order.
+/// Feeding the resulting stream to the engine unwinds the original one.
+///
+/// Note that the whole stream is buffered in order to reverse it.
+/// Transactions that can't be reversed are skipped, but running out of ids fails the whole stream
+/// with an [`EngineError::TransactionIdSpaceExhausted`], as it would only be unwound partially.
+/// See [`inverse`] for more details.
+pub async fn inverse_stream(
+    transactions: impl Stream<Item = Transaction> + Send,
+    id_generator: &dyn IdGenerator,
+) -> EngineResult<impl Stream<Item = Transaction>> {
+    let transactions = transactions.collect::<Vec<_>>().await;
+    let mut inverses = Vec::with_capacity(transactions.len());
+    for transaction in transactions.iter().rev() {
+        match inverse(transaction, id_generator)? {
+            Some(inverse) => inverses.push(inverse),
+            None => tracing::warn!(
+                "Skipping transaction {} as it can't be reversed",
+                transaction.info().id
+            ),
+        }
+    }
+    Ok(futures::stream::iter(inverses))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Engine, MonotonicIdGenerator};
+    use payments_engine_core::{common::Amount, dec, engine::Engine as CoreEngine};
+    use payments_engine_store_memory::MemoryStore;
+
+    #[test]
+    fn chargebacks_are_not_reversible() {
+        let id_generator = MonotonicIdGenerator::default();
+        assert_eq!(
+            inverse(&Transaction::chargeback(1, 1), &id_generator),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn deposits_fail_to_be_reversed_once_the_id_space_is_exhausted() {
+        let id_generator = MonotonicIdGenerator::starting_after(u32::MAX);
+        assert_eq!(
+            inverse(&Transaction::deposit(1, 1, dec!(10)), &id_generator),
+            Err(EngineError::TransactionIdSpaceExhausted)
+        );
+    }
+
+    #[tokio::test]
+    async fn inverse_stream_fails_once_the_id_space_is_exhausted() {
+        let id_generator = MonotonicIdGenerator::starting_after(u32::MAX);
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(10)),
+            Transaction::dispute(1, 1),
+        ];
+
+        let result = inverse_stream(futures::stream::iter(transactions), &id_generator).await;
+
+        assert!(matches!(
+            result,
+            Err(EngineError::TransactionIdSpaceExhausted)
+        ));
+    }
+
+    #[test]
+    fn inverse_of_disputes_and_resolves_reference_the_same_deposit() {
+        let id_generator = MonotonicIdGenerator::default();
+        assert_eq!(
+            inverse(&Transaction::dispute(1, 1), &id_generator),
+            Ok(Some(Transaction::resolve(1, 1)))
+        );
+        assert_eq!(
+            inverse(&Transaction::resolve(1, 1), &id_generator),
+            Ok(Some(Transaction::dispute(1, 1)))
+        );
+    }
+
+    #[tokio::test]
+    async fn inverse_stream_unwinds_deposits_and_withdrawals() {
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(100)),
+            Transaction::withdrawal(2, 1, dec!(30)),
+            Transaction::deposit(3, 2, dec!(20)),
+            Transaction::withdrawal(4, 1, dec!(70)),
+        ];
+        let store = MemoryStore::new();
+        let engine = Engine::new(store.clone());
+        for transaction in transactions.clone() {
+            engine.process_transaction(transaction).await.unwrap();
+        }
+
+        let id_generator = MonotonicIdGenerator::starting_after(4);
+        let mut inverses = inverse_stream(futures::stream::iter(transactions), &id_generator)
+            .await
+            .unwrap();
+        while let Some(transaction) = inverses.next().await {
+            engine.process_transaction(transaction).await.unwrap();
+        }
+
+        let accounts = engine.report().await.unwrap().collect::<Vec<_>>().await;
+        assert_eq!(accounts.len(), 2);
+        for account in accounts {
+            assert_eq!(account.available, Amount::ZERO);
+            assert_eq!(account.held, Amount::ZERO);
+            assert_eq!(account.total, Amount::ZERO);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use payments_engine_core::{common::ClientId, sanctions::SanctionsChecker};
+use std::collections::HashSet;
+
+/// [`SanctionsChecker`] implementation based on a fixed denylist of client ids.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SanctionsList {
+    clients: HashSet<ClientId>,
+}
+
+impl SanctionsList {
+    /// Creates a new [`SanctionsList`] with the given sanctioned clients.
+    #[must_use]
+    pub fn new(clients: impl IntoIterator<Item = ClientId>) -> Self {
+        Self {
+            clients: clients.into_iter().collect(),
+        }
+    }
+}
+
+impl SanctionsChecker for SanctionsList {
+    fn is_sanctioned(&self, client: ClientId) -> bool {
+        self.clients.contains(&client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_listed_clients_are_sanctioned() {
+        let list = SanctionsList::new([1, 3]);
+        assert!(list.is_sanctioned(1));
+        assert!(!list.is_sanctioned(2));
+        assert!(list.is_sanctioned(3));
+        assert!(!SanctionsList::default().is_sanctioned(1));
+    }
+}
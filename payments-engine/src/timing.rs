@@ -0,0 +1,236 @@
+use async_trait::async_trait;
+use payments_engine_core::{
+    account::Account,
+    common::ClientId,
+    engine::{Engine as CoreEngine, EngineResult},
+    transaction::Transaction,
+};
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tracing::instrument;
+
+/// Number of buckets of a [`LatencyHistogram`].
+/// The last one holds every sample of 2^38 nanoseconds (~4.5 minutes) or more.
+const BUCKETS: usize = 40;
+
+/// Histogram of processing latencies, bucketed by powers of two nanoseconds.
+/// Percentiles are approximated by the upper bound of their bucket, so they're at most twice the real value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    max: Duration,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: [0; BUCKETS],
+            count: 0,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl LatencyHistogram {
+    /// Records a new sample.
+    pub fn record(&mut self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let bucket = (u64::BITS - nanos.leading_zeros()) as usize;
+        self.buckets[bucket.min(BUCKETS - 1)] += 1;
+        self.count += 1;
+        self.max = self.max.max(duration);
+    }
+
+    /// Returns the number of recorded samples.
+    #[must_use]
+    pub const fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the longest recorded sample.
+    #[must_use]
+    pub const fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns the latency below which the given `quantile` (between 0 and 1) of the samples fall,
+    /// e.g. `0.99` for the p99, or [`None`] if there are no samples.
+    #[must_use]
+    pub fn percentile(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        let bucket = self
+            .buckets
+            .iter()
+            .position(|samples| {
+                seen += samples;
+                seen >= rank
+            })
+            .unwrap_or(BUCKETS - 1);
+        // bucket `n` holds the samples below 2^n nanoseconds
+        let upper_bound = Duration::from_nanos(1 << bucket);
+        Some(upper_bound.min(self.max))
+    }
+}
+
+/// [`Engine`](CoreEngine) adapter timing every [`CoreEngine::process_transaction`] of the inner engine
+/// and keeping a [`LatencyHistogram`] per kind of [`Transaction`], e.g. for SLO monitoring.
+///
+/// Failed transactions are timed too.
+/// Note that [`CoreEngine::process_transactions`] goes through [`CoreEngine::process_transaction`] to time every transaction,
+/// so the inner engine can't tell ignored transactions apart from applied ones.
+#[derive(Debug, Default)]
+pub struct TimingEngine<E> {
+    engine: E,
+    histograms: Mutex<HashMap<&'static str, LatencyHistogram>>,
+}
+
+impl<E: CoreEngine> TimingEngine<E> {
+    /// Creates a new [`TimingEngine`] wrapping the given engine.
+    #[must_use]
+    pub fn new(engine: E) -> Self {
+        Self {
+            engine,
+            histograms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the inner engine.
+    #[must_use]
+    pub const fn inner(&self) -> &E {
+        &self.engine
+    }
+
+    /// Returns the [`LatencyHistogram`] of the given kind of [`Transaction`], e.g. `deposit`,
+    /// or [`None`] if none of them was processed yet.
+    #[must_use]
+    pub fn histogram(&self, kind: &str) -> Option<LatencyHistogram> {
+        self.histograms
+            .lock()
+            .ok()
+            .and_then(|histograms| histograms.get(kind).cloned())
+    }
+
+    /// Returns the [`LatencyHistogram`] of every kind of [`Transaction`] processed so far.
+    #[must_use]
+    pub fn histograms(&self) -> HashMap<&'static str, LatencyHistogram> {
+        self.histograms
+            .lock()
+            .map(|histograms| histograms.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[async_trait]
+impl<E: CoreEngine> CoreEngine for TimingEngine<E> {
+    /// Processes the [`Transaction`] with the inner engine and records how long it took.
+    #[instrument(skip(self))]
+    async fn process_transaction(&self, transaction: Transaction) -> EngineResult<Account> {
+        let kind = transaction.kind();
+        let start = Instant::now();
+        let result = self.engine.process_transaction(transaction).await;
+        let elapsed = start.elapsed();
+        match self.histograms.lock() {
+            Ok(mut histograms) => histograms.entry(kind).or_default().record(elapsed),
+            Err(e) => tracing::error!("Error while recording the latency: {}", e),
+        }
+        result
+    }
+
+    async fn report(&self) -> EngineResult<Pin<Box<dyn futures::Stream<Item = Account> + Send>>> {
+        self.engine.report().await
+    }
+
+    async fn report_page(&self, offset: usize, limit: usize) -> EngineResult<Vec<Account>> {
+        self.engine.report_page(offset, limit).await
+    }
+
+    async fn client_ids(&self) -> EngineResult<Vec<ClientId>> {
+        self.engine.client_ids().await
+    }
+
+    async fn get_account(&self, client: ClientId) -> EngineResult<Account> {
+        self.engine.get_account(client).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Engine;
+    use payments_engine_core::dec;
+    use payments_engine_store_memory::MemoryStore;
+
+    #[test]
+    fn percentiles_are_approximated_by_their_bucket() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.percentile(0.5), None);
+
+        for _ in 0..99 {
+            histogram.record(Duration::from_nanos(100));
+        }
+        histogram.record(Duration::from_millis(1));
+
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.max(), Duration::from_millis(1));
+        assert_eq!(histogram.percentile(0.5), Some(Duration::from_nanos(128)));
+        assert_eq!(histogram.percentile(0.99), Some(Duration::from_nanos(128)));
+        assert_eq!(histogram.percentile(1.0), Some(Duration::from_millis(1)));
+    }
+
+    #[tokio::test]
+    async fn records_a_sample_per_transaction_kind() {
+        let engine = TimingEngine::new(Engine::new(MemoryStore::new()));
+        let transactions = vec![
+            Transaction::deposit(1, 1, dec!(100)),
+            Transaction::deposit(2, 1, dec!(50)),
+            Transaction::deposit(3, 2, dec!(10)),
+            Transaction::withdrawal(4, 1, dec!(20)),
+            // rejected, but timed anyway
+            Transaction::withdrawal(5, 2, dec!(1000)),
+            Transaction::dispute(2, 1),
+            Transaction::resolve(2, 1),
+            Transaction::dispute(3, 2),
+            Transaction::chargeback(3, 2),
+        ];
+        let summary = engine
+            .process_transactions(futures::stream::iter(transactions))
+            .await;
+        assert_eq!(summary.rejected, 1);
+
+        let counts = engine
+            .histograms()
+            .into_iter()
+            .map(|(kind, histogram)| (kind, histogram.count()))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            counts,
+            HashMap::from([
+                ("deposit", 3),
+                ("withdrawal", 2),
+                ("dispute", 2),
+                ("resolve", 1),
+                ("chargeback", 1),
+            ])
+        );
+        assert!(engine.histogram("review").is_none());
+        assert!(engine
+            .histogram("deposit")
+            .and_then(|histogram| histogram.percentile(0.99))
+            .is_some());
+        assert_eq!(engine.get_account(1).await.unwrap().total, dec!(130));
+    }
+}